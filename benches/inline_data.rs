@@ -0,0 +1,102 @@
+//! Peak memory and time of serializing a request with a 20 MB inline file
+//!
+//! Run with `cargo bench --bench inline_data`. Compares a blob holding
+//! base64 text serialized into a growing buffer, the way request bodies
+//! used to be built, with a blob holding the raw bytes serialized into a
+//! buffer sized up front, the way they are built now.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use gemini_rust::{Blob, Content, GenerateContentRequest};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const FILE_SIZE: usize = 20 * 1024 * 1024;
+
+struct Tracking;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(now, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+/// Stands in for reading a file
+fn file() -> Vec<u8> {
+    (0..FILE_SIZE).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn request(blob: Blob) -> GenerateContentRequest {
+    GenerateContentRequest {
+        contents: vec![Content::inline_data(blob)],
+        generation_config: None,
+        safety_settings: None,
+        tools: None,
+        tool_config: None,
+        system_instruction: None,
+        labels: None,
+    }
+}
+
+fn encoded() -> usize {
+    let bytes = file();
+    let blob = Blob::new("application/pdf", BASE64.encode(&bytes));
+    drop(bytes);
+    serde_json::to_vec(&request(blob)).unwrap().len()
+}
+
+fn raw() -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let request = request(Blob::from_vec("application/pdf", file()));
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, &request).unwrap();
+    let mut body = Vec::with_capacity(counter.0);
+    serde_json::to_writer(&mut body, &request).unwrap();
+    body.len()
+}
+
+fn measure(name: &str, run: fn() -> usize) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let started = Instant::now();
+    let body_len = run();
+    let elapsed = started.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+    println!(
+        "{:<8} body {:>5.1} MiB  peak {:>5.1} MiB  {:>7.1?}",
+        name,
+        body_len as f64 / 1048576.0,
+        peak as f64 / 1048576.0,
+        elapsed
+    );
+}
+
+fn main() {
+    measure("encoded", encoded);
+    measure("raw", raw);
+}
@@ -0,0 +1,64 @@
+//! Cost of reading the text of a response once per frame
+//!
+//! Run with `cargo bench --bench response_text`. Compares copying the text
+//! into a `String`, as `text()` used to, with the borrowing `text()` and
+//! `text_ref()` accessors.
+
+use gemini_rust::GenerationResponse;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const FRAMES: usize = 1_000_000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn measure(name: &str, response: &GenerationResponse, read: fn(&GenerationResponse) -> usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let mut total = 0;
+    for _ in 0..FRAMES {
+        total += read(black_box(response));
+    }
+    let elapsed = started.elapsed();
+    black_box(total);
+    println!(
+        "{:<10} {:>8.1?}  {:>5.1} ns/frame  {} allocations",
+        name,
+        elapsed,
+        elapsed.as_nanos() as f64 / FRAMES as f64,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations
+    );
+}
+
+fn main() {
+    let text = "Streaming reply text. ".repeat(200);
+    let response: GenerationResponse = serde_json::from_value(serde_json::json!({
+        "candidates": [{
+            "content": { "role": "model", "parts": [{ "text": text }] },
+            "finishReason": "STOP"
+        }]
+    }))
+    .unwrap();
+
+    measure("owned", &response, |r| r.text().into_owned().len());
+    measure("text", &response, |r| r.text().len());
+    measure("text_ref", &response, |r| r.text_ref().map_or(0, str::len));
+}
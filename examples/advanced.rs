@@ -2,7 +2,6 @@ use gemini_rust::{
     Content, FunctionCallingMode, FunctionDeclaration, FunctionParameters, Gemini, Part,
     PropertyDetails,
 };
-use serde_json;
 use std::env;
 
 #[tokio::main]
@@ -76,6 +75,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut call_content = Content::default();
         call_content.parts.push(Part::FunctionCall {
             function_call: (*function_call).clone(),
+            thought_signature: None,
         });
         final_request.contents.push(call_content);
 
@@ -0,0 +1,45 @@
+use gemini_rust::{ChatSession, Gemini, JsonFileStore, SessionStore};
+use std::env;
+use std::io::{self, BufRead, Write};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    // Resume the conversation from the last run, if there was one
+    let store = JsonFileStore::new(env::temp_dir().join("gemini-rust-chat"))?;
+    let mut session = match store.load("example")? {
+        Some(session) => {
+            println!(
+                "Resuming a conversation with {} turns",
+                session.history.len()
+            );
+            session
+        }
+        None => ChatSession::new("example")
+            .with_system_instruction("You are a concise, friendly assistant."),
+    };
+
+    println!("Type a message, or an empty line to quit.");
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        let response = session.send(&client, line.trim()).await?;
+        println!("{}\n", response.text());
+
+        // Save after every turn so nothing is lost if the process dies
+        store.save(&session)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use gemini_rust::ClientConfig;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Load client and request settings from a file
+    let config =
+        ClientConfig::from_path(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/config.toml"))?;
+
+    // The config round-trips, printed here in the API's own spelling
+    println!("Effective config:\n{}", config.to_toml()?);
+
+    // Build the client from the config (the API key comes from GEMINI_API_KEY)
+    let client = config.build()?;
+
+    // Apply the request settings, then add the message
+    let response = config
+        .apply(client.generate_content())
+        .with_user_message("Why is the sky blue?")
+        .execute()
+        .await?;
+
+    println!("Response: {}", response.text());
+
+    Ok(())
+}
@@ -42,11 +42,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a content part that matches the JSON in the curl example
     let text_part = Part::Text {
         text: "Explain how AI works in a few words".to_string(),
+        thought_signature: None,
     };
 
     let content = Content {
         parts: vec![text_part],
-        role: None,
+        ..Default::default()
     };
 
     // Add the content directly to the request
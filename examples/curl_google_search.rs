@@ -32,11 +32,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a content part that matches the JSON in the curl example
     let text_part = Part::Text {
         text: "What is the current Google stock price?".to_string(),
+        thought_signature: None,
     };
 
     let content = Content {
         parts: vec![text_part],
-        role: None,
+        ..Default::default()
     };
 
     // Create a Google Search tool
@@ -0,0 +1,56 @@
+use gemini_rust::Gemini;
+use std::env;
+use std::str::FromStr;
+
+#[derive(Debug)]
+enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+    Unknown,
+}
+
+impl FromStr for Sentiment {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "positive" => Ok(Self::Positive),
+            "neutral" => Ok(Self::Neutral),
+            "negative" => Ok(Self::Negative),
+            _ => Err(()),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    println!("--- Enum Output Example ---");
+
+    let reviews = [
+        "The battery lasts all day and the screen is gorgeous.",
+        "It arrived on time. It does what the box says.",
+        "Stopped working after a week and support never answered.",
+    ];
+
+    for review in reviews {
+        // The answer is constrained to one of the values, as plain text
+        let sentiment = client
+            .generate_content()
+            .with_system_prompt("Classify the sentiment of the product review.")
+            .with_user_message(review)
+            .with_enum_output(["positive", "neutral", "negative"])
+            .execute_enum_or(Sentiment::Unknown)
+            .await?;
+
+        println!("{:?}: {}", sentiment, review);
+    }
+
+    Ok(())
+}
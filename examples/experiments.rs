@@ -0,0 +1,41 @@
+use gemini_rust::{Experiment, Gemini};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    // Two system prompts, each receiving half of the traffic
+    let experiment = Experiment::new("summary-style")
+        .with_variant("terse", 50, |builder| {
+            builder.with_system_prompt("Answer in one sentence.")
+        })
+        .with_variant("bullets", 50, |builder| {
+            builder
+                .with_system_prompt("Answer with exactly three bullet points.")
+                .with_temperature(0.3)
+        });
+
+    let questions = [
+        "What is HTTP/2 server push?",
+        "Why do databases use write-ahead logs?",
+        "What does a CDN do?",
+        "How does TLS session resumption work?",
+    ];
+    for question in questions {
+        let builder = client.generate_content().with_user_message(question);
+        match experiment.run(builder).await {
+            Ok((variant, response)) => println!("[{}] {}\n", variant, response.text()),
+            Err(e) => println!("Request failed: {}\n", e),
+        }
+    }
+
+    // Per-variant latency, token usage and finish reasons
+    println!("{}", experiment.report());
+
+    Ok(())
+}
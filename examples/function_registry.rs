@@ -0,0 +1,53 @@
+use gemini_rust::{
+    FunctionCall, FunctionDeclaration, FunctionParameters, FunctionRegistry, Gemini, GuardDecision,
+    PropertyDetails,
+};
+use serde_json::json;
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    println!("--- Automatic function calling with a guard ---");
+
+    // Define a function that reads files
+    let read_file = FunctionDeclaration::new(
+        "read_file",
+        "Read a text file from the workspace",
+        FunctionParameters::object().with_property(
+            "path",
+            PropertyDetails::string("Path of the file, relative to the workspace"),
+            true,
+        ),
+    );
+
+    // Register the handler and a guard that keeps calls inside the sandbox
+    let registry = FunctionRegistry::new()
+        .register(read_file, |call: FunctionCall| async move {
+            let path: String = call.get("path")?;
+            Ok(json!({ "path": path, "contents": "fn main() {}" }))
+        })
+        .with_guard(|call: &FunctionCall| match call.get::<String>("path") {
+            Ok(path) if path.starts_with('/') || path.contains("..") => {
+                GuardDecision::Deny(format!("{} is outside the workspace", path))
+            }
+            _ => GuardDecision::Allow,
+        });
+
+    // The registry dispatches the model's calls until it answers in text
+    let response = client
+        .generate_content()
+        .with_user_message("What is in src/main.rs? Also show me /etc/passwd.")
+        .with_function_registry(registry)
+        .execute()
+        .await?;
+
+    println!("Response: {}", response.text());
+
+    Ok(())
+}
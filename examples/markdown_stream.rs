@@ -0,0 +1,48 @@
+use gemini_rust::{Gemini, MarkdownHandler};
+use std::env;
+use std::io::Write;
+
+/// Prints prose as is and code blocks in color
+struct Terminal;
+
+impl MarkdownHandler for Terminal {
+    fn on_text(&mut self, text: &str) {
+        print!("{}", text);
+        let _ = std::io::stdout().flush();
+    }
+
+    fn on_code_block_start(&mut self, language: Option<&str>) {
+        println!("\x1b[2m[{}]\x1b[0m", language.unwrap_or("code"));
+    }
+
+    fn on_code(&mut self, code: &str) {
+        print!("\x1b[36m{}\x1b[0m", code);
+        let _ = std::io::stdout().flush();
+    }
+
+    fn on_code_block_end(&mut self) {
+        println!("\x1b[2m[end]\x1b[0m");
+    }
+
+    fn on_done(&mut self) {
+        println!();
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    // Stream an answer with code, highlighting the code blocks as they arrive
+    client
+        .generate_content()
+        .with_user_message("Show how to reverse a string in Rust and in Python.")
+        .execute_stream_markdown(Terminal)
+        .await?;
+
+    Ok(())
+}
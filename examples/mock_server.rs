@@ -0,0 +1,80 @@
+use futures_util::StreamExt;
+use gemini_rust::{
+    mock_server::{
+        self,
+        wiremock::{Mock, MockServer},
+    },
+    RetryBudget,
+};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // A local server standing in for the API
+    let server = MockServer::start().await;
+    let client = mock_server::client(&server);
+
+    println!("--- Unary call ---");
+
+    // Answer questions about the weather, and check exactly one is asked
+    Mock::given(mock_server::generate_content().for_model("gemini-2.0-flash"))
+        .and(mock_server::prompt_contains("weather"))
+        .respond_with(mock_server::text("Sunny, 24°C."))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let response = client
+        .generate_content()
+        .with_user_message("What's the weather in Lisbon?")
+        .execute()
+        .await?;
+    println!("Response: {}", response.text());
+
+    println!("\n--- Streamed call ---");
+
+    Mock::given(mock_server::stream_generate_content())
+        .respond_with(mock_server::stream_text(
+            "Once upon a time, a robot learned to paint.",
+            4,
+        ))
+        .mount(&server)
+        .await;
+
+    let mut stream = client
+        .generate_content()
+        .with_user_message("Tell me a story")
+        .execute_stream()
+        .await?;
+    while let Some(chunk) = stream.next().await {
+        println!("Chunk: {:?}", chunk?.text());
+    }
+
+    println!("\n--- Retried error ---");
+
+    // The first call is rate limited, the retry succeeds
+    Mock::given(mock_server::generate_content())
+        .and(mock_server::prompt_contains("haiku"))
+        .respond_with(mock_server::rate_limited(Some(Duration::from_secs(1))))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(mock_server::generate_content())
+        .and(mock_server::prompt_contains("haiku"))
+        .respond_with(mock_server::text("Old pond, a frog jumps"))
+        .mount(&server)
+        .await;
+
+    let response = client
+        .generate_content()
+        .with_user_message("Write a haiku")
+        .with_retry_budget(RetryBudget::new(3))
+        .execute()
+        .await?;
+    println!("Response after retry: {}", response.text());
+
+    // Fails if the expectations set with `expect` were not met
+    server.verify().await;
+
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use gemini_rust::{Gemini, PromptStore};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Load every prompt in the directory; file stems become prompt names
+    let store = PromptStore::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/prompts"))?;
+    println!("Loaded prompts: {:?}", store.names().collect::<Vec<_>>());
+
+    // Create client
+    let client = Gemini::new(api_key).with_prompt_store(store);
+
+    // Run a prompt by name, filling in its variables
+    let response = client
+        .run_prompt(
+            "summarize_ticket",
+            [
+                ("audience", "the engineering team"),
+                (
+                    "ticket",
+                    "Customer reports that exports time out for reports over 10k rows \
+                     since Tuesday's release. Workaround: filter by month.",
+                ),
+            ],
+        )
+        .await?;
+
+    println!("Summary: {}", response.text());
+
+    Ok(())
+}
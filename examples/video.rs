@@ -0,0 +1,37 @@
+use gemini_rust::{Gemini, Video, VideoMetadata};
+use std::env;
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Video path from the command line
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: cargo run --example video -- <video>");
+        return Ok(());
+    };
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    // Only look at the first minute, two frames per second
+    let video = Video::from_path(&path)?.with_metadata(
+        VideoMetadata::new()
+            .with_clip(Duration::ZERO, Duration::from_secs(60))
+            .with_fps(2.0),
+    );
+
+    let answer = client
+        .ask_about_video(&video, "What are the key moments in this video?")
+        .await?;
+
+    println!("Answer:\n{}\n", answer.text);
+    for segment in &answer.segments {
+        let secs = segment.start.as_secs();
+        println!("[{:02}:{:02}] {}", secs / 60, secs % 60, segment.text);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use gemini_rust::{Gemini, Image};
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Get API key from environment variable
+    let api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY environment variable not set");
+
+    // Image paths from the command line
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: cargo run --example vision -- <image> [<other image>]");
+        return Ok(());
+    }
+
+    // Create client
+    let client = Gemini::new(api_key);
+
+    // Describe the first image
+    let first = Image::from_path(&paths[0])?;
+    println!("Description:\n{}\n", client.describe_image(&first).await?);
+
+    // Find the objects in it, with boxes in pixels
+    for object in client.detect_objects(&first).await? {
+        let b = object.box_2d;
+        println!(
+            "{} at ({}, {}) size {}x{}",
+            object.label,
+            b.x_min,
+            b.y_min,
+            b.width(),
+            b.height()
+        );
+    }
+    println!();
+
+    // Compare it with the second one, if given
+    if let Some(path) = paths.get(1) {
+        let second = Image::from_path(path)?;
+        let comparison = client.compare_images(&first, &second).await?;
+        println!("Similarities: {:#?}", comparison.similarities);
+        println!("Differences: {:#?}", comparison.differences);
+        println!("Summary: {}", comparison.summary);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use crate::{Error, Result};
+use secrecy::{ExposeSecret, SecretString};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Supplies the API key for each request
+///
+/// The key is looked up again for every API call, so providers can pick up
+/// rotated keys without rebuilding the client. Attach one with
+/// `Gemini::builder_with_key_provider`. Keys are handed out as
+/// [`SecretString`]s, which are zeroed when dropped and print as
+/// `[REDACTED]`.
+pub trait ApiKeyProvider: Send + Sync + std::fmt::Debug {
+    /// The key to use for the next request
+    fn api_key(&self) -> Result<SecretString>;
+}
+
+/// A fixed API key
+#[derive(Clone)]
+pub struct StaticKey(SecretString);
+
+impl StaticKey {
+    /// Use the given key for every request
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self(SecretString::from(api_key.into()))
+    }
+}
+
+impl From<SecretString> for StaticKey {
+    fn from(api_key: SecretString) -> Self {
+        Self(api_key)
+    }
+}
+
+impl ApiKeyProvider for StaticKey {
+    fn api_key(&self) -> Result<SecretString> {
+        Ok(self.0.clone())
+    }
+}
+
+impl std::fmt::Debug for StaticKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StaticKey(..)")
+    }
+}
+
+/// Reads the API key from an environment variable on every request
+#[derive(Debug, Clone)]
+pub struct EnvKey {
+    var: String,
+}
+
+impl EnvKey {
+    /// Read the key from the given variable
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl Default for EnvKey {
+    /// Read the key from `GEMINI_API_KEY`
+    fn default() -> Self {
+        Self::new("GEMINI_API_KEY")
+    }
+}
+
+impl ApiKeyProvider for EnvKey {
+    fn api_key(&self) -> Result<SecretString> {
+        match std::env::var(&self.var) {
+            Ok(key) if !key.trim().is_empty() => Ok(SecretString::from(key.trim())),
+            _ => Err(Error::MissingApiKey),
+        }
+    }
+}
+
+/// Reads the API key from a file, picking up changes to it
+///
+/// Meant for secrets mounted into a container, e.g. a Kubernetes secret
+/// volume, that are rotated in place. The file is checked before every
+/// request and read again when its modification time changes; surrounding
+/// whitespace is ignored.
+pub struct FileKey {
+    path: PathBuf,
+    cached: Mutex<Option<(SystemTime, SecretString)>>,
+}
+
+impl FileKey {
+    /// Read the key from the given file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    fn read_error(&self, error: std::io::Error) -> Error {
+        Error::RequestError(format!(
+            "Cannot read API key from {}: {}",
+            self.path.display(),
+            error
+        ))
+    }
+}
+
+impl std::fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileKey").field("path", &self.path).finish()
+    }
+}
+
+impl ApiKeyProvider for FileKey {
+    fn api_key(&self) -> Result<SecretString> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .map_err(|e| self.read_error(e))?;
+
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((time, key)) = cached.as_ref() {
+            if *time == modified {
+                return Ok(key.clone());
+            }
+        }
+
+        let contents = SecretString::from(
+            std::fs::read_to_string(&self.path).map_err(|e| self.read_error(e))?,
+        );
+        let key = SecretString::from(contents.expose_secret().trim());
+        if key.expose_secret().is_empty() {
+            return Err(Error::MissingApiKey);
+        }
+        *cached = Some((modified, key.clone()));
+        Ok(key)
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    models::{GenerateContentRequest, GenerationResponse},
+    Error, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+/// Largest input file the Batch API accepts (2 GB)
+pub const MAX_BATCH_FILE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Largest total size of requests sent inline in a batch job (20 MB)
+pub const MAX_INLINE_BATCH_SIZE: usize = 20 * 1024 * 1024;
+
+/// One line of a batch input file
+#[derive(Serialize)]
+struct FileLine<'a> {
+    key: &'a str,
+    request: &'a GenerateContentRequest,
+}
+
+/// One entry of an inline batch
+#[derive(Serialize)]
+struct InlineEntry<'a> {
+    request: &'a GenerateContentRequest,
+    metadata: Metadata<'a>,
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    key: &'a str,
+}
+
+/// A result line or inline result, in either format
+#[derive(Deserialize)]
+struct ResultEntry {
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    metadata: Option<ResultMetadata>,
+    #[serde(default)]
+    response: Option<GenerationResponse>,
+    #[serde(default)]
+    error: Option<BatchError>,
+}
+
+#[derive(Deserialize)]
+struct ResultMetadata {
+    key: String,
+}
+
+/// Why a request of a batch failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchError {
+    /// The status code, e.g. `3` for an invalid argument
+    #[serde(default)]
+    pub code: i32,
+    /// What went wrong
+    #[serde(default)]
+    pub message: String,
+}
+
+/// The outcome of one request of a batch
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// The key the request was added with
+    pub key: String,
+    /// The response, or why there is none
+    pub outcome: std::result::Result<GenerationResponse, BatchError>,
+}
+
+/// The keys of a batch, in the order its requests were added
+///
+/// Returned when a batch is written; use it to read the results back in
+/// the same order, whatever order the API wrote them in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchManifest {
+    /// Request keys, in order
+    pub keys: Vec<String>,
+    /// Size of the written batch in bytes
+    pub bytes: u64,
+}
+
+/// Writes requests to a Batch API input file (JSON Lines)
+///
+/// Each line holds a request and the key that identifies its result.
+/// Requests are checked as they are added; the file must stay under
+/// [`MAX_BATCH_FILE_SIZE`].
+pub struct BatchWriter<W: Write> {
+    writer: W,
+    manifest: BatchManifest,
+    seen: HashSet<String>,
+}
+
+impl<W: Write> BatchWriter<W> {
+    /// Write a batch to a file or any other writer
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            manifest: BatchManifest::default(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Add a request under a unique key
+    pub fn push(&mut self, key: impl Into<String>, request: &GenerateContentRequest) -> Result<()> {
+        let key = check_key(key.into(), &self.seen)?;
+        request.validate()?;
+        let mut line = serde_json::to_vec(&FileLine { key: &key, request })?;
+        line.push(b'\n');
+        let bytes = self.manifest.bytes + line.len() as u64;
+        if bytes > MAX_BATCH_FILE_SIZE {
+            return Err(Error::RequestError(format!(
+                "Batch file would exceed {} bytes at request {:?}",
+                MAX_BATCH_FILE_SIZE, key
+            )));
+        }
+        self.writer
+            .write_all(&line)
+            .map_err(|e| Error::RequestError(format!("Cannot write batch file: {}", e)))?;
+        self.manifest.bytes = bytes;
+        self.seen.insert(key.clone());
+        self.manifest.keys.push(key);
+        Ok(())
+    }
+
+    /// Flush the writer and return the manifest
+    pub fn finish(mut self) -> Result<BatchManifest> {
+        self.writer
+            .flush()
+            .map_err(|e| Error::RequestError(format!("Cannot write batch file: {}", e)))?;
+        Ok(self.manifest)
+    }
+}
+
+/// Build the inline `requests` of a batch job
+///
+/// Returns the value to send as `inputConfig.requests`, holding every
+/// request with its key as metadata, and the manifest to read the results
+/// with. Fails if a request is invalid, a key is repeated or the batch is
+/// over [`MAX_INLINE_BATCH_SIZE`].
+pub fn inline_batch<K: Into<String>>(
+    requests: impl IntoIterator<Item = (K, GenerateContentRequest)>,
+) -> Result<(serde_json::Value, BatchManifest)> {
+    let mut manifest = BatchManifest::default();
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for (key, request) in requests {
+        let key = check_key(key.into(), &seen)?;
+        request.validate()?;
+        let entry = serde_json::to_value(InlineEntry {
+            request: &request,
+            metadata: Metadata { key: &key },
+        })?;
+        manifest.bytes += serde_json::to_vec(&entry)?.len() as u64;
+        if manifest.bytes > MAX_INLINE_BATCH_SIZE as u64 {
+            return Err(Error::RequestError(format!(
+                "Inline batch would exceed {} bytes at request {:?}; write a batch file instead",
+                MAX_INLINE_BATCH_SIZE, key
+            )));
+        }
+        entries.push(entry);
+        seen.insert(key.clone());
+        manifest.keys.push(key);
+    }
+    Ok((serde_json::json!({ "requests": entries }), manifest))
+}
+
+impl BatchManifest {
+    /// Read a batch result file (JSON Lines), in the order of the keys
+    ///
+    /// Requests without a result line get a [`BatchError`] saying so.
+    /// Blank lines are skipped.
+    pub fn read_results(&self, reader: impl BufRead) -> Result<Vec<BatchResult>> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line =
+                line.map_err(|e| Error::RequestError(format!("Cannot read batch results: {}", e)))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        self.order(entries)
+    }
+
+    /// Read the inline results of a batch job, in the order of the keys
+    ///
+    /// Accepts the job's `inlinedResponses` object or the array inside it.
+    pub fn read_inline_results(&self, value: serde_json::Value) -> Result<Vec<BatchResult>> {
+        let value = match value {
+            serde_json::Value::Object(mut object) => object
+                .remove("inlinedResponses")
+                .unwrap_or(serde_json::Value::Object(object)),
+            value => value,
+        };
+        self.order(serde_json::from_value(value)?)
+    }
+
+    fn order(&self, entries: Vec<ResultEntry>) -> Result<Vec<BatchResult>> {
+        let mut by_key = HashMap::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            // Results without a key follow the order of the requests
+            let key = entry
+                .key
+                .or(entry.metadata.map(|m| m.key))
+                .or_else(|| self.keys.get(index).cloned())
+                .ok_or_else(|| {
+                    Error::RequestError(format!("Batch result {} has no key", index + 1))
+                })?;
+            let outcome = match (entry.response, entry.error) {
+                (_, Some(error)) => Err(error),
+                (Some(response), None) => Ok(response),
+                (None, None) => Err(BatchError {
+                    code: 0,
+                    message: "Result has neither a response nor an error".to_string(),
+                }),
+            };
+            by_key.insert(key, outcome);
+        }
+
+        Ok(self
+            .keys
+            .iter()
+            .map(|key| BatchResult {
+                key: key.clone(),
+                outcome: by_key.remove(key).unwrap_or_else(|| {
+                    Err(BatchError {
+                        code: 0,
+                        message: "No result for this request".to_string(),
+                    })
+                }),
+            })
+            .collect())
+    }
+}
+
+fn check_key(key: String, seen: &HashSet<String>) -> Result<String> {
+    if key.is_empty() {
+        return Err(Error::RequestError(
+            "Batch request keys must not be empty".to_string(),
+        ));
+    }
+    if seen.contains(&key) {
+        return Err(Error::RequestError(format!(
+            "Batch request key {:?} is used twice",
+            key
+        )));
+    }
+    Ok(key)
+}
@@ -0,0 +1,214 @@
+//! `gemini`: a small command-line client built on the gemini-rust crate
+//!
+//! Reads the API key from `GEMINI_API_KEY` (or `--api-key`).
+
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use gemini_rust::{ChatSession, Gemini, PartDelta, TaskType};
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "gemini", version, about = "Talk to Google's Gemini API")]
+struct Cli {
+    /// API key
+    #[arg(long, env = "GEMINI_API_KEY", hide_env_values = true)]
+    api_key: String,
+
+    /// Model to use, e.g. gemini-2.0-flash
+    #[arg(long, short, global = true)]
+    model: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Ask a single question and stream the answer
+    Ask {
+        /// System instruction
+        #[arg(long, short)]
+        system: Option<String>,
+        /// The question; read from stdin if omitted
+        question: Vec<String>,
+    },
+    /// Start an interactive chat with streamed replies
+    Chat {
+        /// System instruction
+        #[arg(long, short)]
+        system: Option<String>,
+    },
+    /// Print the embedding of a text as a JSON array
+    Embed {
+        /// What the embedding is for
+        #[arg(long, value_enum)]
+        task_type: Option<Task>,
+        /// The text; read from stdin if omitted
+        text: Vec<String>,
+    },
+    /// Inspect available models
+    Models {
+        #[command(subcommand)]
+        command: ModelsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelsCommand {
+    /// List all models
+    List,
+    /// Show details of one model
+    Get {
+        /// Model name, e.g. gemini-2.0-flash
+        name: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Task {
+    RetrievalQuery,
+    RetrievalDocument,
+    SemanticSimilarity,
+    Classification,
+    Clustering,
+    QuestionAnswering,
+    FactVerification,
+}
+
+impl From<Task> for TaskType {
+    fn from(task: Task) -> Self {
+        match task {
+            Task::RetrievalQuery => TaskType::RetrievalQuery,
+            Task::RetrievalDocument => TaskType::RetrievalDocument,
+            Task::SemanticSimilarity => TaskType::SemanticSimilarity,
+            Task::Classification => TaskType::Classification,
+            Task::Clustering => TaskType::Clustering,
+            Task::QuestionAnswering => TaskType::QuestionAnswering,
+            Task::FactVerification => TaskType::FactVerification,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = Gemini::builder_from_secret(cli.api_key.into());
+    if let Some(model) = &cli.model {
+        builder = builder.with_model(model.clone());
+    }
+    let client = builder.build()?;
+
+    match cli.command {
+        Command::Ask { system, question } => {
+            let question = words_or_stdin(question)?;
+            let mut request = client.generate_content().with_user_message(question);
+            if let Some(system) = system {
+                request = request.with_system_instruction(system);
+            }
+            let mut stream = request.execute_stream_parts().await?;
+            print_stream(&mut stream).await?;
+        }
+        Command::Chat { system } => chat(&client, system).await?,
+        Command::Embed { task_type, text } => {
+            let mut request = client.embed_content(words_or_stdin(text)?);
+            if let Some(task_type) = task_type {
+                request = request.with_task_type(task_type.into());
+            }
+            let embedding = request.execute().await?;
+            println!("{}", serde_json::to_string(&embedding.values)?);
+        }
+        Command::Models {
+            command: ModelsCommand::List,
+        } => {
+            for model in client.list_models().await? {
+                let name = model.name.strip_prefix("models/").unwrap_or(&model.name);
+                println!("{:<45} {}", name, model.display_name);
+            }
+        }
+        Command::Models {
+            command: ModelsCommand::Get { name },
+        } => {
+            let model = client.get_model(&name).await?;
+            println!("{}", serde_json::to_string_pretty(&model)?);
+        }
+    }
+    Ok(())
+}
+
+/// Run a read-eval-print loop until end of input or an empty line
+async fn chat(client: &Gemini, system: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut session = ChatSession::new("cli");
+    session.system_instruction = system;
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(());
+        }
+
+        session.push_user_text(line.trim());
+        let result = match session.request(client).execute_stream_parts().await {
+            Ok(mut stream) => print_stream(&mut stream).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(reply) => session.push_model_text(reply),
+            Err(e) => {
+                // Drop the unanswered message so the conversation stays valid
+                session.history.pop();
+                eprintln!("error: {}", e);
+            }
+        }
+        println!();
+    }
+}
+
+/// Print streamed text as it arrives and return the full reply
+async fn print_stream<S>(stream: &mut S) -> Result<String, Box<dyn std::error::Error>>
+where
+    S: futures_util::Stream<Item = gemini_rust::Result<PartDelta>> + Unpin,
+{
+    let mut reply = String::new();
+    let mut stdout = io::stdout();
+    while let Some(delta) = stream.next().await {
+        match delta? {
+            PartDelta::TextDelta(text) => {
+                write!(stdout, "{}", text)?;
+                stdout.flush()?;
+                reply.push_str(&text);
+            }
+            PartDelta::Finish { reason, .. } => {
+                if let Some(reason) = reason.filter(|r| r != "STOP") {
+                    write!(stdout, "\n[finished: {}]", reason)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    writeln!(stdout)?;
+    Ok(reply)
+}
+
+/// Join command-line words, or read all of stdin if there are none
+fn words_or_stdin(words: Vec<String>) -> io::Result<String> {
+    if !words.is_empty() {
+        return Ok(words.join(" "));
+    }
+    let mut input = String::new();
+    io::Read::read_to_string(&mut io::stdin(), &mut input)?;
+    Ok(input.trim().to_string())
+}
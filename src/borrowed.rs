@@ -0,0 +1,155 @@
+use crate::{models::UsageMetadata, Error, Result};
+use serde::{Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+
+/// Parse a `generateContent` response body without copying its strings
+///
+/// Pair with `ContentBuilder::execute_raw_bytes`. Strings borrow from
+/// `body` unless they contain JSON escapes such as `\n`, which have to be
+/// unescaped into a new string; function call arguments are kept as raw
+/// JSON. Fields not needed to read an answer, like safety ratings and
+/// citations, are skipped.
+pub fn parse_borrowed(body: &[u8]) -> Result<GenerationResponseRef<'_>> {
+    serde_json::from_slice(body).map_err(Error::JsonError)
+}
+
+/// A response parsed by [`parse_borrowed`], borrowing from the body
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationResponseRef<'a> {
+    /// The candidates generated
+    #[serde(default, borrow)]
+    pub candidates: Vec<CandidateRef<'a>>,
+    /// Token usage of the request
+    #[serde(default)]
+    pub usage_metadata: Option<UsageMetadata>,
+    /// The model version that generated the response
+    #[serde(default, borrow)]
+    pub model_version: Option<&'a str>,
+    /// Identifier of the response
+    #[serde(default, borrow)]
+    pub response_id: Option<&'a str>,
+}
+
+/// A candidate of a [`GenerationResponseRef`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateRef<'a> {
+    /// The content of the candidate
+    #[serde(default, borrow)]
+    pub content: ContentRef<'a>,
+    /// Why generation stopped, e.g. `STOP` or `MAX_TOKENS`
+    #[serde(default, borrow)]
+    pub finish_reason: Option<&'a str>,
+}
+
+/// The content of a [`CandidateRef`]
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ContentRef<'a> {
+    /// The parts of the content
+    #[serde(default, borrow)]
+    pub parts: Vec<PartRef<'a>>,
+    /// Who produced the content, normally `model`
+    #[serde(default, borrow)]
+    pub role: Option<&'a str>,
+}
+
+/// A part of a [`ContentRef`]
+///
+/// Parts of other kinds than text and function calls have neither field
+/// set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartRef<'a> {
+    /// Answer or thought text
+    #[serde(default, borrow, deserialize_with = "optional_cow")]
+    pub text: Option<Cow<'a, str>>,
+    /// Whether the text is a thought summary
+    #[serde(default)]
+    pub thought: bool,
+    /// A function call requested by the model
+    #[serde(default, borrow)]
+    pub function_call: Option<FunctionCallRef<'a>>,
+}
+
+/// A function call of a [`PartRef`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionCallRef<'a> {
+    /// The name of the function
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    /// The arguments, as the JSON text that was received
+    #[serde(default, borrow)]
+    pub args: Option<&'a RawValue>,
+}
+
+impl<'a> GenerationResponseRef<'a> {
+    /// Get the text of the first candidate, skipping thoughts
+    ///
+    /// Like `GenerationResponse::text`, text split over several parts is
+    /// joined. A single text part without escapes is borrowed from the body.
+    pub fn text(&self) -> Cow<'a, str> {
+        let mut texts = self
+            .candidates
+            .first()
+            .into_iter()
+            .flat_map(|candidate| &candidate.content.parts)
+            .filter(|part| !part.thought)
+            .filter_map(|part| part.text.as_ref());
+        let Some(first) = texts.next() else {
+            return Cow::Borrowed("");
+        };
+        match texts.next() {
+            None => first.clone(),
+            Some(second) => {
+                let mut text = format!("{}{}", first, second);
+                texts.for_each(|more| text.push_str(more));
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    /// Get the function calls of all candidates
+    pub fn function_calls(&self) -> impl Iterator<Item = &FunctionCallRef<'_>> {
+        self.candidates
+            .iter()
+            .flat_map(|c| &c.content.parts)
+            .filter_map(|part| part.function_call.as_ref())
+    }
+}
+
+/// Borrow an optional string when it has no escapes
+///
+/// `Option<Cow<str>>` always deserializes to an owned string otherwise.
+fn optional_cow<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+
+    Ok(Option::<Borrowed>::deserialize(deserializer)?.map(|b| b.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GenerationResponse;
+
+    #[test]
+    fn text_matches_owned_response() {
+        let body = br#"{"candidates": [{"content": {"role": "model", "parts": [
+            {"text": "Let me think.", "thought": true},
+            {"text": "Hello, "},
+            {"text": "world"}
+        ]}}]}"#;
+        let borrowed = parse_borrowed(body).unwrap();
+        let owned: GenerationResponse = serde_json::from_slice(body).unwrap();
+        assert_eq!(borrowed.text(), "Hello, world");
+        assert_eq!(borrowed.text(), owned.text());
+
+        let body = br#"{"candidates": [{"content": {"parts": [{"text": "Hi"}]}}]}"#;
+        let borrowed = parse_borrowed(body).unwrap();
+        assert!(matches!(borrowed.text(), Cow::Borrowed("Hi")));
+    }
+}
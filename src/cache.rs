@@ -0,0 +1,301 @@
+use crate::{
+    client::GeminiClient,
+    embeddings::{EmbedBuilder, TaskType},
+    models::{GenerateContentRequest, GenerationResponse, Part, Role},
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Model used to embed prompts for semantic matching
+const DEFAULT_EMBEDDING_MODEL: &str = "models/text-embedding-004";
+
+/// Remembers responses so that repeated prompts are answered without a call
+///
+/// Set on a client with `GeminiBuilder::with_response_cache`; `execute`
+/// and `execute_detailed` then look requests up before sending them.
+/// Without semantic matching, only a request identical to an earlier one,
+/// down to its generation config, is a hit. Streamed calls and requests
+/// with function calling are never cached, nor are blocked responses.
+/// Clones share their entries.
+#[derive(Clone)]
+pub struct ResponseCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    semantic: Option<Semantic>,
+    state: Arc<Mutex<CacheState>>,
+}
+
+#[derive(Clone)]
+struct Semantic {
+    threshold: f32,
+    model: String,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Entries by their exact key
+    entries: HashMap<String, Entry>,
+    stats: CacheStats,
+}
+
+struct Entry {
+    /// The request without its prompt; semantic hits need the same context
+    context: String,
+    /// Embedding of the prompt, for semantic matching
+    embedding: Option<Vec<f32>>,
+    response: GenerationResponse,
+    stored: Instant,
+    used: Instant,
+}
+
+/// How a [`ResponseCache`] has been doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Requests answered by an identical earlier request
+    pub hits: u64,
+    /// Requests answered by an earlier request with a similar prompt
+    pub semantic_hits: u64,
+    /// Requests that had to be sent
+    pub misses: u64,
+}
+
+impl ResponseCache {
+    /// Create a cache holding up to `capacity` responses
+    ///
+    /// When it is full, the least recently used response makes room.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl: None,
+            semantic: None,
+            state: Arc::default(),
+        }
+    }
+
+    /// Forget responses once they are older than `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Also answer prompts that mean the same as a cached one
+    ///
+    /// The prompt, the text of the last user message, is embedded with the
+    /// embeddings API; a cached response is used if its prompt has a cosine
+    /// similarity of at least `threshold` and everything else in the
+    /// request is identical. Values around 0.95 match paraphrases of FAQ
+    /// style questions; lower values risk answering a different question.
+    /// Each lookup costs an embedding call, and a failed one is treated as a
+    /// miss.
+    pub fn with_semantic_matching(mut self, threshold: f32) -> Self {
+        let model = match self.semantic.take() {
+            Some(semantic) => semantic.model,
+            None => DEFAULT_EMBEDDING_MODEL.to_string(),
+        };
+        self.semantic = Some(Semantic { threshold, model });
+        self
+    }
+
+    /// Embed prompts with another model than `text-embedding-004`
+    ///
+    /// Only has an effect together with `with_semantic_matching`.
+    pub fn with_embedding_model(mut self, model: impl Into<String>) -> Self {
+        if let Some(semantic) = &mut self.semantic {
+            semantic.model = model.into();
+        }
+        self
+    }
+
+    /// The number of cached responses
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    /// Whether no responses are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all responses
+    pub fn clear(&self) {
+        self.state().entries.clear();
+    }
+
+    /// Hits and misses so far
+    pub fn stats(&self) -> CacheStats {
+        self.state().stats
+    }
+
+    /// Look a request up, returning the cached response or what is needed
+    /// to store the response once it arrives
+    pub(crate) async fn lookup(
+        &self,
+        client: &GeminiClient,
+        request: &GenerateContentRequest,
+    ) -> Lookup {
+        let key = cache_key(client, request);
+        if let Some(response) = self.get_exact(&key) {
+            return Lookup::Hit(response);
+        }
+
+        let mut pending = PendingEntry {
+            key,
+            context: String::new(),
+            embedding: None,
+        };
+        if let (Some(semantic), Some((context, prompt))) = (&self.semantic, split_prompt(request)) {
+            pending.context = cache_key(client, &context);
+            pending.embedding = EmbedBuilder::for_client(client, prompt)
+                .with_model(semantic.model.clone())
+                .with_task_type(TaskType::SemanticSimilarity)
+                .execute()
+                .await
+                .ok()
+                .map(|embedding| embedding.values);
+            if let Some(embedding) = &pending.embedding {
+                if let Some(response) = self.get_similar(&pending.context, embedding) {
+                    return Lookup::Hit(response);
+                }
+            }
+        }
+        self.state().stats.misses += 1;
+        Lookup::Miss(pending)
+    }
+
+    /// Store the response to a request that missed
+    pub(crate) fn insert(&self, pending: PendingEntry, response: &GenerationResponse) {
+        if response.candidates.is_empty() || response.block_details().is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let mut state = self.state();
+        self.evict_expired(&mut state);
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&pending.key) {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.entries.insert(
+            pending.key,
+            Entry {
+                context: pending.context,
+                embedding: pending.embedding,
+                response: response.clone(),
+                stored: now,
+                used: now,
+            },
+        );
+    }
+
+    fn get_exact(&self, key: &str) -> Option<GenerationResponse> {
+        let mut state = self.state();
+        self.evict_expired(&mut state);
+        let entry = state.entries.get_mut(key)?;
+        entry.used = Instant::now();
+        let response = entry.response.clone();
+        state.stats.hits += 1;
+        Some(response)
+    }
+
+    fn get_similar(&self, context: &str, embedding: &[f32]) -> Option<GenerationResponse> {
+        let threshold = self.semantic.as_ref()?.threshold;
+        let mut state = self.state();
+        self.evict_expired(&mut state);
+        let entry = state
+            .entries
+            .values_mut()
+            .filter(|entry| entry.context == context)
+            .filter_map(|entry| {
+                let similarity = cosine_similarity(entry.embedding.as_deref()?, embedding);
+                Some((entry, similarity))
+            })
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entry, _)| entry)?;
+        entry.used = Instant::now();
+        let response = entry.response.clone();
+        state.stats.semantic_hits += 1;
+        Some(response)
+    }
+
+    fn evict_expired(&self, state: &mut CacheState) {
+        if let Some(ttl) = self.ttl {
+            state
+                .entries
+                .retain(|_, entry| entry.stored.elapsed() < ttl);
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("len", &self.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// The outcome of [`ResponseCache::lookup`]
+pub(crate) enum Lookup {
+    Hit(GenerationResponse),
+    Miss(PendingEntry),
+}
+
+/// Where to store the response to a request that missed
+pub(crate) struct PendingEntry {
+    key: String,
+    context: String,
+    embedding: Option<Vec<f32>>,
+}
+
+/// The model and request body, which together decide the response
+fn cache_key(client: &GeminiClient, request: &GenerateContentRequest) -> String {
+    format!(
+        "{}\n{}",
+        client.model,
+        serde_json::to_string(request).unwrap_or_default()
+    )
+}
+
+/// Split a request into the request without its prompt and the prompt
+///
+/// The prompt is the last message if it is a user message of text only.
+fn split_prompt(request: &GenerateContentRequest) -> Option<(GenerateContentRequest, String)> {
+    let last = request.contents.last()?;
+    if last.role != Some(Role::User) || last.parts.is_empty() {
+        return None;
+    }
+    let texts: Option<Vec<&str>> = last.parts.iter().map(Part::text).collect();
+    let prompt = texts?.join("\n");
+    let mut context = request.clone();
+    context.contents.pop();
+    Some((context, prompt))
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
@@ -0,0 +1,100 @@
+use crate::models::{Content, GenerationResponse, Message, Role};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::Arc;
+
+/// A conversation history that is cheap to clone and to attach to requests
+///
+/// Messages are stored in shared, immutable segments: cloning a history or
+/// passing it to `ContentBuilder::with_history` only bumps reference
+/// counts, and appending a turn adds a segment without touching earlier
+/// ones. The messages are copied once, when the request is built.
+#[derive(Debug, Clone, Default)]
+pub struct ChatHistory {
+    segments: Vec<Arc<[Content]>>,
+    len: usize,
+}
+
+impl ChatHistory {
+    /// Create an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a message
+    pub fn push(&mut self, content: Content) {
+        self.extend([content]);
+    }
+
+    /// Append several messages as one segment
+    pub fn extend(&mut self, contents: impl IntoIterator<Item = Content>) {
+        let segment: Arc<[Content]> = contents.into_iter().collect();
+        if !segment.is_empty() {
+            self.len += segment.len();
+            self.segments.push(segment);
+        }
+    }
+
+    /// Append a user message
+    pub fn push_user_text(&mut self, text: impl Into<String>) {
+        self.push(Message::user(text).content);
+    }
+
+    /// Append the first candidate of a response as the model's turn
+    ///
+    /// The parts are kept as received, including thought signatures.
+    pub fn record(&mut self, response: &GenerationResponse) {
+        if let Some(candidate) = response.candidates.first() {
+            self.push(candidate.content.clone().with_role(Role::Model));
+        }
+    }
+
+    /// Number of messages
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no messages
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The messages, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Content> {
+        self.segments.iter().flat_map(|segment| segment.iter())
+    }
+
+    /// Copy the messages into a vector
+    pub fn to_vec(&self) -> Vec<Content> {
+        let mut contents = Vec::with_capacity(self.len);
+        contents.extend(self.iter().cloned());
+        contents
+    }
+}
+
+impl From<Vec<Content>> for ChatHistory {
+    fn from(contents: Vec<Content>) -> Self {
+        let mut history = Self::new();
+        history.extend(contents);
+        history
+    }
+}
+
+impl FromIterator<Content> for ChatHistory {
+    fn from_iter<I: IntoIterator<Item = Content>>(iter: I) -> Self {
+        let mut history = Self::new();
+        history.extend(iter);
+        history
+    }
+}
+
+impl Serialize for ChatHistory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for ChatHistory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Vec::<Content>::deserialize(deserializer).map(Self::from)
+    }
+}
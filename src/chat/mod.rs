@@ -0,0 +1,239 @@
+//! Multi-turn conversations and their persistence
+
+mod history;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+
+pub use history::ChatHistory;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+pub use store::{JsonFileStore, SessionStore};
+
+use crate::{
+    client::{ContentBuilder, Gemini},
+    guard,
+    models::{Content, GenerationResponse, Message, Part, Role},
+    tokens, Error, Result,
+};
+use serde::{Deserialize, Serialize};
+
+/// System instruction for compressing old turns into a summary
+const SUMMARY_INSTRUCTION: &str = "Summarize the conversation you are given for your own later \
+     reference. Keep facts, names, numbers, decisions, open questions and the user's \
+     preferences; leave out pleasantries. Write plain prose, no headings.";
+
+/// A conversation whose history is sent with every turn
+///
+/// Sessions are plain data, so they can be exported with
+/// [`ChatSession::to_json`] or kept in a [`SessionStore`] between runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatSession {
+    /// Identifier used by session stores
+    pub id: String,
+    /// System instruction sent with every turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<String>,
+    /// The turns so far, alternating between user and model
+    #[serde(default)]
+    pub history: Vec<Content>,
+    /// Summary of earlier turns removed by
+    /// [`ChatSession::summarize_history`], sent after the system instruction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl ChatSession {
+    /// Start an empty session
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the system instruction for all turns
+    pub fn with_system_instruction(mut self, text: impl Into<String>) -> Self {
+        self.system_instruction = Some(text.into());
+        self
+    }
+
+    /// Send a user message and record both it and the model's reply
+    ///
+    /// If the request fails the user message is removed again, so the
+    /// call can simply be retried.
+    pub async fn send(
+        &mut self,
+        client: &Gemini,
+        text: impl Into<String>,
+    ) -> Result<GenerationResponse> {
+        self.push_user_text(text);
+        match self.request(client).execute().await {
+            Ok(response) => {
+                self.record(&response);
+                Ok(response)
+            }
+            Err(e) => {
+                self.history.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// A request carrying the system instruction and the full history
+    ///
+    /// Useful for streaming: send it with `execute_stream`, then add the
+    /// collected reply with [`ChatSession::push_model_text`].
+    pub fn request(&self, client: &Gemini) -> ContentBuilder {
+        let mut builder = client.generate_content();
+        let summary = self
+            .summary
+            .as_ref()
+            .map(|summary| format!("Summary of the earlier conversation:\n{}", summary));
+        let system = match (&self.system_instruction, summary) {
+            (Some(system), Some(summary)) => Some(format!("{}\n\n{}", system, summary)),
+            (system, summary) => system.clone().or(summary),
+        };
+        if let Some(system) = system {
+            builder = builder.with_system_instruction(system);
+        }
+        builder.contents.extend(self.history.iter().cloned());
+        builder
+    }
+
+    /// Append a user message to the history
+    pub fn push_user_text(&mut self, text: impl Into<String>) {
+        self.history.push(Message::user(text).content);
+    }
+
+    /// Append a model reply to the history
+    ///
+    /// Only the text is kept; prefer [`ChatSession::record`] with thinking
+    /// models so their thought signatures are sent back.
+    pub fn push_model_text(&mut self, text: impl Into<String>) {
+        self.history.push(Message::model(text).content);
+    }
+
+    /// Append the first candidate of a response to the history
+    ///
+    /// The parts are kept as received, including the thought signatures
+    /// thinking models need back on later turns.
+    pub fn record(&mut self, response: &GenerationResponse) {
+        if let Some(candidate) = response.candidates.first() {
+            self.history
+                .push(candidate.content.clone().with_role(Role::Model));
+        }
+    }
+
+    /// Compress old turns into a summary once the history gets long
+    ///
+    /// If the estimated size of the history is over `max_tokens`, the
+    /// oldest turns are summarized by the model and removed, keeping recent
+    /// turns that take up to half the budget. The summary, which folds in
+    /// any earlier one, is sent as part of the system instruction from then
+    /// on. Returns whether the history was compressed.
+    pub async fn summarize_history(&mut self, client: &Gemini, max_tokens: usize) -> Result<bool> {
+        let sizes: Vec<usize> = self.history.iter().map(tokens::content_tokens).collect();
+        if sizes.iter().sum::<usize>() <= max_tokens {
+            return Ok(false);
+        }
+
+        // Keep the most recent turns that fit, starting at a user turn
+        let mut split = self.history.len();
+        let mut kept = 0;
+        while split > 0 && kept + sizes[split - 1] <= max_tokens / 2 {
+            split -= 1;
+            kept += sizes[split];
+        }
+        while split < self.history.len() && !guard::starts_turn(&self.history[split]) {
+            split += 1;
+        }
+        if split == 0 {
+            return Ok(false);
+        }
+
+        let mut transcript = String::new();
+        if let Some(summary) = &self.summary {
+            transcript.push_str(&format!("Earlier summary: {}\n\n", summary));
+        }
+        for content in &self.history[..split] {
+            transcript.push_str(&describe(content));
+        }
+        let response = client
+            .generate_content()
+            .with_system_instruction(SUMMARY_INSTRUCTION)
+            .with_user_message(transcript)
+            .with_max_output_tokens((max_tokens / 4).max(64) as i32)
+            .execute()
+            .await?;
+        let summary = response.text().trim().to_string();
+        if summary.is_empty() {
+            return Err(Error::RequestError(
+                "The model returned an empty summary".to_string(),
+            ));
+        }
+
+        self.summary = Some(summary);
+        self.history.drain(..split);
+        Ok(true)
+    }
+
+    /// Remove all turns and the summary, keeping the id and system
+    /// instruction
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.summary = None;
+    }
+
+    /// Export the session as JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Import a session exported with [`ChatSession::to_json`]
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(Error::JsonError)
+    }
+}
+
+/// Render a turn as transcript lines for summarizing
+fn describe(content: &Content) -> String {
+    let speaker = match content.role {
+        Some(Role::Model) => "Model",
+        _ => "User",
+    };
+    let mut line = format!("{}:", speaker);
+    for part in &content.parts {
+        match part {
+            Part::Text { text, .. } => line.push_str(&format!(" {}", text)),
+            Part::Thought { .. } => {}
+            Part::InlineData { inline_data, .. } => {
+                line.push_str(&format!(" [{} attachment]", inline_data.mime_type))
+            }
+            Part::FileData { file_data, .. } if file_data.mime_type.is_empty() => {
+                line.push_str(&format!(" [file {}]", file_data.file_uri))
+            }
+            Part::FileData { file_data, .. } => {
+                line.push_str(&format!(" [{} file]", file_data.mime_type))
+            }
+            Part::FunctionCall { function_call, .. } => line.push_str(&format!(
+                " [called {}({})]",
+                function_call.name, function_call.args
+            )),
+            Part::FunctionResponse { function_response } => line.push_str(&format!(
+                " [{} returned {}]",
+                function_response.name,
+                function_response
+                    .response
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            )),
+            Part::Other(fields) => {
+                line.push_str(&format!(" {}", serde_json::Value::from(fields.clone())))
+            }
+        }
+    }
+    line.push('\n');
+    line
+}
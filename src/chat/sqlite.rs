@@ -0,0 +1,98 @@
+use super::{ChatSession, SessionStore};
+use crate::{Error, Result};
+use rusqlite::{Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Stores sessions as JSON rows in a SQLite database
+///
+/// The table `gemini_sessions` is created on first use, so the database
+/// can be shared with the application's own tables.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open or create a database file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(sql_error)?)
+    }
+
+    /// Use an in-memory database, mostly useful for tests
+    pub fn in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(sql_error)?)
+    }
+
+    /// Use an existing connection
+    pub fn from_connection(connection: Connection) -> Result<Self> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS gemini_sessions (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    session TEXT NOT NULL,
+                    updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+                )",
+                (),
+            )
+            .map_err(sql_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionStore for SqliteStore {
+    fn load(&self, id: &str) -> Result<Option<ChatSession>> {
+        let json: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT session FROM gemini_sessions WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        json.map(|json| ChatSession::from_json(&json)).transpose()
+    }
+
+    fn save(&self, session: &ChatSession) -> Result<()> {
+        self.connection()
+            .execute(
+                "INSERT INTO gemini_sessions (id, session) VALUES (?1, ?2)
+                 ON CONFLICT(id) DO UPDATE SET session = excluded.session,
+                                               updated_at = unixepoch()",
+                (&session.id, session.to_json()?),
+            )
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT id FROM gemini_sessions ORDER BY id")
+            .map_err(sql_error)?;
+        let ids = statement
+            .query_map((), |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(sql_error)?;
+        Ok(ids)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        self.connection()
+            .execute("DELETE FROM gemini_sessions WHERE id = ?1", [id])
+            .map_err(sql_error)?;
+        Ok(())
+    }
+}
+
+fn sql_error(error: rusqlite::Error) -> Error {
+    Error::StoreError(error.to_string())
+}
@@ -0,0 +1,98 @@
+use super::ChatSession;
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+
+/// Durable storage for chat sessions, keyed by session id
+///
+/// Implementations are synchronous; from async code, call them inside
+/// `spawn_blocking` if a save may take noticeable time.
+pub trait SessionStore: Send + Sync {
+    /// Load a session, or `None` if no session has this id
+    fn load(&self, id: &str) -> Result<Option<ChatSession>>;
+
+    /// Save a session, replacing any previous version with the same id
+    fn save(&self, session: &ChatSession) -> Result<()>;
+
+    /// The ids of all stored sessions, sorted
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// Delete a session; deleting an unknown id is not an error
+    fn delete(&self, id: &str) -> Result<()>;
+}
+
+/// Stores each session as a pretty-printed `<id>.json` file in a directory
+///
+/// Ids may contain ASCII letters, digits, `-`, `_` and `.`, and must not
+/// start with a dot, so they are always plain file names.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Use a directory, creating it if it does not exist
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf> {
+        let valid = !id.is_empty()
+            && !id.starts_with('.')
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+        if !valid {
+            return Err(Error::StoreError(format!("Invalid session id: {:?}", id)));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+impl SessionStore for JsonFileStore {
+    fn load(&self, id: &str) -> Result<Option<ChatSession>> {
+        let path = self.path(id)?;
+        match std::fs::read_to_string(&path) {
+            Ok(json) => ChatSession::from_json(&json).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    fn save(&self, session: &ChatSession) -> Result<()> {
+        let path = self.path(&session.id)?;
+        // Write to a temporary file first so a crash never leaves a
+        // truncated session behind
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, session.to_json()?).map_err(|e| io_error(&temp, e))?;
+        std::fs::rename(&temp, &path).map_err(|e| io_error(&path, e))
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| io_error(&self.dir, e))?;
+        let mut ids = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| io_error(&self.dir, e))?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let path = self.path(id)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> Error {
+    Error::StoreError(format!("{}: {}", path.display(), error))
+}
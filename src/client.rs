@@ -1,29 +1,99 @@
+#[cfg(feature = "files")]
+use crate::files::PendingUploads;
+#[cfg(all(feature = "files", feature = "streaming"))]
+use crate::files::UploadedFiles;
+#[cfg(feature = "streaming")]
+use crate::markdown::{MarkdownHandler, MarkdownSegmenter};
+#[cfg(feature = "streaming")]
+use crate::models::HarmBlockThreshold;
+#[cfg(feature = "pool")]
+use crate::pool::{RateLimiter, UsageCounter};
+#[cfg(feature = "streaming")]
+use crate::recorder::StreamRecorder;
+#[cfg(feature = "tracing")]
+use crate::redaction::Redactor;
+#[cfg(feature = "streaming")]
+use crate::streaming::{self, ChunkCoalescing, FirstTokenCallback, GenerationStream, PartDelta};
 use crate::{
+    auth::{ApiKeyProvider, StaticKey},
+    cache::{Lookup, ResponseCache},
+    chat::ChatHistory,
+    functions::FunctionRegistry,
+    guard::PromptGuard,
+    language,
+    model_info::ModelCapabilities,
     models::{
-        Content, FunctionCallingConfig, FunctionCallingMode, GenerateContentRequest,
-        GenerationConfig, GenerationResponse, Message, Role, ToolConfig,
+        Content, FunctionCallingMode, GenerateContentRequest, GenerateContentRequestBuilder,
+        GenerationConfig, GenerationResponse, IntoMessage, Message, Part, Role, SafetySetting,
+        ToolConfig, UsageMetadata,
     },
-    tools::{FunctionDeclaration, Tool},
+    persona::Persona,
+    postprocess::{self, PostProcessor, ResponseFilter},
+    queue::{Permit, Priority, RequestQueue},
+    retry::{self, RetryBudget, IDEMPOTENCY_KEY_HEADER},
+    routing::{LatencyTracker, DEFAULT_FAST_MODEL},
+    tokens::{self, TokenBreakdown},
+    tools::{FunctionDeclaration, Tool, Tools},
     Error, Result,
 };
+#[cfg(feature = "streaming")]
 use futures::stream::Stream;
+#[cfg(feature = "streaming")]
 use futures_util::StreamExt;
 use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+#[cfg(feature = "streaming")]
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use url::Url;
 
-const BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta/";
+const BASE_URL: &str = "https://generativelanguage.googleapis.com/";
 const DEFAULT_MODEL: &str = "models/gemini-2.0-flash";
+const DEFAULT_MAX_FUNCTION_TURNS: usize = 10;
+/// Finish reason of a reply cut off at `max_output_tokens`
+const FINISH_REASON_MAX_TOKENS: &str = "MAX_TOKENS";
+/// User turn sent by `with_auto_continue`
+const CONTINUE_PROMPT: &str = "Continue exactly where you stopped. Do not repeat anything \
+     you already wrote and do not add an introduction.";
+/// Start of the user turn sent by `execute_validated` after a rejected reply
+const VALIDATION_RETRY_PROMPT: &str =
+    "Your previous answer was rejected. Answer again, fixing this problem: ";
+/// Smaller bodies are sent uncompressed since gzip would barely help
+#[cfg(feature = "gzip")]
+const COMPRESSION_MIN_SIZE: usize = 1024;
+
+/// Header carrying the API key
+pub(crate) const API_KEY_HEADER: &str = "x-goog-api-key";
 
 /// Builder for content generation requests
 pub struct ContentBuilder {
-    client: Arc<GeminiClient>,
+    pub(crate) client: Arc<GeminiClient>,
+    history: ChatHistory,
     pub contents: Vec<Content>,
-    generation_config: Option<GenerationConfig>,
-    tools: Option<Vec<Tool>>,
-    tool_config: Option<ToolConfig>,
-    system_instruction: Option<Content>,
+    /// Everything else sent in the request; its contents are kept in
+    /// `contents`
+    request: GenerateContentRequestBuilder,
+    response_language: Option<String>,
+    function_registry: Option<FunctionRegistry>,
+    max_function_turns: usize,
+    retry_budget: Option<RetryBudget>,
+    latency_budget: Option<Duration>,
+    post_processors: Vec<Arc<dyn PostProcessor>>,
+    response_filters: Vec<Arc<dyn ResponseFilter>>,
+    max_continues: usize,
+    max_tokens_ceiling: Option<i32>,
+    #[cfg(feature = "files")]
+    pub(crate) uploads: PendingUploads,
+    #[cfg(feature = "streaming")]
+    safety_abort: Option<HarmBlockThreshold>,
+    #[cfg(feature = "streaming")]
+    on_first_token: Option<FirstTokenCallback>,
+    #[cfg(feature = "streaming")]
+    chunk_coalescing: Option<ChunkCoalescing>,
 }
 
 impl ContentBuilder {
@@ -31,372 +101,2787 @@ impl ContentBuilder {
     fn new(client: Arc<GeminiClient>) -> Self {
         Self {
             client,
+            history: ChatHistory::new(),
             contents: Vec::new(),
-            generation_config: None,
-            tools: None,
-            tool_config: None,
-            system_instruction: None,
+            request: GenerateContentRequestBuilder::new(),
+            response_language: None,
+            function_registry: None,
+            max_function_turns: DEFAULT_MAX_FUNCTION_TURNS,
+            retry_budget: None,
+            latency_budget: None,
+            post_processors: Vec::new(),
+            response_filters: Vec::new(),
+            max_continues: 0,
+            max_tokens_ceiling: None,
+            #[cfg(feature = "files")]
+            uploads: PendingUploads::default(),
+            #[cfg(feature = "streaming")]
+            safety_abort: None,
+            #[cfg(feature = "streaming")]
+            on_first_token: None,
+            #[cfg(feature = "streaming")]
+            chunk_coalescing: None,
         }
     }
 
+    /// Start from an existing request, replacing everything it sets
+    ///
+    /// Pair with [`GenerateContentRequest::from_json`] to replay a raw JSON
+    /// body through the client, or with a [`GenerateContentRequestBuilder`],
+    /// then keep adjusting it with the other builder methods.
+    pub fn with_request(mut self, request: impl Into<GenerateContentRequest>) -> Self {
+        let request = request.into();
+        self.history = ChatHistory::new();
+        self.contents = request.contents;
+        self.request = GenerateContentRequestBuilder {
+            contents: Vec::new(),
+            generation_config: request.generation_config,
+            tools: request.tools.into_iter().flatten().collect(),
+            tool_config: request.tool_config,
+            system_instruction: request.system_instruction,
+            safety_settings: request.safety_settings,
+            labels: request.labels,
+        };
+        self
+    }
+
+    /// Apply a [`GenerateContentRequestBuilder`] method to this request
+    fn map_request(
+        mut self,
+        f: impl FnOnce(GenerateContentRequestBuilder) -> GenerateContentRequestBuilder,
+    ) -> Self {
+        let mut request = std::mem::take(&mut self.request);
+        request.contents = std::mem::take(&mut self.contents);
+        let mut request = f(request);
+        self.contents = std::mem::take(&mut request.contents);
+        self.request = request;
+        self
+    }
+
     /// Add a system prompt to the request
     pub fn with_system_prompt(self, text: impl Into<String>) -> Self {
-        // Create a Content with text parts specifically for system_instruction field
         self.with_system_instruction(text)
     }
 
     /// Set the system instruction directly (matching the API format in the curl example)
-    pub fn with_system_instruction(mut self, text: impl Into<String>) -> Self {
-        // Create a Content with text parts specifically for system_instruction field
-        let content = Content::text(text);
-        self.system_instruction = Some(content);
-        self
+    pub fn with_system_instruction(self, text: impl Into<String>) -> Self {
+        self.map_request(|r| r.with_system_instruction(text))
     }
 
     /// Add a user message to the request
-    pub fn with_user_message(mut self, text: impl Into<String>) -> Self {
-        let message = Message::user(text);
-        let content = message.content;
-        self.contents.push(content);
-        self
+    pub fn with_user_message(self, text: impl Into<String>) -> Self {
+        self.map_request(|r| r.with_user_message(text))
     }
 
     /// Add a model message to the request
-    pub fn with_model_message(mut self, text: impl Into<String>) -> Self {
-        let message = Message::model(text);
-        let content = message.content;
-        self.contents.push(content);
-        self
+    pub fn with_model_message(self, text: impl Into<String>) -> Self {
+        self.map_request(|r| r.with_model_message(text))
+    }
+
+    /// Add few-shot `(input, output)` examples as alternating user and model
+    /// messages, before the real user message
+    pub fn with_examples<I, O>(self, examples: impl IntoIterator<Item = (I, O)>) -> Self
+    where
+        I: Into<String>,
+        O: Into<String>,
+    {
+        self.map_request(|r| r.with_examples(examples))
+    }
+
+    /// Add few-shot examples as one user message wrapped in `delimiter` lines
+    pub fn with_delimited_examples<I, O>(
+        self,
+        delimiter: impl Into<String>,
+        examples: impl IntoIterator<Item = (I, O)>,
+    ) -> Self
+    where
+        I: Into<String>,
+        O: Into<String>,
+    {
+        self.map_request(|r| r.with_delimited_examples(delimiter, examples))
+    }
+
+    /// Add inline data (image, audio, document, ...) from raw bytes as a user message
+    ///
+    /// The bytes are base64-encoded while the request is sent; size limits
+    /// are checked when the request is executed.
+    pub fn with_inline_data(self, mime_type: impl Into<String>, data: impl AsRef<[u8]>) -> Self {
+        self.map_request(|r| r.with_inline_data(mime_type, data))
     }
 
     /// Add a function response to the request using a JSON value
     pub fn with_function_response(
-        mut self,
+        self,
         name: impl Into<String>,
         response: serde_json::Value,
     ) -> Self {
-        let content = Content::function_response_json(name, response).with_role(Role::User);
-        self.contents.push(content);
-        self
+        self.map_request(|r| r.with_function_response(name, response))
     }
 
     /// Add a function response to the request using a JSON string
     pub fn with_function_response_str(
-        mut self,
+        self,
         name: impl Into<String>,
         response: impl Into<String>,
     ) -> std::result::Result<Self, serde_json::Error> {
-        let response_str = response.into();
-        let json = serde_json::from_str(&response_str)?;
-        let content = Content::function_response_json(name, json).with_role(Role::User);
-        self.contents.push(content);
-        Ok(self)
+        let json = serde_json::from_str(&response.into())?;
+        Ok(self.with_function_response(name, json))
+    }
+
+    /// Add a function response followed by a note in the same user turn
+    ///
+    /// Saves a separate turn for context that goes with the tool output,
+    /// e.g. "the list is truncated to 50 entries".
+    pub fn with_function_response_and_text(
+        self,
+        name: impl Into<String>,
+        response: serde_json::Value,
+        text: impl Into<String>,
+    ) -> Self {
+        self.map_request(|r| r.with_function_response_and_text(name, response, text))
     }
 
     /// Add a message to the request
-    pub fn with_message(mut self, message: Message) -> Self {
-        let content = message.content.clone();
-        match &content.role {
-            Some(role) => {
-                let role_clone = role.clone();
-                self.contents.push(content.with_role(role_clone));
-            }
-            None => {
-                self.contents.push(content.with_role(message.role));
-            }
-        }
-        self
+    ///
+    /// Accepts a [`Message`] or anything convertible into one, e.g. `"text"`
+    /// for a user message or `(Role::Model, "text")`.
+    pub fn with_message(self, message: impl IntoMessage) -> Self {
+        self.map_request(|r| r.with_message(message))
     }
 
     /// Add multiple messages to the request
-    pub fn with_messages(mut self, messages: impl IntoIterator<Item = Message>) -> Self {
-        for message in messages {
-            self = self.with_message(message);
-        }
+    pub fn with_messages<M: IntoMessage>(self, messages: impl IntoIterator<Item = M>) -> Self {
+        self.map_request(|r| r.with_messages(messages))
+    }
+
+    /// Start the conversation with a shared history
+    ///
+    /// The history goes before the messages added with the other builder
+    /// methods and replaces any history set earlier. Only reference counts
+    /// are touched here; the messages are copied once, when the request is
+    /// built, instead of on every `with_message` call.
+    pub fn with_history(mut self, history: &ChatHistory) -> Self {
+        self.history = history.clone();
         self
     }
 
     /// Set the generation config for the request
-    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
-        self.generation_config = Some(config);
-        self
+    pub fn with_generation_config(self, config: GenerationConfig) -> Self {
+        self.map_request(|r| r.with_generation_config(config))
     }
 
     /// Set the temperature for the request
-    pub fn with_temperature(mut self, temperature: f32) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.temperature = Some(temperature);
-        }
-        self
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        self.map_request(|r| r.with_temperature(temperature))
     }
 
     /// Set the top-p value for the request
-    pub fn with_top_p(mut self, top_p: f32) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.top_p = Some(top_p);
-        }
-        self
+    pub fn with_top_p(self, top_p: f32) -> Self {
+        self.map_request(|r| r.with_top_p(top_p))
     }
 
     /// Set the top-k value for the request
-    pub fn with_top_k(mut self, top_k: i32) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.top_k = Some(top_k);
-        }
-        self
+    pub fn with_top_k(self, top_k: i32) -> Self {
+        self.map_request(|r| r.with_top_k(top_k))
     }
 
     /// Set the maximum output tokens for the request
-    pub fn with_max_output_tokens(mut self, max_output_tokens: i32) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.max_output_tokens = Some(max_output_tokens);
-        }
-        self
+    pub fn with_max_output_tokens(self, max_output_tokens: i32) -> Self {
+        self.map_request(|r| r.with_max_output_tokens(max_output_tokens))
     }
 
     /// Set the candidate count for the request
-    pub fn with_candidate_count(mut self, candidate_count: i32) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.candidate_count = Some(candidate_count);
-        }
-        self
+    pub fn with_candidate_count(self, candidate_count: i32) -> Self {
+        self.map_request(|r| r.with_candidate_count(candidate_count))
     }
 
     /// Set the stop sequences for the request
-    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.stop_sequences = Some(stop_sequences);
-        }
-        self
+    pub fn with_stop_sequences(self, stop_sequences: Vec<String>) -> Self {
+        self.map_request(|r| r.with_stop_sequences(stop_sequences))
     }
 
     /// Set the response mime type for the request
-    pub fn with_response_mime_type(mut self, mime_type: impl Into<String>) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
-        }
-        if let Some(config) = &mut self.generation_config {
-            config.response_mime_type = Some(mime_type.into());
-        }
-        self
+    pub fn with_response_mime_type(self, mime_type: impl Into<String>) -> Self {
+        self.map_request(|r| r.with_response_mime_type(mime_type))
     }
 
     /// Set the response schema for structured output
-    pub fn with_response_schema(mut self, schema: serde_json::Value) -> Self {
-        if self.generation_config.is_none() {
-            self.generation_config = Some(GenerationConfig::default());
+    pub fn with_response_schema(self, schema: serde_json::Value) -> Self {
+        self.map_request(|r| r.with_response_schema(schema))
+    }
+
+    /// Constrain the answer to exactly one of the given values
+    ///
+    /// Sets the `text/x.enum` response type with a schema listing the
+    /// values, so the reply is plain text rather than JSON. Read it with
+    /// [`ContentBuilder::execute_enum`] or
+    /// [`GenerationResponse::parse_enum`].
+    pub fn with_enum_output<I, S>(self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.map_request(|r| r.with_enum_output(values))
+    }
+
+    /// Set the safety settings for the request
+    pub fn with_safety_settings(self, safety_settings: Vec<SafetySetting>) -> Self {
+        self.map_request(|r| r.with_safety_settings(safety_settings))
+    }
+
+    /// Apply a persona's system instruction, temperature and safety preset
+    ///
+    /// Call builder methods afterwards to override individual settings.
+    pub fn with_persona(mut self, persona: Persona) -> Self {
+        self = self.with_system_instruction(persona.system_instruction);
+        if let Some(temperature) = persona.temperature {
+            self = self.with_temperature(temperature);
         }
-        if let Some(config) = &mut self.generation_config {
-            config.response_schema = Some(schema);
+        if let Some(safety) = persona.safety {
+            self = self.with_safety_settings(safety.settings());
         }
         self
     }
 
     /// Add a tool to the request
-    pub fn with_tool(mut self, tool: Tool) -> Self {
-        if self.tools.is_none() {
-            self.tools = Some(Vec::new());
-        }
-        if let Some(tools) = &mut self.tools {
-            tools.push(tool);
-        }
-        self
+    ///
+    /// Function declarations from all tools are merged into one list.
+    pub fn with_tool(self, tool: Tool) -> Self {
+        self.map_request(|r| r.with_tool(tool))
+    }
+
+    /// Add a set of tools to the request
+    pub fn with_tools(self, tools: Tools) -> Self {
+        self.map_request(|r| r.with_tools(tools))
     }
 
     /// Add a function declaration as a tool
-    pub fn with_function(mut self, function: FunctionDeclaration) -> Self {
-        let tool = Tool::new(function);
-        self = self.with_tool(tool);
-        self
+    pub fn with_function(self, function: FunctionDeclaration) -> Self {
+        self.map_request(|r| r.with_function(function))
     }
 
     /// Set the function calling mode for the request
-    pub fn with_function_calling_mode(mut self, mode: FunctionCallingMode) -> Self {
-        if self.tool_config.is_none() {
-            self.tool_config = Some(ToolConfig {
-                function_calling_config: Some(FunctionCallingConfig { mode }),
-            });
-        } else if let Some(tool_config) = &mut self.tool_config {
-            tool_config.function_calling_config = Some(FunctionCallingConfig { mode });
-        }
+    pub fn with_function_calling_mode(self, mode: FunctionCallingMode) -> Self {
+        self.map_request(|r| r.with_function_calling_mode(mode))
+    }
+
+    /// Set the whole tool config, replacing the function calling mode and names
+    pub fn with_tool_config(self, tool_config: ToolConfig) -> Self {
+        self.map_request(|r| r.with_tool_config(tool_config))
+    }
+
+    /// Restrict function calling to a subset of the declared functions
+    ///
+    /// Only valid in [`FunctionCallingMode::Any`], which is selected unless
+    /// a mode was set already; the model must then call one of these.
+    pub fn with_allowed_function_names(
+        self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.map_request(|r| r.with_allowed_function_names(names))
+    }
+
+    /// Attach a label to the request for billing and usage reports
+    ///
+    /// Labels are only honored by Vertex AI endpoints.
+    pub fn with_label(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.map_request(|r| r.with_label(key, value))
+    }
+
+    /// Ask the model to reply in a specific language
+    ///
+    /// Accepts ISO 639-1 codes (`"de"`), BCP 47 tags (`"pt-BR"`) or English
+    /// names. An instruction is added to the system instruction; with the
+    /// `language-detection` feature the reply is also checked and the request
+    /// retried once with a stronger instruction, failing with
+    /// [`Error::LanguageMismatch`] if the language is still wrong.
+    pub fn with_response_language(mut self, language: impl Into<String>) -> Self {
+        self.response_language = Some(language.into());
         self
     }
 
-    /// Execute the request
-    pub async fn execute(self) -> Result<GenerationResponse> {
-        let request = GenerateContentRequest {
-            contents: self.contents,
-            generation_config: self.generation_config,
-            safety_settings: None,
-            tools: self.tools,
-            tool_config: self.tool_config,
-            system_instruction: self.system_instruction,
-        };
+    /// Dispatch the model's function calls automatically using a registry
+    ///
+    /// The registered declarations are sent as a tool, and `execute()` keeps
+    /// answering function calls until the model replies without one.
+    pub fn with_function_registry(mut self, registry: FunctionRegistry) -> Self {
+        self.function_registry = Some(registry);
+        self
+    }
 
-        self.client.generate_content_raw(request).await
+    /// Send this request to a different model than the client's
+    ///
+    /// Accepts both `gemini-2.0-flash` and `models/gemini-2.0-flash`.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.client = Arc::new(self.client.for_model(model));
+        self
     }
 
-    /// Execute the request with streaming
-    pub async fn execute_stream(
-        self,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>> {
-        let request = GenerateContentRequest {
-            contents: self.contents,
-            generation_config: self.generation_config,
-            safety_settings: None,
-            tools: self.tools,
-            tool_config: self.tool_config,
-            system_instruction: self.system_instruction,
-        };
+    /// Send this request to the client's fast model if its own model is
+    /// unlikely to answer within `budget`
+    ///
+    /// The decision uses the 90th percentile of the model's recent
+    /// latencies, see [`Gemini::latency_stats`]; until a few calls have been
+    /// made, the request goes to the client's model. The fast model is set
+    /// with `GeminiBuilder::with_fast_model`. The budget is not a timeout:
+    /// a slow call still runs to completion.
+    pub fn with_latency_budget(mut self, budget: Duration) -> Self {
+        self.latency_budget = Some(budget);
+        self
+    }
 
-        self.client.generate_content_stream(request).await
+    /// Send this request to a different API version than the client's
+    ///
+    /// Lets a single client mix stable calls with features that only exist
+    /// on `v1alpha`.
+    pub fn with_api_version(mut self, api_version: ApiVersion) -> Self {
+        let mut client = GeminiClient::clone(&self.client);
+        client.api_version = api_version;
+        self.client = Arc::new(client);
+        self
     }
-}
 
-/// Internal client for making requests to the Gemini API
-struct GeminiClient {
-    http_client: Client,
-    api_key: String,
-    model: String,
-}
+    /// Set the queue priority of this request, see `GeminiBuilder::with_max_in_flight`
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        let mut client = GeminiClient::clone(&self.client);
+        client.priority = priority;
+        self.client = Arc::new(client);
+        self
+    }
 
-impl GeminiClient {
-    /// Create a new client
-    fn new(api_key: impl Into<String>, model: String) -> Self {
-        Self {
-            http_client: Client::new(),
-            api_key: api_key.into(),
-            model,
-        }
+    /// Attach caller metadata, such as a tenant id or feature name
+    ///
+    /// The metadata is not sent to the API. It travels with the call
+    /// instead: it is added to the context of errors (see
+    /// [`Error::metadata`]), to the `gemini.request` tracing span and to
+    /// the `start` events of stream transcripts. Repeated calls add to it.
+    pub fn with_metadata<K, V>(mut self, metadata: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let mut client = GeminiClient::clone(&self.client);
+        client
+            .metadata
+            .extend(metadata.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self.client = Arc::new(client);
+        self
     }
 
-    /// Generate content
-    async fn generate_content_raw(
-        &self,
-        request: GenerateContentRequest,
-    ) -> Result<GenerationResponse> {
-        let url = self.build_url("generateContent")?;
+    /// Record this request's streamed response to a transcript file
+    #[cfg(feature = "streaming")]
+    pub fn with_stream_recorder(mut self, recorder: StreamRecorder) -> Self {
+        let mut client = GeminiClient::clone(&self.client);
+        client.recorder = Some(recorder);
+        self.client = Arc::new(client);
+        self
+    }
 
-        let response = self.http_client.post(url).json(&request).send().await?;
+    /// Stop streaming as soon as a chunk is rated at or above a threshold
+    ///
+    /// The stream then yields [`Error::SafetyAbort`] instead of the flagged
+    /// chunk and ends, so no more of the response is shown or paid for.
+    /// Applies to all streaming methods; ratings are also reported as
+    /// [`PartDelta::SafetyRatings`] by `execute_stream_parts` either way.
+    #[cfg(feature = "streaming")]
+    pub fn with_safety_abort(mut self, threshold: HarmBlockThreshold) -> Self {
+        self.safety_abort = Some(threshold);
+        self
+    }
 
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response.text().await?;
-            return Err(Error::ApiError {
-                status_code: status.as_u16(),
-                message: error_text,
-            });
+    /// Call `callback` with the time to first token of a streamed response
+    ///
+    /// Measured from sending the request to the first chunk with answer
+    /// text, which is when a chat UI would replace its typing indicator.
+    /// Applies to `execute_stream` and the methods built on it, and to the
+    /// first text of any turn of `execute_stream_parts` with a function
+    /// registry; the value is also available from
+    /// [`GenerationStream::first_token_latency`].
+    #[cfg(feature = "streaming")]
+    pub fn with_first_token_callback(
+        mut self,
+        callback: impl Fn(Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_first_token = Some(Arc::new(callback));
+        self
+    }
+
+    /// Merge small streamed text chunks into larger ones, see
+    /// [`ChunkCoalescing`]
+    ///
+    /// Applies to `execute_stream` and the methods built on it, including
+    /// the text deltas of `execute_stream_parts`.
+    #[cfg(feature = "streaming")]
+    pub fn with_chunk_coalescing(mut self, coalescing: ChunkCoalescing) -> Self {
+        self.chunk_coalescing = Some(coalescing);
+        self
+    }
+
+    /// Send this request to another host than the client's
+    ///
+    /// For staging environments, proxies or traffic mirroring. The URL
+    /// replaces `https://generativelanguage.googleapis.com/` and the API
+    /// version and endpoint are appended as usual, for unary and streaming
+    /// calls alike, including follow-up turns of function calling and
+    /// `with_auto_continue`.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
         }
+        let mut client = GeminiClient::clone(&self.client);
+        client.base_url = base_url;
+        self.client = Arc::new(client);
+        self
+    }
 
-        let response = response.json().await?;
-        Ok(response)
+    /// Send this request even if the client's response cache has an
+    /// answer, without storing the response
+    pub fn with_cache_bypass(mut self) -> Self {
+        let mut client = GeminiClient::clone(&self.client);
+        client.response_cache = None;
+        self.client = Arc::new(client);
+        self
     }
 
-    /// Generate content with streaming
-    async fn generate_content_stream(
-        &self,
-        request: GenerateContentRequest,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>> {
-        let url = self.build_url("streamGenerateContent")?;
+    /// Keep generating when a reply is cut off at the output token limit
+    ///
+    /// If the first candidate finishes with `MAX_TOKENS`, up to
+    /// `max_continues` follow-up turns ask the model to carry on, and their
+    /// text is stitched onto the reply. A code block left open by one turn
+    /// is not reopened by the next. Only applies to `execute` and
+    /// `execute_detailed`.
+    pub fn with_auto_continue(mut self, max_continues: usize) -> Self {
+        self.max_continues = max_continues;
+        self
+    }
+
+    /// Retry a JSON reply cut off at the output token limit once with a
+    /// larger limit
+    ///
+    /// Truncated JSON can't be parsed, so when a request with a response
+    /// schema or the `application/json` MIME type finishes with
+    /// `MAX_TOKENS`, it is sent again with `max_output_tokens` doubled, up
+    /// to `ceiling` and the model's output token limit; without a limit set,
+    /// the retry uses `ceiling`. The escalation is reported in
+    /// [`DetailedResponse::max_tokens_escalation`]. Only the final call is
+    /// repeated: function calls answered for the first reply are not made
+    /// again. Only applies to `execute`, `execute_detailed` and the methods
+    /// built on them.
+    pub fn with_max_tokens_escalation(mut self, ceiling: i32) -> Self {
+        self.max_tokens_ceiling = Some(ceiling);
+        self
+    }
 
-        let response = self.http_client.post(url).json(&request).send().await?;
+    /// Set the maximum number of automatic function calling round trips
+    pub fn with_max_function_turns(mut self, max_turns: usize) -> Self {
+        self.max_function_turns = max_turns;
+        self
+    }
 
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response.text().await?;
-            return Err(Error::ApiError {
-                status_code: status.as_u16(),
-                message: error_text,
-            });
+    /// Count API calls against a shared budget and retry transient failures
+    ///
+    /// Every call made for this request, including function calling round
+    /// trips, takes an attempt from the budget. Pass clones of the same
+    /// budget to nested requests, e.g. those made by function handlers, to
+    /// bound the whole operation. Without a budget nothing is retried.
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Clean up the text of each candidate before the response is returned
+    ///
+    /// Processors run in the order they are added. They apply to `execute`,
+    /// `execute_detailed` and `execute_stream_until`, not to streamed chunks.
+    pub fn with_post_processor(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.post_processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Veto or rewrite each candidate before the response is returned
+    ///
+    /// Filters run after the post-processors, in the order they are added,
+    /// and apply to the same methods. Rejected candidates are removed; if
+    /// none is left the request fails with [`Error::AllCandidatesRejected`].
+    pub fn with_response_filter(mut self, filter: impl ResponseFilter + 'static) -> Self {
+        self.response_filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Assemble the request body, leaving execution options in the builder
+    fn build_request(&mut self) -> GenerateContentRequest {
+        let mut builder = std::mem::take(&mut self.request);
+        if let Some(registry) = self.function_registry.as_ref().filter(|r| !r.is_empty()) {
+            builder
+                .tools
+                .add(Tool::with_functions(registry.declarations()));
         }
 
-        let stream = response
-            .bytes_stream()
-            .map(|result| {
-                match result {
-                    Ok(bytes) => {
-                        let text = String::from_utf8_lossy(&bytes);
-                        // The stream returns each chunk as a separate JSON object
-                        // Each line that starts with "data: " contains a JSON object
-                        let mut responses = Vec::new();
-                        for line in text.lines() {
-                            if let Some(json_str) = line.strip_prefix("data: ") {
-                                if json_str == "[DONE]" {
-                                    continue;
-                                }
-                                match serde_json::from_str::<GenerationResponse>(json_str) {
-                                    Ok(response) => responses.push(Ok(response)),
-                                    Err(e) => responses.push(Err(Error::JsonError(e))),
-                                }
-                            }
-                        }
-                        futures::stream::iter(responses)
-                    }
-                    Err(e) => futures::stream::iter(vec![Err(Error::HttpError(e))]),
+        builder.contents = match std::mem::take(&mut self.history) {
+            history if history.is_empty() => std::mem::take(&mut self.contents),
+            history => {
+                let mut contents = Vec::with_capacity(history.len() + self.contents.len());
+                contents.extend(history.iter().cloned());
+                contents.append(&mut self.contents);
+                contents
+            }
+        };
+        let mut request = builder.build();
+        if let Some(language) = &self.response_language {
+            add_system_text(&mut request, language::instruction(language, false));
+        }
+        request
+    }
+
+    /// Build the request and check it before anything is sent
+    fn build_checked_request(&mut self) -> Result<GenerateContentRequest> {
+        #[cfg(feature = "files")]
+        if !self.uploads.is_empty() {
+            return Err(Error::RequestError(
+                "files added with with_uploaded_file or with_user_image_url are only added by execute, \
+                 execute_detailed, execute_validated, execute_raw_bytes and execute_stream"
+                    .to_string(),
+            ));
+        }
+        if let Some(budget) = self.latency_budget {
+            let client = &self.client;
+            let model = client
+                .latency
+                .route(&client.model, &client.fast_model, budget);
+            if model != client.model {
+                self.client = Arc::new(client.for_model(model.to_string()));
+            }
+        }
+        let mut request = self.build_request();
+        if let Some(guard) = &self.client.prompt_guard {
+            guard.check(&mut request)?;
+        }
+        request.validate()?;
+        request
+            .tools
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Tools>()
+            .validate_for_model(&self.client.model)?;
+        if let Some(capabilities) = self.client.capabilities_of(&self.client.model) {
+            capabilities.check(&self.client.model, &request)?;
+        }
+        Ok(request)
+    }
+
+    /// Execute the request and return the response body without parsing it
+    ///
+    /// For high-throughput services: parse the body with
+    /// [`parse_borrowed`](crate::parse_borrowed) to read the response
+    /// without copying its text. Transient failures are retried within the
+    /// retry budget; function calling, language checks, continuation and
+    /// post-processing are skipped since they need a parsed response.
+    pub async fn execute_raw_bytes(mut self) -> Result<Vec<u8>> {
+        let uploads = self.upload_pending().await?;
+        let result = async {
+            let request = self.build_checked_request()?;
+            let key = retry::idempotency_key();
+            let key = key.as_str();
+            let client = &self.client;
+            self.retrying(|| client.generate_content_bytes(&request, key))
+                .await
+        }
+        .await;
+        uploads.note_result(&result);
+        result
+    }
+
+    /// Count the prompt tokens of the request per message and part
+    ///
+    /// Shows which attachment or stretch of history takes up the context
+    /// window. Makes one `countTokens` call per part, plus two, so avoid it
+    /// on hot paths; [`GenerateContentRequest::estimated_prompt_tokens`] is a
+    /// free approximation of the total.
+    pub async fn count_tokens_breakdown(mut self) -> Result<TokenBreakdown> {
+        let request = self.build_checked_request()?;
+        tokens::count_breakdown(&self.client, &request).await
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<GenerationResponse> {
+        Ok(self.execute_detailed().await?.inner)
+    }
+
+    /// Execute the request and parse the answer into an enum
+    ///
+    /// Meant for requests set up with [`ContentBuilder::with_enum_output`],
+    /// whose values `T::from_str` accepts. Fails with
+    /// [`Error::PostProcessError`] if the answer can't be parsed.
+    pub async fn execute_enum<T: std::str::FromStr>(self) -> Result<T> {
+        self.execute().await?.parse_enum()
+    }
+
+    /// Execute the request and parse the answer into an enum, falling back
+    /// to `fallback` if the answer can't be parsed
+    ///
+    /// Errors of the call itself are still returned.
+    pub async fn execute_enum_or<T: std::str::FromStr>(self, fallback: T) -> Result<T> {
+        Ok(self.execute().await?.parse_enum().unwrap_or(fallback))
+    }
+
+    /// Execute the request and parse the JSON answer into `T`
+    ///
+    /// Meant for requests set up with a response schema. Fields of the
+    /// answer that `T` has no place for are ignored.
+    pub async fn execute_json<T: serde::de::DeserializeOwned>(self) -> Result<T> {
+        self.execute().await?.parse_json()
+    }
+
+    /// Execute the request and parse the JSON answer into `T`, failing
+    /// with [`Error::UnexpectedFields`] if it has fields `T` lacks
+    ///
+    /// See [`GenerationResponse::parse_json_strict`].
+    pub async fn execute_json_strict<T>(self) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned + Serialize,
+    {
+        self.execute().await?.parse_json_strict()
+    }
+
+    /// Execute the request, also returning HTTP metadata
+    ///
+    /// The latency covers the whole execution, including automatic function
+    /// calling round trips and language retries; status, headers and request
+    /// id are those of the final API call.
+    pub async fn execute_detailed(mut self) -> Result<DetailedResponse> {
+        let started = Instant::now();
+        let uploads = self.upload_pending().await?;
+        let result = async {
+            let request = self.build_checked_request()?;
+            let cache = match &self.client.response_cache {
+                Some(cache) if self.function_registry.is_none() => cache,
+                _ => return self.respond(request).await,
+            };
+            let pending = match cache.lookup(&self.client, &request).await {
+                Lookup::Hit(inner) => {
+                    return Ok(DetailedResponse {
+                        request_id: inner.response_id.clone(),
+                        inner,
+                        status: reqwest::StatusCode::OK,
+                        headers: reqwest::header::HeaderMap::new(),
+                        latency: Duration::ZERO,
+                        max_tokens_escalation: None,
+                    })
                 }
-            })
-            .flatten();
+                Lookup::Miss(pending) => pending,
+            };
+            let response = self.respond(request).await?;
+            cache.insert(pending, &response.inner);
+            Ok(response)
+        }
+        .await;
+        uploads.note_result(&result);
+        let mut response = result?;
+        response.latency = started.elapsed();
+        Ok(response)
+    }
 
-        Ok(Box::pin(stream))
+    /// Execute the request, failing with [`Error::PromptBlocked`] if the
+    /// prompt is blocked
+    ///
+    /// Unlike `execute`, which returns the empty response, the error carries
+    /// the offending categories and the thresholds this request set for
+    /// them.
+    pub async fn execute_unblocked(self) -> Result<GenerationResponse> {
+        let settings = self.request.safety_settings.clone().unwrap_or_default();
+        let response = self.execute().await?;
+        match response.prompt_block() {
+            Some(block) => Err(Error::PromptBlocked(block.with_safety_settings(&settings))),
+            None => Ok(response),
+        }
     }
 
-    /// Build a URL for the API
-    fn build_url(&self, endpoint: &str) -> Result<Url> {
-        // All Gemini API endpoints now use the format with colon:
-        // "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key=$API_KEY"
-        let url_str = format!(
-            "{}{}:{}?key={}",
-            BASE_URL, self.model, endpoint, self.api_key
-        );
-        Url::parse(&url_str).map_err(|e| Error::RequestError(e.to_string()))
+    /// Execute the request, asking again until the response passes a check
+    ///
+    /// The validator turns the response into the value the caller needs or
+    /// explains what is wrong with it. On an error, the model's reply and
+    /// the explanation are added to the conversation as a new user turn and
+    /// the request is sent again, up to `max_retries` times; after that
+    /// [`Error::ValidationFailed`] carries the last explanation.
+    pub async fn execute_validated<T, F>(mut self, max_retries: usize, validate: F) -> Result<T>
+    where
+        F: FnMut(&GenerationResponse) -> std::result::Result<T, String>,
+    {
+        let uploads = self.upload_pending().await?;
+        let result = self.validate_loop(max_retries, validate).await;
+        uploads.note_result(&result);
+        result
     }
-}
 
-/// Client for the Gemini API
-#[derive(Clone)]
-pub struct Gemini {
-    client: Arc<GeminiClient>,
-}
+    /// The retry loop of `execute_validated`
+    async fn validate_loop<T, F>(&mut self, max_retries: usize, mut validate: F) -> Result<T>
+    where
+        F: FnMut(&GenerationResponse) -> std::result::Result<T, String>,
+    {
+        let mut request = self.build_checked_request()?;
+        let mut attempts = 0;
+        loop {
+            let response = self.respond(request.clone()).await?.inner;
+            attempts += 1;
+            let message = match validate(&response) {
+                Ok(value) => return Ok(value),
+                Err(message) => message,
+            };
+            if attempts > max_retries {
+                return Err(Error::ValidationFailed { attempts, message });
+            }
 
-impl Gemini {
-    /// Create a new client with the specified API key
-    pub fn new(api_key: impl Into<String>) -> Self {
-        Self::with_model(api_key, DEFAULT_MODEL.to_string())
+            if let Some(candidate) = response.candidates.into_iter().next() {
+                request
+                    .contents
+                    .push(candidate.content.with_role(Role::Model));
+            }
+            request
+                .contents
+                .push(Message::user(format!("{}{}", VALIDATION_RETRY_PROMPT, message)).content);
+        }
     }
 
-    /// Create a new client for the Gemini Pro model
-    pub fn pro(api_key: impl Into<String>) -> Self {
-        Self::with_model(api_key, "models/gemini-2.0-pro-exp-02-05".to_string())
+    /// Send a checked request through language checks, continuation and
+    /// post-processing
+    async fn respond(&self, mut request: GenerateContentRequest) -> Result<DetailedResponse> {
+        let response = self.send_checked(&mut request, true).await?;
+        let response = self.escalate_max_tokens(&mut request, response).await?;
+        let mut response = self.continue_truncated(request, response).await?;
+        postprocess::apply(&self.post_processors, &mut response.inner)?;
+        postprocess::filter(&self.response_filters, &mut response.inner)?;
+        Ok(response)
     }
 
-    /// Create a new client with the specified API key and model
-    pub fn with_model(api_key: impl Into<String>, model: String) -> Self {
-        let client = GeminiClient::new(api_key, model);
-        Self {
-            client: Arc::new(client),
+    /// Send a JSON request again with a higher output token limit if its
+    /// reply was cut off, see `with_max_tokens_escalation`
+    async fn escalate_max_tokens(
+        &self,
+        request: &mut GenerateContentRequest,
+        response: DetailedResponse,
+    ) -> Result<DetailedResponse> {
+        let Some(mut ceiling) = self.max_tokens_ceiling else {
+            return Ok(response);
+        };
+        let json = |config: &&mut GenerationConfig| {
+            config.response_schema.is_some()
+                || config.response_mime_type.as_deref() == Some("application/json")
+        };
+        let Some(config) = request.generation_config.as_mut().filter(json) else {
+            return Ok(response);
+        };
+        if !is_truncated(&response.inner) {
+            return Ok(response);
+        }
+        if let Some(capabilities) = self.client.capabilities_of(&self.client.model) {
+            if capabilities.output_token_limit > 0 {
+                ceiling = ceiling.min(capabilities.output_token_limit as i32);
+            }
+        }
+        let from = config.max_output_tokens;
+        let to = match from {
+            Some(limit) => limit.saturating_mul(2).min(ceiling),
+            None => ceiling,
+        };
+        if from.is_some_and(|limit| to <= limit) {
+            return Ok(response);
         }
+        config.max_output_tokens = Some(to);
+
+        // Only the final call is repeated; function calls were already
+        // answered and their turns are part of `request`
+        let mut escalated = self.send_checked(request, false).await?;
+        escalated.inner.usage_metadata = sum_usage(
+            response.inner.usage_metadata,
+            escalated.inner.usage_metadata,
+        );
+        escalated.max_tokens_escalation = Some(MaxTokensEscalation { from, to });
+        Ok(escalated)
     }
 
-    /// Start building a content generation request
-    pub fn generate_content(&self) -> ContentBuilder {
-        ContentBuilder::new(self.client.clone())
+    /// Ask for more while the reply stops at the output token limit
+    ///
+    /// Each round sends the model's truncated turn back followed by a
+    /// "continue" message, then appends the new text to the first
+    /// candidate. Token counts are summed over all rounds.
+    async fn continue_truncated(
+        &self,
+        mut request: GenerateContentRequest,
+        mut response: DetailedResponse,
+    ) -> Result<DetailedResponse> {
+        // The model's last turn as sent, before it was stitched into `response`
+        let mut last_turn = match response.inner.candidates.first() {
+            Some(candidate) => candidate.content.clone(),
+            None => return Ok(response),
+        };
+        for _ in 0..self.max_continues {
+            if !is_truncated(&response.inner) {
+                break;
+            }
+            request.contents.push(last_turn.with_role(Role::Model));
+            request
+                .contents
+                .push(Message::user(CONTINUE_PROMPT).content);
+
+            let mut next = self.send(&mut request, true).await?;
+            let Some(more) = next.inner.candidates.first_mut() else {
+                break;
+            };
+            last_turn = more.content.clone();
+            let mut parts = std::mem::take(&mut response.inner.candidates[0].content.parts);
+            for part in std::mem::take(&mut more.content.parts) {
+                match (parts.last_mut(), part) {
+                    (
+                        Some(Part::Text {
+                            text,
+                            thought_signature,
+                        }),
+                        Part::Text {
+                            text: more,
+                            thought_signature: signature,
+                        },
+                    ) => {
+                        stitch(text, &more);
+                        if signature.is_some() {
+                            *thought_signature = signature;
+                        }
+                    }
+                    (_, part) => parts.push(part),
+                }
+            }
+            more.content.parts = parts;
+            next.inner.usage_metadata =
+                sum_usage(response.inner.usage_metadata, next.inner.usage_metadata);
+            response = next;
+        }
+        Ok(response)
+    }
+
+    /// Send the request, retrying once with a stronger instruction if the
+    /// reply is detected to be in the wrong language
+    #[cfg(feature = "language-detection")]
+    async fn send_in_language(
+        &self,
+        request: &mut GenerateContentRequest,
+        language: &str,
+        functions: bool,
+    ) -> Result<DetailedResponse> {
+        let response = self.send(request, functions).await?;
+        if language::mismatch(&response.inner.text(), language).is_none() {
+            return Ok(response);
+        }
+
+        add_system_text(request, language::instruction(language, true));
+        let response = self.send(request, functions).await?;
+        match language::mismatch(&response.inner.text(), language) {
+            None => Ok(response),
+            Some(detected) => Err(Error::LanguageMismatch {
+                expected: language::language_name(language),
+                detected,
+            }),
+        }
+    }
+
+    /// Send a built request, checking the language of the reply if one is
+    /// required
+    ///
+    /// See [`send`](Self::send) for `functions` and what is left in `request`.
+    async fn send_checked(
+        &self,
+        request: &mut GenerateContentRequest,
+        functions: bool,
+    ) -> Result<DetailedResponse> {
+        #[cfg(feature = "language-detection")]
+        if let Some(language) = &self.response_language {
+            return self.send_in_language(request, language, functions).await;
+        }
+        self.send(request, functions).await
+    }
+
+    /// Send a built request, running the function loop if `functions` is
+    /// set and a registry is
+    ///
+    /// `request` is left as sent by the final call, including the function
+    /// call turns added by the loop.
+    async fn send(
+        &self,
+        request: &mut GenerateContentRequest,
+        functions: bool,
+    ) -> Result<DetailedResponse> {
+        match &self.function_registry {
+            Some(registry) if functions && !registry.is_empty() => {
+                self.run_function_loop(registry, request).await
+            }
+            _ => self.call(request).await,
+        }
+    }
+
+    /// Make one `generateContent` call, retrying within the retry budget
+    ///
+    /// All attempts carry the same idempotency key.
+    async fn call(&self, request: &GenerateContentRequest) -> Result<DetailedResponse> {
+        let key = retry::idempotency_key();
+        let key = key.as_str();
+        self.retrying(move || self.client.generate_content_raw(request, key))
+            .await
+    }
+
+    /// Run a `generateContent` call, retrying transient failures within the
+    /// retry budget
+    async fn retrying<T, F, Fut>(&self, send: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let Some(budget) = &self.retry_budget else {
+            return send().await;
+        };
+        budget.acquire()?;
+        let mut attempt = 1;
+        loop {
+            match send().await {
+                Err(e) if e.is_transient() => match budget.acquire_retry(attempt) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => {
+                        return Err(e.with_context("generateContent", &self.client.model, attempt))
+                    }
+                },
+                result => {
+                    return result.map_err(|e| {
+                        e.with_context("generateContent", &self.client.model, attempt)
+                    })
+                }
+            }
+        }
+    }
+
+    /// Send the request, dispatching function calls until the model stops making them
+    async fn run_function_loop(
+        &self,
+        registry: &FunctionRegistry,
+        request: &mut GenerateContentRequest,
+    ) -> Result<DetailedResponse> {
+        for _ in 0..=self.max_function_turns {
+            let response = self.call(request).await?;
+            let calls: Vec<_> = response
+                .inner
+                .function_calls()
+                .into_iter()
+                .cloned()
+                .collect();
+            if calls.is_empty() {
+                return Ok(response);
+            }
+
+            if let Some(candidate) = response.inner.candidates.first() {
+                request
+                    .contents
+                    .push(candidate.content.clone().with_role(Role::Model));
+            }
+            let mut parts = Vec::with_capacity(calls.len());
+            for call in calls {
+                let function_response = registry.dispatch_limited(&self.client, call).await;
+                parts.push(Part::FunctionResponse { function_response });
+            }
+            request.contents.push(Content {
+                parts,
+                role: Some(Role::User),
+                ..Default::default()
+            });
+        }
+
+        Err(Error::FunctionCallError(format!(
+            "Model was still calling functions after {} turns",
+            self.max_function_turns
+        )))
+    }
+
+    /// Execute the request with streaming
+    ///
+    /// Drop the stream or call [`GenerationStream::cancel`] to stop
+    /// generation early; the connection is closed right away.
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream(mut self) -> Result<GenerationStream> {
+        let uploads = self.upload_pending().await?;
+        let started = Instant::now();
+        let result = async {
+            let request = self.build_checked_request()?;
+            if let Some(budget) = &self.retry_budget {
+                budget.acquire()?;
+            }
+            self.client
+                .generate_content_stream(request, &retry::idempotency_key())
+                .await
+        }
+        .await;
+        uploads.note_result(&result);
+        Ok(self.into_stream(result?, uploads, started))
+    }
+
+    /// Execute the request with streaming, falling back to a single call
+    /// where streaming is not available
+    ///
+    /// If `streamGenerateContent` is rejected, e.g. by a proxy that doesn't
+    /// support it, the request is sent to `generateContent` instead and the
+    /// complete response arrives as the stream's only item. UI code can then
+    /// handle both cases with one code path.
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream_or_fallback(mut self) -> Result<GenerationStream> {
+        let uploads = self.upload_pending().await?;
+        let started = Instant::now();
+        let result = async {
+            let request = self.build_checked_request()?;
+            if let Some(budget) = &self.retry_budget {
+                budget.acquire()?;
+            }
+            match self
+                .client
+                .generate_content_stream(request.clone(), &retry::idempotency_key())
+                .await
+            {
+                Err(e) if streaming::is_unsupported(&e) => {
+                    Ok(streaming::once(self.call(&request).await?.inner))
+                }
+                result => result,
+            }
+        }
+        .await;
+        uploads.note_result(&result);
+        Ok(self.into_stream(result?, uploads, started))
+    }
+
+    /// Wrap a response stream, applying the safety abort threshold
+    ///
+    /// Uploaded files are kept until the stream is dropped. `started` is
+    /// when the request was sent, for the time to first token.
+    #[cfg(feature = "streaming")]
+    fn into_stream(
+        self,
+        stream: Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>,
+        uploads: UploadedFiles,
+        started: Instant,
+    ) -> GenerationStream {
+        let stream = match self.safety_abort {
+            Some(threshold) => streaming::abort_on_safety(stream, threshold),
+            None => stream,
+        };
+        let stream = match self.chunk_coalescing {
+            Some(coalescing) => streaming::coalesce(stream, coalescing),
+            None => stream,
+        };
+        let stream = stream.map(move |result| {
+            let _ = &uploads;
+            result
+        });
+        GenerationStream::new(Box::pin(stream), started, self.on_first_token)
+    }
+
+    /// Stream the request until a deadline, returning what was generated
+    ///
+    /// If generation completes in time the full response is returned.
+    /// Otherwise the text streamed so far is returned as a best-effort
+    /// response whose candidates have the finish reason
+    /// [`FINISH_REASON_DEADLINE`](crate::FINISH_REASON_DEADLINE); this also
+    /// applies when the deadline passes before the first chunk arrives, in
+    /// which case the candidate is empty. Errors before the deadline are
+    /// returned as usual.
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream_until(
+        mut self,
+        deadline: std::time::Instant,
+    ) -> Result<GenerationResponse> {
+        let processors = std::mem::take(&mut self.post_processors);
+        let filters = std::mem::take(&mut self.response_filters);
+        let deadline = tokio::time::Instant::from_std(deadline);
+        let mut response = GenerationResponse {
+            candidates: Vec::new(),
+            prompt_feedback: None,
+            usage_metadata: None,
+            response_id: None,
+        };
+
+        let Ok(stream) = tokio::time::timeout_at(deadline, self.execute_stream()).await else {
+            streaming::mark_deadline(&mut response);
+            return Ok(response);
+        };
+        let mut stream = stream?;
+        loop {
+            match tokio::time::timeout_at(deadline, stream.next()).await {
+                Ok(Some(chunk)) => streaming::accumulate(&mut response, chunk?),
+                Ok(None) => break,
+                Err(_) => {
+                    streaming::mark_deadline(&mut response);
+                    break;
+                }
+            }
+        }
+        postprocess::apply(&processors, &mut response)?;
+        postprocess::filter(&filters, &mut response)?;
+        Ok(response)
+    }
+
+    /// Execute the request with streaming, yielding typed part deltas
+    ///
+    /// A better fit for UI renderers than `execute_stream`: text, thoughts
+    /// and function calls arrive as separate events, followed by a single
+    /// `Finish` event carrying the finish reason and token usage.
+    ///
+    /// With a function registry attached, function calls are buffered until
+    /// the model's turn completes, dispatched (each result is surfaced as a
+    /// `FunctionResult` event) and the follow-up turn is streamed into the
+    /// same event stream. Only the final turn emits `Finish`.
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream_parts(
+        mut self,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<PartDelta>> + Send>>> {
+        if let Some(registry) = self.function_registry.clone().filter(|r| !r.is_empty()) {
+            let uploads = self.upload_pending().await?;
+            let started = Instant::now();
+            let request = self.build_checked_request();
+            uploads.note_result(&request);
+            let request = request?;
+            let state = StreamingFunctionLoop {
+                client: self.client.clone(),
+                registry,
+                request,
+                stream: None,
+                parts: Vec::new(),
+                calls: Vec::new(),
+                turns: 0,
+                max_turns: self.max_function_turns,
+                retry_budget: self.retry_budget.clone(),
+                safety_abort: self.safety_abort,
+                chunk_coalescing: self.chunk_coalescing,
+                started,
+                on_first_token: self.on_first_token.clone(),
+                uploads,
+                done: false,
+            };
+            let deltas = futures::stream::unfold(state, |mut state| async move {
+                if state.done {
+                    return None;
+                }
+                let events = state.step().await;
+                Some((futures::stream::iter(events), state))
+            })
+            .flatten();
+            return Ok(Box::pin(deltas));
+        }
+
+        let stream = self.execute_stream().await?;
+        let deltas = stream
+            .map(|result| {
+                let deltas = match result {
+                    Ok(response) => PartDelta::from_response(response)
+                        .into_iter()
+                        .map(Ok)
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                futures::stream::iter(deltas)
+            })
+            .flatten();
+        Ok(Box::pin(deltas))
+    }
+
+    /// Stream the request into a markdown handler
+    ///
+    /// Answer text is split into prose and fenced code blocks as it arrives
+    /// (see [`MarkdownHandler`]); thoughts and function calls are skipped.
+    /// Returns the handler once the stream is done. If the stream fails,
+    /// the error is returned and `on_done` is not called.
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream_markdown<H: MarkdownHandler>(self, handler: H) -> Result<H> {
+        let mut segmenter = MarkdownSegmenter::new(handler);
+        let mut deltas = self.execute_stream_parts().await?;
+        while let Some(delta) = deltas.next().await {
+            if let PartDelta::TextDelta(text) = delta? {
+                segmenter.push(&text);
+            }
+        }
+        Ok(segmenter.finish())
+    }
+}
+
+/// Without the `files` feature there is nothing to upload
+#[cfg(not(feature = "files"))]
+struct UploadedFiles;
+
+#[cfg(not(feature = "files"))]
+impl UploadedFiles {
+    fn note_result<T>(&self, _result: &Result<T>) {}
+}
+
+#[cfg(not(feature = "files"))]
+impl ContentBuilder {
+    async fn upload_pending(&mut self) -> Result<UploadedFiles> {
+        Ok(UploadedFiles)
+    }
+}
+
+/// State of a streamed automatic function calling conversation
+#[cfg(feature = "streaming")]
+struct StreamingFunctionLoop {
+    client: Arc<GeminiClient>,
+    registry: FunctionRegistry,
+    request: GenerateContentRequest,
+    stream: Option<Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>>,
+    /// Parts streamed so far in the current model turn
+    parts: Vec<Part>,
+    /// Function calls requested in the current model turn
+    calls: Vec<crate::tools::FunctionCall>,
+    turns: usize,
+    max_turns: usize,
+    retry_budget: Option<RetryBudget>,
+    safety_abort: Option<HarmBlockThreshold>,
+    chunk_coalescing: Option<ChunkCoalescing>,
+    /// When the first turn was sent, for the time to first token
+    started: Instant,
+    /// Taken once a turn has streamed text
+    on_first_token: Option<FirstTokenCallback>,
+    /// Files uploaded for the request, kept until the stream is dropped
+    uploads: UploadedFiles,
+    done: bool,
+}
+
+#[cfg(feature = "streaming")]
+impl StreamingFunctionLoop {
+    /// Take an attempt from the retry budget, if any, for the next turn
+    fn acquire_attempt(&self) -> Result<()> {
+        match &self.retry_budget {
+            Some(budget) => budget.acquire(),
+            None => Ok(()),
+        }
+    }
+
+    /// Advance by one streamed chunk or one round of function dispatch
+    async fn step(&mut self) -> Vec<Result<PartDelta>> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                let started = match self.acquire_attempt() {
+                    Ok(()) => {
+                        self.client
+                            .generate_content_stream(
+                                self.request.clone(),
+                                &retry::idempotency_key(),
+                            )
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                self.uploads.note_result(&started);
+                match started {
+                    Ok(stream) => {
+                        let stream = match self.safety_abort {
+                            Some(threshold) => streaming::abort_on_safety(stream, threshold),
+                            None => stream,
+                        };
+                        let stream = match self.chunk_coalescing {
+                            Some(coalescing) => streaming::coalesce(stream, coalescing),
+                            None => stream,
+                        };
+                        self.stream.insert(Box::pin(GenerationStream::new(
+                            stream,
+                            self.started,
+                            self.on_first_token.clone(),
+                        )))
+                    }
+                    Err(e) => {
+                        self.done = true;
+                        return vec![Err(e)];
+                    }
+                }
+            }
+        };
+
+        match stream.next().await {
+            Some(Ok(response)) => {
+                if let Some(candidate) = response.candidates.first() {
+                    self.parts.extend(candidate.content.parts.iter().cloned());
+                }
+                let mut events = Vec::new();
+                for delta in PartDelta::from_response(response) {
+                    match delta {
+                        PartDelta::FunctionCallDelta(call) => {
+                            self.calls.push(call.clone());
+                            events.push(Ok(PartDelta::FunctionCallDelta(call)));
+                        }
+                        // The turn ends in function calls, so the conversation continues
+                        PartDelta::Finish { .. } if !self.calls.is_empty() => {}
+                        // The first token has been reported, later turns must not report again
+                        PartDelta::TextDelta(text) => {
+                            self.on_first_token = None;
+                            events.push(Ok(PartDelta::TextDelta(text)));
+                        }
+                        delta => events.push(Ok(delta)),
+                    }
+                }
+                events
+            }
+            Some(Err(e)) => {
+                self.done = true;
+                vec![Err(e)]
+            }
+            None => {
+                self.stream = None;
+                if self.calls.is_empty() {
+                    self.done = true;
+                    return Vec::new();
+                }
+                if self.turns >= self.max_turns {
+                    self.done = true;
+                    return vec![Err(Error::FunctionCallError(format!(
+                        "Model was still calling functions after {} turns",
+                        self.max_turns
+                    )))];
+                }
+                self.turns += 1;
+
+                self.request.contents.push(Content {
+                    parts: std::mem::take(&mut self.parts),
+                    role: Some(Role::Model),
+                    ..Default::default()
+                });
+                let mut events = Vec::new();
+                let mut parts = Vec::new();
+                for call in std::mem::take(&mut self.calls) {
+                    let function_response =
+                        self.registry.dispatch_limited(&self.client, call).await;
+                    events.push(Ok(PartDelta::FunctionResult(function_response.clone())));
+                    parts.push(Part::FunctionResponse { function_response });
+                }
+                self.request.contents.push(Content {
+                    parts,
+                    role: Some(Role::User),
+                    ..Default::default()
+                });
+                events
+            }
+        }
+    }
+}
+
+/// A generated response together with HTTP-level metadata
+///
+/// Returned by `ContentBuilder::execute_detailed` for latency and
+/// availability tracking.
+#[derive(Debug, Clone)]
+pub struct DetailedResponse {
+    /// The generated response
+    pub inner: GenerationResponse,
+    /// HTTP status of the final API call
+    pub status: reqwest::StatusCode,
+    /// HTTP headers of the final API call
+    pub headers: reqwest::header::HeaderMap,
+    /// Wall-clock time from building the request to the parsed response
+    pub latency: Duration,
+    /// Request identifier from the response headers, or the API's
+    /// `responseId` if no such header was sent
+    pub request_id: Option<String>,
+    /// Set if a truncated JSON reply was retried with a higher output token
+    /// limit, see `ContentBuilder::with_max_tokens_escalation`
+    pub max_tokens_escalation: Option<MaxTokensEscalation>,
+}
+
+/// How the output token limit of a request was raised after its reply was
+/// cut off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxTokensEscalation {
+    /// The limit of the truncated reply, `None` for the model's default
+    pub from: Option<i32>,
+    /// The limit the request was sent again with
+    pub to: i32,
+}
+
+/// Whether the first candidate stopped at the output token limit
+fn is_truncated(response: &GenerationResponse) -> bool {
+    response
+        .candidates
+        .first()
+        .and_then(|c| c.finish_reason.as_deref())
+        == Some(FINISH_REASON_MAX_TOKENS)
+}
+
+/// Serialize a request body into a buffer of exactly the right size
+///
+/// Measuring first keeps the buffer from doubling while it grows, which
+/// with megabytes of inline data would briefly hold the body twice.
+fn json_body(body: &impl Serialize) -> Result<Vec<u8>> {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, body)?;
+    let mut buffer = Vec::with_capacity(counter.0);
+    serde_json::to_writer(&mut buffer, body)?;
+    Ok(buffer)
+}
+
+/// Append a continuation to text, dropping a code fence the model reopened
+///
+/// Models asked to continue inside a code block often start the next turn
+/// by opening a new fence, which would end the block instead. Only a fence
+/// with the same info string as the open one, e.g. "```rust", counts as
+/// reopened; a bare fence closes the block and is kept.
+fn stitch(text: &mut String, more: &str) {
+    let open_info = match text.rmatch_indices("```").next() {
+        Some((at, _)) if text.matches("```").count() % 2 == 1 => {
+            let line = &text[at + 3..];
+            line.split('\n').next().unwrap_or_default().trim()
+        }
+        _ => "",
+    };
+    let trimmed = more.trim_start();
+    let (first, rest) = trimmed.split_once('\n').unwrap_or((trimmed, ""));
+    let reopened = first
+        .strip_prefix("```")
+        .is_some_and(|info| !open_info.is_empty() && info.trim() == open_info);
+    if reopened {
+        text.push_str(rest);
+    } else {
+        text.push_str(more);
+    }
+}
+
+/// Add up the token counts of two calls
+fn sum_usage(a: Option<UsageMetadata>, b: Option<UsageMetadata>) -> Option<UsageMetadata> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(UsageMetadata {
+            prompt_token_count: a.prompt_token_count + b.prompt_token_count,
+            candidates_token_count: a.candidates_token_count + b.candidates_token_count,
+            total_token_count: a.total_token_count + b.total_token_count,
+        }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Append a text part to the request's system instruction, creating it if needed
+fn add_system_text(request: &mut GenerateContentRequest, text: String) {
+    request
+        .system_instruction
+        .get_or_insert_with(Content::default)
+        .parts
+        .push(Part::Text {
+            text,
+            thought_signature: None,
+        });
+}
+
+/// Version of the Gemini REST API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    /// The stable API
+    V1,
+    /// The beta API, with most recent features (the default)
+    #[default]
+    V1Beta,
+    /// Experimental features, e.g. some thinking and live options
+    V1Alpha,
+}
+
+impl ApiVersion {
+    /// The version as used in request URLs, e.g. `v1beta`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V1Beta => "v1beta",
+            ApiVersion::V1Alpha => "v1alpha",
+        }
+    }
+}
+
+/// Prefix a bare model name with `models/`
+pub(crate) fn model_path(model: String) -> String {
+    if model.starts_with("models/") || model.starts_with("tunedModels/") {
+        model
+    } else {
+        format!("models/{}", model)
+    }
+}
+
+/// Internal client for making requests to the Gemini API
+#[derive(Clone)]
+pub(crate) struct GeminiClient {
+    http_client: Client,
+    pub(crate) api_key: Arc<dyn ApiKeyProvider>,
+    pub(crate) model: String,
+    api_version: ApiVersion,
+    /// Root URL of the API, ending in a slash
+    pub(crate) base_url: String,
+    /// Model capabilities fetched from the API, by model name
+    pub(crate) capabilities: Arc<RwLock<HashMap<String, ModelCapabilities>>>,
+    /// Limits concurrent calls; shared by all copies of the client
+    pub(crate) queue: Option<Arc<RequestQueue>>,
+    /// Limits the rate of calls; shared by all copies of the client
+    #[cfg(feature = "pool")]
+    pub(crate) rate_limit: Option<Arc<RateLimiter>>,
+    /// Counts the calls and tokens of a pool tenant
+    #[cfg(feature = "pool")]
+    pub(crate) usage: Option<Arc<UsageCounter>>,
+    /// Priority of calls made with this copy of the client
+    pub(crate) priority: Priority,
+    /// Caller metadata attached to the calls of this copy of the client
+    pub(crate) metadata: BTreeMap<String, String>,
+    /// Gzip request bodies of at least `COMPRESSION_MIN_SIZE` bytes
+    #[cfg(feature = "gzip")]
+    request_compression: bool,
+    /// Checks every prompt before it is sent
+    prompt_guard: Option<Arc<PromptGuard>>,
+    /// Answers repeated prompts without a call
+    response_cache: Option<ResponseCache>,
+    /// Recent latencies per model; shared by all copies of the client
+    pub(crate) latency: Arc<LatencyTracker>,
+    /// Model for requests whose latency budget the client's model misses
+    fast_model: String,
+    /// Tees streamed chunks to a transcript file
+    #[cfg(feature = "streaming")]
+    recorder: Option<StreamRecorder>,
+    /// Applied to request bodies before they are logged
+    #[cfg(feature = "tracing")]
+    redactor: Option<Redactor>,
+}
+
+impl GeminiClient {
+    /// Create a new client
+    fn new(api_key: StaticKey, model: String) -> Self {
+        Self {
+            http_client: Client::new(),
+            api_key: Arc::new(api_key),
+            model,
+            api_version: ApiVersion::default(),
+            base_url: BASE_URL.to_string(),
+            capabilities: Arc::default(),
+            queue: None,
+            #[cfg(feature = "pool")]
+            rate_limit: None,
+            #[cfg(feature = "pool")]
+            usage: None,
+            priority: Priority::default(),
+            metadata: BTreeMap::new(),
+            #[cfg(feature = "gzip")]
+            request_compression: false,
+            prompt_guard: None,
+            response_cache: None,
+            latency: Arc::default(),
+            fast_model: DEFAULT_FAST_MODEL.to_string(),
+            #[cfg(feature = "streaming")]
+            recorder: None,
+            #[cfg(feature = "tracing")]
+            redactor: None,
+        }
+    }
+
+    /// Generate content
+    async fn generate_content_raw(
+        &self,
+        request: &GenerateContentRequest,
+        idempotency_key: &str,
+    ) -> Result<DetailedResponse> {
+        let endpoint = "generateContent";
+        let started = Instant::now();
+        let result: Result<DetailedResponse> = async {
+            // Keep the slot until the body has been read
+            let _slot = self.queue_slot().await;
+            let sent = Instant::now();
+            let response = self.send_json(endpoint, request, idempotency_key).await?;
+            let status = response.status();
+            let headers = response.headers().clone();
+            let inner: GenerationResponse = response.json().await?;
+            self.latency.record(&self.model, sent.elapsed());
+            #[cfg(feature = "pool")]
+            if let (Some(usage), Some(tokens)) = (&self.usage, &inner.usage_metadata) {
+                usage.tokens(tokens);
+            }
+            let request_id = ["x-request-id", "x-goog-request-id"]
+                .iter()
+                .find_map(|name| headers.get(*name)?.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| inner.response_id.clone());
+            Ok(DetailedResponse {
+                inner,
+                status,
+                headers,
+                latency: started.elapsed(),
+                request_id,
+                max_tokens_escalation: None,
+            })
+        }
+        .await;
+        result.map_err(|e| self.context(e, endpoint))
+    }
+
+    /// Generate content, returning the response body as received
+    async fn generate_content_bytes(
+        &self,
+        request: &GenerateContentRequest,
+        idempotency_key: &str,
+    ) -> Result<Vec<u8>> {
+        let endpoint = "generateContent";
+        let result: Result<Vec<u8>> = async {
+            let _slot = self.queue_slot().await;
+            let response = self.send_json(endpoint, request, idempotency_key).await?;
+            Ok(Vec::from(response.bytes().await?))
+        }
+        .await;
+        result.map_err(|e| self.context(e, endpoint))
+    }
+
+    /// Send a single user message without any options, returning the answer
+    pub(crate) async fn generate_text(&self, prompt: String) -> Result<String> {
+        let mut request = GenerateContentRequest {
+            contents: vec![Message::user(prompt).content],
+            generation_config: None,
+            safety_settings: None,
+            tools: None,
+            tool_config: None,
+            system_instruction: None,
+            labels: None,
+        };
+        if let Some(guard) = &self.prompt_guard {
+            guard.check(&mut request)?;
+        }
+        let response = self
+            .generate_content_raw(&request, &retry::idempotency_key())
+            .await?;
+        Ok(response.inner.text().into_owned())
+    }
+
+    /// Generate content with streaming
+    #[cfg(feature = "streaming")]
+    async fn generate_content_stream(
+        &self,
+        request: GenerateContentRequest,
+        idempotency_key: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>> {
+        let endpoint = "streamGenerateContent";
+        let slot = self.queue_slot().await;
+        let recording = self.recorder.as_ref().map(|recorder| {
+            recorder.session(idempotency_key, &self.model, &self.metadata, Instant::now())
+        });
+        let response = match self.send_json(endpoint, &request, idempotency_key).await {
+            Ok(response) => response,
+            Err(e) => {
+                if let Some(recording) = &recording {
+                    recording.error(&e);
+                }
+                return Err(self.context(e, endpoint));
+            }
+        };
+
+        // Server-sent events may be split across network chunks, so complete
+        // lines are parsed as they arrive and any remainder is kept in `lines`.
+        // `None` marks the end of the body, where the remainder is parsed too.
+        let stream = response
+            .bytes_stream()
+            .map(Some)
+            .chain(futures::stream::once(futures::future::ready(None)))
+            .scan(
+                (SseLines::default(), recording),
+                |(lines, recording), item| {
+                    let data = match item {
+                        Some(Ok(bytes)) => lines.push(&bytes),
+                        Some(Err(e)) => {
+                            let e = Error::from(e);
+                            if let Some(recording) = recording {
+                                recording.error(&e);
+                            }
+                            return futures::future::ready(Some(futures::stream::iter(vec![Err(
+                                e,
+                            )])));
+                        }
+                        None => lines.finish().into_iter().collect(),
+                    };
+                    let responses: Vec<_> = data
+                        .into_iter()
+                        .map(|data| {
+                            let response = serde_json::from_str::<GenerationResponse>(&data)
+                                .map_err(Error::JsonError);
+                            if let Some(recording) = recording {
+                                recording.chunk(&data, &response);
+                            }
+                            response
+                        })
+                        .collect();
+                    futures::future::ready(Some(futures::stream::iter(responses)))
+                },
+            )
+            .flatten();
+        let model = self.model.clone();
+        let metadata = self.metadata.clone();
+        #[cfg(feature = "pool")]
+        let usage = self.usage.clone();
+        // The queue slot is held until the stream is dropped
+        let stream = stream.map(move |result| {
+            let _ = &slot;
+            // Chunks report the usage so far; the final one has the totals
+            #[cfg(feature = "pool")]
+            if let (Some(usage), Ok(chunk)) = (&usage, &result) {
+                let finished = chunk.candidates.iter().any(|c| c.finish_reason.is_some());
+                if let Some(tokens) = chunk.usage_metadata.as_ref().filter(|_| finished) {
+                    usage.tokens(tokens);
+                }
+            }
+            result.map_err(|e| {
+                e.with_context(endpoint, model.as_str(), 1)
+                    .with_metadata(&metadata)
+            })
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    /// Annotate an error with the endpoint, model and metadata of a call
+    pub(crate) fn context(&self, error: Error, endpoint: &str) -> Error {
+        error
+            .with_context(endpoint, &self.model, 1)
+            .with_metadata(&self.metadata)
+    }
+
+    /// POST a JSON body to an endpoint, turning non-success statuses into errors
+    ///
+    /// Waits for a slot if the client limits concurrent calls.
+    pub(crate) async fn post(
+        &self,
+        endpoint: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<reqwest::Response> {
+        let _slot = self.queue_slot().await;
+        self.send_json(endpoint, body, &retry::idempotency_key())
+            .await
+    }
+
+    /// Wait for a free slot in the request queue, if the client has one,
+    /// and for the rate limit
+    async fn queue_slot(&self) -> Option<Permit> {
+        #[cfg(feature = "pool")]
+        if let Some(limiter) = &self.rate_limit {
+            limiter.acquire().await;
+        }
+        match &self.queue {
+            Some(queue) => Some(queue.acquire(self.priority).await),
+            None => None,
+        }
+    }
+
+    /// POST a JSON body without waiting for a queue slot
+    async fn send_json(
+        &self,
+        endpoint: &str,
+        body: &impl serde::Serialize,
+        idempotency_key: &str,
+    ) -> Result<reqwest::Response> {
+        let mut url = self.build_url(endpoint)?;
+        if endpoint == "streamGenerateContent" {
+            url.query_pairs_mut().append_pair("alt", "sse");
+        }
+        let body = json_body(body)?;
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "gemini.request",
+            endpoint,
+            model = %self.model,
+            body_bytes = body.len(),
+            sent_bytes = tracing::field::Empty,
+            status = tracing::field::Empty,
+            metadata = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        if !self.metadata.is_empty() {
+            span.record("metadata", tracing::field::debug(&self.metadata));
+        }
+        #[cfg(feature = "tracing")]
+        if tracing::enabled!(tracing::Level::TRACE) {
+            let logged = match &self.redactor {
+                Some(redactor) => serde_json::from_slice(&body)
+                    .map(|value: serde_json::Value| redactor.redact_json(&value))
+                    .unwrap_or_default(),
+                None => String::from_utf8_lossy(&body).into_owned(),
+            };
+            span.in_scope(|| tracing::trace!(body = %logged, "request body"));
+        }
+        let send = self.send_body(url, body, idempotency_key);
+        #[cfg(feature = "tracing")]
+        let send = tracing::Instrument::instrument(send, span.clone());
+        let response = match send.await {
+            Ok(response) => response,
+            Err(e) => return self.count(Err(e)),
+        };
+
+        #[cfg(feature = "tracing")]
+        span.record("status", response.status().as_u16());
+        self.count(check_status(response).await)
+    }
+
+    /// GET a resource below the API version root, e.g. `models`
+    pub(crate) async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<reqwest::Response> {
+        let url_str = format!("{}{}/{}", self.base_url, self.api_version.as_str(), path);
+        let mut url = Url::parse(&url_str).map_err(|e| Error::RequestError(e.to_string()))?;
+        url.query_pairs_mut().extend_pairs(query);
+        let request = self
+            .http_client
+            .get(url)
+            .header(API_KEY_HEADER, self.api_key_header()?);
+
+        let _slot = self.queue_slot().await;
+        let result = match request.send().await {
+            Ok(response) => check_status(response).await,
+            Err(e) => Err(e.into()),
+        };
+        self.count(result)
+    }
+
+    /// DELETE a resource below the API version root, e.g. `files/abc-123`
+    #[cfg(feature = "files")]
+    pub(crate) async fn delete(&self, path: &str) -> Result<()> {
+        let url_str = format!("{}{}/{}", self.base_url, self.api_version.as_str(), path);
+        let url = Url::parse(&url_str).map_err(|e| Error::RequestError(e.to_string()))?;
+        let request = self
+            .http_client
+            .delete(url)
+            .header(API_KEY_HEADER, self.api_key_header()?);
+
+        let _slot = self.queue_slot().await;
+        let result = match request.send().await {
+            Ok(response) => check_status(response).await,
+            Err(e) => Err(e.into()),
+        };
+        self.count(result)?;
+        Ok(())
+    }
+
+    /// Count a call in the usage of a pool tenant
+    fn count<T>(&self, result: Result<T>) -> Result<T> {
+        #[cfg(feature = "pool")]
+        if let Some(usage) = &self.usage {
+            usage.request(result.is_ok());
+        }
+        result
+    }
+
+    /// The Files API endpoint that starts uploads
+    ///
+    /// Like every call, uploads send the API key in a header, see
+    /// [`api_key_header`](Self::api_key_header), so the upload URL the
+    /// server hands back doesn't carry it.
+    #[cfg(feature = "files")]
+    pub(crate) fn upload_url(&self) -> Result<Url> {
+        let url_str = format!(
+            "{}upload/{}/files",
+            self.base_url,
+            self.api_version.as_str()
+        );
+        Url::parse(&url_str).map_err(|e| Error::RequestError(e.to_string()))
+    }
+
+    /// The API key as a value for the `x-goog-api-key` header, marked
+    /// sensitive so it is left out of debug output
+    ///
+    /// The key never goes in URLs, which end up in proxy and access logs.
+    pub(crate) fn api_key_header(&self) -> Result<reqwest::header::HeaderValue> {
+        let mut value =
+            reqwest::header::HeaderValue::from_str(self.api_key.api_key()?.expose_secret())
+                .map_err(|_| {
+                    Error::RequestError("The API key is not a valid header value".to_string())
+                })?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    /// The HTTP client, for calls that don't go to a model endpoint
+    #[cfg(feature = "files")]
+    pub(crate) fn http(&self) -> &Client {
+        &self.http_client
+    }
+
+    /// A copy of this client that sends requests to another model
+    pub(crate) fn for_model(&self, model: impl Into<String>) -> GeminiClient {
+        let mut client = self.clone();
+        client.model = model_path(model.into());
+        client
+    }
+
+    /// Send a serialized JSON body, gzipped if compression is enabled
+    ///
+    /// Servers that reject compressed bodies with 415 Unsupported Media Type
+    /// get the request again uncompressed.
+    async fn send_body(
+        &self,
+        url: Url,
+        body: Vec<u8>,
+        idempotency_key: &str,
+    ) -> Result<reqwest::Response> {
+        let api_key = self.api_key_header()?;
+        #[cfg(feature = "gzip")]
+        if self.request_compression && body.len() >= COMPRESSION_MIN_SIZE {
+            let compressed = gzip(&body)?;
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("sent_bytes", compressed.len());
+            let response = self
+                .http_client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::CONTENT_ENCODING, "gzip")
+                .header(API_KEY_HEADER, api_key.clone())
+                .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+                .body(compressed)
+                .send()
+                .await?;
+            if response.status() != reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                return Ok(response);
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("sent_bytes", body.len());
+        Ok(self
+            .http_client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(API_KEY_HEADER, api_key)
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+            .body(body)
+            .send()
+            .await?)
+    }
+
+    /// Build a URL for the API
+    ///
+    /// The API key is not part of it, see [`api_key_header`](Self::api_key_header).
+    fn build_url(&self, endpoint: &str) -> Result<Url> {
+        // All Gemini API endpoints now use the format with colon:
+        // "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent"
+        let url_str = format!(
+            "{}{}/{}:{}",
+            self.base_url,
+            self.api_version.as_str(),
+            self.model,
+            endpoint
+        );
+        Url::parse(&url_str).map_err(|e| Error::RequestError(e.to_string()))
+    }
+}
+
+/// Turn a non-success response into an `ApiError`
+pub(crate) async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if !status.is_success() {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+            .map(Duration::from_secs);
+        let error_text = response.text().await?;
+        return Err(Error::ApiError {
+            status_code: status.as_u16(),
+            message: error_text,
+            retry_after,
+        });
+    }
+    Ok(response)
+}
+
+/// Gzip a request body
+#[cfg(feature = "gzip")]
+fn gzip(body: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder
+        .write_all(body)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| Error::RequestError(format!("Failed to compress request body: {}", e)))
+}
+
+/// The JSON payload of one line of a server-sent event stream
+///
+/// Each line that starts with "data: " contains a JSON encoded chunk; other
+/// lines (blank separators, comments) are ignored.
+#[cfg(feature = "streaming")]
+fn sse_data(line: &str) -> Option<&str> {
+    let json_str = line.strip_prefix("data:")?.trim_start();
+    if json_str.is_empty() || json_str == "[DONE]" {
+        return None;
+    }
+    Some(json_str)
+}
+
+/// Splits a server-sent event stream into the data of its lines
+///
+/// Bytes are kept until their line is complete, so lines and multibyte
+/// characters split across network chunks are decoded whole.
+#[cfg(feature = "streaming")]
+#[derive(Default)]
+struct SseLines {
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "streaming")]
+impl SseLines {
+    /// Add a network chunk, returning the data of the lines it completes
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut data = Vec::new();
+        let mut scanned = self.buffer.len();
+        self.buffer.extend_from_slice(bytes);
+        while let Some(newline) = self.buffer[scanned..].iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=scanned + newline).collect();
+            scanned = 0;
+            if let Some(json) = sse_data(String::from_utf8_lossy(&line).trim_end()) {
+                data.push(json.to_string());
+            }
+        }
+        data
+    }
+
+    /// End the stream, returning the data of a last line that had no
+    /// newline
+    fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.buffer);
+        sse_data(String::from_utf8_lossy(&line).trim_end()).map(str::to_string)
+    }
+}
+
+/// Client for the Gemini API
+#[derive(Clone)]
+pub struct Gemini {
+    pub(crate) client: Arc<GeminiClient>,
+    #[cfg(feature = "prompts")]
+    pub(crate) prompts: Option<Arc<crate::prompts::PromptStore>>,
+}
+
+impl Gemini {
+    /// Create a new client with the specified API key
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self::with_model(api_key, DEFAULT_MODEL.to_string())
+    }
+
+    /// Create a new client for the Gemini Pro model
+    pub fn pro(api_key: impl Into<String>) -> Self {
+        Self::with_model(api_key, "models/gemini-2.0-pro-exp-02-05".to_string())
+    }
+
+    /// Create a new client with the specified API key and model
+    pub fn with_model(api_key: impl Into<String>, model: String) -> Self {
+        Self::from_static_key(StaticKey::new(api_key), model)
+    }
+
+    /// Create a new client from a key that is already kept secret
+    ///
+    /// The key is never copied into a plain `String`, so it can't end up in
+    /// logs or error messages.
+    pub fn from_secret(api_key: SecretString) -> Self {
+        Self::from_static_key(StaticKey::from(api_key), DEFAULT_MODEL.to_string())
+    }
+
+    fn from_static_key(api_key: StaticKey, model: String) -> Self {
+        let client = GeminiClient::new(api_key, model);
+        Self {
+            client: Arc::new(client),
+            #[cfg(feature = "prompts")]
+            prompts: None,
+        }
+    }
+
+    /// Configure a client with non-default options
+    pub fn builder(api_key: impl Into<String>) -> GeminiBuilder {
+        GeminiBuilder::new(StaticKey::new(api_key))
+    }
+
+    /// Configure a client with non-default options from a secret key
+    pub fn builder_from_secret(api_key: SecretString) -> GeminiBuilder {
+        GeminiBuilder::new(StaticKey::from(api_key))
+    }
+
+    /// Configure a client whose API key is looked up for every request
+    ///
+    /// Use [`EnvKey`](crate::EnvKey) or [`FileKey`](crate::FileKey) to pick
+    /// up rotated keys without restarting the process.
+    pub fn builder_with_key_provider(provider: impl ApiKeyProvider + 'static) -> GeminiBuilder {
+        GeminiBuilder::new(provider)
+    }
+
+    /// Start building a content generation request
+    pub fn generate_content(&self) -> ContentBuilder {
+        ContentBuilder::new(self.client.clone())
+    }
+
+    /// Send a request built elsewhere, e.g. with a
+    /// [`GenerateContentRequestBuilder`]
+    pub async fn execute(
+        &self,
+        request: impl Into<GenerateContentRequest>,
+    ) -> Result<GenerationResponse> {
+        self.generate_content()
+            .with_request(request)
+            .execute()
+            .await
+    }
+
+    /// Send a request built elsewhere with streaming
+    #[cfg(feature = "streaming")]
+    pub async fn execute_stream(
+        &self,
+        request: impl Into<GenerateContentRequest>,
+    ) -> Result<GenerationStream> {
+        self.generate_content()
+            .with_request(request)
+            .execute_stream()
+            .await
+    }
+
+    /// Ask a single question and return the text of the answer
+    pub async fn ask(&self, question: impl Into<String>) -> Result<String> {
+        let response = self
+            .generate_content()
+            .with_user_message(question)
+            .execute()
+            .await?;
+        Ok(response.text().into_owned())
+    }
+
+    /// Ask a single question with a system prompt and return the text of the answer
+    pub async fn ask_with_system(
+        &self,
+        system: impl Into<String>,
+        question: impl Into<String>,
+    ) -> Result<String> {
+        let response = self
+            .generate_content()
+            .with_system_prompt(system)
+            .with_user_message(question)
+            .execute()
+            .await?;
+        Ok(response.text().into_owned())
+    }
+}
+
+/// Builder for a [`Gemini`] client with non-default options
+///
+/// Created with [`Gemini::builder`].
+#[derive(Debug, Clone)]
+pub struct GeminiBuilder {
+    api_key: Arc<dyn ApiKeyProvider>,
+    model: String,
+    api_version: ApiVersion,
+    #[cfg(feature = "gzip")]
+    request_compression: bool,
+    #[cfg(feature = "gzip")]
+    response_decompression: bool,
+    max_in_flight: Option<usize>,
+    priority: Priority,
+    connection: ConnectionSettings,
+    prompt_guard: Option<PromptGuard>,
+    response_cache: Option<ResponseCache>,
+    fast_model: String,
+    #[cfg(feature = "streaming")]
+    recorder: Option<StreamRecorder>,
+    #[cfg(feature = "tracing")]
+    redactor: Option<Redactor>,
+}
+
+/// Connection pool and keep-alive settings, left to reqwest's defaults
+/// unless set
+#[derive(Debug, Clone, Default)]
+struct ConnectionSettings {
+    http2_keep_alive_interval: Option<Duration>,
+    http2_keep_alive_timeout: Option<Duration>,
+    http2_keep_alive_while_idle: bool,
+    pool_idle_timeout: Option<Option<Duration>>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    /// Addresses to connect to instead of resolving the host
+    dns_overrides: BTreeMap<String, Vec<SocketAddr>>,
+}
+
+impl ConnectionSettings {
+    fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(self.http2_keep_alive_while_idle);
+        }
+        if let Some(timeout) = self.http2_keep_alive_timeout {
+            builder = builder.http2_keep_alive_timeout(timeout);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        for (host, addrs) in &self.dns_overrides {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+        builder
+    }
+}
+
+impl GeminiBuilder {
+    /// Create a builder using the default model
+    fn new(api_key: impl ApiKeyProvider + 'static) -> Self {
+        Self {
+            api_key: Arc::new(api_key),
+            model: DEFAULT_MODEL.to_string(),
+            api_version: ApiVersion::default(),
+            #[cfg(feature = "gzip")]
+            request_compression: false,
+            #[cfg(feature = "gzip")]
+            response_decompression: true,
+            max_in_flight: None,
+            priority: Priority::default(),
+            connection: ConnectionSettings::default(),
+            prompt_guard: None,
+            response_cache: None,
+            fast_model: DEFAULT_FAST_MODEL.to_string(),
+            #[cfg(feature = "streaming")]
+            recorder: None,
+            #[cfg(feature = "tracing")]
+            redactor: None,
+        }
+    }
+
+    /// Set the model, e.g. `gemini-2.0-flash` or `models/gemini-2.0-flash`
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model_path(model.into());
+        self
+    }
+
+    /// Set the API version used by all requests (default: `v1beta`)
+    pub fn with_api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Limit the number of API calls in flight at once
+    ///
+    /// Further calls wait in a queue, and free slots go to waiting
+    /// [`Priority::Interactive`] calls before [`Priority::Background`] ones,
+    /// so batch jobs sharing the client cannot starve a chat UI. The limit
+    /// is shared by every request made through the client, including
+    /// streams, which hold their slot until dropped.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Set the priority of requests that don't set their own
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Ping HTTP/2 connections at an interval to keep them open
+    ///
+    /// Load balancers and NAT gateways drop connections that stay quiet
+    /// for too long, which surfaces as failed requests and a wave of
+    /// reconnects. With `while_idle` connections without open streams are
+    /// pinged too, so pooled connections survive pauses between requests.
+    /// Off by default.
+    pub fn with_http2_keep_alive(mut self, interval: Duration, while_idle: bool) -> Self {
+        self.connection.http2_keep_alive_interval = Some(interval);
+        self.connection.http2_keep_alive_while_idle = while_idle;
+        self
+    }
+
+    /// Close an HTTP/2 connection whose keep-alive ping is not answered in
+    /// time (default: 20 seconds)
+    pub fn with_http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.connection.http2_keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Close pooled connections that stay unused for this long (default:
+    /// 90 seconds); `None` keeps them open
+    pub fn with_pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connection.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most this many unused connections open (default: no limit)
+    pub fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.connection.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Enable TCP keep-alive probes at an interval (default: off)
+    pub fn with_tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.connection.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Connect to `host` at these addresses instead of looking it up in DNS
+    ///
+    /// For networks whose egress rules only allow approved IPs, e.g. to pin
+    /// `generativelanguage.googleapis.com`. Addresses are tried in order;
+    /// their ports are ignored in favor of the URL's, and TLS still checks
+    /// the certificate against `host`. An empty list removes the override.
+    pub fn with_dns_override(mut self, host: impl Into<String>, addrs: &[SocketAddr]) -> Self {
+        let host = host.into();
+        if addrs.is_empty() {
+            self.connection.dns_overrides.remove(&host);
+        } else {
+            self.connection.dns_overrides.insert(host, addrs.to_vec());
+        }
+        self
+    }
+
+    /// Check every prompt against a guard before it is sent
+    pub fn with_prompt_guard(mut self, guard: PromptGuard) -> Self {
+        self.prompt_guard = Some(guard);
+        self
+    }
+
+    /// Answer repeated prompts from a cache instead of calling the API
+    pub fn with_response_cache(mut self, cache: ResponseCache) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// Model for requests whose latency budget the client's model misses
+    /// (default: `gemini-2.0-flash-lite`)
+    ///
+    /// See `ContentBuilder::with_latency_budget`.
+    pub fn with_fast_model(mut self, model: impl Into<String>) -> Self {
+        self.fast_model = model_path(model.into());
+        self
+    }
+
+    /// Record every streamed response to a transcript file
+    #[cfg(feature = "streaming")]
+    pub fn with_stream_recorder(mut self, recorder: StreamRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Gzip request bodies of 1 KiB or more
+    ///
+    /// Worthwhile for requests with large inline files. Off by default;
+    /// if the server rejects a compressed body with 415 Unsupported Media
+    /// Type the request is resent uncompressed.
+    #[cfg(feature = "gzip")]
+    pub fn with_request_compression(mut self, enabled: bool) -> Self {
+        self.request_compression = enabled;
+        self
+    }
+
+    /// Ask for gzip responses and decompress them transparently (default: on)
+    #[cfg(feature = "gzip")]
+    pub fn with_response_decompression(mut self, enabled: bool) -> Self {
+        self.response_decompression = enabled;
+        self
+    }
+
+    /// Redact request bodies in the `tracing` output of this client
+    ///
+    /// Request bodies are logged at `TRACE` level inside the
+    /// `gemini.request` span. The request sent to the API is unaffected.
+    #[cfg(feature = "tracing")]
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Create the client
+    ///
+    /// Fails if the HTTP client cannot be initialized, e.g. when the TLS
+    /// backend is unavailable.
+    pub fn build(self) -> Result<Gemini> {
+        let http_client = self.connection.apply(Client::builder());
+        #[cfg(feature = "gzip")]
+        let http_client = http_client.gzip(self.response_decompression);
+
+        let client = GeminiClient {
+            http_client: http_client.build()?,
+            api_key: self.api_key,
+            model: self.model,
+            api_version: self.api_version,
+            base_url: BASE_URL.to_string(),
+            capabilities: Arc::default(),
+            queue: self
+                .max_in_flight
+                .map(|max| Arc::new(RequestQueue::new(max))),
+            #[cfg(feature = "pool")]
+            rate_limit: None,
+            #[cfg(feature = "pool")]
+            usage: None,
+            priority: self.priority,
+            metadata: BTreeMap::new(),
+            #[cfg(feature = "gzip")]
+            request_compression: self.request_compression,
+            prompt_guard: self.prompt_guard.map(Arc::new),
+            response_cache: self.response_cache,
+            latency: Arc::default(),
+            fast_model: self.fast_model,
+            #[cfg(feature = "streaming")]
+            recorder: self.recorder,
+            #[cfg(feature = "tracing")]
+            redactor: self.redactor,
+        };
+        Ok(Gemini {
+            client: Arc::new(client),
+            #[cfg(feature = "prompts")]
+            prompts: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures,
+        mock::{MockGemini, MockReply},
+        tools::FunctionParameters,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn truncated_json_is_retried_once_without_calling_tools_again() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Response(fixtures::function_call(
+            "get_order",
+            serde_json::json!({"id": 7}),
+        )))
+        .push(MockReply::Response(fixtures::max_tokens(
+            "{\"items\": [\"tea\", ",
+        )))
+        .push_text("{\"items\": [\"tea\", \"scones\"]}");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let registry = FunctionRegistry::new().register(
+            FunctionDeclaration::new(
+                "get_order",
+                "Look up an order",
+                FunctionParameters::object(),
+            ),
+            move |_call| {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Ok(serde_json::json!({"items": ["tea", "scones"]}))
+                }
+            },
+        );
+
+        let response = mock
+            .client()
+            .generate_content()
+            .with_model("gemini-3-flash-preview")
+            .with_user_message("What is in order 7?")
+            .with_function_registry(registry)
+            .with_response_mime_type("application/json")
+            .with_max_output_tokens(100)
+            .with_max_tokens_escalation(1000)
+            .execute_detailed()
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            response.max_tokens_escalation,
+            Some(MaxTokensEscalation {
+                from: Some(100),
+                to: 200
+            })
+        );
+        assert_eq!(response.inner.text(), "{\"items\": [\"tea\", \"scones\"]}");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        let [truncated, retried] = [&requests[1].body, &requests[2].body];
+        assert_eq!(truncated["generationConfig"]["maxOutputTokens"], 100);
+        assert_eq!(retried["generationConfig"]["maxOutputTokens"], 200);
+        // The retry resends the conversation with the function's answer
+        assert_eq!(retried["contents"], truncated["contents"]);
+        assert!(retried["contents"][2]["parts"][0]["functionResponse"].is_object());
+    }
+
+    #[cfg(feature = "streaming")]
+    /// A registry with a `get_order` function answering with fixed items
+    fn order_registry() -> FunctionRegistry {
+        FunctionRegistry::new().register(
+            FunctionDeclaration::new(
+                "get_order",
+                "Look up an order",
+                FunctionParameters::object(),
+            ),
+            |_call| async { Ok(serde_json::json!({"items": ["tea", "scones"]})) },
+        )
+    }
+
+    #[cfg(feature = "streaming")]
+    #[tokio::test]
+    async fn streamed_function_loop_coalesces_text() {
+        let mock = MockGemini::start().await.unwrap().with_stream_chunks(6);
+        mock.push(MockReply::Response(fixtures::function_call(
+            "get_order",
+            serde_json::json!({"id": 7}),
+        )))
+        .push_text("Order 7 has tea and scones in it");
+
+        let deltas: Vec<PartDelta> = mock
+            .client()
+            .generate_content()
+            .with_user_message("What is in order 7?")
+            .with_function_registry(order_registry())
+            .with_chunk_coalescing(ChunkCoalescing::min_chars(1000))
+            .execute_stream_parts()
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let texts: Vec<String> = deltas
+            .iter()
+            .filter_map(|delta| match delta {
+                PartDelta::TextDelta(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        // The first text is passed on at once, the rest with the final chunk
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts.concat(), "Order 7 has tea and scones in it");
+        assert!(matches!(deltas.last(), Some(PartDelta::Finish { .. })));
+    }
+
+    #[cfg(feature = "streaming")]
+    #[tokio::test]
+    async fn streamed_function_loop_reports_first_token() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Response(fixtures::function_call(
+            "get_order",
+            serde_json::json!({"id": 7}),
+        )))
+        .push_text("Order 7 has tea and scones in it");
+
+        let reported = Arc::new(AtomicUsize::new(0));
+        let counter = reported.clone();
+        let deltas = mock
+            .client()
+            .generate_content()
+            .with_user_message("What is in order 7?")
+            .with_function_registry(order_registry())
+            .with_first_token_callback(move |_latency| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .execute_stream_parts()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(deltas.iter().all(Result::is_ok));
+        // Only the first text chunk of the second turn counts
+        assert_eq!(reported.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "streaming")]
+    /// Two chunks of a `streamGenerateContent?alt=sse` reply as the API sends them
+    const SSE_PAYLOAD: &str = concat!(
+        "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"Grüße aus \"}],",
+        "\"role\": \"model\"},\"index\": 0}],\"usageMetadata\": {\"promptTokenCount\": 5,",
+        "\"totalTokenCount\": 5},\"modelVersion\": \"gemini-2.0-flash\"}\r\n\r\n",
+        "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"München 🥨\"}],",
+        "\"role\": \"model\"},\"finishReason\": \"STOP\",\"index\": 0}],\"usageMetadata\": ",
+        "{\"promptTokenCount\": 5,\"candidatesTokenCount\": 6,\"totalTokenCount\": 11},",
+        "\"modelVersion\": \"gemini-2.0-flash\"}\r\n\r\n",
+    );
+
+    #[cfg(feature = "streaming")]
+    /// Feed chunks to a splitter and parse the events it completes
+    fn parse_chunked(chunks: &[&[u8]]) -> Vec<GenerationResponse> {
+        let mut lines = SseLines::default();
+        chunks
+            .iter()
+            .flat_map(|chunk| lines.push(chunk))
+            .map(|data| serde_json::from_str(&data).unwrap())
+            .collect()
+    }
+
+    #[cfg(feature = "streaming")]
+    fn texts(responses: &[GenerationResponse]) -> Vec<String> {
+        responses.iter().map(|r| r.text().into_owned()).collect()
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn sse_lines_in_one_chunk() {
+        let responses = parse_chunked(&[SSE_PAYLOAD.as_bytes()]);
+        assert_eq!(texts(&responses), ["Grüße aus ", "München 🥨"]);
+        assert_eq!(
+            responses[1].candidates[0].finish_reason.as_deref(),
+            Some("STOP")
+        );
+        // camelCase usage metadata is read from every chunk
+        let first = responses[0].usage_metadata.as_ref().unwrap();
+        assert_eq!(first.prompt_token_count, 5);
+        let last = responses[1].usage_metadata.as_ref().unwrap();
+        assert_eq!(last.prompt_token_count, 5);
+        assert_eq!(last.candidates_token_count, 6);
+        assert_eq!(last.total_token_count, 11);
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn sse_line_split_across_chunks() {
+        let bytes = SSE_PAYLOAD.as_bytes();
+        let split = SSE_PAYLOAD.find("\"role\"").unwrap();
+        let responses = parse_chunked(&[&bytes[..split], &bytes[split..]]);
+        assert_eq!(texts(&responses), ["Grüße aus ", "München 🥨"]);
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn sse_multibyte_character_split_across_chunks() {
+        let bytes = SSE_PAYLOAD.as_bytes();
+        // Inside the four bytes of the pretzel emoji, and inside the `ü`
+        let pretzel = SSE_PAYLOAD.find('🥨').unwrap() + 2;
+        let umlaut = SSE_PAYLOAD.find('ü').unwrap() + 1;
+        let responses =
+            parse_chunked(&[&bytes[..umlaut], &bytes[umlaut..pretzel], &bytes[pretzel..]]);
+        assert_eq!(texts(&responses), ["Grüße aus ", "München 🥨"]);
+
+        // One byte at a time
+        let bytes: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(texts(&parse_chunked(&bytes)), ["Grüße aus ", "München 🥨"]);
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn sse_crlf_and_blank_lines() {
+        let payload = format!(": keep-alive\r\n\r\n\r\n{SSE_PAYLOAD}data: [DONE]\r\n\r\n");
+        let bytes = payload.as_bytes();
+        // Split between the `\r` and the `\n` of a line ending
+        let split = payload.find("}\r\n").unwrap() + 2;
+        let responses = parse_chunked(&[&bytes[..split], &bytes[split..]]);
+        assert_eq!(texts(&responses), ["Grüße aus ", "München 🥨"]);
+
+        let mut lines = SseLines::default();
+        assert!(lines.push(b"data: {\"candidates\": []}").is_empty());
+        assert_eq!(lines.push(b"\n"), ["{\"candidates\": []}"]);
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn sse_last_line_without_newline() {
+        let payload = SSE_PAYLOAD.trim_end();
+        let mut lines = SseLines::default();
+        let mut data = lines.push(payload.as_bytes());
+        assert_eq!(data.len(), 1);
+        data.extend(lines.finish());
+        let responses: Vec<GenerationResponse> = data
+            .iter()
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(texts(&responses), ["Grüße aus ", "München 🥨"]);
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn stitch_keeps_closing_fence() {
+        let mut text = "```rust\nfn a() {\n".to_string();
+        stitch(&mut text, "}\n```\nDone.");
+        assert_eq!(text, "```rust\nfn a() {\n}\n```\nDone.");
+
+        // A bare fence right at the start closes the block too
+        let mut text = "```rust\nfn a() {}\n".to_string();
+        stitch(&mut text, "```\nDone.");
+        assert_eq!(text, "```rust\nfn a() {}\n```\nDone.");
+    }
+
+    #[test]
+    fn stitch_drops_reopened_fence() {
+        let mut text = "```rust\nfn a() {\n".to_string();
+        stitch(&mut text, "```rust\n}\n```");
+        assert_eq!(text, "```rust\nfn a() {\n}\n```");
+
+        // Without a newline after it, nothing of the reopened fence is kept
+        let mut text = "```rust\nfn a() {\n".to_string();
+        stitch(&mut text, "```rust");
+        assert_eq!(text, "```rust\nfn a() {\n");
+
+        // A fence for another language is not a reopen
+        let mut text = "```rust\nfn a() {}\n".to_string();
+        stitch(&mut text, "```python\nprint()");
+        assert_eq!(text, "```rust\nfn a() {}\n```python\nprint()");
+
+        // Outside a block the continuation is appended as is
+        let mut text = "Some code:\n".to_string();
+        stitch(&mut text, "```rust\nfn a() {}\n```");
+        assert_eq!(text, "Some code:\n```rust\nfn a() {}\n```");
+    }
+
+    #[tokio::test]
+    async fn api_key_is_sent_in_a_header() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push_text("Hi");
+        let client = mock.client();
+        client
+            .generate_content()
+            .with_user_message("Hello")
+            .execute()
+            .await
+            .unwrap();
+        let _ = client.list_models().await;
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert_eq!(request.api_key.as_deref(), Some("mock-api-key"));
+            assert!(!request.query.contains("key="), "{}", request.query);
+        }
+    }
+
+    #[tokio::test]
+    async fn recorded_text_keeps_thought_signature() {
+        let response: GenerationResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [{"text": "Paris.", "thoughtSignature": "X"}]
+                }
+            }]
+        }))
+        .unwrap();
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Response(response))
+            .push_text("About 2 million.");
+
+        let client = mock.client();
+        let mut session = crate::ChatSession::new("geography");
+        session.send(&client, "Capital of France?").await.unwrap();
+        session.send(&client, "Population?").await.unwrap();
+
+        let requests = mock.requests();
+        let model_turn = &requests[1].body["contents"][1];
+        assert_eq!(model_turn["role"], "model");
+        assert_eq!(model_turn["parts"][0]["text"], "Paris.");
+        assert_eq!(model_turn["parts"][0]["thoughtSignature"], "X");
     }
 }
@@ -0,0 +1,4 @@
+//! Conversions between Gemini types and other providers' wire formats
+
+#[cfg(feature = "openai-compat")]
+pub mod openai;
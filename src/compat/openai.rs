@@ -0,0 +1,887 @@
+//! OpenAI chat-completions compatible request and response types
+//!
+//! These mirror the subset of the chat-completions format needed to move
+//! conversations, tool calls and structured output settings between the two
+//! APIs, in either direction. Gemini has no tool call ids, so ids are
+//! synthesized (`call_0`, `call_1`, ...) when converting to OpenAI and tool
+//! results are matched back to function names by id when converting from it.
+
+use crate::{
+    models::{
+        Blob, Candidate, Content, FunctionCallingConfig, FunctionCallingMode,
+        GenerateContentRequest, GenerationConfig, GenerationResponse, Part, Role, ToolConfig,
+        UsageMetadata,
+    },
+    tools::{FunctionCall, FunctionDeclaration, FunctionResponse, PropertyDetails, Tool},
+    Error, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A chat-completions request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    /// The model name
+    pub model: String,
+    /// The conversation so far
+    pub messages: Vec<ChatMessage>,
+    /// Sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Maximum number of tokens to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    /// Number of choices to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<i32>,
+    /// Stop sequences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ChatTool>>,
+    /// `"none"`, `"auto"`, `"required"` or `{"type": "function", "function": {"name": ...}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    /// Structured output settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// Role of a chat message
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    /// System prompt
+    System,
+    /// Message from the user
+    User,
+    /// Message from the model
+    Assistant,
+    /// Result of a tool call
+    Tool,
+}
+
+/// A message in a chat-completions conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// The author of the message
+    pub role: ChatRole,
+    /// The message content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<ChatContent>,
+    /// Tool calls made by the assistant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// The tool call this message answers (tool messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Optional participant name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: ChatRole, content: Option<ChatContent>) -> Self {
+        Self {
+            role,
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+}
+
+/// Content of a chat message: a plain string or a list of typed parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+    /// Plain text
+    Text(String),
+    /// Multimodal content parts
+    Parts(Vec<ChatContentPart>),
+}
+
+/// A multimodal chat content part
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    /// Text part
+    Text {
+        /// The text
+        text: String,
+    },
+    /// Image part
+    ImageUrl {
+        /// The image location
+        image_url: ImageUrl,
+    },
+}
+
+/// Image location; only `data:` URLs can be converted to Gemini inline data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// An `https:` or `data:<mime>;base64,<data>` URL
+    pub url: String,
+}
+
+/// A tool call made by the assistant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Identifier referenced by the matching tool message
+    pub id: String,
+    /// Always `"function"`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function being called
+    pub function: ToolCallFunction,
+}
+
+/// Function name and JSON-encoded arguments of a tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallFunction {
+    /// The function name
+    pub name: String,
+    /// The arguments as a JSON string
+    pub arguments: String,
+}
+
+/// A tool definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTool {
+    /// Always `"function"`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function definition
+    pub function: FunctionDefinition,
+}
+
+/// A function definition with a JSON schema for its parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    /// The function name
+    pub name: String,
+    /// What the function does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+}
+
+/// Structured output settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Free-form text
+    Text,
+    /// Any JSON object
+    JsonObject,
+    /// JSON matching a schema
+    JsonSchema {
+        /// The schema definition
+        json_schema: JsonSchemaFormat,
+    },
+}
+
+/// A named JSON schema for structured output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    /// Name of the schema
+    pub name: String,
+    /// The JSON schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+    /// Whether the schema must be followed exactly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// A chat-completions response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionResponse {
+    /// Response identifier
+    pub id: String,
+    /// Always `"chat.completion"`
+    pub object: String,
+    /// Unix timestamp (seconds) of creation
+    pub created: u64,
+    /// The model that generated the response
+    pub model: String,
+    /// The generated choices
+    pub choices: Vec<ChatChoice>,
+    /// Token usage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ChatUsage>,
+}
+
+/// A generated choice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatChoice {
+    /// Index of the choice
+    pub index: u32,
+    /// The generated message
+    pub message: ChatMessage,
+    /// Why generation stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+}
+
+/// Token usage of a chat-completions call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatUsage {
+    /// Tokens in the prompt
+    pub prompt_tokens: i32,
+    /// Tokens in the generated choices
+    pub completion_tokens: i32,
+    /// Total tokens
+    pub total_tokens: i32,
+}
+
+impl ChatCompletionRequest {
+    /// Convert a Gemini request into a chat-completions request for `model`
+    ///
+    /// Fails for what chat-completions cannot express, such as Files API
+    /// uploads, and for function responses that follow no call.
+    pub fn from_gemini(request: &GenerateContentRequest, model: impl Into<String>) -> Result<Self> {
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system_instruction {
+            messages.push(ChatMessage::new(
+                ChatRole::System,
+                Some(ChatContent::Text(joined_text(system))),
+            ));
+        }
+
+        let mut next_id = 0;
+        let mut pending_ids: HashMap<String, VecDeque<String>> = HashMap::new();
+        for content in &request.contents {
+            let role = match content.role {
+                Some(Role::Model) => ChatRole::Assistant,
+                _ => ChatRole::User,
+            };
+            let mut parts = Vec::new();
+            let mut tool_calls = Vec::new();
+            let mut tool_messages = Vec::new();
+            for part in &content.parts {
+                match part {
+                    Part::Text { text, .. } => {
+                        parts.push(ChatContentPart::Text { text: text.clone() })
+                    }
+                    // Chat-completions has no representation for thought summaries
+                    Part::Thought { .. } => {}
+                    Part::FileData { file_data, .. } => {
+                        return Err(Error::RequestError(format!(
+                            "file {} has no chat-completions equivalent, send it inline instead",
+                            file_data.file_uri
+                        )))
+                    }
+                    Part::Other(other) => {
+                        return Err(Error::RequestError(format!(
+                            "part with {} has no chat-completions equivalent",
+                            other.keys().cloned().collect::<Vec<_>>().join(", ")
+                        )))
+                    }
+                    Part::InlineData { inline_data, .. } => parts.push(ChatContentPart::ImageUrl {
+                        image_url: ImageUrl {
+                            url: format!(
+                                "data:{};base64,{}",
+                                inline_data.mime_type,
+                                inline_data.data()
+                            ),
+                        },
+                    }),
+                    Part::FunctionCall { function_call, .. } => {
+                        let id = format!("call_{}", next_id);
+                        next_id += 1;
+                        pending_ids
+                            .entry(function_call.name.clone())
+                            .or_default()
+                            .push_back(id.clone());
+                        tool_calls.push(ToolCall {
+                            id,
+                            kind: "function".to_string(),
+                            function: ToolCallFunction {
+                                name: function_call.name.clone(),
+                                arguments: function_call.args.to_string(),
+                            },
+                        });
+                    }
+                    Part::FunctionResponse { function_response } => {
+                        let id = pending_ids
+                            .get_mut(&function_response.name)
+                            .and_then(|ids| ids.pop_front())
+                            .ok_or_else(|| {
+                                Error::RequestError(format!(
+                                    "function response for {} follows no call to it",
+                                    function_response.name
+                                ))
+                            })?;
+                        let output = function_response
+                            .response
+                            .as_ref()
+                            .map(|r| r.to_string())
+                            .unwrap_or_default();
+                        let mut message =
+                            ChatMessage::new(ChatRole::Tool, Some(ChatContent::Text(output)));
+                        message.tool_call_id = Some(id);
+                        message.name = Some(function_response.name.clone());
+                        tool_messages.push(message);
+                    }
+                }
+            }
+
+            // Tool messages have to directly follow the assistant message
+            // with their calls, so they come before text of the same turn
+            messages.append(&mut tool_messages);
+            if !parts.is_empty() || !tool_calls.is_empty() {
+                let content = if parts
+                    .iter()
+                    .all(|p| matches!(p, ChatContentPart::Text { .. }))
+                {
+                    let text: String = parts
+                        .iter()
+                        .filter_map(|p| match p {
+                            ChatContentPart::Text { text } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    (!text.is_empty()).then_some(ChatContent::Text(text))
+                } else {
+                    Some(ChatContent::Parts(parts))
+                };
+                let mut message = ChatMessage::new(role, content);
+                if !tool_calls.is_empty() {
+                    message.tool_calls = Some(tool_calls);
+                }
+                messages.push(message);
+            }
+        }
+
+        let mut tools = Vec::new();
+        for tool in request.tools.iter().flatten() {
+            match tool {
+                Tool::Function {
+                    function_declarations,
+                } => {
+                    for declaration in function_declarations {
+                        tools.push(serde_json::from_value(declaration.to_openai_schema())?);
+                    }
+                }
+                Tool::GoogleSearch { .. } => {
+                    return Err(Error::RequestError(
+                        "the google_search tool has no chat-completions equivalent".to_string(),
+                    ))
+                }
+            }
+        }
+
+        let tool_choice = request
+            .tool_config
+            .as_ref()
+            .and_then(|c| c.function_calling_config.as_ref())
+            .map(|c| match (&c.mode, c.allowed_function_names.as_deref()) {
+                (FunctionCallingMode::Any, Some([name])) => {
+                    serde_json::json!({ "type": "function", "function": { "name": name } })
+                }
+                (FunctionCallingMode::Auto, _) => "auto".into(),
+                (FunctionCallingMode::Any, _) => "required".into(),
+                (FunctionCallingMode::None, _) => "none".into(),
+            });
+
+        let config = request.generation_config.as_ref();
+        let response_format =
+            config.and_then(
+                |c| match (c.response_mime_type.as_deref(), &c.response_schema) {
+                    (_, Some(schema)) => Some(ResponseFormat::JsonSchema {
+                        json_schema: JsonSchemaFormat {
+                            name: "response".to_string(),
+                            schema: Some(to_json_schema(schema.clone())),
+                            strict: None,
+                        },
+                    }),
+                    (Some("application/json"), None) => Some(ResponseFormat::JsonObject),
+                    _ => None,
+                },
+            );
+
+        Ok(Self {
+            model: model.into(),
+            messages,
+            temperature: config.and_then(|c| c.temperature),
+            top_p: config.and_then(|c| c.top_p),
+            max_tokens: config.and_then(|c| c.max_output_tokens),
+            n: config.and_then(|c| c.candidate_count),
+            stop: config.and_then(|c| c.stop_sequences.clone()),
+            tools: (!tools.is_empty()).then_some(tools),
+            tool_choice,
+            response_format,
+        })
+    }
+
+    /// Convert into a Gemini request
+    ///
+    /// The model name is not part of a Gemini request body; use
+    /// [`ChatCompletionRequest::model`] to pick the client.
+    pub fn to_gemini(&self) -> Result<GenerateContentRequest> {
+        let mut system_texts = Vec::new();
+        let mut contents: Vec<Content> = Vec::new();
+        let mut call_names: HashMap<&str, &str> = HashMap::new();
+
+        for message in &self.messages {
+            match message.role {
+                ChatRole::System => {
+                    if let Some(content) = &message.content {
+                        system_texts.push(text_of(content));
+                    }
+                }
+                ChatRole::User => {
+                    let parts = match &message.content {
+                        Some(content) => parts_of(content)?,
+                        None => Vec::new(),
+                    };
+                    contents.push(
+                        Content {
+                            parts,
+                            ..Default::default()
+                        }
+                        .with_role(Role::User),
+                    );
+                }
+                ChatRole::Assistant => {
+                    let mut parts = match &message.content {
+                        Some(content) => parts_of(content)?,
+                        None => Vec::new(),
+                    };
+                    for call in message.tool_calls.iter().flatten() {
+                        call_names.insert(&call.id, &call.function.name);
+                        let args = if call.function.arguments.trim().is_empty() {
+                            serde_json::Value::Object(Default::default())
+                        } else {
+                            serde_json::from_str(&call.function.arguments)?
+                        };
+                        parts.push(Part::FunctionCall {
+                            function_call: FunctionCall::new(&call.function.name, args),
+                            thought_signature: None,
+                        });
+                    }
+                    contents.push(
+                        Content {
+                            parts,
+                            ..Default::default()
+                        }
+                        .with_role(Role::Model),
+                    );
+                }
+                ChatRole::Tool => {
+                    let name = message
+                        .tool_call_id
+                        .as_deref()
+                        .and_then(|id| call_names.get(id).copied())
+                        .or(message.name.as_deref())
+                        .ok_or_else(|| {
+                            Error::RequestError(format!(
+                                "tool message {:?} does not match any tool call",
+                                message.tool_call_id
+                            ))
+                        })?;
+                    let output = message.content.as_ref().map(text_of).unwrap_or_default();
+                    let response = match serde_json::from_str::<serde_json::Value>(&output) {
+                        Ok(value @ serde_json::Value::Object(_)) => value,
+                        Ok(value) => serde_json::json!({ "content": value }),
+                        Err(_) => serde_json::json!({ "content": output }),
+                    };
+                    let part = Part::FunctionResponse {
+                        function_response: FunctionResponse::new(name, response),
+                    };
+                    // Consecutive tool results belong to the same user turn
+                    match contents.last_mut() {
+                        Some(last)
+                            if matches!(last.role, Some(Role::User))
+                                && last
+                                    .parts
+                                    .iter()
+                                    .all(|p| matches!(p, Part::FunctionResponse { .. })) =>
+                        {
+                            last.parts.push(part)
+                        }
+                        _ => contents.push(Content {
+                            parts: vec![part],
+                            role: Some(Role::User),
+                            ..Default::default()
+                        }),
+                    }
+                }
+            }
+        }
+
+        let declarations = self
+            .tools
+            .iter()
+            .flatten()
+            .map(|tool| FunctionDeclaration::from_openai_schema(serde_json::to_value(tool)?))
+            .collect::<Result<Vec<_>>>()?;
+
+        let function_calling_config = match &self.tool_choice {
+            None => None,
+            Some(serde_json::Value::String(choice)) => match choice.as_str() {
+                "auto" => Some(FunctionCallingConfig::new(FunctionCallingMode::Auto)),
+                "required" => Some(FunctionCallingConfig::new(FunctionCallingMode::Any)),
+                "none" => Some(FunctionCallingConfig::new(FunctionCallingMode::None)),
+                other => {
+                    return Err(Error::RequestError(format!(
+                        "unsupported tool_choice {:?}",
+                        other
+                    )))
+                }
+            },
+            Some(serde_json::Value::Object(choice)) => Some(FunctionCallingConfig {
+                mode: FunctionCallingMode::Any,
+                allowed_function_names: choice
+                    .get("function")
+                    .and_then(|f| f.get("name"))
+                    .and_then(|n| n.as_str())
+                    .map(|name| vec![name.to_string()]),
+            }),
+            Some(other) => {
+                return Err(Error::RequestError(format!(
+                    "unsupported tool_choice {}",
+                    other
+                )))
+            }
+        };
+
+        let (response_mime_type, response_schema) = match &self.response_format {
+            None | Some(ResponseFormat::Text) => (None, None),
+            Some(ResponseFormat::JsonObject) => (Some("application/json".to_string()), None),
+            Some(ResponseFormat::JsonSchema { json_schema }) => {
+                let schema = json_schema
+                    .schema
+                    .as_ref()
+                    .map(|schema| {
+                        let details = PropertyDetails::from_openai_schema(schema)?;
+                        Ok::<_, Error>(serde_json::to_value(details)?)
+                    })
+                    .transpose()?;
+                (Some("application/json".to_string()), schema)
+            }
+        };
+
+        let generation_config = GenerationConfig {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: None,
+            max_output_tokens: self.max_tokens,
+            candidate_count: self.n,
+            stop_sequences: self.stop.clone(),
+            response_mime_type,
+            response_schema,
+        };
+        let has_config = generation_config.temperature.is_some()
+            || generation_config.top_p.is_some()
+            || generation_config.max_output_tokens.is_some()
+            || generation_config.candidate_count.is_some()
+            || generation_config.stop_sequences.is_some()
+            || generation_config.response_mime_type.is_some();
+
+        Ok(GenerateContentRequest {
+            contents,
+            generation_config: has_config.then_some(generation_config),
+            safety_settings: None,
+            tools: (!declarations.is_empty()).then(|| vec![Tool::with_functions(declarations)]),
+            tool_config: function_calling_config.map(|config| ToolConfig {
+                function_calling_config: Some(config),
+            }),
+            system_instruction: (!system_texts.is_empty())
+                .then(|| Content::text(system_texts.join("\n\n"))),
+            labels: None,
+        })
+    }
+}
+
+impl ChatCompletionResponse {
+    /// Convert a Gemini response into a chat-completions response
+    pub fn from_gemini(response: &GenerationResponse, model: impl Into<String>) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut next_id = 0;
+        let choices = response
+            .candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let text = joined_text(&candidate.content);
+                let tool_calls = candidate
+                    .content
+                    .parts
+                    .iter()
+                    .filter_map(|p| match p {
+                        Part::FunctionCall { function_call, .. } => Some(function_call),
+                        _ => None,
+                    })
+                    .map(|call| {
+                        let id = format!("call_{}", next_id);
+                        next_id += 1;
+                        ToolCall {
+                            id,
+                            kind: "function".to_string(),
+                            function: ToolCallFunction {
+                                name: call.name.clone(),
+                                arguments: call.args.to_string(),
+                            },
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let finish_reason = if tool_calls.is_empty() {
+                    candidate.finish_reason.as_deref().map(openai_finish_reason)
+                } else {
+                    Some("tool_calls".to_string())
+                };
+                let mut message = ChatMessage::new(
+                    ChatRole::Assistant,
+                    (!text.is_empty()).then_some(ChatContent::Text(text)),
+                );
+                if !tool_calls.is_empty() {
+                    message.tool_calls = Some(tool_calls);
+                }
+                ChatChoice {
+                    index: index as u32,
+                    message,
+                    finish_reason,
+                }
+            })
+            .collect();
+
+        Self {
+            id: response
+                .response_id
+                .clone()
+                .unwrap_or_else(|| format!("chatcmpl-{}", created)),
+            object: "chat.completion".to_string(),
+            created,
+            model: model.into(),
+            choices,
+            usage: response.usage_metadata.as_ref().map(|u| ChatUsage {
+                prompt_tokens: u.prompt_token_count,
+                completion_tokens: u.candidates_token_count,
+                total_tokens: u.total_token_count,
+            }),
+        }
+    }
+
+    /// Convert into a Gemini response
+    pub fn to_gemini(&self) -> Result<GenerationResponse> {
+        let candidates = self
+            .choices
+            .iter()
+            .map(|choice| {
+                let mut parts = match &choice.message.content {
+                    Some(content) => parts_of(content)?,
+                    None => Vec::new(),
+                };
+                for call in choice.message.tool_calls.iter().flatten() {
+                    let args = if call.function.arguments.trim().is_empty() {
+                        serde_json::Value::Object(Default::default())
+                    } else {
+                        serde_json::from_str(&call.function.arguments)?
+                    };
+                    parts.push(Part::FunctionCall {
+                        function_call: FunctionCall::new(&call.function.name, args),
+                        thought_signature: None,
+                    });
+                }
+                Ok(Candidate {
+                    content: Content {
+                        parts,
+                        ..Default::default()
+                    }
+                    .with_role(Role::Model),
+                    safety_ratings: None,
+                    citation_metadata: None,
+                    grounding_metadata: None,
+                    finish_reason: choice.finish_reason.as_deref().map(gemini_finish_reason),
+                    finish_message: None,
+                    usage_metadata: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GenerationResponse {
+            candidates,
+            prompt_feedback: None,
+            response_id: Some(self.id.clone()),
+            usage_metadata: self.usage.as_ref().map(|u| UsageMetadata {
+                prompt_token_count: u.prompt_tokens,
+                candidates_token_count: u.completion_tokens,
+                total_token_count: u.total_tokens,
+            }),
+        })
+    }
+}
+
+/// Concatenate the text parts of a content
+fn joined_text(content: &Content) -> String {
+    content
+        .parts
+        .iter()
+        .filter_map(|p| match p {
+            Part::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Concatenate the text of a chat content, ignoring images
+fn text_of(content: &ChatContent) -> String {
+    match content {
+        ChatContent::Text(text) => text.clone(),
+        ChatContent::Parts(parts) => parts
+            .iter()
+            .filter_map(|p| match p {
+                ChatContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+/// Convert chat content into Gemini parts
+fn parts_of(content: &ChatContent) -> Result<Vec<Part>> {
+    match content {
+        ChatContent::Text(text) => Ok(vec![Part::Text {
+            text: text.clone(),
+            thought_signature: None,
+        }]),
+        ChatContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ChatContentPart::Text { text } => Ok(Part::Text {
+                    text: text.clone(),
+                    thought_signature: None,
+                }),
+                ChatContentPart::ImageUrl { image_url } => {
+                    let (mime_type, data) = image_url
+                        .url
+                        .strip_prefix("data:")
+                        .and_then(|rest| rest.split_once(";base64,"))
+                        .ok_or_else(|| {
+                            Error::RequestError(
+                                "only base64 data: image URLs can be converted to inline data"
+                                    .to_string(),
+                            )
+                        })?;
+                    Ok(Part::InlineData {
+                        inline_data: Blob::new(mime_type, data),
+                        video_metadata: None,
+                    })
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Lowercase the upper-case `type` names used by Gemini schemas
+fn to_json_schema(mut schema: serde_json::Value) -> serde_json::Value {
+    fn visit(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(obj) => {
+                for (key, child) in obj.iter_mut() {
+                    match child {
+                        serde_json::Value::String(s) if key == "type" => {
+                            *s = s.to_lowercase();
+                        }
+                        _ => visit(child),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(visit),
+            _ => {}
+        }
+    }
+    visit(&mut schema);
+    schema
+}
+
+fn openai_finish_reason(reason: &str) -> String {
+    match reason {
+        "STOP" => "stop",
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "content_filter",
+        other => return other.to_lowercase(),
+    }
+    .to_string()
+}
+
+fn gemini_finish_reason(reason: &str) -> String {
+    match reason {
+        "stop" | "tool_calls" | "function_call" => "STOP",
+        "length" => "MAX_TOKENS",
+        "content_filter" => "SAFETY",
+        other => return other.to_uppercase(),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(contents: serde_json::Value) -> GenerateContentRequest {
+        serde_json::from_value(json!({ "contents": contents })).unwrap()
+    }
+
+    #[test]
+    fn tool_messages_follow_their_calls() {
+        let request = request(json!([
+            { "role": "user", "parts": [{ "text": "What is in order 7?" }] },
+            { "role": "model", "parts": [{ "functionCall": { "name": "get_order", "args": { "id": 7 } } }] },
+            { "role": "user", "parts": [
+                { "text": "Answer briefly." },
+                { "functionResponse": { "name": "get_order", "response": { "items": ["tea"] } } }
+            ] }
+        ]));
+        let chat = ChatCompletionRequest::from_gemini(&request, "gpt-4o").unwrap();
+        let roles: Vec<_> = chat.messages.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            [
+                ChatRole::User,
+                ChatRole::Assistant,
+                ChatRole::Tool,
+                ChatRole::User
+            ]
+        );
+        assert_eq!(chat.messages[2].tool_call_id.as_deref(), Some("call_0"));
+    }
+
+    #[test]
+    fn unconvertible_parts_are_errors() {
+        let unanswered = request(json!([
+            { "role": "user", "parts": [
+                { "functionResponse": { "name": "get_order", "response": {} } }
+            ] }
+        ]));
+        assert!(ChatCompletionRequest::from_gemini(&unanswered, "gpt-4o").is_err());
+
+        let file = request(json!([
+            { "role": "user", "parts": [
+                { "fileData": { "mimeType": "application/pdf", "fileUri": "https://example.com/f" } }
+            ] }
+        ]));
+        assert!(ChatCompletionRequest::from_gemini(&file, "gpt-4o").is_err());
+
+        let other = request(json!([
+            { "role": "model", "parts": [{ "executableCode": { "code": "print(1)" } }] }
+        ]));
+        assert!(ChatCompletionRequest::from_gemini(&other, "gpt-4o").is_err());
+    }
+}
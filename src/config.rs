@@ -0,0 +1,150 @@
+use crate::{
+    client::{ApiVersion, ContentBuilder, Gemini},
+    models::{GenerationConfig, SafetySetting, ToolConfig},
+    Error, Result,
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize, Serializer};
+use std::path::Path;
+
+/// Environment variable read when a config file has no `api_key`
+const API_KEY_VAR: &str = "GEMINI_API_KEY";
+
+/// Client and request settings loaded from a config file
+///
+/// Keys may be written in snake_case or in the REST API's camelCase, and
+/// enum values in lowercase or as the API spells them:
+///
+/// ```toml
+/// model = "gemini-2.0-flash"
+/// api_version = "v1beta"
+/// system_instruction = "Answer in one paragraph."
+///
+/// [generation_config]
+/// temperature = 0.3
+/// max_output_tokens = 512
+///
+/// [[safety_settings]]
+/// category = "harassment"
+/// threshold = "block_only_high"
+///
+/// [tool_config.function_calling_config]
+/// mode = "auto"
+/// ```
+///
+/// The API key is best left out of the file; it is then read from
+/// `GEMINI_API_KEY` when the client is built.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// API key, falling back to the `GEMINI_API_KEY` environment variable
+    #[serde(
+        default,
+        serialize_with = "expose_key",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub api_key: Option<SecretString>,
+    /// Model to use instead of the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// API version to use instead of `v1beta`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<ApiVersion>,
+    /// Generation settings applied to every request
+    #[serde(
+        default,
+        alias = "generationConfig",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub generation_config: Option<GenerationConfig>,
+    /// Safety settings applied to every request
+    #[serde(
+        default,
+        alias = "safetySettings",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    /// Tool config applied to every request
+    #[serde(default, alias = "toolConfig", skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    /// System instruction applied to every request
+    #[serde(
+        default,
+        alias = "systemInstruction",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub system_instruction: Option<String>,
+}
+
+impl ClientConfig {
+    /// Load a config from a `.toml` or `.json` file
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| Error::ConfigError(format!("Cannot read {}: {}", path.display(), e)))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&source).map_err(|e| e.to_string()),
+            Some("json") => serde_json::from_str(&source).map_err(|e| e.to_string()),
+            _ => Err("expected a .toml or .json file".to_string()),
+        }
+        .map_err(|e| Error::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parse a config from TOML
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|e| Error::ConfigError(e.to_string()))
+    }
+
+    /// Serialize the config as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::ConfigError(e.to_string()))
+    }
+
+    /// Build a client with the configured key, model and API version
+    pub fn build(&self) -> Result<Gemini> {
+        let api_key = match &self.api_key {
+            Some(key) => key.clone(),
+            None => std::env::var(API_KEY_VAR)
+                .map_err(|_| Error::MissingApiKey)?
+                .into(),
+        };
+        let mut builder = Gemini::builder_from_secret(api_key);
+        if let Some(model) = &self.model {
+            builder = builder.with_model(model.clone());
+        }
+        if let Some(version) = self.api_version {
+            builder = builder.with_api_version(version);
+        }
+        builder.build()
+    }
+
+    /// Apply the configured request settings to a request
+    ///
+    /// Call builder methods afterwards to override individual settings.
+    pub fn apply(&self, mut builder: ContentBuilder) -> ContentBuilder {
+        if let Some(system) = &self.system_instruction {
+            builder = builder.with_system_instruction(system.clone());
+        }
+        if let Some(config) = &self.generation_config {
+            builder = builder.with_generation_config(config.clone());
+        }
+        if let Some(settings) = &self.safety_settings {
+            builder = builder.with_safety_settings(settings.clone());
+        }
+        if let Some(tool_config) = &self.tool_config {
+            builder = builder.with_tool_config(tool_config.clone());
+        }
+        builder
+    }
+}
+
+/// Write the key itself when a config is saved
+fn expose_key<S: Serializer>(
+    key: &Option<SecretString>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match key {
+        Some(key) => serializer.serialize_some(key.expose_secret()),
+        None => serializer.serialize_none(),
+    }
+}
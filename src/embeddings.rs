@@ -0,0 +1,401 @@
+use crate::{
+    client::{Gemini, GeminiClient},
+    models::Content,
+    queue::Priority,
+    retry, Error, ErrorCode, Result,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_EMBEDDING_MODEL: &str = "models/text-embedding-004";
+
+/// Most texts the API embeds in one `batchEmbedContents` call
+const MAX_BATCH_SIZE: usize = 100;
+
+/// What an embedding will be used for
+///
+/// Embedding models produce vectors tuned for the task, so queries and the
+/// documents they should match need the matching pair of task types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaskType {
+    /// A search query matched against `RetrievalDocument` embeddings
+    RetrievalQuery,
+    /// A document in a search corpus
+    RetrievalDocument,
+    /// Comparing texts for similarity
+    SemanticSimilarity,
+    /// Input to a classifier
+    Classification,
+    /// Input to clustering
+    Clustering,
+    /// A question matched against answers
+    QuestionAnswering,
+    /// A statement checked against evidence
+    FactVerification,
+}
+
+/// An embedding vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    /// The vector components
+    pub values: Vec<f32>,
+}
+
+/// Request body for `embedContent`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EmbedContentRequest {
+    /// Only set in batches, where every request names the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    content: Content,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_type: Option<TaskType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbedContentResponse {
+    embedding: Embedding,
+}
+
+/// Request body for `batchEmbedContents`
+#[derive(Debug, Serialize)]
+struct BatchEmbedContentsRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchEmbedContentsResponse {
+    #[serde(default)]
+    embeddings: Vec<Embedding>,
+}
+
+/// Builder for embedding requests
+pub struct EmbedBuilder {
+    client: GeminiClient,
+    text: String,
+    task_type: Option<TaskType>,
+    title: Option<String>,
+    output_dimensionality: Option<u32>,
+}
+
+impl EmbedBuilder {
+    /// Embed with the key and options of `client`, using the default
+    /// embedding model
+    pub(crate) fn for_client(client: &GeminiClient, text: impl Into<String>) -> Self {
+        Self {
+            client: client.for_model(DEFAULT_EMBEDDING_MODEL),
+            text: text.into(),
+            task_type: None,
+            title: None,
+            output_dimensionality: None,
+        }
+    }
+
+    /// Use a different embedding model than `text-embedding-004`
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.client = self.client.for_model(model);
+        self
+    }
+
+    /// Set the queue priority of this request, see `GeminiBuilder::with_max_in_flight`
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.client.priority = priority;
+        self
+    }
+
+    /// Set the task the embedding is for
+    pub fn with_task_type(mut self, task_type: TaskType) -> Self {
+        self.task_type = Some(task_type);
+        self
+    }
+
+    /// Set the document title, only used with `TaskType::RetrievalDocument`
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Truncate the embedding to fewer dimensions, on models that support it
+    pub fn with_output_dimensionality(mut self, dimensions: u32) -> Self {
+        self.output_dimensionality = Some(dimensions);
+        self
+    }
+
+    /// Execute the request
+    pub async fn execute(self) -> Result<Embedding> {
+        let endpoint = "embedContent";
+        let request = EmbedContentRequest {
+            model: None,
+            content: Content::text(self.text),
+            task_type: self.task_type,
+            title: self.title,
+            output_dimensionality: self.output_dimensionality,
+        };
+        let result: Result<Embedding> = async {
+            let response = self.client.post(endpoint, &request).await?;
+            let response: EmbedContentResponse = response.json().await?;
+            Ok(response.embedding)
+        }
+        .await;
+        result.map_err(|e| self.client.context(e, endpoint))
+    }
+}
+
+/// Why one text of a batch got no embedding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemError {
+    /// What kind of failure it was
+    pub code: ErrorCode,
+    /// The error message
+    pub message: String,
+    /// How many calls the text was sent in
+    pub attempts: u32,
+    retryable: bool,
+}
+
+impl ItemError {
+    fn from_error(error: &Error, attempts: u32) -> Self {
+        Self {
+            code: error.code(),
+            message: error.to_string(),
+            attempts,
+            retryable: error.is_transient(),
+        }
+    }
+
+    /// Whether sending the text again may succeed
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+}
+
+impl std::fmt::Display for ItemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (after {} attempts)", self.message, self.attempts)
+    }
+}
+
+impl std::error::Error for ItemError {}
+
+/// Builder for embedding many texts with `batchEmbedContents`
+///
+/// Texts are sent in calls of up to 100. Every text gets its own result,
+/// so one bad text does not cost the embeddings of the others: when the
+/// API rejects a call as invalid, it is split to find the texts at fault.
+pub struct BatchEmbedBuilder {
+    client: GeminiClient,
+    texts: Vec<String>,
+    task_type: Option<TaskType>,
+    output_dimensionality: Option<u32>,
+    retries: u32,
+}
+
+impl BatchEmbedBuilder {
+    /// Use a different embedding model than `text-embedding-004`
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.client = self.client.for_model(model);
+        self
+    }
+
+    /// Set the queue priority of the calls, see `GeminiBuilder::with_max_in_flight`
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.client.priority = priority;
+        self
+    }
+
+    /// Set the task the embeddings are for
+    pub fn with_task_type(mut self, task_type: TaskType) -> Self {
+        self.task_type = Some(task_type);
+        self
+    }
+
+    /// Truncate the embeddings to fewer dimensions, on models that support it
+    pub fn with_output_dimensionality(mut self, dimensions: u32) -> Self {
+        self.output_dimensionality = Some(dimensions);
+        self
+    }
+
+    /// Send texts that failed for a temporary reason again, up to `rounds`
+    /// more times
+    ///
+    /// Only the failed texts are sent, after a backoff; their results are
+    /// merged with the others in the original order. Off by default.
+    pub fn with_failed_item_retries(mut self, rounds: u32) -> Self {
+        self.retries = rounds;
+        self
+    }
+
+    /// Embed the texts, returning a result for each in the order given
+    pub async fn execute(self) -> Vec<std::result::Result<Embedding, ItemError>> {
+        let mut results: Vec<Option<std::result::Result<Embedding, ItemError>>> =
+            vec![None; self.texts.len()];
+        let mut attempts = vec![0; self.texts.len()];
+        let mut pending: Vec<usize> = (0..self.texts.len()).collect();
+        for round in 0..=self.retries {
+            if round > 0 {
+                tokio::time::sleep(retry::backoff(round)).await;
+            }
+            for batch in pending.chunks(MAX_BATCH_SIZE) {
+                self.embed_batch(batch, &mut results, &mut attempts).await;
+            }
+            pending.retain(|&i| matches!(&results[i], Some(Err(e)) if e.is_retryable()));
+            if pending.is_empty() {
+                break;
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("every text is sent at least once"))
+            .collect()
+    }
+
+    /// Embed the texts at `indices`, splitting calls the API rejects
+    async fn embed_batch(
+        &self,
+        indices: &[usize],
+        results: &mut [Option<std::result::Result<Embedding, ItemError>>],
+        attempts: &mut [u32],
+    ) {
+        let mut batches = vec![indices];
+        while let Some(batch) = batches.pop() {
+            for &i in batch {
+                attempts[i] += 1;
+            }
+            match self.send(batch).await {
+                Ok(embeddings) => {
+                    let mut embeddings = embeddings.into_iter();
+                    for &i in batch {
+                        results[i] = Some(match embeddings.next() {
+                            Some(embedding) if !embedding.values.is_empty() => Ok(embedding),
+                            _ => Err(ItemError {
+                                code: ErrorCode::InvalidResponse,
+                                message: "The response has no embedding for this text".to_string(),
+                                attempts: attempts[i],
+                                retryable: true,
+                            }),
+                        });
+                    }
+                }
+                Err(e) if batch.len() > 1 && e.code() == ErrorCode::InvalidRequest => {
+                    let (first, second) = batch.split_at(batch.len() / 2);
+                    batches.push(second);
+                    batches.push(first);
+                }
+                Err(e) => {
+                    for &i in batch {
+                        results[i] = Some(Err(ItemError::from_error(&e, attempts[i])));
+                    }
+                }
+            }
+        }
+    }
+
+    /// One `batchEmbedContents` call
+    async fn send(&self, indices: &[usize]) -> Result<Vec<Embedding>> {
+        let endpoint = "batchEmbedContents";
+        let request = BatchEmbedContentsRequest {
+            requests: indices
+                .iter()
+                .map(|&i| EmbedContentRequest {
+                    model: Some(self.client.model.clone()),
+                    content: Content::text(self.texts[i].clone()),
+                    task_type: self.task_type,
+                    title: None,
+                    output_dimensionality: self.output_dimensionality,
+                })
+                .collect(),
+        };
+        let result: Result<Vec<Embedding>> = async {
+            let response = self.client.post(endpoint, &request).await?;
+            let response: BatchEmbedContentsResponse = response.json().await?;
+            Ok(response.embeddings)
+        }
+        .await;
+        result.map_err(|e| self.client.context(e, endpoint))
+    }
+}
+
+impl Gemini {
+    /// Start building a request to embed many texts at once
+    pub fn batch_embed_contents<S: Into<String>>(
+        &self,
+        texts: impl IntoIterator<Item = S>,
+    ) -> BatchEmbedBuilder {
+        BatchEmbedBuilder {
+            client: self.client.for_model(DEFAULT_EMBEDDING_MODEL),
+            texts: texts.into_iter().map(Into::into).collect(),
+            task_type: None,
+            output_dimensionality: None,
+            retries: 0,
+        }
+    }
+
+    /// Start building a request to embed a text
+    pub fn embed_content(&self, text: impl Into<String>) -> EmbedBuilder {
+        EmbedBuilder::for_client(&self.client, text)
+    }
+
+    /// Embed a search query, to be matched against documents embedded with
+    /// [`Gemini::embed_for_retrieval_document`]
+    pub fn embed_for_retrieval_query(&self, query: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(query)
+            .with_task_type(TaskType::RetrievalQuery)
+    }
+
+    /// Embed a document of a search corpus, with its title if it has one
+    ///
+    /// Titles improve retrieval quality; pass an empty string for documents
+    /// without one.
+    pub fn embed_for_retrieval_document(
+        &self,
+        title: impl Into<String>,
+        text: impl Into<String>,
+    ) -> EmbedBuilder {
+        let title = title.into();
+        let builder = self
+            .embed_content(text)
+            .with_task_type(TaskType::RetrievalDocument);
+        if title.is_empty() {
+            builder
+        } else {
+            builder.with_title(title)
+        }
+    }
+
+    /// Embed a text to compare with others embedded the same way
+    pub fn embed_for_semantic_similarity(&self, text: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(text)
+            .with_task_type(TaskType::SemanticSimilarity)
+    }
+
+    /// Embed a text as input to a classifier
+    pub fn embed_for_classification(&self, text: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(text)
+            .with_task_type(TaskType::Classification)
+    }
+
+    /// Embed a text for clustering
+    pub fn embed_for_clustering(&self, text: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(text)
+            .with_task_type(TaskType::Clustering)
+    }
+
+    /// Embed a question, to be matched against documents embedded with
+    /// [`Gemini::embed_for_retrieval_document`] that answer it
+    pub fn embed_for_question_answering(&self, question: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(question)
+            .with_task_type(TaskType::QuestionAnswering)
+    }
+
+    /// Embed a statement, to be matched against documents embedded with
+    /// [`Gemini::embed_for_retrieval_document`] that prove or disprove it
+    pub fn embed_for_fact_verification(&self, statement: impl Into<String>) -> EmbedBuilder {
+        self.embed_content(statement)
+            .with_task_type(TaskType::FactVerification)
+    }
+}
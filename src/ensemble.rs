@@ -0,0 +1,268 @@
+use crate::{
+    client::Gemini,
+    models::{GenerateContentRequest, GenerationResponse, Part},
+    Error, Result,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+
+const JUDGE_PROMPT: &str = "Several assistants answered the same request. Pick the answer \
+     that agrees with the majority of the others and is most likely correct. Reply with the \
+     number of that answer only.";
+
+/// How an [`Ensemble`] turns several models into one answer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnsembleStrategy {
+    /// Ask the models one at a time, in order, until one succeeds (the
+    /// default)
+    ///
+    /// Costs a single call when the first model is healthy.
+    #[default]
+    FirstSuccess,
+    /// Ask all models at once and keep the first successful answer
+    ///
+    /// The slower calls are cancelled.
+    Fastest,
+    /// Ask all models at once and have a judge pick the majority answer
+    ///
+    /// The judge is the first client unless set with
+    /// [`Ensemble::with_judge`]; it makes one extra call when more than one
+    /// model answered.
+    MajorityVote,
+}
+
+/// The answer of an ensemble and where it came from
+#[derive(Debug)]
+pub struct EnsembleResponse {
+    /// The chosen response
+    pub response: GenerationResponse,
+    /// Position of the client that produced it
+    pub index: usize,
+    /// Model that produced it
+    pub model: String,
+    /// Errors of the clients that failed, which did not stop the ensemble
+    pub failures: Vec<Error>,
+}
+
+/// Sends the same request to several models and reconciles the results
+///
+/// For reliability-critical work such as extraction: combine clients for
+/// different models, or the same model behind different keys or regions,
+/// and choose an [`EnsembleStrategy`].
+#[derive(Clone)]
+pub struct Ensemble {
+    clients: Vec<Gemini>,
+    strategy: EnsembleStrategy,
+    judge: Option<Gemini>,
+}
+
+impl Ensemble {
+    /// Combine clients, in order of preference
+    pub fn new(clients: impl IntoIterator<Item = Gemini>) -> Self {
+        Self {
+            clients: clients.into_iter().collect(),
+            strategy: EnsembleStrategy::default(),
+            judge: None,
+        }
+    }
+
+    /// Choose how results are reconciled
+    pub fn with_strategy(mut self, strategy: EnsembleStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Use another client to pick the majority answer
+    pub fn with_judge(mut self, judge: Gemini) -> Self {
+        self.judge = Some(judge);
+        self
+    }
+
+    /// Send a request to the models and return the reconciled answer
+    ///
+    /// Build the request with [`GenerateContentRequest::from_json`] or by
+    /// hand; each client sends it to its own model. Fails with the last
+    /// error if no model succeeds.
+    pub async fn generate(&self, request: &GenerateContentRequest) -> Result<EnsembleResponse> {
+        if self.clients.is_empty() {
+            return Err(Error::RequestError("Ensemble has no clients".to_string()));
+        }
+        match self.strategy {
+            EnsembleStrategy::FirstSuccess => self.first_success(request).await,
+            EnsembleStrategy::Fastest => self.fastest(request).await,
+            EnsembleStrategy::MajorityVote => self.majority_vote(request).await,
+        }
+    }
+
+    async fn first_success(&self, request: &GenerateContentRequest) -> Result<EnsembleResponse> {
+        let mut failures = Vec::new();
+        for index in 0..self.clients.len() {
+            match self.call(index, request).await {
+                Ok(response) => return Ok(self.answer(index, response, failures)),
+                Err(e) => failures.push(e),
+            }
+        }
+        Err(failures.pop().expect("ensemble has clients"))
+    }
+
+    async fn fastest(&self, request: &GenerateContentRequest) -> Result<EnsembleResponse> {
+        let mut pending = Pending::new(self.calls(request));
+        let mut failures = Vec::new();
+        while let Some((index, result)) = pending.next().await {
+            match result {
+                Ok(response) => return Ok(self.answer(index, response, failures)),
+                Err(e) => failures.push(e),
+            }
+        }
+        Err(failures.pop().expect("ensemble has clients"))
+    }
+
+    async fn majority_vote(&self, request: &GenerateContentRequest) -> Result<EnsembleResponse> {
+        let mut pending = Pending::new(self.calls(request));
+        let mut answers = Vec::new();
+        let mut failures = Vec::new();
+        while let Some((index, result)) = pending.next().await {
+            match result {
+                Ok(response) => answers.push((index, response)),
+                Err(e) => failures.push(e),
+            }
+        }
+        answers.sort_by_key(|(index, _)| *index);
+        if answers.is_empty() {
+            return Err(failures.pop().expect("ensemble has clients"));
+        }
+
+        let chosen = match answers.len() {
+            1 => 0,
+            _ => match self.judge(request, &answers).await {
+                Ok(chosen) => chosen,
+                Err(e) => {
+                    failures.push(e);
+                    0
+                }
+            },
+        };
+        let (index, response) = answers.swap_remove(chosen);
+        Ok(self.answer(index, response, failures))
+    }
+
+    /// Ask the judge which answer to keep, by position in `answers`
+    async fn judge(
+        &self,
+        request: &GenerateContentRequest,
+        answers: &[(usize, GenerationResponse)],
+    ) -> Result<usize> {
+        let question: String = request
+            .contents
+            .last()
+            .map(|content| {
+                content
+                    .parts
+                    .iter()
+                    .filter_map(Part::text)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        let mut prompt = format!("{}\n\nRequest:\n{}\n", JUDGE_PROMPT, question);
+        for (number, (_, response)) in answers.iter().enumerate() {
+            prompt.push_str(&format!("\nAnswer {}:\n{}\n", number + 1, response.text()));
+        }
+
+        let judge = self.judge.as_ref().unwrap_or(&self.clients[0]);
+        let verdict = judge.ask(prompt).await?;
+        verdict
+            .split(|c: char| !c.is_ascii_digit())
+            .find_map(|digits| digits.parse::<usize>().ok())
+            .filter(|number| (1..=answers.len()).contains(number))
+            .map(|number| number - 1)
+            .ok_or_else(|| {
+                Error::RequestError(format!("Judge did not pick an answer: {:?}", verdict))
+            })
+    }
+
+    fn calls<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+    ) -> Vec<impl Future<Output = Result<GenerationResponse>> + 'a> {
+        (0..self.clients.len())
+            .map(|index| self.call(index, request))
+            .collect()
+    }
+
+    async fn call(
+        &self,
+        index: usize,
+        request: &GenerateContentRequest,
+    ) -> Result<GenerationResponse> {
+        self.clients[index]
+            .generate_content()
+            .with_request(request.clone())
+            .execute()
+            .await
+    }
+
+    fn answer(
+        &self,
+        index: usize,
+        response: GenerationResponse,
+        failures: Vec<Error>,
+    ) -> EnsembleResponse {
+        EnsembleResponse {
+            response,
+            index,
+            model: self.clients[index].client.model.clone(),
+            failures,
+        }
+    }
+}
+
+impl std::fmt::Debug for Ensemble {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let models: Vec<_> = self.clients.iter().map(|c| &c.client.model).collect();
+        f.debug_struct("Ensemble")
+            .field("models", &models)
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+/// Futures run concurrently, yielding results as they complete
+///
+/// Dropping it cancels the futures that are still running.
+struct Pending<F> {
+    futures: Vec<Option<Pin<Box<F>>>>,
+}
+
+impl<F: Future> Pending<F> {
+    fn new(futures: Vec<F>) -> Self {
+        Self {
+            futures: futures.into_iter().map(|f| Some(Box::pin(f))).collect(),
+        }
+    }
+
+    /// The next result and the position of its future, or `None` when all
+    /// have completed
+    async fn next(&mut self) -> Option<(usize, F::Output)> {
+        std::future::poll_fn(|cx| {
+            let mut running = false;
+            for (index, slot) in self.futures.iter_mut().enumerate() {
+                let Some(future) = slot else {
+                    continue;
+                };
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    *slot = None;
+                    return Poll::Ready(Some((index, output)));
+                }
+                running = true;
+            }
+            if running {
+                Poll::Pending
+            } else {
+                Poll::Ready(None)
+            }
+        })
+        .await
+    }
+}
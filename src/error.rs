@@ -1,11 +1,14 @@
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 /// Errors that can occur when using the Gemini API
 #[derive(Error, Debug)]
 pub enum Error {
     /// Error from the reqwest HTTP client
+    ///
+    /// The API key is masked in the URL the error carries.
     #[error("HTTP error: {0}")]
-    HttpError(#[from] reqwest::Error),
+    HttpError(#[source] reqwest::Error),
 
     /// Error parsing JSON
     #[error("JSON error: {0}")]
@@ -18,12 +21,93 @@ pub enum Error {
         status_code: u16,
         /// Error message
         message: String,
+        /// Delay requested by a `Retry-After` header given in seconds
+        retry_after: Option<std::time::Duration>,
     },
 
     /// Error building a valid request
     #[error("Request building error: {0}")]
     RequestError(String),
 
+    /// The request combines options that the API does not accept together
+    #[error("Incompatible request options: {0}")]
+    IncompatibleOptions(String),
+
+    /// The reply was not in the language requested with `with_response_language`
+    #[error("Response language mismatch: expected {expected}, detected {detected}")]
+    LanguageMismatch {
+        /// The requested language
+        expected: String,
+        /// The language the reply was detected to be in
+        detected: String,
+    },
+
+    /// A prompt template could not be loaded or rendered
+    #[error("Prompt error: {0}")]
+    PromptError(String),
+
+    /// A client config file could not be loaded
+    #[error("Config error: {0}")]
+    ConfigError(String),
+
+    /// A post-processor rejected the response text
+    #[error("Post-processing error: {0}")]
+    PostProcessError(String),
+
+    /// A session store could not read or write a session
+    #[error("Session store error: {0}")]
+    StoreError(String),
+
+    /// Function declarations have problems the API would reject
+    #[error("Invalid tool declaration: {}", join_issues(.0))]
+    InvalidToolDeclaration(Vec<crate::tools::Issue>),
+
+    /// A `PromptGuard` rejected the request before it was sent
+    #[error("Prompt rejected: {}", join_violations(.0))]
+    PromptRejected(Vec<crate::guard::PromptViolation>),
+
+    /// The API refused to answer the prompt, from
+    /// `ContentBuilder::execute_unblocked`
+    #[error("Prompt blocked: {0}")]
+    PromptBlocked(crate::moderation::PromptBlock),
+
+    /// Every candidate was rejected by a `ResponseFilter`
+    #[error("All candidates rejected: {}", .0.join("; "))]
+    AllCandidatesRejected(Vec<String>),
+
+    /// A `RetryBudget` had no attempts left for another API call
+    #[error("Retry budget exhausted after {attempts} attempts and {delay:?} of backoff")]
+    RetryBudgetExhausted {
+        /// API calls made under the budget
+        attempts: u32,
+        /// Total time spent waiting between retries
+        delay: std::time::Duration,
+    },
+
+    /// A response kept failing the validator of `execute_validated`
+    #[error("Response failed validation after {attempts} attempts: {message}")]
+    ValidationFailed {
+        /// Responses generated, including the first
+        attempts: usize,
+        /// What the validator said about the last response
+        message: String,
+    },
+
+    /// A structured answer had fields the target type has no place for,
+    /// from `GenerationResponse::parse_json_strict`
+    #[error("Unexpected fields in structured output: {}", .0.join(", "))]
+    UnexpectedFields(Vec<String>),
+
+    /// A stream was stopped because a chunk was rated at or above the
+    /// threshold set with `ContentBuilder::with_safety_abort`
+    #[error("Stream aborted by safety rating: {category} is {probability}")]
+    SafetyAbort {
+        /// Harm category of the rating, e.g. `HARM_CATEGORY_HARASSMENT`
+        category: String,
+        /// Probability of the rating, e.g. `HIGH`
+        probability: String,
+    },
+
     /// Missing API key
     #[error("Missing API key")]
     MissingApiKey,
@@ -31,4 +115,365 @@ pub enum Error {
     /// Error with function calls
     #[error("Function call error: {0}")]
     FunctionCallError(String),
+
+    /// An error annotated with the call that produced it
+    #[error(
+        "{source} (endpoint: {endpoint}, model: {model}, attempt: {attempt}{})",
+        join_metadata(.metadata)
+    )]
+    Contextual {
+        /// The API endpoint that was called, e.g. `generateContent`
+        endpoint: String,
+        /// The model the request was sent to
+        model: String,
+        /// The attempt number, starting at 1
+        attempt: u32,
+        /// Metadata set with `ContentBuilder::with_metadata`
+        metadata: BTreeMap<String, String>,
+        /// The underlying error
+        source: Box<Error>,
+    },
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(mut error: reqwest::Error) -> Self {
+        if let Some(url) = error.url_mut() {
+            redact_key(url);
+        }
+        Error::HttpError(error)
+    }
+}
+
+/// Replace the value of the `key` query parameter
+///
+/// The client sends the key in a header, so this only catches keys put in
+/// a URL some other way, e.g. in a custom base URL.
+fn redact_key(url: &mut url::Url) {
+    if !url.query_pairs().any(|(name, _)| name == "key") {
+        return;
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| match name.as_ref() {
+            "key" => (name.into_owned(), "[REDACTED]".to_string()),
+            _ => (name.into_owned(), value.into_owned()),
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+}
+
+impl Error {
+    /// Attach call context to an error, replacing any context already present
+    ///
+    /// Metadata of the replaced context is kept.
+    pub(crate) fn with_context(
+        self,
+        endpoint: impl Into<String>,
+        model: impl Into<String>,
+        attempt: u32,
+    ) -> Self {
+        let metadata = self.metadata().cloned().unwrap_or_default();
+        Error::Contextual {
+            endpoint: endpoint.into(),
+            model: model.into(),
+            attempt,
+            metadata,
+            source: Box::new(self.into_root()),
+        }
+    }
+
+    /// Add request metadata to the call context of an error
+    pub(crate) fn with_metadata(mut self, extra: &BTreeMap<String, String>) -> Self {
+        if let Error::Contextual { metadata, .. } = &mut self {
+            metadata.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        self
+    }
+
+    /// The endpoint that produced this error, if known
+    pub fn endpoint(&self) -> Option<&str> {
+        match self {
+            Error::Contextual { endpoint, .. } => Some(endpoint),
+            _ => None,
+        }
+    }
+
+    /// The model that produced this error, if known
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            Error::Contextual { model, .. } => Some(model),
+            _ => None,
+        }
+    }
+
+    /// The attempt number that produced this error, if known
+    pub fn attempt(&self) -> Option<u32> {
+        match self {
+            Error::Contextual { attempt, .. } => Some(*attempt),
+            _ => None,
+        }
+    }
+
+    /// The metadata of the request that produced this error, if known
+    pub fn metadata(&self) -> Option<&BTreeMap<String, String>> {
+        match self {
+            Error::Contextual { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
+    /// Whether the error is likely temporary and the call worth retrying
+    ///
+    /// True for rate limiting (429), server errors (500, 502, 503, 504),
+    /// timeouts and connection failures.
+    pub fn is_transient(&self) -> bool {
+        match self.root() {
+            Error::ApiError { status_code, .. } => {
+                matches!(status_code, 429 | 500 | 502 | 503 | 504)
+            }
+            Error::HttpError(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether and when the failed call is worth retrying
+    ///
+    /// For callers running their own retry loops. The delay comes from a
+    /// `Retry-After` header or the `RetryInfo` detail of the error payload,
+    /// whichever asks for longer; without either, choose a backoff yourself.
+    /// Rate limits on daily quotas are reported as not retryable, since
+    /// retrying before the quota resets only fails again.
+    pub fn retry_advice(&self) -> RetryAdvice {
+        let advice = |retryable, after, reason: &str| RetryAdvice {
+            retryable,
+            after,
+            reason: reason.to_string(),
+        };
+        match self.root() {
+            Error::ApiError {
+                status_code,
+                message,
+                retry_after,
+            } => {
+                let after = (*retry_after).max(retry_delay(message));
+                match status_code {
+                    429 if message.contains("PerDay") => {
+                        advice(false, None, "daily quota exhausted")
+                    }
+                    429 => advice(true, after, "rate limited"),
+                    408 | 504 => advice(true, after, "request timed out"),
+                    500 | 502 | 503 => advice(true, after, "server error"),
+                    400..=499 => advice(false, None, "request rejected"),
+                    _ => advice(false, None, "unexpected status"),
+                }
+            }
+            Error::HttpError(e) if e.is_timeout() => advice(true, None, "request timed out"),
+            Error::HttpError(e) if e.is_connect() => advice(true, None, "connection failed"),
+            Error::RetryBudgetExhausted { .. } => advice(false, None, "retry budget exhausted"),
+            _ => advice(false, None, "not a transient failure"),
+        }
+    }
+
+    /// A stable code for branching on the kind of failure
+    ///
+    /// Derived from the error variant and, for API errors, the status and
+    /// payload. Match on this rather than on `Error` variants, which may be
+    /// reorganized between releases.
+    pub fn code(&self) -> ErrorCode {
+        match self.root() {
+            Error::ApiError {
+                status_code,
+                message,
+                ..
+            } => api_error_code(*status_code, message),
+            Error::HttpError(e) if e.is_timeout() || e.is_connect() => ErrorCode::Transient,
+            Error::HttpError(_) => ErrorCode::Network,
+            Error::MissingApiKey => ErrorCode::InvalidApiKey,
+            Error::PromptBlocked(_) | Error::SafetyAbort { .. } => ErrorCode::SafetyBlocked,
+            Error::PromptRejected(_) | Error::AllCandidatesRejected(_) => ErrorCode::Rejected,
+            Error::RequestError(_)
+            | Error::IncompatibleOptions(_)
+            | Error::InvalidToolDeclaration(_) => ErrorCode::InvalidRequest,
+            Error::JsonError(_)
+            | Error::LanguageMismatch { .. }
+            | Error::PostProcessError(_)
+            | Error::ValidationFailed { .. }
+            | Error::UnexpectedFields(_) => ErrorCode::InvalidResponse,
+            Error::FunctionCallError(_) => ErrorCode::FunctionCall,
+            Error::RetryBudgetExhausted { .. } => ErrorCode::RetryBudgetExhausted,
+            Error::PromptError(_) | Error::ConfigError(_) | Error::StoreError(_) => {
+                ErrorCode::Local
+            }
+            Error::Contextual { .. } => ErrorCode::Unknown,
+        }
+    }
+
+    /// The underlying error with any call context stripped
+    ///
+    /// Useful for matching on the error kind without caring whether the
+    /// context wrapper is present.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Contextual { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    /// Consume the error, returning the underlying error without call context
+    pub fn into_root(self) -> Error {
+        match self {
+            Error::Contextual { source, .. } => source.into_root(),
+            other => other,
+        }
+    }
+}
+
+/// Whether and when to retry a failed call, from [`Error::retry_advice`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryAdvice {
+    /// Whether retrying the same call may succeed
+    pub retryable: bool,
+    /// How long the server asked to wait before retrying, if it said
+    pub after: Option<std::time::Duration>,
+    /// Short description of the failure, e.g. `rate limited`
+    pub reason: String,
+}
+
+/// What kind of failure an error is, from [`Error::code`]
+///
+/// Codes are only ever added, never renamed or repurposed, so match arms
+/// on them keep working across releases. [`ErrorCode::as_str`] gives a
+/// matching string for logs and metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The API key is missing, malformed or revoked
+    InvalidApiKey,
+    /// The key is valid but may not use this model or resource
+    PermissionDenied,
+    /// A daily or project quota is used up; retrying soon will not help
+    QuotaExceeded,
+    /// Too many requests in a short time; retry after a delay
+    RateLimited,
+    /// The prompt or the response was blocked for safety
+    SafetyBlocked,
+    /// The model name does not exist for this API version
+    ModelNotFound,
+    /// Another resource, such as a file or cached content, does not exist
+    NotFound,
+    /// The prompt has more tokens than the model's context window
+    ContextTooLong,
+    /// The request is malformed or uses unsupported options
+    InvalidRequest,
+    /// A temporary server or network failure worth retrying
+    Transient,
+    /// The HTTP request failed for another reason
+    Network,
+    /// The response could not be parsed or failed a check
+    InvalidResponse,
+    /// A guard or response filter of this crate refused the call
+    Rejected,
+    /// A function call could not be handled
+    FunctionCall,
+    /// A `RetryBudget` ran out of attempts
+    RetryBudgetExhausted,
+    /// A local file, such as a config, prompt or session, failed to load
+    Local,
+    /// Any other failure
+    Unknown,
+}
+
+impl ErrorCode {
+    /// The code as a `snake_case` string, e.g. `quota_exceeded`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidApiKey => "invalid_api_key",
+            Self::PermissionDenied => "permission_denied",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::RateLimited => "rate_limited",
+            Self::SafetyBlocked => "safety_blocked",
+            Self::ModelNotFound => "model_not_found",
+            Self::NotFound => "not_found",
+            Self::ContextTooLong => "context_too_long",
+            Self::InvalidRequest => "invalid_request",
+            Self::Transient => "transient",
+            Self::Network => "network",
+            Self::InvalidResponse => "invalid_response",
+            Self::Rejected => "rejected",
+            Self::FunctionCall => "function_call",
+            Self::RetryBudgetExhausted => "retry_budget_exhausted",
+            Self::Local => "local",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Classify an API error by its status and the markers in its payload
+fn api_error_code(status_code: u16, message: &str) -> ErrorCode {
+    let invalid_key = ["API_KEY_INVALID", "API key not valid", "API key expired"]
+        .iter()
+        .any(|marker| message.contains(marker));
+    let too_long = [
+        "exceeds the maximum number of tokens",
+        "input token count",
+        "input context is too long",
+    ]
+    .iter()
+    .any(|marker| message.contains(marker));
+    match status_code {
+        _ if invalid_key => ErrorCode::InvalidApiKey,
+        401 => ErrorCode::InvalidApiKey,
+        403 => ErrorCode::PermissionDenied,
+        404 if message.contains("models/") => ErrorCode::ModelNotFound,
+        404 => ErrorCode::NotFound,
+        429 if message.contains("PerDay") || message.contains("billing") => {
+            ErrorCode::QuotaExceeded
+        }
+        429 => ErrorCode::RateLimited,
+        400 | 413 if too_long => ErrorCode::ContextTooLong,
+        400 if message.contains("model name") => ErrorCode::ModelNotFound,
+        400 | 409 | 411 | 413 | 415 | 422 => ErrorCode::InvalidRequest,
+        408 | 500 | 502 | 503 | 504 => ErrorCode::Transient,
+        _ => ErrorCode::Unknown,
+    }
+}
+
+/// The `retryDelay` of a `google.rpc.RetryInfo` detail in an error payload
+fn retry_delay(message: &str) -> Option<std::time::Duration> {
+    let payload: serde_json::Value = serde_json::from_str(message).ok()?;
+    payload["error"]["details"]
+        .as_array()?
+        .iter()
+        .find_map(|detail| detail["retryDelay"].as_str())
+        .and_then(|delay| delay.strip_suffix('s')?.parse::<f64>().ok())
+        .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok())
+}
+
+fn join_metadata(metadata: &BTreeMap<String, String>) -> String {
+    metadata
+        .iter()
+        .map(|(key, value)| format!(", {}: {}", key, value))
+        .collect()
+}
+
+fn join_issues(issues: &[crate::tools::Issue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn join_violations(violations: &[crate::guard::PromptViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
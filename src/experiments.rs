@@ -0,0 +1,280 @@
+use crate::{client::ContentBuilder, models::GenerationResponse, Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Configure = Arc<dyn Fn(ContentBuilder) -> ContentBuilder + Send + Sync>;
+
+/// One arm of an [`Experiment`]
+struct Variant {
+    name: String,
+    weight: u32,
+    configure: Configure,
+}
+
+/// An A/B test over prompt or configuration variants
+///
+/// Each variant is a function that adjusts a `ContentBuilder`, e.g. by
+/// swapping the system prompt, model or temperature. Calls are routed to
+/// variants in proportion to their weights and the outcome of every call is
+/// recorded, so [`Experiment::report`] can compare latency, token usage and
+/// finish reasons.
+pub struct Experiment {
+    name: String,
+    variants: Vec<Variant>,
+    calls: AtomicU64,
+    stats: Mutex<BTreeMap<String, VariantStats>>,
+}
+
+impl Experiment {
+    /// Create an experiment without variants
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            variants: Vec::new(),
+            calls: AtomicU64::new(0),
+            stats: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Add a variant receiving `weight` parts of the traffic
+    ///
+    /// Weights are relative: two variants weighted 90 and 10 split calls
+    /// 90% / 10%. Variants with a weight of zero are never chosen.
+    pub fn with_variant(
+        mut self,
+        name: impl Into<String>,
+        weight: u32,
+        configure: impl Fn(ContentBuilder) -> ContentBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.variants.push(Variant {
+            name: name.into(),
+            weight,
+            configure: Arc::new(configure),
+        });
+        self
+    }
+
+    /// The experiment's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Run a request through a variant chosen according to the weights
+    ///
+    /// Returns the variant's name alongside the response.
+    pub async fn run(&self, builder: ContentBuilder) -> Result<(String, GenerationResponse)> {
+        // Hashing the call counter interleaves the variants instead of
+        // sending long runs of consecutive calls to the same one
+        let call = self.calls.fetch_add(1, Ordering::Relaxed);
+        let variant = self.variant_for(fnv1a(&call.to_le_bytes()))?;
+        self.run_variant(variant, builder).await
+    }
+
+    /// Run a request through the variant assigned to `key`
+    ///
+    /// The same key (a user or session id, say) always lands on the same
+    /// variant as long as the variants and weights are unchanged.
+    pub async fn run_keyed(
+        &self,
+        key: &str,
+        builder: ContentBuilder,
+    ) -> Result<(String, GenerationResponse)> {
+        let variant = self.variant_for(fnv1a(key.as_bytes()))?;
+        self.run_variant(variant, builder).await
+    }
+
+    /// Statistics collected so far
+    pub fn report(&self) -> ExperimentReport {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let variants = self
+            .variants
+            .iter()
+            .map(|v| {
+                (
+                    v.name.clone(),
+                    stats.get(&v.name).cloned().unwrap_or_default(),
+                )
+            })
+            .collect();
+        ExperimentReport {
+            name: self.name.clone(),
+            variants,
+        }
+    }
+
+    /// Map a hash onto a variant, proportionally to the weights
+    fn variant_for(&self, hash: u64) -> Result<&Variant> {
+        let total: u64 = self.variants.iter().map(|v| u64::from(v.weight)).sum();
+        if total == 0 {
+            return Err(Error::RequestError(format!(
+                "Experiment {} has no variant with a non-zero weight",
+                self.name
+            )));
+        }
+
+        let mut point = hash % total;
+        for variant in &self.variants {
+            let weight = u64::from(variant.weight);
+            if point < weight {
+                return Ok(variant);
+            }
+            point -= weight;
+        }
+        unreachable!("point is below the total weight")
+    }
+
+    async fn run_variant(
+        &self,
+        variant: &Variant,
+        builder: ContentBuilder,
+    ) -> Result<(String, GenerationResponse)> {
+        let started = Instant::now();
+        let result = (variant.configure)(builder).execute().await;
+        let latency = started.elapsed();
+
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats
+            .entry(variant.name.clone())
+            .or_default()
+            .record(latency, result.as_ref().ok());
+        drop(stats);
+
+        result.map(|response| (variant.name.clone(), response))
+    }
+}
+
+impl fmt::Debug for Experiment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Experiment")
+            .field("name", &self.name)
+            .field(
+                "variants",
+                &self
+                    .variants
+                    .iter()
+                    .map(|v| (&v.name, v.weight))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Outcomes recorded for one variant of an [`Experiment`]
+#[derive(Debug, Clone, Default)]
+pub struct VariantStats {
+    /// Number of calls routed to the variant
+    pub calls: u64,
+    /// Number of calls that returned an error
+    pub errors: u64,
+    /// Sum of the latencies of all calls, including failed ones
+    pub total_latency: Duration,
+    /// Slowest call
+    pub max_latency: Duration,
+    /// Prompt tokens over all successful calls
+    pub prompt_tokens: u64,
+    /// Response tokens over all successful calls
+    pub candidates_tokens: u64,
+    /// How often each finish reason occurred
+    pub finish_reasons: BTreeMap<String, u64>,
+}
+
+impl VariantStats {
+    /// Average latency per call
+    pub fn mean_latency(&self) -> Duration {
+        match u32::try_from(self.calls) {
+            Ok(0) => Duration::ZERO,
+            Ok(calls) => self.total_latency / calls,
+            Err(_) => Duration::from_secs_f64(self.total_latency.as_secs_f64() / self.calls as f64),
+        }
+    }
+
+    /// Fraction of calls that failed
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+
+    fn record(&mut self, latency: Duration, response: Option<&GenerationResponse>) {
+        self.calls += 1;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+
+        let Some(response) = response else {
+            self.errors += 1;
+            return;
+        };
+        if let Some(usage) = &response.usage_metadata {
+            self.prompt_tokens += u64::try_from(usage.prompt_token_count).unwrap_or(0);
+            self.candidates_tokens += u64::try_from(usage.candidates_token_count).unwrap_or(0);
+        }
+        let reason = response
+            .candidates
+            .first()
+            .and_then(|c| c.finish_reason.clone())
+            .or_else(|| {
+                response
+                    .prompt_feedback
+                    .as_ref()
+                    .and_then(|f| f.block_reason.clone())
+            })
+            .unwrap_or_else(|| "UNSPECIFIED".to_string());
+        *self.finish_reasons.entry(reason).or_default() += 1;
+    }
+}
+
+/// Summary of an [`Experiment`], one entry per variant
+///
+/// The `Display` implementation renders a plain-text table.
+#[derive(Debug, Clone)]
+pub struct ExperimentReport {
+    /// The experiment's name
+    pub name: String,
+    /// Statistics per variant, in registration order
+    pub variants: Vec<(String, VariantStats)>,
+}
+
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Experiment: {}", self.name)?;
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>7} {:>10} {:>10} {:>12} {:>12}  finish reasons",
+            "variant", "calls", "errors", "mean ms", "max ms", "prompt tok", "output tok"
+        )?;
+        for (name, stats) in &self.variants {
+            let reasons = stats
+                .finish_reasons
+                .iter()
+                .map(|(reason, count)| format!("{}={}", reason, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>7} {:>10} {:>10} {:>12} {:>12}  {}",
+                name,
+                stats.calls,
+                stats.errors,
+                stats.mean_latency().as_millis(),
+                stats.max_latency.as_millis(),
+                stats.prompt_tokens,
+                stats.candidates_tokens,
+                reasons
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a, used for key-based assignment because it is stable across
+/// processes and Rust releases, unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,791 @@
+use crate::{
+    client::{check_status, ContentBuilder, Gemini, GeminiClient, API_KEY_HEADER},
+    mime,
+    models::{Blob, Content, FileData, Part, Role},
+    retry, Error, Result,
+};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Upload chunks must be a multiple of this size, except the last one
+const CHUNK_GRANULARITY: usize = 256 * 1024;
+
+/// Default size of upload chunks (8 MiB)
+const DEFAULT_CHUNK_SIZE: usize = 32 * CHUNK_GRANULARITY;
+
+/// How much of a file is read to detect its type
+const SNIFF_LENGTH: usize = 512;
+
+/// Default number of retries for each chunk
+const DEFAULT_CHUNK_RETRIES: u32 = 3;
+
+/// Largest image `with_user_image_url` downloads (100 MiB)
+const MAX_REMOTE_IMAGE_SIZE: usize = 100 * 1024 * 1024;
+
+/// Downloaded images up to this size are sent inline (4 MiB)
+const REMOTE_IMAGE_INLINE_LIMIT: usize = 4 * 1024 * 1024;
+
+/// How often the state of a file being processed is checked
+const PROCESSING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for an uploaded file to become usable
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A file stored with the Files API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    /// Resource name, e.g. `files/abc-123`
+    pub name: String,
+    /// Name given when the file was uploaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// MIME type of the file
+    pub mime_type: String,
+    /// Size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<String>,
+    /// URI to reference the file with in requests
+    pub uri: String,
+    /// Processing state, e.g. `PROCESSING` or `ACTIVE`
+    ///
+    /// Videos can only be used once they are `ACTIVE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// When the API deletes the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<String>,
+}
+
+impl FileInfo {
+    /// A part referencing this file
+    pub fn to_part(&self) -> Part {
+        Part::FileData {
+            file_data: FileData::new(self.mime_type.clone(), self.uri.clone()),
+            video_metadata: None,
+        }
+    }
+}
+
+/// Reports upload progress as `(bytes_sent, total_bytes)`
+type ProgressCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// A resumable upload to the Files API
+///
+/// Created with [`Gemini::upload_file`]. The file is sent in chunks; a
+/// chunk that fails is retried with backoff from the offset the server
+/// confirms, so a dropped connection only costs the chunk in flight. With
+/// [`FileUpload::with_session_file`] an upload interrupted by a restart
+/// continues where it stopped.
+pub struct FileUpload {
+    client: Arc<GeminiClient>,
+    source: UploadSource,
+    mime_type: Option<String>,
+    display_name: Option<String>,
+    chunk_size: usize,
+    chunk_retries: u32,
+    progress: Option<ProgressCallback>,
+    session_file: Option<PathBuf>,
+}
+
+/// What an upload sends
+enum UploadSource {
+    File(PathBuf),
+    /// Bytes already in memory, e.g. a downloaded image, and where they
+    /// came from
+    Bytes {
+        origin: String,
+        bytes: Vec<u8>,
+    },
+}
+
+/// What is persisted to resume an upload
+///
+/// The upload URL is stored without a `key` parameter; the API key is sent
+/// in a header instead, so it never ends up on disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadSession {
+    upload_url: String,
+    size: u64,
+    modified_secs: u64,
+}
+
+impl Gemini {
+    /// Get the metadata of a file, e.g. to check its state
+    pub async fn get_file(&self, name: &str) -> Result<FileInfo> {
+        let response = self.client.get(name, &[]).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Delete a file before it expires
+    pub async fn delete_file(&self, name: &str) -> Result<()> {
+        self.client.delete(name).await
+    }
+
+    /// Start uploading a file to the Files API
+    ///
+    /// The type is taken from the extension, or from the file's contents
+    /// when the extension is missing or unknown; set it with
+    /// [`FileUpload::with_mime_type`] to skip detection.
+    pub fn upload_file(&self, path: impl Into<PathBuf>) -> FileUpload {
+        FileUpload::new(self.client.clone(), path)
+    }
+}
+
+impl ContentBuilder {
+    /// Add a file uploaded with the Files API as a user message
+    pub fn with_file(mut self, file: &FileInfo) -> Self {
+        self.contents.push(Content {
+            parts: vec![file.to_part()],
+            role: Some(Role::User),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Upload a file when the request is executed and add it as a user
+    /// message
+    ///
+    /// Nothing is uploaded until `execute` (or `execute_stream`, ...) runs;
+    /// videos are then waited on until the API has processed them. The file
+    /// belongs to the request: it is deleted as soon as the request fails,
+    /// and otherwise once it completes, or once a streamed response is
+    /// dropped. Use [`ContentBuilder::with_uploaded_file_lifetime`] to keep
+    /// it longer.
+    pub fn with_uploaded_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let upload = FileUpload::new(self.client.clone(), path);
+        self.uploads
+            .files
+            .push((self.contents.len(), PendingFile::Upload(upload)));
+        self
+    }
+
+    /// Download an image when the request is executed and add it as a user
+    /// message
+    ///
+    /// The URL has to answer with an image of at most 100 MiB; anything
+    /// else, such as an HTML error page, fails the request before it is
+    /// sent. Images up to 4 MiB are sent inline. Larger ones are uploaded
+    /// with the Files API and deleted like files added with
+    /// [`ContentBuilder::with_uploaded_file`].
+    pub fn with_user_image_url(mut self, url: impl Into<String>) -> Self {
+        self.uploads
+            .files
+            .push((self.contents.len(), PendingFile::ImageUrl(url.into())));
+        self
+    }
+
+    /// Keep files added with `with_uploaded_file` or `with_user_image_url`
+    /// for a while after a
+    /// successful request, e.g. to ask follow-up questions about them
+    ///
+    /// They are deleted once `lifetime` has passed, as long as the process
+    /// keeps running; otherwise the API removes them after 48 hours.
+    pub fn with_uploaded_file_lifetime(mut self, lifetime: Duration) -> Self {
+        self.uploads.lifetime = lifetime;
+        self
+    }
+
+    /// Upload the files added with `with_uploaded_file` and fetch the images
+    /// added with `with_user_image_url`, placing them where they were added
+    /// among the messages
+    pub(crate) async fn upload_pending(&mut self) -> Result<UploadedFiles> {
+        let pending = std::mem::take(&mut self.uploads);
+        let mut uploaded = UploadedFiles {
+            client: self.client.clone(),
+            names: Vec::new(),
+            lifetime: pending.lifetime,
+            failed: AtomicBool::new(false),
+        };
+        for (offset, (index, file)) in pending.files.into_iter().enumerate() {
+            let part = match file.resolve(&self.client, &mut uploaded.names).await {
+                Ok(part) => part,
+                Err(e) => {
+                    uploaded.failed.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            };
+            let index = (index + offset).min(self.contents.len());
+            self.contents.insert(
+                index,
+                Content {
+                    parts: vec![part],
+                    role: Some(Role::User),
+                    ..Default::default()
+                },
+            );
+        }
+        Ok(uploaded)
+    }
+}
+
+/// Files to upload when a request is executed
+#[derive(Default)]
+pub(crate) struct PendingUploads {
+    /// Each file with the number of messages added before it
+    files: Vec<(usize, PendingFile)>,
+    lifetime: Duration,
+}
+
+/// A file to add to a request when it is executed
+enum PendingFile {
+    Upload(FileUpload),
+    ImageUrl(String),
+}
+
+impl PendingFile {
+    /// Upload or download the file and return the part to send, noting the
+    /// names of uploaded files
+    async fn resolve(self, client: &Arc<GeminiClient>, uploaded: &mut Vec<String>) -> Result<Part> {
+        let upload = match self {
+            PendingFile::Upload(upload) => upload,
+            PendingFile::ImageUrl(url) => {
+                let (mime_type, bytes) = fetch_image(client, &url).await?;
+                if bytes.len() <= REMOTE_IMAGE_INLINE_LIMIT {
+                    return Ok(Part::InlineData {
+                        inline_data: Blob::from_vec(mime_type, bytes),
+                        video_metadata: None,
+                    });
+                }
+                FileUpload::from_bytes(client.clone(), url, bytes).with_mime_type(mime_type)
+            }
+        };
+        let file = upload.execute().await?;
+        uploaded.push(file.name.clone());
+        Ok(wait_until_active(client, file).await?.to_part())
+    }
+}
+
+/// Download an image, checking its size and type, and return its MIME type
+/// and bytes
+async fn fetch_image(client: &GeminiClient, url: &str) -> Result<(String, Vec<u8>)> {
+    let fail = |reason: String| Error::RequestError(format!("Cannot fetch {}: {}", url, reason));
+    let parsed = Url::parse(url).map_err(|e| fail(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(fail("only http and https URLs are supported".to_string()));
+    }
+    let mut response = client.http().get(parsed).send().await?;
+    if !response.status().is_success() {
+        return Err(fail(format!("the server answered {}", response.status())));
+    }
+    let too_large = || {
+        fail(format!(
+            "the image is larger than {} bytes",
+            MAX_REMOTE_IMAGE_SIZE
+        ))
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_REMOTE_IMAGE_SIZE as u64)
+    {
+        return Err(too_large());
+    }
+    let declared = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+    if let Some(declared) = &declared {
+        if !declared.starts_with("image/") && declared != "application/octet-stream" {
+            return Err(fail(format!("the content type is {}", declared)));
+        }
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_REMOTE_IMAGE_SIZE {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let mime_type = match mime::sniff_mime_type(&bytes) {
+        Some(sniffed) if sniffed.starts_with("image/") => sniffed.to_string(),
+        Some(sniffed) => return Err(fail(format!("the content is {}", sniffed))),
+        None => declared
+            .filter(|declared| declared.starts_with("image/"))
+            .ok_or_else(|| fail("the content is not a known image type".to_string()))?,
+    };
+    Ok((mime_type, bytes))
+}
+
+impl PendingUploads {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Files uploaded for a request, deleted when this is dropped
+///
+/// Deletion happens in the background on the current Tokio runtime;
+/// failures are ignored since the API expires files anyway.
+pub(crate) struct UploadedFiles {
+    client: Arc<GeminiClient>,
+    names: Vec<String>,
+    lifetime: Duration,
+    failed: AtomicBool,
+}
+
+impl UploadedFiles {
+    /// Note how the request went; files of a failed request are deleted
+    /// without waiting for their lifetime
+    pub(crate) fn note_result<T>(&self, result: &Result<T>) {
+        if result.is_err() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for UploadedFiles {
+    fn drop(&mut self) {
+        if self.names.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let client = self.client.clone();
+        let names = std::mem::take(&mut self.names);
+        let delay = match self.failed.load(Ordering::Relaxed) {
+            true => Duration::ZERO,
+            false => self.lifetime,
+        };
+        runtime.spawn(async move {
+            tokio::time::sleep(delay).await;
+            for name in names {
+                let _ = client.delete(&name).await;
+            }
+        });
+    }
+}
+
+/// Wait while the API processes an uploaded file
+async fn wait_until_active(client: &GeminiClient, mut file: FileInfo) -> Result<FileInfo> {
+    let started = std::time::Instant::now();
+    while file.state.as_deref() == Some("PROCESSING") {
+        if started.elapsed() > PROCESSING_TIMEOUT {
+            return Err(Error::RequestError(format!(
+                "{} was still being processed after {:?}",
+                file.name, PROCESSING_TIMEOUT
+            )));
+        }
+        tokio::time::sleep(PROCESSING_POLL_INTERVAL).await;
+        file = client.get(&file.name, &[]).await?.json().await?;
+    }
+    if file.state.as_deref() == Some("FAILED") {
+        return Err(Error::RequestError(format!(
+            "The API could not process {}",
+            file.name
+        )));
+    }
+    Ok(file)
+}
+
+impl FileUpload {
+    fn new(client: Arc<GeminiClient>, path: impl Into<PathBuf>) -> Self {
+        Self::from_source(client, UploadSource::File(path.into()))
+    }
+
+    /// Upload bytes held in memory; `origin` names them in errors
+    pub(crate) fn from_bytes(
+        client: Arc<GeminiClient>,
+        origin: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let origin = origin.into();
+        Self::from_source(client, UploadSource::Bytes { origin, bytes })
+    }
+
+    fn from_source(client: Arc<GeminiClient>, source: UploadSource) -> Self {
+        Self {
+            client,
+            source,
+            mime_type: None,
+            display_name: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_retries: DEFAULT_CHUNK_RETRIES,
+            progress: None,
+            session_file: None,
+        }
+    }
+
+    /// Set the MIME type instead of detecting it
+    pub fn with_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Set the name the file is shown with
+    pub fn with_display_name(mut self, name: impl Into<String>) -> Self {
+        self.display_name = Some(name.into());
+        self
+    }
+
+    /// Set the chunk size, rounded up to a multiple of 256 KiB
+    ///
+    /// Larger chunks are faster on good connections; smaller ones lose less
+    /// when a chunk has to be sent again. The default is 8 MiB.
+    pub fn with_chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(1).div_ceil(CHUNK_GRANULARITY) * CHUNK_GRANULARITY;
+        self
+    }
+
+    /// Set how often a failing chunk is retried before giving up
+    pub fn with_chunk_retries(mut self, retries: u32) -> Self {
+        self.chunk_retries = retries;
+        self
+    }
+
+    /// Call `progress(bytes_sent, total_bytes)` as the upload advances
+    ///
+    /// Called before the first chunk, after every chunk and once more when
+    /// the upload is complete.
+    pub fn with_progress(mut self, progress: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Keep the upload URI in a file so a restarted process can resume
+    ///
+    /// If the file holds a session for the same, unchanged file, the upload
+    /// continues from the offset the server has; otherwise a new upload is
+    /// started. The file is removed once the upload completes.
+    pub fn with_session_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.session_file = Some(path.into());
+        self
+    }
+
+    /// Upload the file and return its metadata
+    pub async fn execute(self) -> Result<FileInfo> {
+        let (size, modified_secs) = match &self.source {
+            UploadSource::File(path) => {
+                let metadata = fs::metadata(path).map_err(|e| self.io_error(e))?;
+                let modified_secs = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |age| age.as_secs());
+                (metadata.len(), modified_secs)
+            }
+            UploadSource::Bytes { bytes, .. } => (bytes.len() as u64, 0),
+        };
+
+        let resumed = match self.load_session(size, modified_secs) {
+            Some(session) => match self.query(&session.upload_url).await {
+                Ok(Progress::Active(offset)) => Some((session.upload_url, offset)),
+                Ok(Progress::Done(file)) => return self.finish(file),
+                // The session expired or was never valid; start over
+                Err(_) => None,
+            },
+            None => None,
+        };
+        let (upload_url, mut offset) = match resumed {
+            Some(resumed) => resumed,
+            None => {
+                let upload_url = self.start(size, &self.mime_type()?).await?;
+                self.save_session(&UploadSession {
+                    upload_url: upload_url.clone(),
+                    size,
+                    modified_secs,
+                })?;
+                (upload_url, 0)
+            }
+        };
+
+        let mut reader: Box<dyn ReadSeek + '_> = match &self.source {
+            UploadSource::File(path) => {
+                Box::new(fs::File::open(path).map_err(|e| self.io_error(e))?)
+            }
+            UploadSource::Bytes { bytes, .. } => Box::new(Cursor::new(&bytes[..])),
+        };
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        loop {
+            self.report(offset, size);
+            chunk.clear();
+            reader
+                .seek(SeekFrom::Start(offset))
+                .map_err(|e| self.io_error(e))?;
+            (&mut reader)
+                .take(self.chunk_size as u64)
+                .read_to_end(&mut chunk)
+                .map_err(|e| self.io_error(e))?;
+            let last = offset + chunk.len() as u64 >= size;
+
+            let mut retries = 0;
+            let result = loop {
+                match self.send_chunk(&upload_url, offset, &chunk, last).await {
+                    Ok(result) => break result,
+                    Err(e) if retries < self.chunk_retries && retryable(&e) => {
+                        retries += 1;
+                        tokio::time::sleep(retry::backoff(retries)).await;
+                        // Part of the chunk may have arrived
+                        match self.query(&upload_url).await {
+                            Ok(Progress::Active(received)) => {
+                                if received != offset {
+                                    break Progress::Active(received);
+                                }
+                            }
+                            Ok(done) => break done,
+                            Err(_) => {}
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            match result {
+                Progress::Active(received) => offset = received,
+                Progress::Done(file) => {
+                    self.report(size, size);
+                    return self.finish(file);
+                }
+            }
+        }
+    }
+
+    /// Ask for an upload URI
+    async fn start(&self, size: u64, mime_type: &str) -> Result<String> {
+        let body = match &self.display_name {
+            Some(name) => serde_json::json!({ "file": { "displayName": name } }),
+            None => serde_json::json!({}),
+        };
+        let response = self
+            .client
+            .http()
+            .post(self.client.upload_url()?)
+            .header(API_KEY_HEADER, self.client.api_key_header()?)
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", size)
+            .header("X-Goog-Upload-Header-Content-Type", mime_type)
+            .json(&body)
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+        response
+            .headers()
+            .get("x-goog-upload-url")
+            .and_then(|url| url.to_str().ok())
+            .map(without_key)
+            .ok_or_else(|| Error::RequestError("The upload response has no upload URL".to_string()))
+    }
+
+    /// Send one chunk, finalizing the upload with the last one
+    async fn send_chunk(
+        &self,
+        upload_url: &str,
+        offset: u64,
+        chunk: &[u8],
+        last: bool,
+    ) -> Result<Progress> {
+        let command = if last { "upload, finalize" } else { "upload" };
+        let response = self
+            .client
+            .http()
+            .post(upload_url)
+            .header(API_KEY_HEADER, self.client.api_key_header()?)
+            .header("X-Goog-Upload-Command", command)
+            .header("X-Goog-Upload-Offset", offset)
+            .body(chunk.to_vec())
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+        if last {
+            Ok(Progress::Done(parse_file(response).await?))
+        } else {
+            Ok(Progress::Active(offset + chunk.len() as u64))
+        }
+    }
+
+    /// Ask how much of the file the server has
+    async fn query(&self, upload_url: &str) -> Result<Progress> {
+        let response = self
+            .client
+            .http()
+            .post(upload_url)
+            .header(API_KEY_HEADER, self.client.api_key_header()?)
+            .header("X-Goog-Upload-Command", "query")
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        if header("x-goog-upload-status").as_deref() == Some("final") {
+            return Ok(Progress::Done(parse_file(response).await?));
+        }
+        header("x-goog-upload-size-received")
+            .and_then(|received| received.parse().ok())
+            .map(Progress::Active)
+            .ok_or_else(|| {
+                Error::RequestError("The upload status has no received size".to_string())
+            })
+    }
+
+    /// The type that was set, or the detected one
+    fn mime_type(&self) -> Result<String> {
+        if let Some(mime_type) = &self.mime_type {
+            return Ok(mime_type.clone());
+        }
+        let detected = match &self.source {
+            UploadSource::File(path) => {
+                let mut head = Vec::with_capacity(SNIFF_LENGTH);
+                fs::File::open(path)
+                    .and_then(|file| file.take(SNIFF_LENGTH as u64).read_to_end(&mut head))
+                    .map_err(|e| self.io_error(e))?;
+                mime::resolve(path, &head, None)
+            }
+            UploadSource::Bytes { bytes, .. } => mime::sniff_mime_type(bytes).map(str::to_string),
+        };
+        detected.ok_or_else(|| {
+            Error::RequestError(format!(
+                "Cannot tell the type of {}; set it with with_mime_type",
+                self.origin()
+            ))
+        })
+    }
+
+    fn report(&self, sent: u64, total: u64) {
+        if let Some(progress) = &self.progress {
+            progress(sent, total);
+        }
+    }
+
+    fn load_session(&self, size: u64, modified_secs: u64) -> Option<UploadSession> {
+        let text = fs::read_to_string(self.session_file.as_ref()?).ok()?;
+        let mut session: UploadSession = serde_json::from_str(&text).ok()?;
+        // Sessions saved by older versions may still carry the key
+        session.upload_url = without_key(&session.upload_url);
+        (session.size == size && session.modified_secs == modified_secs).then_some(session)
+    }
+
+    fn save_session(&self, session: &UploadSession) -> Result<()> {
+        let Some(path) = &self.session_file else {
+            return Ok(());
+        };
+        fs::write(path, serde_json::to_vec(session)?).map_err(|e| {
+            Error::RequestError(format!(
+                "Cannot write upload session {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    fn finish(&self, file: FileInfo) -> Result<FileInfo> {
+        if let Some(path) = &self.session_file {
+            let _ = fs::remove_file(path);
+        }
+        Ok(file)
+    }
+
+    /// The file or URL being uploaded, for messages
+    fn origin(&self) -> String {
+        match &self.source {
+            UploadSource::File(path) => path.display().to_string(),
+            UploadSource::Bytes { origin, .. } => origin.clone(),
+        }
+    }
+
+    fn io_error(&self, error: std::io::Error) -> Error {
+        Error::RequestError(format!("Cannot read {}: {}", self.origin(), error))
+    }
+}
+
+/// A source chunks can be read from at any offset
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Where an upload stands
+enum Progress {
+    /// The server has this many bytes
+    Active(u64),
+    /// The upload is complete
+    Done(FileInfo),
+}
+
+async fn parse_file(response: reqwest::Response) -> Result<FileInfo> {
+    #[derive(Deserialize)]
+    struct Uploaded {
+        file: FileInfo,
+    }
+    Ok(response.json::<Uploaded>().await?.file)
+}
+
+/// Failures worth sending a chunk again for: timeouts, failed
+/// connections, and 408, 429 and 5xx statuses
+fn retryable(error: &Error) -> bool {
+    match error.root() {
+        Error::ApiError { status_code, .. } => matches!(status_code, 408 | 429 | 500..=599),
+        Error::HttpError(e) => e.is_timeout() || e.is_connect(),
+        _ => false,
+    }
+}
+
+/// An upload URL without the `key` query parameter
+fn without_key(upload_url: &str) -> String {
+    let Ok(mut url) = Url::parse(upload_url) else {
+        return upload_url.to_string();
+    };
+    if url.query_pairs().any(|(name, _)| name == "key") {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| name != "key")
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        url.set_query(None);
+        if !pairs.is_empty() {
+            url.query_pairs_mut().extend_pairs(pairs);
+        }
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_upload_urls_drop_the_api_key() {
+        assert_eq!(
+            without_key("https://example.com/upload/v1beta/files?key=secret&upload_id=abc&upload_protocol=resumable"),
+            "https://example.com/upload/v1beta/files?upload_id=abc&upload_protocol=resumable"
+        );
+        assert_eq!(
+            without_key("https://example.com/upload/v1beta/files?key=secret"),
+            "https://example.com/upload/v1beta/files"
+        );
+        assert_eq!(
+            without_key("https://example.com/upload?upload_id=abc"),
+            "https://example.com/upload?upload_id=abc"
+        );
+    }
+
+    #[test]
+    fn only_transient_failures_are_retried() {
+        let status = |status_code| Error::ApiError {
+            status_code,
+            message: String::new(),
+            retry_after: None,
+        };
+        for code in [408, 429, 500, 503, 599] {
+            assert!(retryable(&status(code)), "{code}");
+        }
+        for code in [400, 401, 404] {
+            assert!(!retryable(&status(code)), "{code}");
+        }
+        let decode = serde_json::from_str::<FileInfo>("{").unwrap_err();
+        assert!(!retryable(&Error::JsonError(decode)));
+    }
+}
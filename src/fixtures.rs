@@ -0,0 +1,126 @@
+//! Canned responses for unit tests of code built on this crate
+//!
+//! Each function returns a [`GenerationResponse`] shaped like a real API
+//! response, so tests can feed handlers, parsers and UI code without a
+//! network or hand-written JSON.
+
+use crate::models::GenerationResponse;
+use serde_json::{json, Value};
+
+/// A complete text answer
+pub fn text(text: &str) -> GenerationResponse {
+    parse(json!({
+        "candidates": [{
+            "content": { "role": "model", "parts": [{ "text": text }] },
+            "finishReason": "STOP",
+            "index": 0,
+            "safetyRatings": negligible_ratings(),
+        }],
+        "usageMetadata": usage(text),
+        "modelVersion": "gemini-2.0-flash",
+        "responseId": "fixture-text",
+    }))
+}
+
+/// A single function call
+pub fn function_call(name: &str, args: Value) -> GenerationResponse {
+    function_calls(&[(name, args)])
+}
+
+/// Several function calls in one turn, as returned for parallel calling
+pub fn function_calls(calls: &[(&str, Value)]) -> GenerationResponse {
+    let parts: Vec<Value> = calls
+        .iter()
+        .map(|(name, args)| json!({ "functionCall": { "name": name, "args": args } }))
+        .collect();
+    parse(json!({
+        "candidates": [{
+            "content": { "role": "model", "parts": parts },
+            "finishReason": "STOP",
+            "index": 0,
+        }],
+        "usageMetadata": {
+            "promptTokenCount": 40,
+            "candidatesTokenCount": 10 * calls.len(),
+            "totalTokenCount": 40 + 10 * calls.len(),
+        },
+        "responseId": "fixture-function-calls",
+    }))
+}
+
+/// A prompt blocked by the safety filters, without candidates
+///
+/// `category` is an API category name such as
+/// `HARM_CATEGORY_HARASSMENT`; it is rated `HIGH`.
+pub fn safety_block(category: &str) -> GenerationResponse {
+    let mut ratings = negligible_ratings();
+    for rating in ratings.as_array_mut().into_iter().flatten() {
+        if rating["category"] == category {
+            rating["probability"] = json!("HIGH");
+        }
+    }
+    parse(json!({
+        "promptFeedback": { "blockReason": "SAFETY", "safetyRatings": ratings },
+        "usageMetadata": { "promptTokenCount": 12, "totalTokenCount": 12 },
+        "responseId": "fixture-safety-block",
+    }))
+}
+
+/// An answer cut off at the output token limit
+pub fn max_tokens(partial_text: &str) -> GenerationResponse {
+    let mut response = text(partial_text);
+    response.candidates[0].finish_reason = Some("MAX_TOKENS".to_string());
+    response.response_id = Some("fixture-max-tokens".to_string());
+    response
+}
+
+/// The chunks of a streamed answer, split into roughly `chunks` pieces
+///
+/// Every chunk carries part of the text; the last one adds the finish
+/// reason and usage, like `streamGenerateContent` does. Joining the chunk
+/// texts gives back `text`.
+pub fn stream_chunks(text: &str, chunks: usize) -> Vec<GenerationResponse> {
+    let chars: Vec<char> = text.chars().collect();
+    let size = chars.len().div_ceil(chunks.max(1)).max(1);
+    let pieces: Vec<String> = chars.chunks(size).map(|c| c.iter().collect()).collect();
+    let last = pieces.len().saturating_sub(1);
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let mut candidate = json!({
+                "content": { "role": "model", "parts": [{ "text": piece }] },
+                "index": 0,
+            });
+            let mut chunk = json!({ "responseId": "fixture-stream" });
+            if i == last {
+                candidate["finishReason"] = json!("STOP");
+                chunk["usageMetadata"] = usage(text);
+            }
+            chunk["candidates"] = json!([candidate]);
+            parse(chunk)
+        })
+        .collect()
+}
+
+fn negligible_ratings() -> Value {
+    json!([
+        { "category": "HARM_CATEGORY_HATE_SPEECH", "probability": "NEGLIGIBLE" },
+        { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "NEGLIGIBLE" },
+        { "category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE" },
+        { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "probability": "NEGLIGIBLE" },
+    ])
+}
+
+fn usage(text: &str) -> Value {
+    let candidates = crate::tokens::estimate_tokens(text);
+    json!({
+        "promptTokenCount": 8,
+        "candidatesTokenCount": candidates,
+        "totalTokenCount": 8 + candidates,
+    })
+}
+
+fn parse(value: Value) -> GenerationResponse {
+    serde_json::from_value(value).expect("fixture matches GenerationResponse")
+}
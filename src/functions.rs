@@ -0,0 +1,456 @@
+use crate::{
+    client::GeminiClient,
+    tokens::estimate_tokens,
+    tools::{FunctionCall, FunctionDeclaration, FunctionResponse},
+    Result,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by [`FunctionHandler::call`]
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<serde_json::Value>> + Send + 'a>>;
+
+/// Executes a function call requested by the model
+///
+/// Implemented for async closures taking a [`FunctionCall`], so most handlers
+/// can be registered without a dedicated type. Other types return their
+/// async block boxed, e.g. `Box::pin(async move { ... })`.
+pub trait FunctionHandler: Send + Sync {
+    /// Run the function and return its result as a JSON value
+    fn call(&self, call: FunctionCall) -> HandlerFuture<'_>;
+}
+
+impl<F, Fut> FunctionHandler for F
+where
+    F: Fn(FunctionCall) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+{
+    fn call(&self, call: FunctionCall) -> HandlerFuture<'_> {
+        Box::pin((self)(call))
+    }
+}
+
+/// Decision taken by a [`ToolCallGuard`] for a single function call
+#[derive(Debug, Clone)]
+pub enum GuardDecision {
+    /// Dispatch the call unchanged
+    Allow,
+    /// Do not dispatch the call; the reason is reported to the model
+    Deny(String),
+    /// Dispatch this call instead, e.g. with sanitized arguments
+    Modify(FunctionCall),
+}
+
+/// Inspects function calls before they are dispatched to their handlers
+///
+/// Guards run in registration order; the first denial wins and modified
+/// calls are passed on to the next guard.
+pub trait ToolCallGuard: Send + Sync {
+    /// Decide whether and how the call may be dispatched
+    fn check(&self, call: &FunctionCall) -> GuardDecision;
+}
+
+impl<F> ToolCallGuard for F
+where
+    F: Fn(&FunctionCall) -> GuardDecision + Send + Sync,
+{
+    fn check(&self, call: &FunctionCall) -> GuardDecision {
+        (self)(call)
+    }
+}
+
+/// Functions the model may call, together with their handlers
+///
+/// Attach a registry with `ContentBuilder::with_function_registry` and
+/// `execute()` will dispatch the model's function calls and send the results
+/// back until the model answers without calling a function.
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: Vec<(FunctionDeclaration, Arc<dyn FunctionHandler>)>,
+    guards: Vec<Arc<dyn ToolCallGuard>>,
+    result_policy: Option<ToolResultPolicy>,
+}
+
+impl FunctionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a function and the handler that executes it
+    pub fn register(
+        mut self,
+        declaration: FunctionDeclaration,
+        handler: impl FunctionHandler + 'static,
+    ) -> Self {
+        self.functions
+            .retain(|(existing, _)| existing.name != declaration.name);
+        self.functions.push((declaration, Arc::new(handler)));
+        self
+    }
+
+    /// Install a guard that can deny or rewrite calls before dispatch
+    pub fn with_guard(mut self, guard: impl ToolCallGuard + 'static) -> Self {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Limit the size of function results sent back to the model
+    ///
+    /// Applied by the automatic function calling loops of `execute()` and
+    /// `execute_stream_parts()`; [`dispatch`](Self::dispatch) returns
+    /// results unchanged.
+    pub fn with_result_policy(mut self, policy: ToolResultPolicy) -> Self {
+        self.result_policy = Some(policy);
+        self
+    }
+
+    /// Whether no functions are registered
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// The declarations of all registered functions
+    pub fn declarations(&self) -> Vec<FunctionDeclaration> {
+        self.functions.iter().map(|(d, _)| d.clone()).collect()
+    }
+
+    /// Run the guards and the matching handler for a call
+    ///
+    /// Denials, unknown functions and handler errors are turned into an
+    /// `{"error": ...}` response so the model can react to them.
+    pub async fn dispatch(&self, call: FunctionCall) -> FunctionResponse {
+        let name = call.name.clone();
+        let mut call = call;
+        for guard in &self.guards {
+            match guard.check(&call) {
+                GuardDecision::Allow => {}
+                GuardDecision::Modify(modified) => call = modified,
+                GuardDecision::Deny(reason) => {
+                    return error_response(name, format!("Call denied: {}", reason));
+                }
+            }
+        }
+
+        let handler = self
+            .functions
+            .iter()
+            .find(|(declaration, _)| declaration.name == call.name)
+            .map(|(_, handler)| handler.clone());
+        match handler {
+            Some(handler) => match handler.call(call).await {
+                Ok(response) => FunctionResponse::new(name, response),
+                Err(e) => error_response(name, e.to_string()),
+            },
+            None => error_response(name, format!("Unknown function: {}", call.name)),
+        }
+    }
+
+    /// Dispatch a call and apply the result policy, for the automatic loops
+    pub(crate) async fn dispatch_limited(
+        &self,
+        client: &Arc<GeminiClient>,
+        call: FunctionCall,
+    ) -> FunctionResponse {
+        let response = self.dispatch(call).await;
+        match &self.result_policy {
+            Some(policy) => policy.apply(client, response).await,
+            None => response,
+        }
+    }
+}
+
+impl std::fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &self.declarations())
+            .field("guards", &self.guards.len())
+            .field("result_policy", &self.result_policy)
+            .finish()
+    }
+}
+
+/// How a function result over the limit is cut down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Keep the beginning (the default)
+    #[default]
+    Head,
+    /// Keep the beginning and the end, dropping the middle
+    ///
+    /// Suits logs and command output, where the end often matters most.
+    HeadAndTail,
+}
+
+/// Keeps oversized function results from filling the context window
+///
+/// Byte limits apply to the function response as serialized, name
+/// included; token limits to the result. One over the limit is either
+/// summarized by the model, if enabled, or truncated; the model then sees
+/// an object like `{"result": "<cut JSON text>", "truncated": true,
+/// "original_bytes": 5242880}` or `{"summary": "...", "original_bytes":
+/// 5242880}` instead of the original value, and that object, escaping
+/// included, stays within the byte limit. Attach a policy with
+/// [`FunctionRegistry::with_result_policy`].
+#[derive(Debug, Clone, Default)]
+pub struct ToolResultPolicy {
+    max_bytes: Option<usize>,
+    max_tokens: Option<usize>,
+    strategy: TruncationStrategy,
+    summarize: bool,
+    summary_model: Option<String>,
+}
+
+impl ToolResultPolicy {
+    /// Create a policy without limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit results to a number of bytes of JSON
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Limit results to a number of tokens
+    ///
+    /// Tokens are estimated locally with [`estimate_tokens`], so the limit
+    /// is approximate.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Choose which part of a result to keep when truncating
+    pub fn with_strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Have the request's model summarize results over the limit
+    ///
+    /// This makes an extra `generateContent` call with the full result for
+    /// each oversized one. If the call fails or the summary is still too
+    /// long, the result is truncated instead.
+    pub fn with_summarization(mut self) -> Self {
+        self.summarize = true;
+        self
+    }
+
+    /// Summarize results over the limit with another model, e.g. a cheaper
+    /// one with a large context window
+    pub fn with_summary_model(mut self, model: impl Into<String>) -> Self {
+        self.summarize = true;
+        self.summary_model = Some(model.into());
+        self
+    }
+
+    /// Summarize or truncate a result if it is over the limit
+    async fn apply(
+        &self,
+        client: &Arc<GeminiClient>,
+        response: FunctionResponse,
+    ) -> FunctionResponse {
+        let Some(value) = &response.response else {
+            return response;
+        };
+        let text = value.to_string();
+        if self.fits_bytes(&response) && self.fits_tokens(&text) {
+            return response;
+        }
+
+        if self.summarize {
+            if let Some(summary) = self.summarize(client, &response.name, &text).await {
+                let summarized = FunctionResponse::new(
+                    response.name.clone(),
+                    serde_json::json!({ "summary": summary, "original_bytes": text.len() }),
+                );
+                if self.fits_bytes(&summarized) {
+                    return summarized;
+                }
+            }
+        }
+        self.truncate(response.name, &text)
+    }
+
+    fn fits_bytes(&self, response: &FunctionResponse) -> bool {
+        self.max_bytes
+            .is_none_or(|max| serialized_len(response) <= max)
+    }
+
+    fn fits_tokens(&self, text: &str) -> bool {
+        self.max_tokens
+            .is_none_or(|max| estimate_tokens(text) <= max)
+    }
+
+    /// Bytes of `text` that fit the limits, approximately for token limits
+    fn byte_budget(&self, text: &str) -> usize {
+        let mut budget = self.max_bytes.unwrap_or(text.len());
+        if let Some(max_tokens) = self.max_tokens {
+            let tokens = estimate_tokens(text).max(1);
+            budget = budget.min(text.len() * max_tokens / tokens);
+        }
+        budget.min(text.len())
+    }
+
+    async fn summarize(
+        &self,
+        client: &Arc<GeminiClient>,
+        name: &str,
+        text: &str,
+    ) -> Option<String> {
+        // Leave room for the wrapping object
+        let words = self.byte_budget(text).saturating_sub(WRAPPER_ALLOWANCE) / 8;
+        let prompt = format!(
+            "The tool `{}` returned the JSON below, which is too long to use as is. \
+             Summarize it in at most {} words, keeping the identifiers, numbers and \
+             facts most likely to matter to the task. Reply with the summary only.\n\n{}",
+            name, words, text
+        );
+        let summary = match &self.summary_model {
+            Some(model) => client.for_model(model.clone()).generate_text(prompt).await,
+            None => client.generate_text(prompt).await,
+        }
+        .ok()?;
+        let summary = summary.trim();
+        (!summary.is_empty() && self.fits_tokens(summary)).then(|| summary.to_string())
+    }
+
+    /// Wrap as much of `text` as fits the limits in a truncation notice
+    fn truncate(&self, name: String, text: &str) -> FunctionResponse {
+        let wrap = |kept: &str| {
+            FunctionResponse::new(
+                name.clone(),
+                serde_json::json!({
+                    "result": kept,
+                    "truncated": true,
+                    "original_bytes": text.len(),
+                }),
+            )
+        };
+        let mut budget = self.byte_budget(text);
+        let Some(max) = self.max_bytes else {
+            return wrap(&self.cut(text, budget));
+        };
+        // Escaping makes the kept text longer in the response than in
+        // `text`, so shrink it by however much the response is over
+        loop {
+            let wrapped = wrap(&self.cut(text, budget));
+            let over = serialized_len(&wrapped).saturating_sub(max);
+            if over == 0 {
+                return wrapped;
+            }
+            if budget == 0 {
+                // Not even the notice fits; send it anyway
+                return wrap("");
+            }
+            budget = budget.saturating_sub(over);
+        }
+    }
+
+    /// The first `budget` bytes of `text`, or its start and end
+    fn cut(&self, text: &str, budget: usize) -> String {
+        match self.strategy {
+            TruncationStrategy::Head => text[..floor_char_boundary(text, budget)].to_string(),
+            TruncationStrategy::HeadAndTail => {
+                let head = floor_char_boundary(text, budget / 2);
+                let mut tail = text.len() - budget / 2;
+                while !text.is_char_boundary(tail) {
+                    tail += 1;
+                }
+                format!(
+                    "{} [... {} bytes omitted ...] {}",
+                    &text[..head],
+                    tail - head,
+                    &text[tail..]
+                )
+            }
+        }
+    }
+}
+
+/// Room the `summary` and `original_bytes` fields take around a summary
+const WRAPPER_ALLOWANCE: usize = 64;
+
+/// Length of a function response as sent
+fn serialized_len(response: &FunctionResponse) -> usize {
+    serde_json::to_string(response).map_or(0, |json| json.len())
+}
+
+/// The largest char boundary in `text` at or before `index`
+pub(crate) fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn error_response(name: String, message: String) -> FunctionResponse {
+    FunctionResponse::new(name, serde_json::json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockGemini;
+
+    /// A result whose JSON text is full of characters that need escaping
+    fn quoted_result() -> (FunctionResponse, String) {
+        let rows: Vec<String> = (0..400)
+            .map(|i| format!("row {i}: \"quoted\"\tand\\slashed\n«ünïcode»"))
+            .collect();
+        let value = serde_json::json!({ "rows": rows });
+        let text = value.to_string();
+        (FunctionResponse::new("search_logs", value), text)
+    }
+
+    #[test]
+    fn truncated_results_stay_within_max_bytes() {
+        let (_, text) = quoted_result();
+        for strategy in [TruncationStrategy::Head, TruncationStrategy::HeadAndTail] {
+            for max_bytes in [200, 1000, 4096] {
+                let policy = ToolResultPolicy::new()
+                    .with_max_bytes(max_bytes)
+                    .with_strategy(strategy);
+                let response = policy.truncate("search_logs".to_string(), &text);
+                let len = serialized_len(&response);
+                assert!(len <= max_bytes, "{strategy:?}: {len} > {max_bytes}");
+                let wrapped = response.response.unwrap();
+                assert!(!wrapped["result"].as_str().unwrap().is_empty());
+                assert_eq!(wrapped["truncated"], true);
+                assert_eq!(wrapped["original_bytes"], text.len());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn summaries_stay_within_max_bytes() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push_text("400 log rows, all quoted; none report errors.")
+            .push_text("a summary far too long to fit the limit ".repeat(20));
+        let client = mock.client().client;
+        let (response, _) = quoted_result();
+
+        for strategy in [TruncationStrategy::Head, TruncationStrategy::HeadAndTail] {
+            let policy = ToolResultPolicy::new()
+                .with_max_bytes(300)
+                .with_strategy(strategy)
+                .with_summarization();
+            let result = policy.apply(&client, response.clone()).await;
+            assert!(serialized_len(&result) <= 300);
+            let wrapped = result.response.unwrap();
+            match strategy {
+                // The short summary is used
+                TruncationStrategy::Head => assert_eq!(
+                    wrapped["summary"],
+                    "400 log rows, all quoted; none report errors."
+                ),
+                // The long one doesn't fit, so the result is truncated
+                TruncationStrategy::HeadAndTail => assert_eq!(wrapped["truncated"], true),
+            }
+        }
+    }
+}
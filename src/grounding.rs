@@ -0,0 +1,258 @@
+use crate::functions::floor_char_boundary;
+use crate::models::{Candidate, GenerationResponse, Part};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Sources a grounded answer is based on, e.g. from the `google_search` tool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingMetadata {
+    /// The sources; supports refer to them by index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grounding_chunks: Vec<GroundingChunk>,
+    /// Which spans of the answer each source supports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grounding_supports: Vec<GroundingSupport>,
+    /// The search queries the model ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub web_search_queries: Vec<String>,
+    /// The search suggestions to display with the answer, as returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_entry_point: Option<serde_json::Value>,
+}
+
+/// A source of a grounded answer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingChunk {
+    /// A web page found by search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web: Option<WebSource>,
+    /// A document found by retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieved_context: Option<WebSource>,
+}
+
+impl GroundingChunk {
+    /// The URI of the source
+    pub fn uri(&self) -> Option<&str> {
+        self.source()?.uri.as_deref()
+    }
+
+    /// The title of the source
+    pub fn title(&self) -> Option<&str> {
+        self.source()?.title.as_deref()
+    }
+
+    fn source(&self) -> Option<&WebSource> {
+        self.web.as_ref().or(self.retrieved_context.as_ref())
+    }
+}
+
+/// Where a source can be found
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebSource {
+    /// The URI of the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// The title of the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A span of the answer and the sources that support it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingSupport {
+    /// The supported span
+    #[serde(default)]
+    pub segment: Segment,
+    /// Indices into [`GroundingMetadata::grounding_chunks`]
+    #[serde(default)]
+    pub grounding_chunk_indices: Vec<usize>,
+    /// Confidence of each source, in the order of the indices
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confidence_scores: Vec<f32>,
+}
+
+/// A span of a part's text, in bytes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    /// Index of the part the span is in
+    #[serde(default)]
+    pub part_index: usize,
+    /// Byte offset of the start of the span
+    #[serde(default)]
+    pub start_index: usize,
+    /// Byte offset of the end of the span, exclusive
+    #[serde(default)]
+    pub end_index: usize,
+    /// The text of the span
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// A stretch of answer text with the sources that support it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CitedSegment {
+    /// The text
+    pub text: String,
+    /// Indices into [`GroundingMetadata::grounding_chunks`], ascending;
+    /// empty for text no source supports
+    pub sources: Vec<usize>,
+}
+
+impl Candidate {
+    /// The answer text split at the spans the grounding sources support
+    ///
+    /// Joining the segment texts gives back the answer text. Each segment
+    /// lists the sources of every support covering it, so a UI can render
+    /// the text followed by superscript citations. Byte offsets are
+    /// rounded to character boundaries; without grounding metadata the
+    /// whole text is one segment without sources.
+    pub fn cited_segments(&self) -> Vec<CitedSegment> {
+        let supports = self
+            .grounding_metadata
+            .as_ref()
+            .map_or(&[][..], |m| &m.grounding_supports[..]);
+        let mut segments: Vec<CitedSegment> = Vec::new();
+        for (index, part) in self.content.parts.iter().enumerate() {
+            let Part::Text { text, .. } = part else {
+                continue;
+            };
+            let supports: Vec<_> = supports
+                .iter()
+                .filter(|s| s.segment.part_index == index)
+                .collect();
+            let span = |offset: usize| floor_char_boundary(text, offset);
+
+            let mut boundaries = BTreeSet::from([0, text.len()]);
+            for support in &supports {
+                boundaries.insert(span(support.segment.start_index));
+                boundaries.insert(span(support.segment.end_index));
+            }
+            let boundaries: Vec<usize> = boundaries.into_iter().collect();
+            for window in boundaries.windows(2) {
+                let (start, end) = (window[0], window[1]);
+                let sources: BTreeSet<usize> = supports
+                    .iter()
+                    .filter(|s| span(s.segment.start_index) <= start)
+                    .filter(|s| span(s.segment.end_index) >= end)
+                    .flat_map(|s| s.grounding_chunk_indices.iter().copied())
+                    .collect();
+                let sources: Vec<usize> = sources.into_iter().collect();
+                match segments.last_mut() {
+                    Some(last) if last.sources == sources => last.text.push_str(&text[start..end]),
+                    _ => segments.push(CitedSegment {
+                        text: text[start..end].to_string(),
+                        sources,
+                    }),
+                }
+            }
+        }
+        segments
+    }
+}
+
+impl GenerationResponse {
+    /// The first candidate's text split by grounding sources, see
+    /// [`Candidate::cited_segments`]
+    pub fn cited_segments(&self) -> Vec<CitedSegment> {
+        self.candidates
+            .first()
+            .map(Candidate::cited_segments)
+            .unwrap_or_default()
+    }
+
+    /// The grounding metadata of the first candidate
+    pub fn grounding_metadata(&self) -> Option<&GroundingMetadata> {
+        self.candidates.first()?.grounding_metadata.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A candidate with these parts and `(part, start, end, sources)`
+    /// supports
+    fn candidate(
+        parts: serde_json::Value,
+        supports: &[(usize, usize, usize, &[usize])],
+    ) -> Candidate {
+        let supports: Vec<_> = supports
+            .iter()
+            .map(|(part, start, end, sources)| {
+                json!({
+                    "segment": { "partIndex": part, "startIndex": start, "endIndex": end },
+                    "groundingChunkIndices": sources,
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "content": { "role": "model", "parts": parts },
+            "groundingMetadata": { "groundingSupports": supports },
+        }))
+        .unwrap()
+    }
+
+    fn cited(text: &str, sources: &[usize]) -> CitedSegment {
+        CitedSegment {
+            text: text.to_string(),
+            sources: sources.to_vec(),
+        }
+    }
+
+    #[test]
+    fn offsets_inside_multibyte_characters() {
+        // Bytes 3 and 14 are in the middle of the two `ü`s
+        let text = "Grüße aus München";
+        let candidate = candidate(json!([{ "text": text }]), &[(0, 3, 14, &[0])]);
+        assert_eq!(
+            candidate.cited_segments(),
+            [
+                cited("Gr", &[]),
+                cited("üße aus M", &[0]),
+                cited("ünchen", &[])
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_supports() {
+        let candidate = candidate(
+            json!([{ "text": "abcdefghij" }]),
+            &[(0, 0, 6, &[0]), (0, 3, 10, &[2, 1]), (0, 100, 200, &[3])],
+        );
+        assert_eq!(
+            candidate.cited_segments(),
+            [
+                cited("abc", &[0]),
+                cited("def", &[0, 1, 2]),
+                cited("ghij", &[1, 2])
+            ]
+        );
+    }
+
+    #[test]
+    fn supports_on_a_later_part() {
+        let candidate = candidate(
+            json!([
+                { "text": "First. " },
+                { "functionCall": { "name": "search", "args": {} } },
+                { "text": "Second. Third." }
+            ]),
+            &[(2, 0, 7, &[4])],
+        );
+        assert_eq!(
+            candidate.cited_segments(),
+            [
+                cited("First. ", &[]),
+                cited("Second.", &[4]),
+                cited(" Third.", &[])
+            ]
+        );
+    }
+}
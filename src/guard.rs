@@ -0,0 +1,204 @@
+use crate::{
+    models::{Content, GenerateContentRequest, Part, Role},
+    tokens,
+    tools::FunctionResponse,
+    Error, Result,
+};
+use std::borrow::Cow;
+
+/// What a [`PromptGuard`] does with a prompt over its token limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverLimit {
+    /// Reject the request (the default)
+    #[default]
+    Reject,
+    /// Drop the oldest messages until the prompt fits, keeping the
+    /// conversation starting at a user message; rejects the request if the
+    /// last message alone is too long
+    DropOldest,
+}
+
+/// Why a [`PromptGuard`] rejected a prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptViolation {
+    /// The estimated prompt size is over the limit
+    TooManyTokens {
+        /// Estimated prompt tokens
+        estimated: usize,
+        /// The guard's limit
+        limit: usize,
+    },
+    /// A message contains banned content
+    ///
+    /// The matched text itself is not included, since it is typically a
+    /// secret.
+    BannedContent {
+        /// Name of the rule that matched
+        rule: String,
+        /// Where the match is, e.g. `contents.2.parts.0` or
+        /// `systemInstruction.parts.0`
+        location: String,
+    },
+}
+
+impl std::fmt::Display for PromptViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptViolation::TooManyTokens { estimated, limit } => write!(
+                f,
+                "prompt is about {} tokens, the limit is {}",
+                estimated, limit
+            ),
+            PromptViolation::BannedContent { rule, location } => {
+                write!(f, "{} matches banned content rule {:?}", location, rule)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Matcher {
+    Text(String),
+    #[cfg(feature = "regex")]
+    Pattern(regex::Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Text(banned) => text.contains(banned.as_str()),
+            #[cfg(feature = "regex")]
+            Matcher::Pattern(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Client-wide checks run on every prompt before it is sent
+///
+/// Attach one with `GeminiBuilder::with_prompt_guard`. Requests that break
+/// a rule fail with [`Error::PromptRejected`] listing every violation, so
+/// nothing leaves the process. Text parts, function responses and the
+/// system instruction are searched for banned content; the token limit
+/// uses [`GenerateContentRequest::estimated_prompt_tokens`].
+#[derive(Clone, Default)]
+pub struct PromptGuard {
+    max_prompt_tokens: Option<(usize, OverLimit)>,
+    banned: Vec<(String, Matcher)>,
+}
+
+impl PromptGuard {
+    /// Create a guard without rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the estimated prompt size
+    pub fn with_max_prompt_tokens(mut self, limit: usize, action: OverLimit) -> Self {
+        self.max_prompt_tokens = Some((limit, action));
+        self
+    }
+
+    /// Reject prompts containing a piece of text, e.g. an internal hostname
+    pub fn with_banned_text(mut self, rule: impl Into<String>, text: impl Into<String>) -> Self {
+        self.banned.push((rule.into(), Matcher::Text(text.into())));
+        self
+    }
+
+    /// Reject prompts matching a regular expression, e.g. the shape of an
+    /// internal API token
+    #[cfg(feature = "regex")]
+    pub fn with_banned_pattern(mut self, rule: impl Into<String>, pattern: &str) -> Result<Self> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| Error::RequestError(format!("Invalid banned pattern: {}", e)))?;
+        self.banned.push((rule.into(), Matcher::Pattern(regex)));
+        Ok(self)
+    }
+
+    /// Check a request, dropping old messages if the guard is set to
+    pub fn check(&self, request: &mut GenerateContentRequest) -> Result<()> {
+        let mut violations = Vec::new();
+        if let Some((limit, action)) = self.max_prompt_tokens {
+            if action == OverLimit::DropOldest {
+                drop_oldest(request, limit);
+            }
+            let estimated = request.estimated_prompt_tokens();
+            if estimated > limit {
+                violations.push(PromptViolation::TooManyTokens { estimated, limit });
+            }
+        }
+
+        let system = request
+            .system_instruction
+            .iter()
+            .map(|content| ("systemInstruction".to_string(), content));
+        let contents = request
+            .contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| (format!("contents.{}", i), content));
+        for (path, content) in system.chain(contents) {
+            for (i, part) in content.parts.iter().enumerate() {
+                let text = match part {
+                    Part::Text { text, .. } => Cow::Borrowed(text.as_str()),
+                    Part::FunctionResponse {
+                        function_response:
+                            FunctionResponse {
+                                response: Some(response),
+                                ..
+                            },
+                    } => Cow::Owned(response.to_string()),
+                    _ => continue,
+                };
+                for (rule, matcher) in &self.banned {
+                    if matcher.is_match(&text) {
+                        violations.push(PromptViolation::BannedContent {
+                            rule: rule.clone(),
+                            location: format!("{}.parts.{}", path, i),
+                        });
+                    }
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::PromptRejected(violations))
+        }
+    }
+}
+
+impl std::fmt::Debug for PromptGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rules: Vec<_> = self.banned.iter().map(|(rule, _)| rule).collect();
+        f.debug_struct("PromptGuard")
+            .field("max_prompt_tokens", &self.max_prompt_tokens)
+            .field("banned", &rules)
+            .finish()
+    }
+}
+
+/// Drop messages from the front until the prompt fits or one is left
+fn drop_oldest(request: &mut GenerateContentRequest, limit: usize) {
+    let mut estimated = request.estimated_prompt_tokens();
+    let mut drop = 0;
+    while estimated > limit && drop + 1 < request.contents.len() {
+        estimated -= tokens::content_tokens(&request.contents[drop]);
+        drop += 1;
+        // Don't start with a model turn or a function response
+        while drop + 1 < request.contents.len() && !starts_turn(&request.contents[drop]) {
+            estimated -= tokens::content_tokens(&request.contents[drop]);
+            drop += 1;
+        }
+    }
+    request.contents.drain(..drop);
+}
+
+/// Whether a conversation may start with this message
+pub(crate) fn starts_turn(content: &Content) -> bool {
+    content.role != Some(Role::Model)
+        && !content
+            .parts
+            .iter()
+            .any(|part| matches!(part, Part::FunctionResponse { .. }))
+}
@@ -0,0 +1,57 @@
+use crate::{client::Gemini, model_info::ModelInfo, Error, Result};
+use std::time::{Duration, Instant};
+
+/// The outcome of [`Gemini::health_check`]
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// The client's model, e.g. `gemini-2.0-flash`
+    pub model: String,
+    /// Round-trip time of the check, including connection setup when the
+    /// client had no open connection yet
+    pub latency: Duration,
+    /// Whether the model exists and supports `generateContent`
+    pub model_available: bool,
+    /// What the API reports about the model, if it exists
+    pub model_info: Option<ModelInfo>,
+}
+
+impl Gemini {
+    /// Check the API key and the client's model, opening a connection
+    ///
+    /// Meant for service startup: makes one free `models.get` call, which
+    /// fails if the key is missing or rejected or the API is unreachable,
+    /// and leaves a warm TLS/HTTP2 connection for the first real request.
+    /// A model the API does not know is reported, not returned as an error.
+    pub async fn health_check(&self) -> Result<HealthReport> {
+        let model = self
+            .client
+            .model
+            .strip_prefix("models/")
+            .unwrap_or(&self.client.model)
+            .to_string();
+        let started = Instant::now();
+        let model_info = match self.get_model(&model).await {
+            Ok(info) => Some(info),
+            Err(e)
+                if matches!(
+                    e.root(),
+                    Error::ApiError {
+                        status_code: 404,
+                        ..
+                    }
+                ) =>
+            {
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(HealthReport {
+            latency: started.elapsed(),
+            model_available: model_info
+                .as_ref()
+                .is_some_and(|info| info.supports("generateContent")),
+            model,
+            model_info,
+        })
+    }
+}
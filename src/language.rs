@@ -0,0 +1,120 @@
+//! Language names and (optionally) detection for `with_response_language`
+
+/// ISO 639-1 code, ISO 639-3 code (as reported by the detector) and English name
+const LANGUAGES: &[(&str, &str, &str)] = &[
+    ("af", "afr", "Afrikaans"),
+    ("ar", "ara", "Arabic"),
+    ("az", "aze", "Azerbaijani"),
+    ("be", "bel", "Belarusian"),
+    ("bg", "bul", "Bulgarian"),
+    ("bn", "ben", "Bengali"),
+    ("ca", "cat", "Catalan"),
+    ("cs", "ces", "Czech"),
+    ("cy", "cym", "Welsh"),
+    ("da", "dan", "Danish"),
+    ("de", "deu", "German"),
+    ("el", "ell", "Greek"),
+    ("en", "eng", "English"),
+    ("eo", "epo", "Esperanto"),
+    ("es", "spa", "Spanish"),
+    ("et", "est", "Estonian"),
+    ("fa", "pes", "Persian"),
+    ("fi", "fin", "Finnish"),
+    ("fr", "fra", "French"),
+    ("gu", "guj", "Gujarati"),
+    ("he", "heb", "Hebrew"),
+    ("hi", "hin", "Hindi"),
+    ("hr", "hrv", "Croatian"),
+    ("hu", "hun", "Hungarian"),
+    ("hy", "hye", "Armenian"),
+    ("id", "ind", "Indonesian"),
+    ("it", "ita", "Italian"),
+    ("ja", "jpn", "Japanese"),
+    ("ka", "kat", "Georgian"),
+    ("km", "khm", "Khmer"),
+    ("kn", "kan", "Kannada"),
+    ("ko", "kor", "Korean"),
+    ("la", "lat", "Latin"),
+    ("lt", "lit", "Lithuanian"),
+    ("lv", "lav", "Latvian"),
+    ("mk", "mkd", "Macedonian"),
+    ("ml", "mal", "Malayalam"),
+    ("mr", "mar", "Marathi"),
+    ("my", "mya", "Burmese"),
+    ("nb", "nob", "Norwegian"),
+    ("ne", "nep", "Nepali"),
+    ("nl", "nld", "Dutch"),
+    ("no", "nob", "Norwegian"),
+    ("pa", "pan", "Punjabi"),
+    ("pl", "pol", "Polish"),
+    ("pt", "por", "Portuguese"),
+    ("ro", "ron", "Romanian"),
+    ("ru", "rus", "Russian"),
+    ("si", "sin", "Sinhala"),
+    ("sk", "slk", "Slovak"),
+    ("sl", "slv", "Slovenian"),
+    ("sr", "srp", "Serbian"),
+    ("sv", "swe", "Swedish"),
+    ("ta", "tam", "Tamil"),
+    ("te", "tel", "Telugu"),
+    ("th", "tha", "Thai"),
+    ("tl", "tgl", "Tagalog"),
+    ("tr", "tur", "Turkish"),
+    ("uk", "ukr", "Ukrainian"),
+    ("ur", "urd", "Urdu"),
+    ("uz", "uzb", "Uzbek"),
+    ("vi", "vie", "Vietnamese"),
+    ("zh", "cmn", "Chinese"),
+];
+
+/// Look up a language by ISO 639-1 code, ISO 639-3 code, BCP 47 tag or English name
+fn lookup(language: &str) -> Option<&'static (&'static str, &'static str, &'static str)> {
+    let language = language.trim();
+    let primary = language.split(['-', '_']).next().unwrap_or(language);
+    LANGUAGES.iter().find(|(iso1, iso3, name)| {
+        primary.eq_ignore_ascii_case(iso1)
+            || primary.eq_ignore_ascii_case(iso3)
+            || language.eq_ignore_ascii_case(name)
+    })
+}
+
+/// Human-readable name used in the injected instruction
+pub(crate) fn language_name(language: &str) -> String {
+    lookup(language)
+        .map(|(_, _, name)| name.to_string())
+        .unwrap_or_else(|| language.to_string())
+}
+
+/// The instruction appended to the system instruction
+pub(crate) fn instruction(language: &str, strong: bool) -> String {
+    let name = language_name(language);
+    if strong {
+        format!(
+            "IMPORTANT: Your previous reply was not written in {0}. Write your entire reply \
+             in {0}, regardless of the language of the input.",
+            name
+        )
+    } else {
+        format!("Always write your reply in {}.", name)
+    }
+}
+
+/// Detect the language of `text` if it reliably differs from `expected`
+///
+/// Returns the English name of the detected language. Unknown expected
+/// languages and unreliable detections are treated as a match.
+#[cfg(feature = "language-detection")]
+pub(crate) fn mismatch(text: &str, expected: &str) -> Option<String> {
+    let (_, expected_iso3, _) = lookup(expected)?;
+    let info = whatlang::detect(text)?;
+    if !info.is_reliable() || info.lang().code() == *expected_iso3 {
+        return None;
+    }
+    Some(
+        LANGUAGES
+            .iter()
+            .find(|(_, iso3, _)| *iso3 == info.lang().code())
+            .map(|(_, _, name)| name.to_string())
+            .unwrap_or_else(|| info.lang().eng_name().to_string()),
+    )
+}
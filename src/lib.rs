@@ -2,22 +2,141 @@
 //!
 //! A Rust client library for Google's Gemini 2.0 API.
 
+mod auth;
+#[cfg(feature = "batch")]
+mod batch;
+mod borrowed;
+mod cache;
+mod chat;
 mod client;
+#[cfg(feature = "openai-compat")]
+pub mod compat;
+#[cfg(feature = "config")]
+mod config;
+mod embeddings;
+#[cfg(feature = "ensemble")]
+mod ensemble;
 mod error;
+#[cfg(feature = "experiments")]
+mod experiments;
+#[cfg(feature = "files")]
+mod files;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
+mod functions;
+mod grounding;
+mod guard;
+mod health;
+mod language;
+mod markdown;
+mod mime;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
+#[cfg(feature = "test-util")]
+pub mod mock_server;
+mod model_info;
 mod models;
+mod moderation;
+mod persona;
+#[cfg(feature = "pool")]
+mod pool;
+mod postprocess;
+#[cfg(feature = "prompts")]
+mod prompts;
+mod queue;
+#[cfg(feature = "ranking")]
+mod ranking;
+#[cfg(feature = "streaming")]
+mod recorder;
+mod redaction;
+mod retry;
+mod routing;
+mod schema;
+#[cfg(feature = "streaming")]
+mod streaming;
+mod tokens;
 mod tools;
+mod video;
+#[cfg(feature = "vision")]
+mod vision;
 
-pub use client::Gemini;
-pub use error::Error;
+pub use auth::{ApiKeyProvider, EnvKey, FileKey, StaticKey};
+#[cfg(feature = "batch")]
+pub use batch::{
+    inline_batch, BatchError, BatchManifest, BatchResult, BatchWriter, MAX_BATCH_FILE_SIZE,
+    MAX_INLINE_BATCH_SIZE,
+};
+pub use borrowed::{
+    parse_borrowed, CandidateRef, ContentRef, FunctionCallRef, GenerationResponseRef, PartRef,
+};
+pub use cache::{CacheStats, ResponseCache};
+#[cfg(feature = "sqlite")]
+pub use chat::SqliteStore;
+pub use chat::{ChatHistory, ChatSession, JsonFileStore, SessionStore};
+pub use client::{ApiVersion, DetailedResponse, Gemini, GeminiBuilder, MaxTokensEscalation};
+#[cfg(feature = "config")]
+pub use config::ClientConfig;
+pub use embeddings::{BatchEmbedBuilder, EmbedBuilder, Embedding, ItemError, TaskType};
+#[cfg(feature = "ensemble")]
+pub use ensemble::{Ensemble, EnsembleResponse, EnsembleStrategy};
+pub use error::{Error, ErrorCode, RetryAdvice};
+#[cfg(feature = "experiments")]
+pub use experiments::{Experiment, ExperimentReport, VariantStats};
+#[cfg(feature = "files")]
+pub use files::{FileInfo, FileUpload};
+pub use functions::{
+    FunctionHandler, FunctionRegistry, GuardDecision, HandlerFuture, ToolCallGuard,
+    ToolResultPolicy, TruncationStrategy,
+};
+pub use grounding::{
+    CitedSegment, GroundingChunk, GroundingMetadata, GroundingSupport, Segment, WebSource,
+};
+pub use guard::{OverLimit, PromptGuard, PromptViolation};
+pub use health::HealthReport;
+pub use markdown::{MarkdownHandler, MarkdownSegmenter};
+pub use mime::{mime_type_from_extension, sniff_mime_type};
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::{MockGemini, MockReply, MockRequest};
+pub use model_info::{ModelCapabilities, ModelInfo};
 pub use models::{
-    Candidate, CitationMetadata, Content, FunctionCallingMode, GenerateContentRequest,
-    GenerationConfig, GenerationResponse, ImageMediaType, ImageSource, Message, Part, Role,
-    SafetyRating,
+    Blob, Candidate, CitationMetadata, Content, FileData, FunctionCallingConfig,
+    FunctionCallingMode, GenerateContentRequest, GenerateContentRequestBuilder, GenerationConfig,
+    GenerationResponse, HarmBlockThreshold, HarmCategory, ImageMediaType, IntoMessage, Message,
+    Part, Role, SafetyRating, SafetySetting, ToolConfig, UsageMetadata,
+};
+pub use moderation::{BlockDetails, BlockedAt, BlockedRating, ModerationResult, PromptBlock};
+pub use persona::{Persona, SafetyPreset};
+#[cfg(feature = "pool")]
+pub use pool::{GeminiPool, TenantConfig, TenantUsage};
+#[cfg(feature = "regex")]
+pub use postprocess::RegexExtract;
+pub use postprocess::{
+    FilterVerdict, MaxLength, PostProcessor, ResponseFilter, StripCodeFences, TrimWhitespace,
+};
+#[cfg(feature = "prompts")]
+pub use prompts::{Prompt, PromptStore};
+pub use queue::Priority;
+#[cfg(feature = "ranking")]
+pub use ranking::{RankedCandidate, RankedCandidates};
+#[cfg(feature = "streaming")]
+pub use recorder::{RecordedEvent, StreamRecorder};
+pub use redaction::Redactor;
+pub use retry::{RetryBudget, IDEMPOTENCY_KEY_HEADER};
+pub use routing::LatencyStats;
+pub use schema::schema_from_example;
+pub use secrecy::{ExposeSecret, SecretString};
+#[cfg(feature = "streaming")]
+pub use streaming::{
+    CancelHandle, ChunkCoalescing, GenerationStream, PartDelta, FINISH_REASON_DEADLINE,
 };
+pub use tokens::{estimate_tokens, MessageTokens, PartTokens, TokenBreakdown};
 pub use tools::{
     value_to_function_parameters, FunctionCall, FunctionDeclaration, FunctionParameters,
-    PropertyDetails, Tool,
+    FunctionResponse, Issue, JsonRepair, PropertyDetails, Tool, Tools,
 };
+pub use video::{TimestampedAnswer, TimestampedSegment, Video, VideoMetadata};
+#[cfg(feature = "vision")]
+pub use vision::{BoundingBox, DetectedObject, Image, ImageComparison, SegmentationMask};
 
 /// Result type for this crate
 pub type Result<T> = std::result::Result<T, Error>;
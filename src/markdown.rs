@@ -0,0 +1,334 @@
+/// Receives streamed text split into prose and fenced code blocks
+///
+/// Implement this for terminal or TUI chat clients that want to highlight
+/// code as it streams, then feed text through a [`MarkdownSegmenter`] or
+/// use `ContentBuilder::execute_stream_markdown`. Callbacks arrive in text
+/// order; the fence lines themselves are not passed on.
+pub trait MarkdownHandler {
+    /// A piece of text outside code blocks
+    fn on_text(&mut self, text: &str);
+
+    /// A fenced code block opened, with the language from its info string
+    fn on_code_block_start(&mut self, _language: Option<&str>) {}
+
+    /// A piece of code inside a code block
+    ///
+    /// Defaults to `on_text`, so handlers that only care where blocks start
+    /// and end don't need to implement it.
+    fn on_code(&mut self, code: &str) {
+        self.on_text(code);
+    }
+
+    /// The current code block closed
+    fn on_code_block_end(&mut self) {}
+
+    /// The stream ended
+    ///
+    /// An unclosed code block is ended with `on_code_block_end` first.
+    fn on_done(&mut self) {}
+}
+
+/// Incrementally splits streamed markdown into text and code blocks
+///
+/// Text is passed to the handler as soon as it arrives, except at the start
+/// of a line, where it is held back only until it is clear whether the line
+/// is a code fence (```` ``` ```` or `~~~`).
+#[derive(Debug)]
+pub struct MarkdownSegmenter<H> {
+    handler: H,
+    /// Text not yet passed to the handler
+    pending: String,
+    at_line_start: bool,
+    /// The opening fence of the current code block
+    fence: Option<String>,
+}
+
+impl<H: MarkdownHandler> MarkdownSegmenter<H> {
+    /// Create a segmenter that calls the given handler
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            pending: String::new(),
+            at_line_start: true,
+            fence: None,
+        }
+    }
+
+    /// Whether the segmenter is inside a code block
+    pub fn in_code_block(&self) -> bool {
+        self.fence.is_some()
+    }
+
+    /// The handler
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// The handler, mutably
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Feed the next piece of streamed text
+    pub fn push(&mut self, text: &str) {
+        self.pending.push_str(text);
+        self.drain(false);
+    }
+
+    /// Flush held back text, close any open block and call `on_done`
+    pub fn finish(mut self) -> H {
+        self.drain(true);
+        if self.fence.take().is_some() {
+            self.handler.on_code_block_end();
+        }
+        self.handler.on_done();
+        self.handler
+    }
+
+    fn drain(&mut self, at_end: bool) {
+        while !self.pending.is_empty() {
+            let line_end = self.pending.find('\n').map(|i| i + 1);
+            if self.at_line_start {
+                let line = &self.pending[..line_end.unwrap_or(self.pending.len())];
+                match self.classify(line, line_end.is_some() || at_end) {
+                    Line::Undecided => return,
+                    Line::Fence(language) => {
+                        let end = line.len();
+                        let line: String = self.pending.drain(..end).collect();
+                        self.toggle_fence(line.trim(), language);
+                        continue;
+                    }
+                    Line::Content => self.at_line_start = false,
+                }
+            }
+
+            let end = line_end.unwrap_or(self.pending.len());
+            let chunk: String = self.pending.drain(..end).collect();
+            self.emit(&chunk);
+            self.at_line_start = line_end.is_some();
+        }
+    }
+
+    /// Decide whether a line (possibly still incomplete) is a code fence
+    fn classify(&self, line: &str, complete: bool) -> Line {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        let indent = trimmed.len() - trimmed.trim_start_matches(' ').len();
+        if indent > 3 {
+            return Line::Content;
+        }
+        let rest = &trimmed[indent..];
+        let Some(marker) = rest.chars().next() else {
+            // Only spaces so far
+            return if complete {
+                Line::Content
+            } else {
+                Line::Undecided
+            };
+        };
+        if marker != '`' && marker != '~' {
+            return Line::Content;
+        }
+        let run = rest.len() - rest.trim_start_matches(marker).len();
+        if run < 3 {
+            return if complete || run < rest.len() {
+                Line::Content
+            } else {
+                Line::Undecided
+            };
+        }
+        if !complete {
+            return Line::Undecided;
+        }
+
+        let info = rest[run..].trim();
+        match &self.fence {
+            // A closing fence uses the same character, at least as many
+            // times, and has nothing after it
+            Some(open) => {
+                if open.starts_with(marker) && run >= open.len() && info.is_empty() {
+                    Line::Fence(None)
+                } else {
+                    Line::Content
+                }
+            }
+            None if marker == '`' && info.contains('`') => Line::Content,
+            None => Line::Fence(info.split_whitespace().next().map(str::to_string)),
+        }
+    }
+
+    fn toggle_fence(&mut self, line: &str, language: Option<String>) {
+        if self.fence.take().is_some() {
+            self.handler.on_code_block_end();
+        } else {
+            let marker = line.chars().next().unwrap_or('`');
+            let run = line.len() - line.trim_start_matches(marker).len();
+            self.fence = Some(marker.to_string().repeat(run));
+            self.handler.on_code_block_start(language.as_deref());
+        }
+    }
+
+    fn emit(&mut self, text: &str) {
+        if self.fence.is_some() {
+            self.handler.on_code(text);
+        } else {
+            self.handler.on_text(text);
+        }
+    }
+}
+
+enum Line {
+    /// Could still become a fence once more text arrives
+    Undecided,
+    /// A code fence, with the language if it opens a block
+    Fence(Option<String>),
+    Content,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Text(String),
+        Start(Option<String>),
+        Code(String),
+        End,
+        Done,
+    }
+
+    /// Records callbacks, merging consecutive pieces of text or code
+    #[derive(Default)]
+    struct Recorder(Vec<Event>);
+
+    impl MarkdownHandler for Recorder {
+        fn on_text(&mut self, text: &str) {
+            match self.0.last_mut() {
+                Some(Event::Text(previous)) => previous.push_str(text),
+                _ => self.0.push(Event::Text(text.to_string())),
+            }
+        }
+
+        fn on_code_block_start(&mut self, language: Option<&str>) {
+            self.0.push(Event::Start(language.map(str::to_string)));
+        }
+
+        fn on_code(&mut self, code: &str) {
+            match self.0.last_mut() {
+                Some(Event::Code(previous)) => previous.push_str(code),
+                _ => self.0.push(Event::Code(code.to_string())),
+            }
+        }
+
+        fn on_code_block_end(&mut self) {
+            self.0.push(Event::End);
+        }
+
+        fn on_done(&mut self) {
+            self.0.push(Event::Done);
+        }
+    }
+
+    fn text(text: &str) -> Event {
+        Event::Text(text.to_string())
+    }
+
+    fn code(code: &str) -> Event {
+        Event::Code(code.to_string())
+    }
+
+    /// Segment `pieces` as pushed, and again one character at a time
+    fn segment(pieces: &[&str]) -> Vec<Event> {
+        let mut segmenter = MarkdownSegmenter::new(Recorder::default());
+        for piece in pieces {
+            segmenter.push(piece);
+        }
+        let events = segmenter.finish().0;
+
+        let mut segmenter = MarkdownSegmenter::new(Recorder::default());
+        for c in pieces.concat().chars() {
+            segmenter.push(&c.to_string());
+        }
+        assert_eq!(segmenter.finish().0, events, "char by char");
+        events
+    }
+
+    #[test]
+    fn fence_split_across_pushes() {
+        assert_eq!(
+            segment(&[
+                "Intro\n",
+                "``",
+                "`rust\n",
+                "fn main() {}\n",
+                "```\n",
+                "Done"
+            ]),
+            [
+                text("Intro\n"),
+                Event::Start(Some("rust".to_string())),
+                code("fn main() {}\n"),
+                Event::End,
+                text("Done"),
+                Event::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn tilde_block_is_not_closed_by_backticks() {
+        assert_eq!(
+            segment(&["~~~\nlet a = 1;\n```\nlet b = 2;\n~~~\nafter"]),
+            [
+                Event::Start(None),
+                code("let a = 1;\n```\nlet b = 2;\n"),
+                Event::End,
+                text("after"),
+                Event::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn longer_closing_fence() {
+        assert_eq!(
+            segment(&["```py\nx = 1\n`````\ntext"]),
+            [
+                Event::Start(Some("py".to_string())),
+                code("x = 1\n"),
+                Event::End,
+                text("text"),
+                Event::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_block_ends_at_finish() {
+        let mut segmenter = MarkdownSegmenter::new(Recorder::default());
+        segmenter.push("```sh\nls -la");
+        assert!(segmenter.in_code_block());
+        assert_eq!(
+            segmenter.finish().0,
+            [
+                Event::Start(Some("sh".to_string())),
+                code("ls -la"),
+                Event::End,
+                Event::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn indented_fences() {
+        // Four spaces make an indented code line, not a fence
+        assert_eq!(
+            segment(&["    ```\nnot code\n"]),
+            [text("    ```\nnot code\n"), Event::Done]
+        );
+        assert_eq!(
+            segment(&["   ```\ncode\n   ```\n"]),
+            [Event::Start(None), code("code\n"), Event::End, Event::Done]
+        );
+    }
+}
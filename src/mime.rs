@@ -0,0 +1,99 @@
+use std::path::Path;
+
+/// Detect a file type from its leading bytes
+///
+/// Recognizes the image, audio, video and document formats the API
+/// accepts; text formats have no signature and give `None`. A few hundred
+/// bytes from the start of the file are enough.
+pub fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| bytes.get(offset..offset + magic.len()) == Some(magic);
+
+    if at(4, b"ftyp") {
+        return Some(match bytes.get(8..12)? {
+            b"heic" | b"heix" | b"hevc" | b"hevx" => "image/heic",
+            b"mif1" | b"msf1" => "image/heif",
+            b"qt  " => "video/mov",
+            [b'3', b'g', ..] => "video/3gpp",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+    if at(0, b"RIFF") {
+        return match bytes.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/avi"),
+            _ => None,
+        };
+    }
+    if at(0, b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        return Some("audio/aiff");
+    }
+
+    Some(match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        [b'I', b'D', b'3', ..] => "audio/mp3",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [b'f', b'L', b'a', b'C', ..] => "audio/flac",
+        // ADTS frames; checked before MPEG audio, which shares the sync bits
+        [0xFF, 0xF1 | 0xF9, ..] => "audio/aac",
+        [0xFF, 0xFB | 0xFA | 0xF3 | 0xF2 | 0xE3 | 0xE2, ..] => "audio/mp3",
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => "video/webm",
+        [b'F', b'L', b'V', 0x01, ..] => "video/x-flv",
+        [0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, ..] => "video/wmv",
+        [0x00, 0x00, 0x01, 0xBA | 0xB3, ..] => "video/mpeg",
+        _ => return None,
+    })
+}
+
+/// Look up a file type by the extension of a path
+pub fn mime_type_from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "heif" => "image/heif",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mp3",
+        "aiff" | "aif" => "audio/aiff",
+        "aac" => "audio/aac",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "mpeg" | "mpg" => "video/mpeg",
+        "mov" => "video/mov",
+        "avi" => "video/avi",
+        "flv" => "video/x-flv",
+        "webm" => "video/webm",
+        "wmv" => "video/wmv",
+        "3gp" | "3gpp" => "video/3gpp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "xml" => "text/xml",
+        "rtf" => "text/rtf",
+        "json" => "application/json",
+        "js" => "text/javascript",
+        "ts" => "text/x-typescript",
+        "py" => "text/x-python",
+        _ => return None,
+    })
+}
+
+/// The type of a file: `mime_type` if given, else by extension, else by
+/// contents (`head` being the start of the file)
+pub(crate) fn resolve(path: &Path, head: &[u8], mime_type: Option<&str>) -> Option<String> {
+    mime_type
+        .map(str::to_string)
+        .or_else(|| mime_type_from_extension(path).map(str::to_string))
+        .or_else(|| sniff_mime_type(head).map(str::to_string))
+}
@@ -0,0 +1,523 @@
+use crate::{fixtures, models::GenerationResponse, Error, Gemini, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// A local stand-in for the Gemini API, for tests of code built on this
+/// crate
+///
+/// Serves scripted replies over HTTP on a loopback port; clients from
+/// [`MockGemini::client`] talk to it like to the real API, so retries,
+/// timeouts and streaming go through the same code paths. Latencies are
+/// drawn from a seeded generator, which makes runs repeatable. The server
+/// stops when the mock is dropped.
+pub struct MockGemini {
+    base_url: String,
+    state: Arc<Mutex<MockState>>,
+    server: JoinHandle<()>,
+}
+
+/// A scripted reply of [`MockGemini`]
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// Answer with this response; streamed calls get it as one chunk
+    Response(GenerationResponse),
+    /// Answer with this text; streamed calls get it in
+    /// [`MockGemini::with_stream_chunks`] chunks
+    Text(String),
+    /// Stream these chunks; unary calls get them merged into one response
+    #[cfg(feature = "streaming")]
+    Chunks(Vec<GenerationResponse>),
+    /// Fail with an API error
+    Error {
+        /// HTTP status, e.g. 503
+        status: u16,
+        /// Error message
+        message: String,
+    },
+    /// Fail with 429 Too Many Requests
+    RateLimited {
+        /// Sent as the `Retry-After` header, in whole seconds
+        retry_after: Option<Duration>,
+    },
+}
+
+impl MockReply {
+    /// Answer with a text
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text(text.into())
+    }
+
+    /// Fail with 503 Service Unavailable, which requests with a
+    /// `RetryBudget` retry
+    pub fn unavailable() -> Self {
+        Self::Error {
+            status: 503,
+            message: "The model is overloaded. Please try again later.".to_string(),
+        }
+    }
+
+    /// Fail with 429 Too Many Requests
+    pub fn rate_limited(retry_after: Option<Duration>) -> Self {
+        Self::RateLimited { retry_after }
+    }
+}
+
+/// A call received by [`MockGemini`]
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    /// Which call this was, counting from 1
+    pub attempt: usize,
+    /// HTTP method
+    pub method: String,
+    /// URL path without the query, e.g.
+    /// `/v1beta/models/gemini-2.0-flash:generateContent`
+    pub path: String,
+    /// URL query, e.g. `alt=sse`; empty for calls without one
+    pub query: String,
+    /// The `x-goog-api-key` header the call carried
+    pub api_key: Option<String>,
+    /// The JSON body; `Null` for calls without one
+    pub body: serde_json::Value,
+}
+
+struct MockState {
+    replies: VecDeque<MockReply>,
+    default_reply: Option<MockReply>,
+    failures: BTreeMap<usize, MockReply>,
+    requests: Vec<MockRequest>,
+    latency: (Duration, Duration),
+    seed: u64,
+    chunk_delay: Duration,
+    stream_chunks: usize,
+    /// Streamed replies whose connection was closed before the last chunk
+    closed_early: usize,
+}
+
+impl MockState {
+    /// The next latency between the configured bounds, from the seed
+    fn latency(&mut self) -> Duration {
+        let (min, max) = self.latency;
+        if max <= min {
+            return min;
+        }
+        // SplitMix64
+        self.seed = self.seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        let fraction = (z >> 11) as f64 / (1u64 << 53) as f64;
+        min + (max - min).mul_f64(fraction)
+    }
+}
+
+impl MockGemini {
+    /// Start a mock server on a free loopback port
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| Error::RequestError(format!("Failed to start mock server: {}", e)))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| Error::RequestError(format!("Failed to start mock server: {}", e)))?;
+        let state = Arc::new(Mutex::new(MockState {
+            replies: VecDeque::new(),
+            default_reply: None,
+            failures: BTreeMap::new(),
+            requests: Vec::new(),
+            latency: (Duration::ZERO, Duration::ZERO),
+            seed: 0,
+            chunk_delay: Duration::ZERO,
+            stream_chunks: 3,
+            closed_early: 0,
+        }));
+        let server = tokio::spawn(serve(listener, state.clone()));
+        Ok(Self {
+            base_url: format!("http://{}/", address),
+            state,
+            server,
+        })
+    }
+
+    /// A client that sends its requests to this mock
+    pub fn client(&self) -> Gemini {
+        client_for(&self.base_url)
+    }
+
+    /// The root URL of the mock, for `ContentBuilder::with_base_url`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Queue a reply; calls take queued replies in order
+    pub fn push(&self, reply: MockReply) -> &Self {
+        self.state().replies.push_back(reply);
+        self
+    }
+
+    /// Queue a text answer
+    pub fn push_text(&self, text: impl Into<String>) -> &Self {
+        self.push(MockReply::text(text))
+    }
+
+    /// Reply with this once the queue is empty (default: a 500 error
+    /// saying no reply was queued)
+    pub fn with_default_reply(self, reply: MockReply) -> Self {
+        self.state().default_reply = Some(reply);
+        self
+    }
+
+    /// Answer call number `attempt` (counting from 1, across all calls to
+    /// the mock) with `reply` instead of the queue
+    ///
+    /// For example a [`MockReply::unavailable`] on attempt 1 followed by a
+    /// queued text tests that a request is retried once.
+    pub fn with_failure_on_attempt(self, attempt: usize, reply: MockReply) -> Self {
+        self.state().failures.insert(attempt, reply);
+        self
+    }
+
+    /// Wait before answering each call for a latency between `min` and
+    /// `max`, drawn from the seed
+    pub fn with_latency(self, min: Duration, max: Duration) -> Self {
+        self.state().latency = (min, max);
+        self
+    }
+
+    /// Seed the latency generator (default: 0); the same seed gives the
+    /// same latencies in the same order
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.state().seed = seed;
+        self
+    }
+
+    /// Wait between the chunks of streamed replies (default: none)
+    pub fn with_chunk_delay(self, delay: Duration) -> Self {
+        self.state().chunk_delay = delay;
+        self
+    }
+
+    /// Split [`MockReply::Text`] into this many chunks when streaming
+    /// (default: 3)
+    pub fn with_stream_chunks(self, chunks: usize) -> Self {
+        self.state().stream_chunks = chunks.max(1);
+        self
+    }
+
+    /// The calls received so far, in order
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+
+    /// The number of calls received so far
+    pub fn attempts(&self) -> usize {
+        self.state().requests.len()
+    }
+
+    /// The number of streamed replies the client closed before their last
+    /// chunk, e.g. by cancelling or dropping the stream
+    ///
+    /// A closed connection is noticed when the next chunk is sent, so use
+    /// [`with_chunk_delay`](Self::with_chunk_delay) and allow for that delay.
+    pub fn streams_closed_early(&self) -> usize {
+        self.state().closed_early
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockGemini {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// A client with a fake key that sends its requests to `base_url`
+pub(crate) fn client_for(base_url: &str) -> Gemini {
+    let mut gemini = Gemini::new("mock-api-key");
+    let mut client = crate::client::GeminiClient::clone(&gemini.client);
+    client.base_url = base_url.to_string();
+    if !client.base_url.ends_with('/') {
+        client.base_url.push('/');
+    }
+    gemini.client = Arc::new(client);
+    gemini
+}
+
+/// An error body shaped like the API's, with the gRPC status name that
+/// goes with the HTTP status
+pub(crate) fn error_body(status: u16, message: &str) -> serde_json::Value {
+    let code = match status {
+        400 => "INVALID_ARGUMENT",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        500 => "INTERNAL",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "UNKNOWN",
+    };
+    serde_json::json!({
+        "error": { "code": status, "message": message, "status": code },
+    })
+}
+
+fn lock(state: &Mutex<MockState>) -> std::sync::MutexGuard<'_, MockState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn serve(listener: TcpListener, state: Arc<Mutex<MockState>>) {
+    while let Ok((socket, _)) = listener.accept().await {
+        tokio::spawn(handle(socket, state.clone()));
+    }
+}
+
+/// Answer one call; every connection carries a single call
+async fn handle(mut socket: TcpStream, state: Arc<Mutex<MockState>>) -> std::io::Result<()> {
+    let (method, path, query, api_key, body) = read_request(&mut socket).await?;
+    let streamed = path.ends_with(":streamGenerateContent");
+    let (reply, latency, chunk_delay, stream_chunks) = {
+        let mut state = lock(&state);
+        let attempt = state.requests.len() + 1;
+        state.requests.push(MockRequest {
+            attempt,
+            method,
+            path,
+            query,
+            api_key,
+            body: serde_json::from_slice(&body).unwrap_or_default(),
+        });
+        let reply = state
+            .failures
+            .remove(&attempt)
+            .or_else(|| state.replies.pop_front())
+            .or_else(|| state.default_reply.clone());
+        let latency = state.latency();
+        (reply, latency, state.chunk_delay, state.stream_chunks)
+    };
+    tokio::time::sleep(latency).await;
+
+    let chunks = match reply {
+        None => return respond_error(&mut socket, 500, "MockGemini: no reply queued", None).await,
+        Some(MockReply::Error { status, message }) => {
+            return respond_error(&mut socket, status, &message, None).await
+        }
+        Some(MockReply::RateLimited { retry_after }) => {
+            let message = "Resource has been exhausted (e.g. check quota).";
+            return respond_error(&mut socket, 429, message, retry_after).await;
+        }
+        Some(MockReply::Response(response)) => vec![response],
+        Some(MockReply::Text(text)) if streamed => fixtures::stream_chunks(&text, stream_chunks),
+        Some(MockReply::Text(text)) => vec![fixtures::text(&text)],
+        #[cfg(feature = "streaming")]
+        Some(MockReply::Chunks(chunks)) if streamed => chunks,
+        #[cfg(feature = "streaming")]
+        Some(MockReply::Chunks(chunks)) => {
+            let mut chunks = chunks.into_iter();
+            let mut response = chunks.next().unwrap_or_else(|| fixtures::text(""));
+            for chunk in chunks {
+                crate::streaming::accumulate(&mut response, chunk);
+            }
+            vec![response]
+        }
+    };
+
+    if !streamed {
+        let body = serde_json::to_string(&chunks[0]).unwrap_or_default();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        );
+        socket.write_all(head.as_bytes()).await?;
+        socket.write_all(body.as_bytes()).await?;
+        return socket.shutdown().await;
+    }
+    socket
+        .write_all(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n",
+        )
+        .await?;
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(chunk_delay).await;
+        }
+        let data = serde_json::to_string(chunk).unwrap_or_default();
+        let sent = async {
+            socket
+                .write_all(format!("data: {}\r\n\r\n", data).as_bytes())
+                .await?;
+            socket.flush().await
+        };
+        if let Err(e) = sent.await {
+            lock(&state).closed_early += 1;
+            return Err(e);
+        }
+    }
+    socket.shutdown().await
+}
+
+/// Reply with an error body shaped like the API's
+async fn respond_error(
+    socket: &mut TcpStream,
+    status: u16,
+    message: &str,
+    retry_after: Option<Duration>,
+) -> std::io::Result<()> {
+    let reason = reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Error");
+    let body = error_body(status, message).to_string();
+    let retry_after = retry_after
+        .map(|after| format!("retry-after: {}\r\n", after.as_secs()))
+        .unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{}connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        retry_after,
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Read the method, path (without query) and body of an HTTP/1.1 request
+/// Read a call, returning its method, path, query, API key header and body
+async fn read_request(
+    socket: &mut TcpStream,
+) -> std::io::Result<(String, String, String, Option<String>, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let headers: Vec<_> = lines.filter_map(|line| line.split_once(':')).collect();
+    let header = |wanted: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.trim())
+    };
+    let length: usize = header("content-length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let api_key = header("x-goog-api-key").map(str::to_string);
+
+    let mut body = buffer.split_off(header_end);
+    while body.len() < length {
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok((method, path, query, api_key, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RetryBudget;
+
+    #[tokio::test]
+    async fn roundtrip_records_requests_and_retries() {
+        let mock = MockGemini::start()
+            .await
+            .unwrap()
+            .with_failure_on_attempt(2, MockReply::unavailable());
+        mock.push_text("Hello!").push_text("Hello again!");
+        let client = mock.client();
+
+        let response = client
+            .generate_content()
+            .with_user_message("Hi")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "Hello!");
+
+        // Attempt 2 fails with 503 and is retried
+        let response = client
+            .generate_content()
+            .with_user_message("Hi again")
+            .with_retry_budget(RetryBudget::new(3))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "Hello again!");
+
+        let requests = mock.requests();
+        assert_eq!(mock.attempts(), 3);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(
+            requests[0].path,
+            "/v1beta/models/gemini-2.0-flash:generateContent"
+        );
+        assert_eq!(requests[0].body["contents"][0]["parts"][0]["text"], "Hi");
+        assert_eq!(requests[1].body, requests[2].body);
+    }
+
+    #[tokio::test]
+    async fn api_errors_reach_the_caller() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Error {
+            status: 400,
+            message: "Invalid JSON payload".to_string(),
+        });
+        let error = mock
+            .client()
+            .generate_content()
+            .with_user_message("Hi")
+            .execute()
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), crate::ErrorCode::InvalidRequest);
+        assert!(error.to_string().contains("Invalid JSON payload"));
+    }
+
+    #[cfg(feature = "streaming")]
+    #[tokio::test]
+    async fn roundtrip_streams_text_in_chunks() {
+        use futures_util::StreamExt;
+
+        let mock = MockGemini::start().await.unwrap().with_stream_chunks(4);
+        mock.push_text("One two three four five six");
+        let stream = mock
+            .client()
+            .generate_content()
+            .with_user_message("Count")
+            .execute_stream()
+            .await
+            .unwrap();
+        let chunks: Vec<String> = stream
+            .map(|chunk| chunk.unwrap().text().into_owned())
+            .collect()
+            .await;
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), "One two three four five six");
+        assert!(mock.requests()[0].path.ends_with(":streamGenerateContent"));
+    }
+}
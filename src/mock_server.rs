@@ -0,0 +1,159 @@
+//! Helpers for integration tests against a [`wiremock`] server
+//!
+//! [`MockGemini`](crate::MockGemini) covers scripted replies; wiremock adds
+//! request matching, expectations and verification. The matchers here
+//! recognize the API's endpoints for any API version, and the response
+//! templates produce bodies this crate parses, so tests only state what
+//! differs from a normal call. See `examples/mock_server.rs`.
+
+pub use wiremock;
+
+use crate::{fixtures, models::GenerationResponse, Gemini};
+use std::time::Duration;
+use wiremock::{Match, MockServer, Request, ResponseTemplate};
+
+/// A client with a fake key that sends its requests to `server`
+pub fn client(server: &MockServer) -> Gemini {
+    crate::mock::client_for(&server.uri())
+}
+
+/// Matches `POST` calls of an endpoint, e.g. `countTokens`, on any model
+///
+/// The helpers below cover the common endpoints.
+pub fn endpoint(name: &str) -> EndpointMatcher {
+    EndpointMatcher {
+        model: None,
+        endpoint: name.to_string(),
+    }
+}
+
+/// Matches `generateContent` calls on any model
+pub fn generate_content() -> EndpointMatcher {
+    endpoint("generateContent")
+}
+
+/// Matches `streamGenerateContent` calls on any model
+pub fn stream_generate_content() -> EndpointMatcher {
+    endpoint("streamGenerateContent")
+}
+
+/// Matches `embedContent` calls on any model
+pub fn embed_content() -> EndpointMatcher {
+    endpoint("embedContent")
+}
+
+/// Matches `countTokens` calls on any model
+pub fn count_tokens() -> EndpointMatcher {
+    endpoint("countTokens")
+}
+
+/// Matches calls of an endpoint of the API
+#[derive(Debug, Clone)]
+pub struct EndpointMatcher {
+    model: Option<String>,
+    endpoint: String,
+}
+
+impl EndpointMatcher {
+    /// Only match calls to this model, e.g. `gemini-2.0-flash`
+    pub fn for_model(mut self, model: impl Into<String>) -> Self {
+        let model = model.into();
+        let model = model.strip_prefix("models/").unwrap_or(&model).to_string();
+        self.model = Some(model);
+        self
+    }
+}
+
+impl Match for EndpointMatcher {
+    fn matches(&self, request: &Request) -> bool {
+        if request.method != wiremock::http::Method::POST {
+            return false;
+        }
+        // `/{version}/models/{model}:{endpoint}`
+        let mut segments = request.url.path().trim_start_matches('/').splitn(3, '/');
+        let (Some(_version), Some("models"), Some(call)) =
+            (segments.next(), segments.next(), segments.next())
+        else {
+            return false;
+        };
+        let Some((model, endpoint)) = call.rsplit_once(':') else {
+            return false;
+        };
+        endpoint == self.endpoint && self.model.as_deref().is_none_or(|m| m == model)
+    }
+}
+
+/// Matches requests whose message texts contain `text`
+pub fn prompt_contains(text: impl Into<String>) -> PromptContains {
+    PromptContains(text.into())
+}
+
+/// Matches requests whose message texts contain a string
+#[derive(Debug, Clone)]
+pub struct PromptContains(String);
+
+impl Match for PromptContains {
+    fn matches(&self, request: &Request) -> bool {
+        let Ok(body) = serde_json::from_slice::<serde_json::Value>(&request.body) else {
+            return false;
+        };
+        body["contents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|content| content["parts"].as_array().into_iter().flatten())
+            .filter_map(|part| part["text"].as_str())
+            .any(|text| text.contains(&self.0))
+    }
+}
+
+/// Answer with a response
+pub fn response(response: &GenerationResponse) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(response)
+}
+
+/// Answer with a complete text answer, see [`fixtures::text`]
+pub fn text(text: &str) -> ResponseTemplate {
+    response(&fixtures::text(text))
+}
+
+/// Answer a streamed call with these chunks as server-sent events
+pub fn stream(chunks: &[GenerationResponse]) -> ResponseTemplate {
+    let body: String = chunks
+        .iter()
+        .map(|chunk| {
+            format!(
+                "data: {}\r\n\r\n",
+                serde_json::to_string(chunk).unwrap_or_default()
+            )
+        })
+        .collect();
+    ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+}
+
+/// Answer a streamed call with a text split into roughly `chunks` pieces,
+/// see [`fixtures::stream_chunks`]
+pub fn stream_text(text: &str, chunks: usize) -> ResponseTemplate {
+    stream(&fixtures::stream_chunks(text, chunks))
+}
+
+/// Answer an `embedContent` call with this vector
+pub fn embedding(values: &[f32]) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "embedding": { "values": values },
+    }))
+}
+
+/// Fail with an API error, e.g. 503 for an overloaded model
+pub fn error(status: u16, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(crate::mock::error_body(status, message))
+}
+
+/// Fail with 429 Too Many Requests, optionally with a `Retry-After` header
+pub fn rate_limited(retry_after: Option<Duration>) -> ResponseTemplate {
+    let template = error(429, "Resource has been exhausted (e.g. check quota).");
+    match retry_after {
+        Some(after) => template.insert_header("retry-after", after.as_secs().to_string()),
+        None => template,
+    }
+}
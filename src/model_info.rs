@@ -0,0 +1,307 @@
+use crate::{
+    client::{Gemini, GeminiClient},
+    models::{GenerateContentRequest, Part},
+    tools::Tool,
+    Error, Result,
+};
+use serde::{Deserialize, Serialize};
+
+/// Information about a model offered by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    /// Resource name, e.g. `models/gemini-2.0-flash`
+    pub name: String,
+    /// Human-readable name
+    #[serde(default)]
+    pub display_name: String,
+    /// Short description
+    #[serde(default)]
+    pub description: String,
+    /// Model version, e.g. `2.0`
+    #[serde(default)]
+    pub version: String,
+    /// Maximum number of input tokens
+    #[serde(default)]
+    pub input_token_limit: u32,
+    /// Maximum number of output tokens
+    #[serde(default)]
+    pub output_token_limit: u32,
+    /// API methods the model supports, e.g. `generateContent`
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+    /// Whether the model thinks before answering
+    #[serde(default)]
+    pub thinking: bool,
+}
+
+impl ModelInfo {
+    /// Whether the model supports an API method, e.g. `embedContent`
+    pub fn supports(&self, method: &str) -> bool {
+        self.supported_generation_methods
+            .iter()
+            .any(|m| m == method)
+    }
+
+    /// The capabilities of the model
+    ///
+    /// Token limits and thinking come from this info; the other features,
+    /// which the API does not report, from the built-in table, or are
+    /// assumed supported for models missing from it.
+    pub fn capabilities(&self) -> ModelCapabilities {
+        let known = ModelCapabilities::for_model(&self.name);
+        ModelCapabilities {
+            input_token_limit: self.input_token_limit,
+            output_token_limit: self.output_token_limit,
+            thinking: self.thinking,
+            ..known.unwrap_or(ModelCapabilities {
+                input_token_limit: 0,
+                output_token_limit: 0,
+                tools: true,
+                json_mode: true,
+                json_with_tools: true,
+                audio: true,
+                video: true,
+                thinking: false,
+            })
+        }
+    }
+}
+
+/// What a model can do, used to reject requests it would fail on
+///
+/// Requests are checked against the capabilities of their model before
+/// they are sent, so unsupported options fail fast with
+/// [`Error::IncompatibleOptions`] instead of an API error. Models missing
+/// from the built-in table are not checked unless their capabilities were
+/// fetched with [`Gemini::refresh_model_capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// Context window, in tokens
+    pub input_token_limit: u32,
+    /// Maximum number of output tokens
+    pub output_token_limit: u32,
+    /// Function calling and built-in tools
+    pub tools: bool,
+    /// JSON output with `response_mime_type` and `response_schema`
+    pub json_mode: bool,
+    /// JSON output in requests that also use Google Search or function
+    /// calling
+    pub json_with_tools: bool,
+    /// Audio input
+    pub audio: bool,
+    /// Video input
+    pub video: bool,
+    /// Thinking before answering
+    pub thinking: bool,
+}
+
+/// Capabilities of known model families, matched by name prefix
+const CAPABILITIES: &[(&str, ModelCapabilities)] = &[
+    (
+        "gemini-3-pro",
+        ModelCapabilities {
+            json_with_tools: true,
+            ..gemini(1_048_576, 65_536, true)
+        },
+    ),
+    (
+        "gemini-3-flash",
+        ModelCapabilities {
+            json_with_tools: true,
+            ..gemini(1_048_576, 65_536, true)
+        },
+    ),
+    ("gemini-2.5-pro", gemini(1_048_576, 65_536, true)),
+    ("gemini-2.5-flash", gemini(1_048_576, 65_536, true)),
+    ("gemini-2.0-pro", gemini(2_097_152, 8_192, false)),
+    ("gemini-2.0-flash-thinking", gemini(1_048_576, 65_536, true)),
+    ("gemini-2.0-flash", gemini(1_048_576, 8_192, false)),
+    ("gemini-1.5-pro", gemini(2_097_152, 8_192, false)),
+    ("gemini-1.5-flash", gemini(1_048_576, 8_192, false)),
+    (
+        "gemma-3",
+        ModelCapabilities {
+            input_token_limit: 131_072,
+            output_token_limit: 8_192,
+            tools: false,
+            json_mode: false,
+            json_with_tools: false,
+            audio: false,
+            video: false,
+            thinking: false,
+        },
+    ),
+];
+
+const fn gemini(
+    input_token_limit: u32,
+    output_token_limit: u32,
+    thinking: bool,
+) -> ModelCapabilities {
+    ModelCapabilities {
+        input_token_limit,
+        output_token_limit,
+        tools: true,
+        json_mode: true,
+        json_with_tools: false,
+        audio: true,
+        video: true,
+        thinking,
+    }
+}
+
+impl ModelCapabilities {
+    /// Look up a model in the built-in table, e.g. `gemini-2.0-flash`
+    ///
+    /// Versioned and preview names match their family, so
+    /// `models/gemini-2.5-flash-preview-05-20` gets the capabilities of
+    /// `gemini-2.5-flash`.
+    pub fn for_model(model: &str) -> Option<Self> {
+        let name = model.strip_prefix("models/").unwrap_or(model);
+        CAPABILITIES
+            .iter()
+            .filter(|(prefix, _)| name.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, capabilities)| *capabilities)
+    }
+
+    /// Check that a request only uses features the model supports
+    pub(crate) fn check(&self, model: &str, request: &GenerateContentRequest) -> Result<()> {
+        let name = model.strip_prefix("models/").unwrap_or(model);
+        let unsupported = |feature: &str| {
+            Err(Error::IncompatibleOptions(format!(
+                "{} does not support {}",
+                name, feature
+            )))
+        };
+
+        if !self.tools && request.tools.as_ref().is_some_and(|t| !t.is_empty()) {
+            return unsupported("tools");
+        }
+        if let Some(config) = &request.generation_config {
+            let json_mime_type = config.response_mime_type.as_deref() == Some("application/json");
+            let json = config.response_schema.is_some() || json_mime_type;
+            if json && !self.json_mode {
+                return unsupported("JSON mode");
+            }
+            let tools = request.tools.as_deref().unwrap_or_default();
+            if json && !self.json_with_tools {
+                if tools.iter().any(|t| matches!(t, Tool::GoogleSearch { .. })) {
+                    return Err(Error::IncompatibleOptions(format!(
+                        "{} does not support the google_search tool combined with a JSON \
+                         response schema or response_mime_type \"application/json\"; request \
+                         plain text and parse it instead",
+                        name
+                    )));
+                }
+                if json_mime_type && tools.iter().any(|t| matches!(t, Tool::Function { .. })) {
+                    return Err(Error::IncompatibleOptions(format!(
+                        "{} does not support function calling combined with \
+                         response_mime_type \"application/json\"; return structured data \
+                         from a function call instead",
+                        name
+                    )));
+                }
+            }
+            if let Some(max) = config.max_output_tokens {
+                if self.output_token_limit > 0 && max > self.output_token_limit as i32 {
+                    return Err(Error::IncompatibleOptions(format!(
+                        "{} generates at most {} output tokens, {} were requested",
+                        name, self.output_token_limit, max
+                    )));
+                }
+            }
+        }
+        for part in request.contents.iter().flat_map(|c| &c.parts) {
+            if let Part::InlineData { inline_data, .. } = part {
+                if inline_data.mime_type.starts_with("audio/") && !self.audio {
+                    return unsupported("audio input");
+                }
+                if inline_data.mime_type.starts_with("video/") && !self.video {
+                    return unsupported("video input");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelInfo>,
+    next_page_token: Option<String>,
+}
+
+impl Gemini {
+    /// List all models available to the API key
+    ///
+    /// Follows pagination until every page has been fetched.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut query = vec![("pageSize", "1000")];
+            if let Some(token) = &page_token {
+                query.push(("pageToken", token));
+            }
+            let page: ListModelsResponse = self.client.get("models", &query).await?.json().await?;
+            models.extend(page.models);
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => return Ok(models),
+            }
+        }
+    }
+
+    /// The capabilities of a model, e.g. `gemini-2.0-flash`
+    ///
+    /// Returns what [`refresh_model_capabilities`](Self::refresh_model_capabilities)
+    /// fetched for the model, if it was called, otherwise the built-in table
+    /// entry.
+    pub fn model_capabilities(&self, model: &str) -> Option<ModelCapabilities> {
+        self.client.capabilities_of(model)
+    }
+
+    /// Fetch the capabilities of a model from the API and use them from now on
+    ///
+    /// Updates the token limits and thinking support of the built-in table
+    /// with what `models.get` reports, and lets requests to models missing
+    /// from the table be checked too. Shared by all clones of the client.
+    pub async fn refresh_model_capabilities(&self, model: &str) -> Result<ModelCapabilities> {
+        let capabilities = self.get_model(model).await?.capabilities();
+        self.client.set_capabilities(model, capabilities);
+        Ok(capabilities)
+    }
+
+    /// Get information about one model, e.g. `gemini-2.0-flash`
+    pub async fn get_model(&self, name: &str) -> Result<ModelInfo> {
+        let name = name.strip_prefix("models/").unwrap_or(name);
+        let path = format!("models/{}", name);
+        Ok(self.client.get(&path, &[]).await?.json().await?)
+    }
+}
+
+impl GeminiClient {
+    /// Capabilities of a model, preferring ones fetched from the API
+    pub(crate) fn capabilities_of(&self, model: &str) -> Option<ModelCapabilities> {
+        let name = model.strip_prefix("models/").unwrap_or(model);
+        let fetched = self
+            .capabilities
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .copied();
+        fetched.or_else(|| ModelCapabilities::for_model(name))
+    }
+
+    fn set_capabilities(&self, model: &str, capabilities: ModelCapabilities) {
+        let name = model.strip_prefix("models/").unwrap_or(model);
+        self.capabilities
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), capabilities);
+    }
+}
@@ -1,4 +1,14 @@
+use crate::grounding::GroundingMetadata;
+use base64::{display::Base64Display, engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Response MIME type that constrains the answer to one of the schema's
+/// enum values
+pub(crate) const ENUM_MIME_TYPE: &str = "text/x.enum";
 
 /// Role of a message in a conversation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -10,28 +20,8 @@ pub enum Role {
     Model,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-#[serde(tag = "type")]
-pub enum ImageSource {
-    #[serde(rename = "base64")]
-    Base64 {
-        /// Media type of the image
-        #[serde(rename = "mimeType")]
-        media_type: ImageMediaType,
-        /// Base64-encoded image data
-        data: String,
-    },
-    /// URL to an image
-    #[serde(rename = "url")]
-    Url {
-        /// URL of the image
-        url: String,
-    },
-}
-
 /// Media type of an image
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum ImageMediaType {
     /// JPEG image
     #[serde(rename = "image/jpeg")]
@@ -47,89 +37,566 @@ pub enum ImageMediaType {
     WebP,
 }
 
+impl ImageMediaType {
+    /// Get the MIME type string for this media type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::WebP => "image/webp",
+        }
+    }
+}
+
+impl From<ImageMediaType> for String {
+    fn from(media_type: ImageMediaType) -> Self {
+        media_type.as_str().to_string()
+    }
+}
+
+/// Raw binary data sent inline with a request (the API's `inlineData`)
+///
+/// Images, audio, video and documents all use this one representation. It
+/// travels base64-encoded; blobs made from bytes keep the bytes and encode
+/// them straight into the request body when it is serialized, so no
+/// encoded copy of a large file is ever held in memory.
+#[derive(Clone)]
+pub struct Blob {
+    /// The IANA MIME type of the data, e.g. `image/png`
+    pub mime_type: String,
+    data: BlobData,
+}
+
+#[derive(Clone)]
+enum BlobData {
+    /// Base64 text, as received or given
+    Encoded(String),
+    /// Raw bytes, encoded when serialized
+    Raw(Arc<Vec<u8>>),
+}
+
+impl Blob {
+    /// Maximum decoded size of inline data accepted by the API (20 MB)
+    ///
+    /// The limit applies to the whole request; larger files have to go
+    /// through the Files API instead.
+    pub const MAX_INLINE_SIZE: usize = 20 * 1024 * 1024;
+
+    /// Create a blob from already base64-encoded data
+    pub fn new(mime_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            data: BlobData::Encoded(data.into()),
+        }
+    }
+
+    /// Create a blob from raw bytes, copying them
+    pub fn from_bytes(mime_type: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        Self::from_vec(mime_type, bytes.as_ref().to_vec())
+    }
+
+    /// Create a blob that takes ownership of raw bytes, without copying
+    ///
+    /// The cheapest way to inline a file read with `std::fs::read`.
+    pub fn from_vec(mime_type: impl Into<String>, bytes: Vec<u8>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            data: BlobData::Raw(Arc::new(bytes)),
+        }
+    }
+
+    /// Read a file into a blob
+    ///
+    /// Without a `mime_type` the type is taken from the extension, or from
+    /// the file's contents when the extension is missing or unknown.
+    pub fn from_path(path: impl AsRef<Path>, mime_type: Option<&str>) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            crate::Error::RequestError(format!("Cannot read {}: {}", path.display(), e))
+        })?;
+        let mime_type = crate::mime::resolve(path, &bytes, mime_type).ok_or_else(|| {
+            crate::Error::RequestError(format!(
+                "Cannot tell the type of {}; pass a MIME type",
+                path.display()
+            ))
+        })?;
+        Ok(Self::from_vec(mime_type, bytes))
+    }
+
+    /// The base64-encoded payload
+    ///
+    /// Encodes on every call for blobs made from bytes.
+    pub fn data(&self) -> Cow<'_, str> {
+        match &self.data {
+            BlobData::Encoded(data) => Cow::Borrowed(data),
+            BlobData::Raw(bytes) => Cow::Owned(BASE64.encode(bytes.as_slice())),
+        }
+    }
+
+    /// Decode the base64 payload into raw bytes
+    pub fn decode(&self) -> crate::Result<Vec<u8>> {
+        match &self.data {
+            BlobData::Encoded(data) => BASE64.decode(data).map_err(|e| {
+                crate::Error::RequestError(format!("Invalid base64 in inline data: {}", e))
+            }),
+            BlobData::Raw(bytes) => Ok(bytes.to_vec()),
+        }
+    }
+
+    /// Size of the payload once decoded, computed without decoding it
+    pub fn decoded_len(&self) -> usize {
+        match &self.data {
+            BlobData::Encoded(data) => {
+                let padding = data.bytes().rev().take_while(|b| *b == b'=').count();
+                (data.len() / 4 * 3).saturating_sub(padding)
+            }
+            BlobData::Raw(bytes) => bytes.len(),
+        }
+    }
+
+    /// Check that the blob can be sent to the API
+    pub fn validate(&self) -> crate::Result<()> {
+        let mime_type = self.mime_type.trim();
+        if mime_type.is_empty() || !mime_type.contains('/') {
+            return Err(crate::Error::RequestError(format!(
+                "Invalid MIME type for inline data: {:?}",
+                self.mime_type
+            )));
+        }
+        if self.decoded_len() == 0 {
+            return Err(crate::Error::RequestError(
+                "Inline data must not be empty".to_string(),
+            ));
+        }
+        if let BlobData::Encoded(data) = &self.data {
+            if !data.len().is_multiple_of(4) {
+                return Err(crate::Error::RequestError(
+                    "Inline data is not padded base64".to_string(),
+                ));
+            }
+        }
+        if self.decoded_len() > Self::MAX_INLINE_SIZE {
+            return Err(crate::Error::RequestError(format!(
+                "Inline data is {} bytes, the limit is {} bytes",
+                self.decoded_len(),
+                Self::MAX_INLINE_SIZE
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Blob {
+    fn eq(&self, other: &Self) -> bool {
+        if self.mime_type != other.mime_type {
+            return false;
+        }
+        match (&self.data, &other.data) {
+            (BlobData::Raw(a), BlobData::Raw(b)) => a == b,
+            _ => self.data() == other.data(),
+        }
+    }
+}
+
+impl Eq for Blob {}
+
+impl std::fmt::Debug for Blob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Blob");
+        debug.field("mime_type", &self.mime_type);
+        match &self.data {
+            BlobData::Encoded(data) => debug.field("data", data),
+            BlobData::Raw(bytes) => debug.field("bytes", &bytes.len()),
+        };
+        debug.finish()
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        /// Writes base64 into the serializer piece by piece
+        struct Encode<'a>(&'a [u8]);
+
+        impl Serialize for Encode<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(&Base64Display::new(self.0, &BASE64))
+            }
+        }
+
+        let mut state = serializer.serialize_struct("Blob", 2)?;
+        state.serialize_field("mimeType", &self.mime_type)?;
+        match &self.data {
+            BlobData::Encoded(data) => state.serialize_field("data", data)?,
+            BlobData::Raw(bytes) => state.serialize_field("data", &Encode(bytes))?,
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Wire {
+            #[serde(alias = "mime_type")]
+            mime_type: String,
+            data: String,
+        }
+
+        let wire = Wire::deserialize(deserializer)?;
+        Ok(Self::new(wire.mime_type, wire.data))
+    }
+}
+
+/// A reference to a file uploaded with the Files API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    /// MIME type of the file; empty for YouTube URLs, whose type the API
+    /// works out itself
+    #[serde(alias = "mime_type", default, skip_serializing_if = "String::is_empty")]
+    pub mime_type: String,
+    /// The file's URI, from `FileInfo::uri`, or a YouTube URL
+    #[serde(alias = "file_uri")]
+    pub file_uri: String,
+}
+
+impl FileData {
+    /// Reference a file by type and URI
+    pub fn new(mime_type: impl Into<String>, file_uri: impl Into<String>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            file_uri: file_uri.into(),
+        }
+    }
+
+    /// Reference a file by URI alone, e.g. a public YouTube video
+    pub fn from_uri(file_uri: impl Into<String>) -> Self {
+        Self::new(String::new(), file_uri)
+    }
+}
+
 /// Content part that can be included in a message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Part {
+    /// Thought summary produced by a thinking model
+    ///
+    /// Must come before `Text` so that the untagged representation picks it
+    /// whenever the `thought` flag is present.
+    Thought {
+        /// The thought text
+        text: String,
+        /// Marks the text as a thought rather than part of the answer
+        thought: bool,
+        /// Opaque signature of the model's reasoning, see [`Part::FunctionCall`]
+        #[serde(
+            rename = "thoughtSignature",
+            alias = "thought_signature",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        thought_signature: Option<String>,
+    },
     /// Text content
     #[serde(rename = "text")]
     Text {
         /// The text content
         text: String,
+        /// Opaque signature of the model's reasoning, see [`Part::FunctionCall`]
+        #[serde(
+            rename = "thoughtSignature",
+            alias = "thought_signature",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        thought_signature: Option<String>,
     },
-    /// Image content
-    #[serde(rename = "inlineData")]
-    Image {
-        #[serde(rename = "source")]
-        source: ImageSource,
+    /// Inline binary data (images, audio, documents, ...)
+    InlineData {
+        /// The inline data
+        #[serde(rename = "inlineData", alias = "inline_data")]
+        inline_data: Blob,
+        /// Clip and frame rate, for video data
+        #[serde(
+            rename = "videoMetadata",
+            alias = "video_metadata",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        video_metadata: Option<crate::video::VideoMetadata>,
     },
     /// Function call from the model
     FunctionCall {
         /// The function call details
-        #[serde(rename = "functionCall")]
+        #[serde(rename = "functionCall", alias = "function_call")]
         function_call: super::tools::FunctionCall,
+        /// Opaque signature of the reasoning that led to the call
+        ///
+        /// Thinking models attach it so that their reasoning can be resumed
+        /// on the next turn. It must be sent back unchanged with the rest of
+        /// the model turn; `ChatSession::record` and automatic function
+        /// calling do so by keeping the model's parts as received.
+        #[serde(
+            rename = "thoughtSignature",
+            alias = "thought_signature",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        thought_signature: Option<String>,
     },
     /// Function response (results from executing a function call)
     FunctionResponse {
         /// The function response details
-        #[serde(rename = "functionResponse")]
+        #[serde(rename = "functionResponse", alias = "function_response")]
         function_response: super::tools::FunctionResponse,
     },
+    /// A file uploaded with the Files API, or a YouTube video
+    FileData {
+        /// The file reference
+        #[serde(rename = "fileData", alias = "file_data")]
+        file_data: FileData,
+        /// Clip and frame rate, for video files
+        #[serde(
+            rename = "videoMetadata",
+            alias = "video_metadata",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        video_metadata: Option<crate::video::VideoMetadata>,
+    },
+    /// A part this crate does not model, e.g. `executableCode`, kept as
+    /// received so it can be sent back unchanged
+    ///
+    /// Must come last so that the untagged representation only picks it
+    /// when no other variant matches.
+    Other(serde_json::Map<String, serde_json::Value>),
+}
+
+impl Part {
+    /// The text of a text part; thoughts are not included
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Part::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The text of a thought summary
+    pub fn thought(&self) -> Option<&str> {
+        match self {
+            Part::Thought { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The function call of a function call part
+    pub fn as_function_call(&self) -> Option<&super::tools::FunctionCall> {
+        match self {
+            Part::FunctionCall { function_call, .. } => Some(function_call),
+            _ => None,
+        }
+    }
+
+    /// The function response of a function response part
+    pub fn as_function_response(&self) -> Option<&super::tools::FunctionResponse> {
+        match self {
+            Part::FunctionResponse { function_response } => Some(function_response),
+            _ => None,
+        }
+    }
+
+    /// The data of an inline data part
+    pub fn as_inline_data(&self) -> Option<&Blob> {
+        match self {
+            Part::InlineData { inline_data, .. } => Some(inline_data),
+            _ => None,
+        }
+    }
+
+    /// The file reference of a file data part
+    pub fn as_file_data(&self) -> Option<&FileData> {
+        match self {
+            Part::FileData { file_data, .. } => Some(file_data),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a text part
+    pub fn is_text(&self) -> bool {
+        matches!(self, Part::Text { .. })
+    }
+
+    /// Whether this is a thought summary
+    pub fn is_thought(&self) -> bool {
+        matches!(self, Part::Thought { .. })
+    }
+
+    /// Whether this is a function call
+    pub fn is_function_call(&self) -> bool {
+        matches!(self, Part::FunctionCall { .. })
+    }
+
+    /// Whether this is a function response
+    pub fn is_function_response(&self) -> bool {
+        matches!(self, Part::FunctionResponse { .. })
+    }
+
+    /// Whether this is inline data
+    pub fn is_inline_data(&self) -> bool {
+        matches!(self, Part::InlineData { .. })
+    }
+
+    /// Whether this is a file reference
+    pub fn is_file_data(&self) -> bool {
+        matches!(self, Part::FileData { .. })
+    }
+
+    /// Take the text out of a text part
+    pub fn into_text(self) -> Option<String> {
+        match self {
+            Part::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Take the function call out of a function call part, dropping its
+    /// thought signature
+    pub fn into_function_call(self) -> Option<super::tools::FunctionCall> {
+        match self {
+            Part::FunctionCall { function_call, .. } => Some(function_call),
+            _ => None,
+        }
+    }
+
+    /// Take the function response out of a function response part
+    pub fn into_function_response(self) -> Option<super::tools::FunctionResponse> {
+        match self {
+            Part::FunctionResponse { function_response } => Some(function_response),
+            _ => None,
+        }
+    }
+
+    /// Convert the part into its JSON representation
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// Convert a JSON part into a typed one, without losing anything
+    ///
+    /// A part that would not serialize back to exactly the same JSON, e.g.
+    /// one with fields this crate does not model, becomes [`Part::Other`],
+    /// so `Part::from_value(v)?.to_value() == v` always holds. Fails if the
+    /// value is not an object.
+    pub fn from_value(value: serde_json::Value) -> crate::Result<Self> {
+        let serde_json::Value::Object(fields) = value else {
+            return Err(crate::Error::RequestError(format!(
+                "A part must be a JSON object, got {}",
+                value
+            )));
+        };
+        match serde_json::from_value::<Part>(serde_json::Value::Object(fields.clone())) {
+            Ok(part) if part.to_value().as_object() == Some(&fields) => Ok(part),
+            _ => Ok(Part::Other(fields)),
+        }
+    }
 }
 
 /// Content of a message
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(from = "ContentWire")]
 pub struct Content {
     /// Parts of the content
     pub parts: Vec<Part>,
     /// Role of the content
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<Role>,
+    /// Whether the JSON this was read from had no `parts`, as the model
+    /// sends for empty turns; it is then left out when serialized again
+    pub parts_omitted: bool,
+    /// Content fields this crate does not model, kept as received so they
+    /// can be sent back unchanged
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-impl Content {
-    /// Create a new text content
-    pub fn text(text: impl Into<String>) -> Self {
+/// How content is read from JSON
+#[derive(Deserialize)]
+struct ContentWire {
+    #[serde(default)]
+    parts: Option<Vec<Part>>,
+    #[serde(default)]
+    role: Option<Role>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<ContentWire> for Content {
+    fn from(wire: ContentWire) -> Self {
         Self {
-            parts: vec![Part::Text { text: text.into() }],
-            role: None,
+            parts_omitted: wire.parts.is_none(),
+            parts: wire.parts.unwrap_or_default(),
+            role: wire.role,
+            extra: wire.extra,
         }
     }
+}
 
-    /// Create a new image block
-    pub fn image(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+impl Serialize for Content {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        if !(self.parts_omitted && self.parts.is_empty()) {
+            map.serialize_entry("parts", &self.parts)?;
+        }
+        if let Some(role) = &self.role {
+            map.serialize_entry("role", role)?;
+        }
+        for (key, value) in &self.extra {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl Content {
+    /// Create a new text content
+    pub fn text(text: impl Into<String>) -> Self {
         Self {
-            parts: vec![Part::Image {
-                source: ImageSource::Base64 {
-                    media_type: match media_type.into().as_str() {
-                        "image/jpeg" => ImageMediaType::Jpeg,
-                        "image/png" => ImageMediaType::Png,
-                        "image/gif" => ImageMediaType::Gif,
-                        "image/webp" => ImageMediaType::WebP,
-                        _ => panic!("Unsupported media type"),
-                    },
-                    data: data.into(),
-                },
+            parts: vec![Part::Text {
+                text: text.into(),
+                thought_signature: None,
             }],
-            role: None,
+            ..Default::default()
         }
     }
 
-    pub fn image_url(url: impl Into<String>) -> Self {
+    /// Create a new content with inline data
+    pub fn inline_data(blob: Blob) -> Self {
         Self {
-            parts: vec![Part::Image {
-                source: ImageSource::Url { url: url.into() },
+            parts: vec![Part::InlineData {
+                inline_data: blob,
+                video_metadata: None,
             }],
-            role: None,
+            ..Default::default()
         }
     }
 
+    /// Create a new image block from base64-encoded data
+    pub fn image(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self::inline_data(Blob::new(media_type, data))
+    }
+
     /// Create a new content with a function call
     pub fn function_call(function_call: super::tools::FunctionCall) -> Self {
         Self {
-            parts: vec![Part::FunctionCall { function_call }],
-            role: None,
+            parts: vec![Part::FunctionCall {
+                function_call,
+                thought_signature: None,
+            }],
+            ..Default::default()
         }
     }
 
@@ -137,7 +604,7 @@ impl Content {
     pub fn function_response(function_response: super::tools::FunctionResponse) -> Self {
         Self {
             parts: vec![Part::FunctionResponse { function_response }],
-            role: None,
+            ..Default::default()
         }
     }
 
@@ -147,7 +614,7 @@ impl Content {
             parts: vec![Part::FunctionResponse {
                 function_response: super::tools::FunctionResponse::new(name, response),
             }],
-            role: None,
+            ..Default::default()
         }
     }
 
@@ -156,6 +623,62 @@ impl Content {
         self.role = Some(role);
         self
     }
+
+    /// Convert the content into its JSON representation
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// Convert JSON content into typed content, without losing anything
+    ///
+    /// Parts are converted with [`Part::from_value`] and content fields
+    /// this crate does not model are kept in `extra`, so
+    /// `Content::from_value(v)?.to_value() == v` always holds.
+    pub fn from_value(value: serde_json::Value) -> crate::Result<Self> {
+        let serde_json::Value::Object(mut fields) = value else {
+            return Err(crate::Error::RequestError(format!(
+                "Content must be a JSON object, got {}",
+                value
+            )));
+        };
+        let parts = match fields.remove("parts") {
+            Some(serde_json::Value::Array(parts)) => Some(
+                parts
+                    .into_iter()
+                    .map(Part::from_value)
+                    .collect::<crate::Result<_>>()?,
+            ),
+            Some(other) => {
+                return Err(crate::Error::RequestError(format!(
+                    "Content parts must be an array, got {}",
+                    other
+                )))
+            }
+            None => None,
+        };
+        let role = match fields.remove("role") {
+            Some(role) => Some(serde_json::from_value(role)?),
+            None => None,
+        };
+        Ok(Self {
+            parts_omitted: parts.is_none(),
+            parts: parts.unwrap_or_default(),
+            role,
+            extra: fields,
+        })
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::text(text)
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::text(text)
+    }
 }
 
 /// Message in a conversation
@@ -206,24 +729,109 @@ impl Message {
     }
 }
 
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Message::user(text)
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Message::user(text)
+    }
+}
+
+impl<T: Into<String>> From<(Role, T)> for Message {
+    fn from((role, text): (Role, T)) -> Self {
+        Self {
+            content: Content::text(text).with_role(role.clone()),
+            role,
+        }
+    }
+}
+
+impl From<Content> for Message {
+    /// Content without a role is treated as a user message
+    fn from(content: Content) -> Self {
+        let role = content.role.clone().unwrap_or(Role::User);
+        Self {
+            content: content.with_role(role.clone()),
+            role,
+        }
+    }
+}
+
+/// Conversion into a [`Message`], accepted by `ContentBuilder::with_message`
+///
+/// Implemented for everything that converts into a `Message`: `&str` and
+/// `String` (user messages), `(Role, text)` pairs and [`Content`]. Apps with
+/// their own message types can implement it for those directly.
+pub trait IntoMessage {
+    /// Convert into a message
+    fn into_message(self) -> Message;
+}
+
+impl<T: Into<Message>> IntoMessage for T {
+    fn into_message(self) -> Message {
+        self.into()
+    }
+}
+
 /// Safety rating for content
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SafetyRating {
     /// The category of the safety rating
     pub category: String,
     /// The probability that the content is harmful
     pub probability: String,
+    /// Whether the content was blocked because of this rating
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<bool>,
+}
+
+impl SafetyRating {
+    /// Whether the rating indicates a medium or high probability of harm
+    pub fn is_concerning(&self) -> bool {
+        self.blocked == Some(true) || matches!(self.probability.as_str(), "MEDIUM" | "HIGH")
+    }
+
+    /// Whether the content was blocked or is at least as likely to be
+    /// harmful as the threshold blocks
+    ///
+    /// With [`HarmBlockThreshold::BlockNone`] only blocked ratings match.
+    pub fn reaches(&self, threshold: HarmBlockThreshold) -> bool {
+        if self.blocked == Some(true) {
+            return true;
+        }
+        let minimum = match threshold {
+            HarmBlockThreshold::BlockLowAndAbove => 1,
+            HarmBlockThreshold::BlockMediumAndAbove => 2,
+            HarmBlockThreshold::BlockHighAndAbove | HarmBlockThreshold::BlockOnlyHigh => 3,
+            HarmBlockThreshold::BlockNone => return false,
+        };
+        let level = match self.probability.as_str() {
+            "LOW" => 1,
+            "MEDIUM" => 2,
+            "HIGH" => 3,
+            _ => 0,
+        };
+        level >= minimum
+    }
 }
 
 /// Citation metadata for content
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CitationMetadata {
     /// The citation sources
+    #[serde(default)]
     pub citation_sources: Vec<CitationSource>,
 }
 
 /// Citation source
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CitationSource {
     /// The URI of the citation source
     pub uri: Option<String>,
@@ -241,8 +849,10 @@ pub struct CitationSource {
 
 /// A candidate response
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Candidate {
     /// The content of the candidate
+    #[serde(default)]
     pub content: Content,
     /// The safety ratings for the candidate
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -250,9 +860,15 @@ pub struct Candidate {
     /// The citation metadata for the candidate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub citation_metadata: Option<CitationMetadata>,
+    /// The sources of a grounded answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<GroundingMetadata>,
     /// The finish reason for the candidate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub finish_reason: Option<String>,
+    /// Why the model stopped, in words, e.g. for a blocked answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_message: Option<String>,
     /// The tokens used in the response
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage_metadata: Option<UsageMetadata>,
@@ -260,19 +876,29 @@ pub struct Candidate {
 
 /// Metadata about token usage
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UsageMetadata {
     /// The number of prompt tokens
+    #[serde(default)]
     pub prompt_token_count: i32,
     /// The number of response tokens
+    ///
+    /// Absent from streaming chunks that carry no candidate output yet.
+    #[serde(default)]
     pub candidates_token_count: i32,
     /// The total number of tokens
+    #[serde(default)]
     pub total_token_count: i32,
 }
 
 /// Response from the Gemini API for content generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GenerationResponse {
     /// The candidates generated
+    ///
+    /// Empty when the prompt itself was blocked.
+    #[serde(default)]
     pub candidates: Vec<Candidate>,
     /// The prompt feedback
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -280,72 +906,709 @@ pub struct GenerationResponse {
     /// Usage metadata
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage_metadata: Option<UsageMetadata>,
+    /// Identifier the API assigned to this response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_id: Option<String>,
 }
 
 /// Feedback about the prompt
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PromptFeedback {
     /// The safety ratings for the prompt
+    #[serde(default)]
     pub safety_ratings: Vec<SafetyRating>,
     /// The block reason if the prompt was blocked
     #[serde(skip_serializing_if = "Option::is_none")]
     pub block_reason: Option<String>,
+    /// Why the prompt was blocked, in words, when the API explains it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_reason_message: Option<String>,
 }
 
 impl GenerationResponse {
     /// Get the text of the first candidate
-    pub fn text(&self) -> String {
-        self.candidates
+    ///
+    /// Thought parts are skipped, so this is the answer text even for
+    /// thinking models. Text split over several parts is joined; a single
+    /// text part is borrowed from the response. Call `into_owned` for a
+    /// `String`.
+    pub fn text(&self) -> Cow<'_, str> {
+        let mut texts = self
+            .candidates
             .first()
-            .and_then(|c| {
-                c.content.parts.first().and_then(|p| match p {
-                    Part::Text { text } => Some(text.clone()),
-                    _ => None,
-                })
-            })
-            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|candidate| &candidate.content.parts)
+            .filter_map(Part::text);
+        let Some(first) = texts.next() else {
+            return Cow::Borrowed("");
+        };
+        match texts.next() {
+            None => Cow::Borrowed(first),
+            Some(second) => {
+                let mut text = format!("{}{}", first, second);
+                texts.for_each(|more| text.push_str(more));
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    /// Get the first text part of the first candidate, or `None` if it has
+    /// no text
+    ///
+    /// Like [`GenerationResponse::text`], without allocating, for render
+    /// loops that read the text on every frame, e.g. of streamed chunks,
+    /// which have one text part. Other text parts are left out.
+    pub fn text_ref(&self) -> Option<&str> {
+        self.candidates
+            .first()?
+            .content
+            .parts
+            .iter()
+            .find_map(Part::text)
+    }
+
+    /// Parse the answer of an enum output into `T`
+    ///
+    /// For responses to requests made with `ContentBuilder::with_enum_output`.
+    /// The text is trimmed, and quotes around it removed, before it is
+    /// passed to `T::from_str`.
+    pub fn parse_enum<T: std::str::FromStr>(&self) -> crate::Result<T> {
+        let text = self.text();
+        let value = text.trim().trim_matches('"');
+        value.parse().map_err(|_| {
+            crate::Error::PostProcessError(format!(
+                "Response {:?} is not one of the expected values",
+                value
+            ))
+        })
+    }
+
+    /// Parse the JSON answer of a structured output into `T`
+    ///
+    /// For responses to requests made with a response schema. Fields that
+    /// `T` has no place for are ignored, as serde does by default; use
+    /// [`GenerationResponse::parse_json_strict`] to catch them.
+    pub fn parse_json<T: serde::de::DeserializeOwned>(&self) -> crate::Result<T> {
+        Ok(serde_json::from_str(&self.text())?)
+    }
+
+    /// Parse the JSON answer into `T`, also returning the paths of fields
+    /// `T` has no place for, e.g. `items[2].confidence`
+    ///
+    /// Unexpected fields are found by serializing the value back and
+    /// comparing, so fields `T` skips when serializing are reported too.
+    /// Null fields are never reported.
+    pub fn parse_json_with_report<T>(&self) -> crate::Result<(T, Vec<String>)>
+    where
+        T: serde::de::DeserializeOwned + Serialize,
+    {
+        let original: serde_json::Value = serde_json::from_str(&self.text())?;
+        let value: T = serde_json::from_value(original.clone())?;
+        let mut unexpected = Vec::new();
+        dropped_fields(
+            &original,
+            &serde_json::to_value(&value)?,
+            "",
+            &mut unexpected,
+        );
+        Ok((value, unexpected))
+    }
+
+    /// Parse the JSON answer into `T`, failing with
+    /// [`Error::UnexpectedFields`](crate::Error::UnexpectedFields) if it has
+    /// fields `T` has no place for
+    ///
+    /// Like `#[serde(deny_unknown_fields)]` on every type in `T`, decided
+    /// per call, for pipelines that must notice when the model's output
+    /// drifts from the schema. See
+    /// [`GenerationResponse::parse_json_with_report`] for how fields are
+    /// compared.
+    pub fn parse_json_strict<T>(&self) -> crate::Result<T>
+    where
+        T: serde::de::DeserializeOwned + Serialize,
+    {
+        let (value, unexpected) = self.parse_json_with_report()?;
+        if !unexpected.is_empty() {
+            return Err(crate::Error::UnexpectedFields(unexpected));
+        }
+        Ok(value)
     }
 
     /// Get function calls from the response
     pub fn function_calls(&self) -> Vec<&super::tools::FunctionCall> {
         self.candidates
             .iter()
-            .flat_map(|c| {
-                c.content.parts.iter().filter_map(|p| match p {
-                    Part::FunctionCall { function_call } => Some(function_call),
-                    _ => None,
-                })
-            })
+            .flat_map(|c| c.content.parts.iter().filter_map(Part::as_function_call))
             .collect()
     }
 }
 
+/// Collect the paths of fields in `original` that did not survive parsing
+///
+/// Keys are compared ignoring case and underscores, since snake_case
+/// aliases come back in camelCase.
+fn dropped_fields(
+    original: &serde_json::Value,
+    parsed: &serde_json::Value,
+    path: &str,
+    dropped: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    let normalize = |key: &str| key.replace('_', "").to_lowercase();
+    match (original, parsed) {
+        (Value::Object(original), Value::Object(parsed)) => {
+            for (key, value) in original.iter().filter(|(_, v)| !v.is_null()) {
+                let field = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match parsed.iter().find(|(k, _)| normalize(k) == normalize(key)) {
+                    Some((_, parsed)) => dropped_fields(value, parsed, &field, dropped),
+                    None => dropped.push(field),
+                }
+            }
+        }
+        (Value::Array(original), Value::Array(parsed)) => {
+            for (i, (value, parsed)) in original.iter().zip(parsed).enumerate() {
+                dropped_fields(value, parsed, &format!("{}[{}]", path, i), dropped);
+            }
+        }
+        _ => {}
+    }
+}
+
 /// Request to generate content
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GenerateContentRequest {
     /// The contents to generate content from
     pub contents: Vec<Content>,
     /// The generation config
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "generation_config", skip_serializing_if = "Option::is_none")]
     pub generation_config: Option<GenerationConfig>,
     /// The safety settings
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "safety_settings", skip_serializing_if = "Option::is_none")]
     pub safety_settings: Option<Vec<SafetySetting>>,
     /// The tools that the model can use
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<super::tools::Tool>>,
     /// The tool config
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "tool_config", skip_serializing_if = "Option::is_none")]
     pub tool_config: Option<ToolConfig>,
     /// The system instruction
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "system_instruction", skip_serializing_if = "Option::is_none")]
     pub system_instruction: Option<Content>,
+    /// Key-value labels for billing and usage reports (Vertex AI)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+impl GenerateContentRequest {
+    /// Parse a raw `generateContent` request body and check it
+    ///
+    /// For replaying payloads from cURL examples or AI Studio's "Get code"
+    /// while migrating to the typed builders. Fields this crate does not
+    /// model would be dropped silently on the way, so they are reported as
+    /// an error instead, e.g. `Unsupported fields in request JSON:
+    /// generationConfig.thinkingConfig`.
+    pub fn from_json(value: serde_json::Value) -> crate::Result<Self> {
+        let request: Self = serde_json::from_value(value.clone())?;
+        let mut dropped = Vec::new();
+        dropped_fields(&value, &serde_json::to_value(&request)?, "", &mut dropped);
+        if !dropped.is_empty() {
+            return Err(crate::Error::RequestError(format!(
+                "Unsupported fields in request JSON: {}",
+                dropped.join(", ")
+            )));
+        }
+        request.validate()?;
+        Ok(request)
+    }
+
+    /// Check the request for problems that the API would reject
+    pub fn validate(&self) -> crate::Result<()> {
+        let mut inline_size = 0;
+        for part in self.contents.iter().flat_map(|c| c.parts.iter()) {
+            if let Part::InlineData { inline_data, .. } = part {
+                inline_data.validate()?;
+                inline_size += inline_data.decoded_len();
+            }
+        }
+        if inline_size > Blob::MAX_INLINE_SIZE {
+            return Err(crate::Error::RequestError(format!(
+                "Inline data totals {} bytes, the per-request limit is {} bytes",
+                inline_size,
+                Blob::MAX_INLINE_SIZE
+            )));
+        }
+        for part in self.contents.iter().flat_map(|c| c.parts.iter()) {
+            let video_metadata = match part {
+                Part::InlineData { video_metadata, .. } | Part::FileData { video_metadata, .. } => {
+                    video_metadata.as_ref()
+                }
+                _ => None,
+            };
+            if let Some(crate::video::VideoMetadata { fps: Some(fps), .. }) = video_metadata {
+                if !fps.is_finite() || *fps <= 0.0 {
+                    return Err(crate::Error::RequestError(format!(
+                        "Video fps must be a positive number, got {}",
+                        fps
+                    )));
+                }
+            }
+        }
+        if let Some(config) = &self.generation_config {
+            config.validate()?;
+        }
+        self.check_compatibility()?;
+        self.check_declarations()
+    }
+
+    /// Collect every problem in the function declarations at once
+    fn check_declarations(&self) -> crate::Result<()> {
+        use super::tools::Tool;
+
+        let declarations = self
+            .tools
+            .iter()
+            .flatten()
+            .filter_map(|t| match t {
+                Tool::Function {
+                    function_declarations,
+                } => Some(function_declarations),
+                _ => None,
+            })
+            .flatten();
+
+        let mut issues = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for declaration in declarations {
+            if !seen.insert(declaration.name.as_str()) {
+                issues.push(super::tools::Issue {
+                    function: declaration.name.clone(),
+                    path: "name".to_string(),
+                    message: "is declared more than once".to_string(),
+                });
+            }
+            issues.extend(declaration.issues());
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::Error::InvalidToolDeclaration(issues))
+        }
+    }
+
+    /// Reject option combinations that the API answers with a generic 400
+    fn check_compatibility(&self) -> crate::Result<()> {
+        use super::tools::Tool;
+
+        // Tools combined with JSON output depend on the model; see
+        // `ModelCapabilities::check`
+        let tools = self.tools.as_deref().unwrap_or_default();
+        let has_functions = tools.iter().any(|t| matches!(t, Tool::Function { .. }));
+
+        let config = self.generation_config.as_ref();
+        let mime_type = config.and_then(|c| c.response_mime_type.as_deref());
+        let has_schema = config.is_some_and(|c| c.response_schema.is_some());
+        let enum_mode = mime_type == Some(ENUM_MIME_TYPE);
+
+        if has_schema && mime_type.is_none() {
+            return Err(crate::Error::IncompatibleOptions(
+                "response_schema requires response_mime_type to be set, e.g. \
+                 \"application/json\""
+                    .to_string(),
+            ));
+        }
+        let has_enum_values = config
+            .and_then(|c| c.response_schema.as_ref())
+            .and_then(|schema| schema["enum"].as_array())
+            .is_some_and(|values| !values.is_empty());
+        if enum_mode && !has_enum_values {
+            return Err(crate::Error::IncompatibleOptions(format!(
+                "response_mime_type {:?} requires a response_schema with enum values; \
+                 use with_enum_output",
+                ENUM_MIME_TYPE
+            )));
+        }
+        let function_calling_config = self
+            .tool_config
+            .as_ref()
+            .and_then(|c| c.function_calling_config.as_ref());
+        if function_calling_config.is_some() && !has_functions {
+            return Err(crate::Error::IncompatibleOptions(
+                "a function calling mode was set but no functions were declared".to_string(),
+            ));
+        }
+        if let Some(config) = function_calling_config {
+            if let Some(allowed) = &config.allowed_function_names {
+                if !matches!(config.mode, FunctionCallingMode::Any) {
+                    return Err(crate::Error::IncompatibleOptions(
+                        "allowed_function_names requires function calling mode Any".to_string(),
+                    ));
+                }
+                let declared: Vec<&str> = tools
+                    .iter()
+                    .filter_map(|t| match t {
+                        Tool::Function {
+                            function_declarations,
+                        } => Some(function_declarations),
+                        _ => None,
+                    })
+                    .flatten()
+                    .map(|d| d.name.as_str())
+                    .collect();
+                if let Some(unknown) = allowed.iter().find(|n| !declared.contains(&n.as_str())) {
+                    return Err(crate::Error::IncompatibleOptions(format!(
+                        "allowed function {} is not declared",
+                        unknown
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builder for a [`GenerateContentRequest`], without a client
+///
+/// Keeps request construction in plain code that can be unit tested by
+/// inspecting the built request, e.g. in a function that maps an app's
+/// state to a prompt. Send the result with `Gemini::execute`, or continue
+/// with `ContentBuilder::with_request`.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateContentRequestBuilder {
+    pub(crate) contents: Vec<Content>,
+    pub(crate) generation_config: Option<GenerationConfig>,
+    pub(crate) tools: super::tools::Tools,
+    pub(crate) tool_config: Option<ToolConfig>,
+    pub(crate) system_instruction: Option<Content>,
+    pub(crate) safety_settings: Option<Vec<SafetySetting>>,
+    pub(crate) labels: Option<BTreeMap<String, String>>,
+}
+
+impl GenerateContentRequestBuilder {
+    /// Create an empty request builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the system instruction
+    pub fn with_system_instruction(mut self, text: impl Into<String>) -> Self {
+        self.system_instruction = Some(Content::text(text));
+        self
+    }
+
+    /// Add a user message
+    pub fn with_user_message(mut self, text: impl Into<String>) -> Self {
+        self.contents.push(Message::user(text).content);
+        self
+    }
+
+    /// Add a model message
+    pub fn with_model_message(mut self, text: impl Into<String>) -> Self {
+        self.contents.push(Message::model(text).content);
+        self
+    }
+
+    /// Add few-shot examples as alternating user and model messages
+    ///
+    /// Each `(input, output)` pair becomes a user message followed by a
+    /// model message, so call this before adding the real user message.
+    pub fn with_examples<I, O>(mut self, examples: impl IntoIterator<Item = (I, O)>) -> Self
+    where
+        I: Into<String>,
+        O: Into<String>,
+    {
+        for (input, output) in examples {
+            self = self.with_user_message(input).with_model_message(output);
+        }
+        self
+    }
+
+    /// Add few-shot examples as one user message, between two `delimiter` lines
+    ///
+    /// Keeps the examples apart from the conversation when they should not
+    /// read as earlier turns. Each example is written as `Input:` and
+    /// `Output:` lines, separated by blank lines.
+    pub fn with_delimited_examples<I, O>(
+        self,
+        delimiter: impl Into<String>,
+        examples: impl IntoIterator<Item = (I, O)>,
+    ) -> Self
+    where
+        I: Into<String>,
+        O: Into<String>,
+    {
+        let delimiter = delimiter.into();
+        let examples: Vec<String> = examples
+            .into_iter()
+            .map(|(input, output)| format!("Input: {}\nOutput: {}", input.into(), output.into()))
+            .collect();
+        if examples.is_empty() {
+            return self;
+        }
+        self.with_user_message(format!(
+            "{delimiter}\n{}\n{delimiter}",
+            examples.join("\n\n")
+        ))
+    }
+
+    /// Add inline data (image, audio, document, ...) from raw bytes as a user message
+    ///
+    /// The bytes are base64-encoded while the request is sent; size limits
+    /// are checked when the request is executed.
+    pub fn with_inline_data(
+        mut self,
+        mime_type: impl Into<String>,
+        data: impl AsRef<[u8]>,
+    ) -> Self {
+        let content = Content::inline_data(Blob::from_bytes(mime_type, data)).with_role(Role::User);
+        self.contents.push(content);
+        self
+    }
+
+    /// Add a function response using a JSON value
+    pub fn with_function_response(
+        mut self,
+        name: impl Into<String>,
+        response: serde_json::Value,
+    ) -> Self {
+        let content = Content::function_response_json(name, response).with_role(Role::User);
+        self.contents.push(content);
+        self
+    }
+
+    /// Add a function response using a JSON string
+    pub fn with_function_response_str(
+        self,
+        name: impl Into<String>,
+        response: impl Into<String>,
+    ) -> std::result::Result<Self, serde_json::Error> {
+        let json = serde_json::from_str(&response.into())?;
+        Ok(self.with_function_response(name, json))
+    }
+
+    /// Add a function response followed by a note in the same user turn
+    ///
+    /// Saves a separate turn for context that goes with the tool output,
+    /// e.g. "the list is truncated to 50 entries".
+    pub fn with_function_response_and_text(
+        mut self,
+        name: impl Into<String>,
+        response: serde_json::Value,
+        text: impl Into<String>,
+    ) -> Self {
+        let mut content = Content::function_response_json(name, response).with_role(Role::User);
+        content.parts.push(Part::Text {
+            text: text.into(),
+            thought_signature: None,
+        });
+        self.contents.push(content);
+        self
+    }
+
+    /// Add a message
+    ///
+    /// Accepts a [`Message`] or anything convertible into one, e.g. `"text"`
+    /// for a user message or `(Role::Model, "text")`.
+    pub fn with_message(mut self, message: impl IntoMessage) -> Self {
+        let message = message.into_message();
+        let content = message.content;
+        let role = content.role.clone().unwrap_or(message.role);
+        self.contents.push(content.with_role(role));
+        self
+    }
+
+    /// Add multiple messages
+    pub fn with_messages<M: IntoMessage>(mut self, messages: impl IntoIterator<Item = M>) -> Self {
+        for message in messages {
+            self = self.with_message(message);
+        }
+        self
+    }
+
+    /// Set the generation config
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.generation_config = Some(config);
+        self
+    }
+
+    /// Set the temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.config().temperature = Some(temperature);
+        self
+    }
+
+    /// Set the top-p value
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.config().top_p = Some(top_p);
+        self
+    }
+
+    /// Set the top-k value
+    pub fn with_top_k(mut self, top_k: i32) -> Self {
+        self.config().top_k = Some(top_k);
+        self
+    }
+
+    /// Set the maximum output tokens
+    pub fn with_max_output_tokens(mut self, max_output_tokens: i32) -> Self {
+        self.config().max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// Set the candidate count
+    pub fn with_candidate_count(mut self, candidate_count: i32) -> Self {
+        self.config().candidate_count = Some(candidate_count);
+        self
+    }
+
+    /// Set the stop sequences
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.config().stop_sequences = Some(stop_sequences);
+        self
+    }
+
+    /// Set the response mime type
+    pub fn with_response_mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.config().response_mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Set the response schema for structured output
+    pub fn with_response_schema(mut self, schema: serde_json::Value) -> Self {
+        self.config().response_schema = Some(schema);
+        self
+    }
+
+    /// Constrain the answer to exactly one of the given values
+    ///
+    /// Sets the `text/x.enum` response type with a schema listing the
+    /// values, so the reply is plain text rather than JSON. Read it with
+    /// [`GenerationResponse::parse_enum`].
+    pub fn with_enum_output<I, S>(self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values: Vec<String> = values.into_iter().map(Into::into).collect();
+        self.with_response_mime_type(ENUM_MIME_TYPE)
+            .with_response_schema(serde_json::json!({ "type": "STRING", "enum": values }))
+    }
+
+    /// Set the safety settings
+    pub fn with_safety_settings(mut self, safety_settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = Some(safety_settings);
+        self
+    }
+
+    /// Add a tool
+    ///
+    /// Function declarations from all tools are merged into one list.
+    pub fn with_tool(mut self, tool: super::tools::Tool) -> Self {
+        self.tools.add(tool);
+        self
+    }
+
+    /// Add a set of tools
+    pub fn with_tools(mut self, tools: super::tools::Tools) -> Self {
+        for tool in tools.into_vec() {
+            self.tools.add(tool);
+        }
+        self
+    }
+
+    /// Add a function declaration as a tool
+    pub fn with_function(self, function: super::tools::FunctionDeclaration) -> Self {
+        self.with_tool(super::tools::Tool::new(function))
+    }
+
+    /// Set the function calling mode
+    pub fn with_function_calling_mode(mut self, mode: FunctionCallingMode) -> Self {
+        self.function_calling_config_or(FunctionCallingMode::Auto)
+            .mode = mode;
+        self
+    }
+
+    /// Set the whole tool config, replacing the function calling mode and names
+    pub fn with_tool_config(mut self, tool_config: ToolConfig) -> Self {
+        self.tool_config = Some(tool_config);
+        self
+    }
+
+    /// Restrict function calling to a subset of the declared functions
+    ///
+    /// Only valid in [`FunctionCallingMode::Any`], which is selected unless
+    /// a mode was set already; the model must then call one of these.
+    pub fn with_allowed_function_names(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let config = self.function_calling_config_or(FunctionCallingMode::Any);
+        config.allowed_function_names = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Attach a label for billing and usage reports
+    ///
+    /// Labels are only honored by Vertex AI endpoints.
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Build the request
+    ///
+    /// Nothing is checked yet; requests are validated when they are sent.
+    pub fn build(self) -> GenerateContentRequest {
+        GenerateContentRequest {
+            contents: self.contents,
+            generation_config: self.generation_config,
+            safety_settings: self.safety_settings,
+            tools: (!self.tools.is_empty()).then(|| self.tools.into_vec()),
+            tool_config: self.tool_config,
+            system_instruction: self.system_instruction,
+            labels: self.labels,
+        }
+    }
+
+    /// The generation config, created with defaults if unset
+    fn config(&mut self) -> &mut GenerationConfig {
+        self.generation_config
+            .get_or_insert_with(GenerationConfig::default)
+    }
+
+    /// The function calling config, created with the given mode if unset
+    fn function_calling_config_or(
+        &mut self,
+        mode: FunctionCallingMode,
+    ) -> &mut FunctionCallingConfig {
+        self.tool_config
+            .get_or_insert(ToolConfig {
+                function_calling_config: None,
+            })
+            .function_calling_config
+            .get_or_insert_with(|| FunctionCallingConfig::new(mode))
+    }
+}
+
+impl From<GenerateContentRequestBuilder> for GenerateContentRequest {
+    fn from(builder: GenerateContentRequestBuilder) -> Self {
+        builder.build()
+    }
 }
 
 /// Configuration for generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GenerationConfig {
-    /// The temperature for the model (0.0 to 1.0)
+    /// The temperature for the model (0.0 to 2.0)
     ///
     /// Controls the randomness of the output. Higher values (e.g., 0.9) make output
     /// more random, lower values (e.g., 0.1) make output more deterministic.
@@ -357,44 +1620,44 @@ pub struct GenerationConfig {
     /// For each token generation step, the model considers the top_p percentage of
     /// probability mass for potential token choices. Lower values are more selective,
     /// higher values allow more variety.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "top_p", skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
 
     /// The top-k value for the model
     ///
     /// For each token generation step, the model considers the top_k most likely tokens.
     /// Lower values are more selective, higher values allow more variety.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "top_k", skip_serializing_if = "Option::is_none")]
     pub top_k: Option<i32>,
 
     /// The maximum number of tokens to generate
     ///
     /// Limits the length of the generated content. One token is roughly 4 characters.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "max_output_tokens", skip_serializing_if = "Option::is_none")]
     pub max_output_tokens: Option<i32>,
 
     /// The candidate count
     ///
     /// Number of alternative responses to generate.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "candidate_count", skip_serializing_if = "Option::is_none")]
     pub candidate_count: Option<i32>,
 
     /// Whether to stop on specific sequences
     ///
     /// The model will stop generating content when it encounters any of these sequences.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "stop_sequences", skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
 
     /// The response mime type
     ///
     /// Specifies the format of the model's response.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "response_mime_type", skip_serializing_if = "Option::is_none")]
     pub response_mime_type: Option<String>,
 
     /// The response schema
     ///
     /// Specifies the JSON schema for structured responses.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "response_schema", skip_serializing_if = "Option::is_none")]
     pub response_schema: Option<serde_json::Value>,
 }
 
@@ -413,19 +1676,120 @@ impl Default for GenerationConfig {
     }
 }
 
+impl GenerationConfig {
+    /// Highest temperature the API accepts
+    pub const MAX_TEMPERATURE: f32 = 2.0;
+
+    /// Check that every number is finite and in the range the API accepts
+    ///
+    /// `NaN` and infinities would otherwise be sent as `null`. Called when a
+    /// request is executed.
+    pub fn validate(&self) -> crate::Result<()> {
+        let ranges = [
+            ("temperature", self.temperature, Self::MAX_TEMPERATURE),
+            ("topP", self.top_p, 1.0),
+        ];
+        for (name, value, max) in ranges {
+            match value {
+                Some(value) if !value.is_finite() => {
+                    return Err(crate::Error::RequestError(format!(
+                        "{} must be a finite number, got {}",
+                        name, value
+                    )));
+                }
+                Some(value) if !(0.0..=max).contains(&value) => {
+                    return Err(crate::Error::RequestError(format!(
+                        "{} must be between 0 and {}, got {}",
+                        name, max, value
+                    )));
+                }
+                _ => {}
+            }
+        }
+        let counts = [
+            ("topK", self.top_k),
+            ("maxOutputTokens", self.max_output_tokens),
+            ("candidateCount", self.candidate_count),
+        ];
+        for (name, value) in counts {
+            if let Some(value) = value.filter(|value| *value < 1) {
+                return Err(crate::Error::RequestError(format!(
+                    "{} must be at least 1, got {}",
+                    name, value
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring the numbers into the ranges the API accepts
+    ///
+    /// For values that come from user input. Non-finite values are unset,
+    /// so the model's defaults apply, and the others are clamped.
+    pub fn clamped(mut self) -> Self {
+        let clamp = |value: Option<f32>, max: f32| {
+            value
+                .filter(|value| value.is_finite())
+                .map(|value| value.clamp(0.0, max))
+        };
+        self.temperature = clamp(self.temperature, Self::MAX_TEMPERATURE);
+        self.top_p = clamp(self.top_p, 1.0);
+        self.top_k = self.top_k.map(|value| value.max(1));
+        self.max_output_tokens = self.max_output_tokens.map(|value| value.max(1));
+        self.candidate_count = self.candidate_count.map(|value| value.max(1));
+        self
+    }
+
+    /// Round the temperature and top-p to a number of decimals
+    ///
+    /// Computed values like `0.1 + 0.2` then serialize as `0.3` rather than
+    /// `0.30000001`, which keeps request bodies and cache keys stable.
+    pub fn rounded(mut self, decimals: u32) -> Self {
+        let factor = 10f64.powi(decimals as i32);
+        let round = |value: Option<f32>| {
+            value.map(|value| ((value as f64 * factor).round() / factor) as f32)
+        };
+        self.temperature = round(self.temperature);
+        self.top_p = round(self.top_p);
+        self
+    }
+}
+
 /// Configuration for tools
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ToolConfig {
     /// The function calling config
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        alias = "function_calling_config",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub function_calling_config: Option<FunctionCallingConfig>,
 }
 
 /// Configuration for function calling
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FunctionCallingConfig {
     /// The mode for function calling
     pub mode: FunctionCallingMode,
+    /// Restrict `Any` mode to these declared functions
+    #[serde(
+        alias = "allowed_function_names",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
+impl FunctionCallingConfig {
+    /// Create a config with the given mode and no function restriction
+    pub fn new(mode: FunctionCallingMode) -> Self {
+        Self {
+            mode,
+            allowed_function_names: None,
+        }
+    }
 }
 
 /// Mode for function calling
@@ -433,15 +1797,18 @@ pub struct FunctionCallingConfig {
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum FunctionCallingMode {
     /// The model may use function calling
+    #[serde(alias = "auto")]
     Auto,
     /// The model must use function calling
+    #[serde(alias = "any")]
     Any,
     /// The model must not use function calling
+    #[serde(alias = "none")]
     None,
 }
 
 /// Setting for safety
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SafetySetting {
     /// The category of content to filter
     pub category: HarmCategory,
@@ -449,33 +1816,127 @@ pub struct SafetySetting {
     pub threshold: HarmBlockThreshold,
 }
 
+impl SafetySetting {
+    /// Create a new safety setting
+    pub fn new(category: HarmCategory, threshold: HarmBlockThreshold) -> Self {
+        Self {
+            category,
+            threshold,
+        }
+    }
+
+    /// The same threshold for every harm category
+    pub fn all(threshold: HarmBlockThreshold) -> Vec<Self> {
+        HarmCategory::ALL
+            .iter()
+            .map(|category| Self::new(*category, threshold))
+            .collect()
+    }
+}
+
 /// Category of harmful content
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum HarmCategory {
     /// Dangerous content
+    #[serde(
+        rename = "HARM_CATEGORY_DANGEROUS_CONTENT",
+        alias = "dangerous_content"
+    )]
     Dangerous,
     /// Harassment content
+    #[serde(rename = "HARM_CATEGORY_HARASSMENT", alias = "harassment")]
     Harassment,
     /// Hate speech
+    #[serde(rename = "HARM_CATEGORY_HATE_SPEECH", alias = "hate_speech")]
     HateSpeech,
     /// Sexually explicit content
+    #[serde(
+        rename = "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+        alias = "sexually_explicit"
+    )]
     SexuallyExplicit,
 }
 
+impl HarmCategory {
+    /// Every category that can be configured for Gemini models
+    pub const ALL: [HarmCategory; 4] = [
+        HarmCategory::Dangerous,
+        HarmCategory::Harassment,
+        HarmCategory::HateSpeech,
+        HarmCategory::SexuallyExplicit,
+    ];
+}
+
 /// Threshold for blocking harmful content
 #[allow(clippy::enum_variant_names)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum HarmBlockThreshold {
     /// Block content with low probability of harm
+    #[serde(alias = "block_low_and_above")]
     BlockLowAndAbove,
     /// Block content with medium probability of harm
+    #[serde(alias = "block_medium_and_above")]
     BlockMediumAndAbove,
     /// Block content with high probability of harm
+    #[serde(alias = "block_high_and_above")]
     BlockHighAndAbove,
     /// Block content with maximum probability of harm
+    #[serde(alias = "block_only_high")]
     BlockOnlyHigh,
     /// Never block content
+    #[serde(alias = "block_none")]
     BlockNone,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parts_round_trip() {
+        let parts = [
+            json!({"text": "Paris.", "thoughtSignature": "X"}),
+            json!({"text": "Thinking...", "thought": true, "thoughtSignature": "Y"}),
+            // Not canonical base64: no padding
+            json!({"inlineData": {"mimeType": "image/png", "data": "iVBORw0KGgo"}}),
+            json!({"text": "Hi", "partMetadata": {"source": "cache"}}),
+            json!({"executableCode": {"language": "PYTHON", "code": "print(1)"}}),
+        ];
+        for part in parts {
+            assert_eq!(Part::from_value(part.clone()).unwrap().to_value(), part);
+        }
+        let signed = Part::from_value(json!({"text": "Paris.", "thoughtSignature": "X"}));
+        assert!(matches!(
+            signed.unwrap(),
+            Part::Text { thought_signature: Some(s), .. } if s == "X"
+        ));
+    }
+
+    #[test]
+    fn content_round_trips() {
+        let contents = [
+            json!({
+                "role": "model",
+                "parts": [{"text": "Paris.", "thoughtSignature": "X"}],
+                "citationMetadata": {"citations": []}
+            }),
+            json!({"role": "model"}),
+            json!({"parts": []}),
+            json!({
+                "parts": [{"inlineData": {"mimeType": "audio/wav", "data": "UklGRg"}}],
+                "role": "user"
+            }),
+        ];
+        for content in contents {
+            assert_eq!(
+                Content::from_value(content.clone()).unwrap().to_value(),
+                content
+            );
+            // Plain deserialization keeps the same fields
+            let parsed: Content = serde_json::from_value(content.clone()).unwrap();
+            assert_eq!(parsed.to_value(), content);
+        }
+    }
+}
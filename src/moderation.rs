@@ -0,0 +1,268 @@
+use crate::{
+    client::Gemini,
+    models::{GenerationResponse, HarmBlockThreshold, HarmCategory, SafetyRating, SafetySetting},
+    Result,
+};
+
+/// Model asked for a rephrasing by [`Gemini::suggest_rephrasing`]
+const REPHRASE_MODEL: &str = "models/gemini-2.0-flash-lite";
+
+/// Safety assessment of a piece of user input
+#[derive(Debug, Clone)]
+pub struct ModerationResult {
+    /// Whether the input was blocked or rated medium/high in any category
+    pub flagged: bool,
+    /// Why the input was blocked, if it was
+    pub block_reason: Option<String>,
+    /// Per-category safety ratings
+    pub ratings: Vec<SafetyRating>,
+}
+
+impl ModerationResult {
+    /// The ratings that caused the input to be flagged
+    pub fn flagged_categories(&self) -> Vec<&SafetyRating> {
+        self.ratings.iter().filter(|r| r.is_concerning()).collect()
+    }
+}
+
+/// Why the API refused to answer a prompt
+#[derive(Debug, Clone)]
+pub struct PromptBlock {
+    /// The block reason, e.g. `SAFETY`, `BLOCKLIST` or `PROHIBITED_CONTENT`
+    pub reason: String,
+    /// The ratings that were blocked or rated medium/high
+    pub ratings: Vec<BlockedRating>,
+    /// The API's explanation, when it gives one
+    pub message: Option<String>,
+}
+
+/// A safety rating that contributed to a [`PromptBlock`] or
+/// [`BlockDetails`]
+#[derive(Debug, Clone)]
+pub struct BlockedRating {
+    /// The category, if it is one that can be configured
+    pub category: Option<HarmCategory>,
+    /// The rating as reported
+    pub rating: SafetyRating,
+    /// The threshold the request set for the category, if any
+    ///
+    /// `None` means the API's default threshold applied.
+    pub threshold: Option<HarmBlockThreshold>,
+}
+
+impl PromptBlock {
+    /// Fill in the thresholds the request was sent with
+    pub fn with_safety_settings(mut self, settings: &[SafetySetting]) -> Self {
+        for blocked in &mut self.ratings {
+            blocked.threshold = settings
+                .iter()
+                .find(|s| Some(s.category) == blocked.category)
+                .map(|s| s.threshold);
+        }
+        self
+    }
+
+    /// The categories of the ratings, for showing to the user
+    pub fn categories(&self) -> Vec<HarmCategory> {
+        self.ratings.iter().filter_map(|r| r.category).collect()
+    }
+}
+
+impl std::fmt::Display for PromptBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_block(f, &self.reason, &self.ratings, self.message.as_deref())
+    }
+}
+
+/// Which stage of a request was blocked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockedAt {
+    /// The prompt; the response has no candidates
+    Prompt,
+    /// The answer of the candidate at this index
+    Candidate(usize),
+}
+
+/// Why a response was blocked, whether at the prompt or the answer
+///
+/// Returned by [`GenerationResponse::block_details`]; its `Display` form
+/// is meant for showing to users.
+#[derive(Debug, Clone)]
+pub struct BlockDetails {
+    /// What was blocked
+    pub blocked_at: BlockedAt,
+    /// The block or finish reason, e.g. `SAFETY`, `RECITATION` or
+    /// `PROHIBITED_CONTENT`
+    pub reason: String,
+    /// The API's explanation, when it gives one
+    pub message: Option<String>,
+    /// The ratings that were blocked or rated medium/high
+    pub ratings: Vec<BlockedRating>,
+}
+
+impl std::fmt::Display for BlockDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let BlockedAt::Candidate(_) = self.blocked_at {
+            write!(f, "answer blocked, ")?;
+        }
+        write_block(f, &self.reason, &self.ratings, self.message.as_deref())
+    }
+}
+
+fn write_block(
+    f: &mut std::fmt::Formatter<'_>,
+    reason: &str,
+    ratings: &[BlockedRating],
+    message: Option<&str>,
+) -> std::fmt::Result {
+    write!(f, "{}", reason)?;
+    for (i, blocked) in ratings.iter().enumerate() {
+        let separator = if i == 0 { ": " } else { ", " };
+        write!(
+            f,
+            "{}{} is {}",
+            separator, blocked.rating.category, blocked.rating.probability
+        )?;
+        if let Some(threshold) = blocked.threshold {
+            write!(f, " (threshold {:?})", threshold)?;
+        }
+    }
+    if let Some(message) = message {
+        write!(f, " ({})", message)?;
+    }
+    Ok(())
+}
+
+/// Finish reasons that mean the answer was withheld
+const BLOCKING_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+    "IMAGE_SAFETY",
+];
+
+impl GenerationResponse {
+    /// Why the prompt was blocked, or `None` if it was not
+    ///
+    /// A blocked prompt gets a response without candidates, so its text is
+    /// empty; this says which categories caused it. Thresholds are not
+    /// known from the response alone, see
+    /// [`PromptBlock::with_safety_settings`].
+    pub fn prompt_block(&self) -> Option<PromptBlock> {
+        let feedback = self.prompt_feedback.as_ref()?;
+        let reason = feedback.block_reason.clone()?;
+        Some(PromptBlock {
+            reason,
+            ratings: blocked_ratings(&feedback.safety_ratings),
+            message: feedback.block_reason_message.clone(),
+        })
+    }
+
+    /// Why the prompt or an answer was blocked, or `None` if nothing was
+    ///
+    /// A blocked prompt comes first; otherwise the first candidate that
+    /// finished for a safety, recitation or policy reason is reported.
+    pub fn block_details(&self) -> Option<BlockDetails> {
+        if let Some(block) = self.prompt_block() {
+            return Some(BlockDetails {
+                blocked_at: BlockedAt::Prompt,
+                reason: block.reason,
+                message: block.message,
+                ratings: block.ratings,
+            });
+        }
+        let (index, candidate) = self.candidates.iter().enumerate().find(|(_, c)| {
+            c.finish_reason
+                .as_deref()
+                .is_some_and(|reason| BLOCKING_FINISH_REASONS.contains(&reason))
+        })?;
+        Some(BlockDetails {
+            blocked_at: BlockedAt::Candidate(index),
+            reason: candidate.finish_reason.clone().unwrap_or_default(),
+            message: candidate.finish_message.clone(),
+            ratings: blocked_ratings(candidate.safety_ratings.as_deref().unwrap_or_default()),
+        })
+    }
+}
+
+/// The ratings that were blocked or rated medium/high
+fn blocked_ratings(ratings: &[SafetyRating]) -> Vec<BlockedRating> {
+    ratings
+        .iter()
+        .filter(|rating| rating.is_concerning())
+        .map(|rating| BlockedRating {
+            category: serde_json::from_value(rating.category.as_str().into()).ok(),
+            rating: rating.clone(),
+            threshold: None,
+        })
+        .collect()
+}
+
+impl Gemini {
+    /// Ask a lightweight model how to rephrase a blocked prompt
+    ///
+    /// Opt-in, since it sends the blocked prompt again and costs a call.
+    /// Returns `None` if no suggestion came back, e.g. because the
+    /// suggestion was blocked as well.
+    pub async fn suggest_rephrasing(
+        &self,
+        prompt: impl Into<String>,
+        block: &PromptBlock,
+    ) -> Result<Option<String>> {
+        let response = self
+            .generate_content()
+            .with_model(REPHRASE_MODEL)
+            .with_system_instruction(format!(
+                "The user's message was blocked by safety filters ({}). Rephrase it so that \
+                 it keeps any legitimate intent and avoids the flagged content. Reply with \
+                 the rephrased message only.",
+                block
+            ))
+            .with_user_message(prompt)
+            .with_max_output_tokens(256)
+            .execute()
+            .await?;
+        let suggestion = response.text().trim().to_string();
+        Ok((!suggestion.is_empty()).then_some(suggestion))
+    }
+
+    /// Screen user input with the API's safety classifiers
+    ///
+    /// Runs a one-token generation with the strictest thresholds, so the
+    /// cost is essentially the prompt tokens, and collects the safety ratings
+    /// reported for the prompt and the candidate.
+    pub async fn moderate(&self, text: impl Into<String>) -> Result<ModerationResult> {
+        let response = self
+            .generate_content()
+            .with_user_message(text)
+            .with_max_output_tokens(1)
+            .with_safety_settings(SafetySetting::all(HarmBlockThreshold::BlockLowAndAbove))
+            .execute()
+            .await?;
+
+        let mut ratings = Vec::new();
+        let mut block_reason = None;
+        if let Some(feedback) = response.prompt_feedback {
+            block_reason = feedback.block_reason;
+            ratings.extend(feedback.safety_ratings);
+        }
+        if let Some(candidate) = response.candidates.into_iter().next() {
+            if candidate.finish_reason.as_deref() == Some("SAFETY") && block_reason.is_none() {
+                block_reason = Some("SAFETY".to_string());
+            }
+            for rating in candidate.safety_ratings.into_iter().flatten() {
+                if !ratings.iter().any(|r| r.category == rating.category) {
+                    ratings.push(rating);
+                }
+            }
+        }
+
+        Ok(ModerationResult {
+            flagged: block_reason.is_some() || ratings.iter().any(|r| r.is_concerning()),
+            block_reason,
+            ratings,
+        })
+    }
+}
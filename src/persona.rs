@@ -0,0 +1,112 @@
+use crate::models::{HarmBlockThreshold, SafetySetting};
+use serde::{Deserialize, Serialize};
+
+/// How strictly potentially harmful content is blocked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyPreset {
+    /// Block content with a low or higher probability of harm
+    Strict,
+    /// Block content with a medium or higher probability of harm
+    Balanced,
+    /// Only block content with a high probability of harm
+    Relaxed,
+    /// Explicit per-category settings
+    Custom(Vec<SafetySetting>),
+}
+
+impl SafetyPreset {
+    /// The safety settings sent for this preset
+    pub fn settings(&self) -> Vec<SafetySetting> {
+        match self {
+            Self::Strict => SafetySetting::all(HarmBlockThreshold::BlockLowAndAbove),
+            Self::Balanced => SafetySetting::all(HarmBlockThreshold::BlockMediumAndAbove),
+            Self::Relaxed => SafetySetting::all(HarmBlockThreshold::BlockOnlyHigh),
+            Self::Custom(settings) => settings.clone(),
+        }
+    }
+}
+
+/// A reusable bundle of system instruction, sampling and safety defaults
+///
+/// Apply with `ContentBuilder::with_persona`. Personas derive serde traits so
+/// products can keep them in TOML or JSON configuration:
+///
+/// ```toml
+/// name = "support"
+/// system_instruction = "You are a friendly support agent for Acme."
+/// temperature = 0.4
+/// safety = "strict"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Persona {
+    /// Name used to refer to the persona in configuration
+    pub name: String,
+    /// The system instruction sent with every request
+    pub system_instruction: String,
+    /// Default temperature, if the persona needs one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Safety preset, if the persona needs one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<SafetyPreset>,
+}
+
+impl Persona {
+    /// Create a persona with only a system instruction
+    pub fn new(name: impl Into<String>, system_instruction: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            system_instruction: system_instruction.into(),
+            temperature: None,
+            safety: None,
+        }
+    }
+
+    /// Set the default temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the safety preset
+    pub fn with_safety(mut self, safety: SafetyPreset) -> Self {
+        self.safety = Some(safety);
+        self
+    }
+
+    /// A precise programming assistant
+    pub fn coder() -> Self {
+        Self::new(
+            "coder",
+            "You are an expert software engineer. Answer with correct, idiomatic code and \
+             brief explanations. Put code in fenced code blocks with a language tag, and say \
+             so when you are unsure instead of guessing.",
+        )
+        .with_temperature(0.2)
+        .with_safety(SafetyPreset::Balanced)
+    }
+
+    /// A concise, faithful summarizer
+    pub fn summarizer() -> Self {
+        Self::new(
+            "summarizer",
+            "You summarize the provided text. Keep only the key points, do not add \
+             information that is not in the text, and prefer short bullet points.",
+        )
+        .with_temperature(0.3)
+        .with_safety(SafetyPreset::Balanced)
+    }
+
+    /// A deterministic information extractor
+    pub fn extractor() -> Self {
+        Self::new(
+            "extractor",
+            "You extract structured information from the provided input. Return only the \
+             requested fields, use null for anything that is not present, and never invent \
+             values.",
+        )
+        .with_temperature(0.0)
+        .with_safety(SafetyPreset::Balanced)
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    auth::{ApiKeyProvider, StaticKey},
+    client::{model_path, GeminiClient},
+    models::UsageMetadata,
+    queue::RequestQueue,
+    Gemini,
+};
+use secrecy::SecretString;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Clients for many tenants, each with its own API key, limits and usage
+///
+/// All tenants share the HTTP connection pool and options of one template
+/// client, so adding a tenant is cheap. Clients handed out by the pool
+/// are ordinary [`Gemini`] clients that can be cloned and kept; usage is
+/// counted for them until the tenant is replaced or removed.
+pub struct GeminiPool {
+    template: Gemini,
+    tenants: RwLock<HashMap<String, Tenant>>,
+}
+
+struct Tenant {
+    client: Gemini,
+    usage: Arc<UsageCounter>,
+}
+
+impl Default for GeminiPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiPool {
+    /// Create an empty pool with default client options
+    pub fn new() -> Self {
+        Self::from_template(Gemini::new(String::new()))
+    }
+
+    /// Create an empty pool whose tenants share the HTTP client, model and
+    /// options of `template`
+    ///
+    /// The template's API key is not used. Its concurrency limit, if it has
+    /// one, applies to all tenants together unless a tenant sets its own.
+    pub fn from_template(template: Gemini) -> Self {
+        Self {
+            template,
+            tenants: RwLock::default(),
+        }
+    }
+
+    /// Add a tenant, replacing any tenant with the same id, and return its
+    /// client
+    pub fn insert(&self, tenant: impl Into<String>, config: TenantConfig) -> Gemini {
+        let usage = Arc::new(UsageCounter::default());
+        let mut client = GeminiClient::clone(&self.template.client);
+        client.api_key = config.api_key;
+        if let Some(model) = config.model {
+            client.model = model;
+        }
+        if let Some(max) = config.max_in_flight {
+            client.queue = Some(Arc::new(RequestQueue::new(max)));
+        }
+        client.rate_limit = config
+            .requests_per_minute
+            .map(|rpm| Arc::new(RateLimiter::new(rpm)));
+        client.usage = Some(usage.clone());
+
+        let mut gemini = self.template.clone();
+        gemini.client = Arc::new(client);
+        self.tenants_mut().insert(
+            tenant.into(),
+            Tenant {
+                client: gemini.clone(),
+                usage,
+            },
+        );
+        gemini
+    }
+
+    /// The client of a tenant
+    pub fn get(&self, tenant: &str) -> Option<Gemini> {
+        Some(self.tenants().get(tenant)?.client.clone())
+    }
+
+    /// Remove a tenant, returning its usage
+    ///
+    /// Clones of the tenant's client keep working, without being counted.
+    pub fn remove(&self, tenant: &str) -> Option<TenantUsage> {
+        Some(self.tenants_mut().remove(tenant)?.usage.snapshot())
+    }
+
+    /// The ids of all tenants, sorted
+    pub fn tenant_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.tenants().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// The usage of a tenant since it was added
+    pub fn usage(&self, tenant: &str) -> Option<TenantUsage> {
+        Some(self.tenants().get(tenant)?.usage.snapshot())
+    }
+
+    /// The usage of every tenant, by id
+    pub fn usage_report(&self) -> BTreeMap<String, TenantUsage> {
+        self.tenants()
+            .iter()
+            .map(|(id, tenant)| (id.clone(), tenant.usage.snapshot()))
+            .collect()
+    }
+
+    /// Reset the usage counters of a tenant, returning the usage so far
+    pub fn reset_usage(&self, tenant: &str) -> Option<TenantUsage> {
+        Some(self.tenants().get(tenant)?.usage.take())
+    }
+
+    fn tenants(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Tenant>> {
+        self.tenants.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn tenants_mut(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Tenant>> {
+        self.tenants.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The API key and limits of a tenant in a [`GeminiPool`]
+#[derive(Debug, Clone)]
+pub struct TenantConfig {
+    api_key: Arc<dyn ApiKeyProvider>,
+    model: Option<String>,
+    max_in_flight: Option<usize>,
+    requests_per_minute: Option<u32>,
+}
+
+impl TenantConfig {
+    /// Use the given API key for the tenant's requests
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self::with_key_provider(StaticKey::new(api_key))
+    }
+
+    /// Use a key that is already kept secret
+    pub fn from_secret(api_key: SecretString) -> Self {
+        Self::with_key_provider(StaticKey::from(api_key))
+    }
+
+    /// Look up the tenant's key for every request
+    pub fn with_key_provider(provider: impl ApiKeyProvider + 'static) -> Self {
+        Self {
+            api_key: Arc::new(provider),
+            model: None,
+            max_in_flight: None,
+            requests_per_minute: None,
+        }
+    }
+
+    /// Use another model than the pool's template
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model_path(model.into()));
+        self
+    }
+
+    /// Limit the number of the tenant's API calls in flight at once
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Limit the tenant to a number of API calls per minute
+    ///
+    /// Calls over the limit wait until the tenant has quota again. Bursts
+    /// of up to a minute's quota are allowed; every attempt, including
+    /// retries, counts as a call.
+    pub fn with_requests_per_minute(mut self, requests_per_minute: u32) -> Self {
+        self.requests_per_minute = Some(requests_per_minute);
+        self
+    }
+}
+
+/// API usage of a tenant of a [`GeminiPool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TenantUsage {
+    /// API calls made, including retries
+    pub requests: u64,
+    /// Calls that failed
+    pub failed_requests: u64,
+    /// Prompt tokens of generation calls
+    pub prompt_tokens: u64,
+    /// Response tokens of generation calls
+    pub candidates_tokens: u64,
+    /// Total tokens of generation calls
+    pub total_tokens: u64,
+}
+
+/// Usage counters shared by the copies of a tenant's client
+#[derive(Debug, Default)]
+pub(crate) struct UsageCounter {
+    requests: AtomicU64,
+    failed_requests: AtomicU64,
+    prompt_tokens: AtomicU64,
+    candidates_tokens: AtomicU64,
+    total_tokens: AtomicU64,
+}
+
+impl UsageCounter {
+    /// Count an API call and whether it succeeded
+    pub(crate) fn request(&self, succeeded: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !succeeded {
+            self.failed_requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the tokens of a completed generation
+    pub(crate) fn tokens(&self, usage: &UsageMetadata) {
+        let count = |tokens: i32| u64::try_from(tokens).unwrap_or(0);
+        self.prompt_tokens
+            .fetch_add(count(usage.prompt_token_count), Ordering::Relaxed);
+        self.candidates_tokens
+            .fetch_add(count(usage.candidates_token_count), Ordering::Relaxed);
+        self.total_tokens
+            .fetch_add(count(usage.total_token_count), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> TenantUsage {
+        TenantUsage {
+            requests: self.requests.load(Ordering::Relaxed),
+            failed_requests: self.failed_requests.load(Ordering::Relaxed),
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            candidates_tokens: self.candidates_tokens.load(Ordering::Relaxed),
+            total_tokens: self.total_tokens.load(Ordering::Relaxed),
+        }
+    }
+
+    fn take(&self) -> TenantUsage {
+        TenantUsage {
+            requests: self.requests.swap(0, Ordering::Relaxed),
+            failed_requests: self.failed_requests.swap(0, Ordering::Relaxed),
+            prompt_tokens: self.prompt_tokens.swap(0, Ordering::Relaxed),
+            candidates_tokens: self.candidates_tokens.swap(0, Ordering::Relaxed),
+            total_tokens: self.total_tokens.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Token bucket holding up to a minute's worth of calls
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_second: f64,
+    capacity: f64,
+    /// Available calls and when they were last topped up
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_minute: u32) -> Self {
+        let capacity = f64::from(requests_per_minute.max(1));
+        Self {
+            per_second: capacity / 60.0,
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Wait until a call may be made
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                let (available, updated) = &mut *state;
+                let now = Instant::now();
+                *available = (*available
+                    + now.duration_since(*updated).as_secs_f64() * self.per_second)
+                    .min(self.capacity);
+                *updated = now;
+                if *available >= 1.0 {
+                    *available -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *available) / self.per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
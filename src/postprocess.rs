@@ -0,0 +1,258 @@
+use crate::{
+    models::{GenerationResponse, Part},
+    Error, Result,
+};
+use std::sync::Arc;
+
+/// A cleanup step applied to the text of each candidate
+///
+/// Processors attached with `ContentBuilder::with_post_processor` run in
+/// the order they were added, each receiving the previous one's output.
+/// Returning an error fails the request with that error.
+///
+/// Any `Fn(String) -> Result<String>` closure is a processor.
+pub trait PostProcessor: Send + Sync {
+    /// Transform the candidate text
+    fn process(&self, text: String) -> Result<String>;
+}
+
+impl<F> PostProcessor for F
+where
+    F: Fn(String) -> Result<String> + Send + Sync,
+{
+    fn process(&self, text: String) -> Result<String> {
+        (self)(text)
+    }
+}
+
+/// What a [`ResponseFilter`] decides about a candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterVerdict {
+    /// Keep the candidate as it is
+    Allow,
+    /// Keep the candidate with its text replaced
+    Replace(String),
+    /// Remove the candidate, for the given reason
+    Reject(String),
+}
+
+/// A check that can veto or rewrite each candidate before it is returned
+///
+/// Filters attached with `ContentBuilder::with_response_filter` see the
+/// candidate text after post-processing and run in the order they were
+/// added; a rejected candidate is not shown to later filters. If every
+/// candidate is rejected the request fails with
+/// [`Error::AllCandidatesRejected`].
+///
+/// Any `Fn(&str) -> FilterVerdict` closure is a filter.
+pub trait ResponseFilter: Send + Sync {
+    /// Decide about the candidate text
+    fn check(&self, text: &str) -> FilterVerdict;
+}
+
+impl<F> ResponseFilter for F
+where
+    F: Fn(&str) -> FilterVerdict + Send + Sync,
+{
+    fn check(&self, text: &str) -> FilterVerdict {
+        (self)(text)
+    }
+}
+
+/// Remove leading and trailing whitespace
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrimWhitespace;
+
+impl PostProcessor for TrimWhitespace {
+    fn process(&self, text: String) -> Result<String> {
+        Ok(text.trim().to_string())
+    }
+}
+
+/// Unwrap the first Markdown code fence, e.g. around JSON output
+///
+/// Text without a complete fenced block is returned unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripCodeFences;
+
+impl PostProcessor for StripCodeFences {
+    fn process(&self, text: String) -> Result<String> {
+        let Some(start) = text.find("```") else {
+            return Ok(text);
+        };
+        // The opening fence runs to the end of its line, including any language tag
+        let Some(body_start) = text[start..].find('\n').map(|i| start + i + 1) else {
+            return Ok(text);
+        };
+        match text[body_start..].find("```") {
+            Some(end) => Ok(text[body_start..body_start + end]
+                .trim_end_matches(['\n', '\r'])
+                .to_string()),
+            None => Ok(text),
+        }
+    }
+}
+
+/// Limit the text to a number of characters
+#[derive(Debug, Clone, Copy)]
+pub struct MaxLength {
+    max_chars: usize,
+    truncate: bool,
+}
+
+impl MaxLength {
+    /// Cut longer text down to `max_chars` characters
+    pub fn truncate(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            truncate: true,
+        }
+    }
+
+    /// Fail the request if the text is longer than `max_chars` characters
+    pub fn reject(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            truncate: false,
+        }
+    }
+}
+
+impl PostProcessor for MaxLength {
+    fn process(&self, text: String) -> Result<String> {
+        match text.char_indices().nth(self.max_chars) {
+            None => Ok(text),
+            Some((end, _)) if self.truncate => Ok(text[..end].to_string()),
+            Some(_) => Err(Error::PostProcessError(format!(
+                "Response is longer than {} characters",
+                self.max_chars
+            ))),
+        }
+    }
+}
+
+/// Keep only the part of the text matching a regular expression
+///
+/// The first capture group is kept if the pattern has one, otherwise the
+/// whole match. Text without a match fails the request.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct RegexExtract {
+    regex: regex::Regex,
+}
+
+#[cfg(feature = "regex")]
+impl RegexExtract {
+    /// Create an extractor from a pattern
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| Error::RequestError(format!("Invalid extraction pattern: {}", e)))?;
+        Ok(Self { regex })
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PostProcessor for RegexExtract {
+    fn process(&self, text: String) -> Result<String> {
+        let captures = self.regex.captures(&text).ok_or_else(|| {
+            Error::PostProcessError(format!("Response does not match `{}`", self.regex))
+        })?;
+        let found = captures.get(1).or_else(|| captures.get(0));
+        Ok(found.map(|m| m.as_str().to_string()).unwrap_or_default())
+    }
+}
+
+/// Run processors over the text of every candidate
+///
+/// The text parts of a candidate are joined and processed as one text,
+/// which replaces the first text part; thoughts and other parts are kept.
+pub(crate) fn apply(
+    processors: &[Arc<dyn PostProcessor>],
+    response: &mut GenerationResponse,
+) -> Result<()> {
+    if processors.is_empty() {
+        return Ok(());
+    }
+    for candidate in &mut response.candidates {
+        let Some((first, mut text, thought_signature)) = take_text(&mut candidate.content.parts)
+        else {
+            continue;
+        };
+        for processor in processors {
+            text = processor.process(text)?;
+        }
+        candidate.content.parts.insert(
+            first,
+            Part::Text {
+                text,
+                thought_signature,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Run filters over every candidate, removing the rejected ones
+///
+/// Candidates without text are checked with an empty text. A response
+/// without candidates, e.g. for a blocked prompt, is left alone.
+pub(crate) fn filter(
+    filters: &[Arc<dyn ResponseFilter>],
+    response: &mut GenerationResponse,
+) -> Result<()> {
+    if filters.is_empty() || response.candidates.is_empty() {
+        return Ok(());
+    }
+    let mut reasons = Vec::new();
+    response.candidates.retain_mut(|candidate| {
+        let parts = &mut candidate.content.parts;
+        let (first, mut text, thought_signature) =
+            take_text(parts).unwrap_or((parts.len(), String::new(), None));
+        for filter in filters {
+            match filter.check(&text) {
+                FilterVerdict::Allow => {}
+                FilterVerdict::Replace(replacement) => text = replacement,
+                FilterVerdict::Reject(reason) => {
+                    reasons.push(reason);
+                    return false;
+                }
+            }
+        }
+        if !text.is_empty() || thought_signature.is_some() {
+            parts.insert(
+                first,
+                Part::Text {
+                    text,
+                    thought_signature,
+                },
+            );
+        }
+        true
+    });
+    if response.candidates.is_empty() {
+        return Err(Error::AllCandidatesRejected(reasons));
+    }
+    Ok(())
+}
+
+/// Remove the text parts, returning where the first was, the joined text
+/// and the thought signature one of them carried
+fn take_text(parts: &mut Vec<Part>) -> Option<(usize, String, Option<String>)> {
+    let first = parts.iter().position(|p| matches!(p, Part::Text { .. }))?;
+    let mut text = String::new();
+    let mut signature = None;
+    parts.retain_mut(|p| match p {
+        Part::Text {
+            text: t,
+            thought_signature,
+        } => {
+            text.push_str(t);
+            if thought_signature.is_some() {
+                signature = thought_signature.take();
+            }
+            false
+        }
+        _ => true,
+    });
+    Some((first, text, signature))
+}
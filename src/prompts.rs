@@ -0,0 +1,223 @@
+use crate::{
+    client::{ContentBuilder, Gemini},
+    models::{GenerationConfig, GenerationResponse},
+    Error, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A named prompt template with the settings it should run with
+///
+/// Prompts are usually loaded from files by a [`PromptStore`]; the file stem
+/// becomes the prompt's name:
+///
+/// ```toml
+/// # prompts/summarize_ticket.toml
+/// description = "One-paragraph summary of a support ticket"
+/// model = "gemini-2.0-flash"
+/// system = "You are a support lead. Be concise."
+/// template = "Summarize this ticket for {{audience}}:\n\n{{ticket}}"
+///
+/// [generation_config]
+/// temperature = 0.2
+/// maxOutputTokens = 256
+/// ```
+///
+/// `generation_config` uses the same keys as the REST API.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Prompt {
+    /// What the prompt is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The model to run the prompt on instead of the client's model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// System instruction, which may also contain `{{variables}}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    /// The user message, with `{{variables}}` to substitute
+    pub template: String,
+    /// Generation settings for the prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+}
+
+impl Prompt {
+    /// Create a prompt from a template
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Substitute variables into the template
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String> {
+        render(&self.template, vars)
+    }
+
+    /// Start a request for the prompt with the given variables
+    pub fn to_builder(
+        &self,
+        client: &Gemini,
+        vars: &HashMap<String, String>,
+    ) -> Result<ContentBuilder> {
+        let mut builder = client
+            .generate_content()
+            .with_user_message(self.render(vars)?);
+        if let Some(system) = &self.system {
+            builder = builder.with_system_instruction(render(system, vars)?);
+        }
+        if let Some(model) = &self.model {
+            builder = builder.with_model(model.clone());
+        }
+        if let Some(config) = &self.generation_config {
+            builder = builder.with_generation_config(config.clone());
+        }
+        Ok(builder)
+    }
+}
+
+/// A registry of named prompt templates
+///
+/// Keeping prompts in files lets them be iterated on without recompiling.
+/// Attach a store with [`Gemini::with_prompt_store`] and run prompts by name
+/// with [`Gemini::run_prompt`].
+#[derive(Debug, Clone, Default)]
+pub struct PromptStore {
+    prompts: BTreeMap<String, Prompt>,
+}
+
+impl PromptStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every `.toml`, `.yaml` and `.yml` file in a directory
+    ///
+    /// Each file holds one [`Prompt`], named after the file stem. Other files
+    /// and subdirectories are ignored.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| Error::PromptError(format!("Cannot read {}: {}", dir.display(), e)))?;
+
+        let mut store = Self::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| Error::PromptError(format!("Cannot read {}: {}", dir.display(), e)))?
+                .path();
+            let is_prompt = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("toml" | "yaml" | "yml")
+            );
+            if path.is_file() && is_prompt {
+                store.load_file(&path)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Load a single TOML or YAML prompt file, named after its file stem
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| Error::PromptError(format!("Invalid file name: {}", path.display())))?;
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| Error::PromptError(format!("Cannot read {}: {}", path.display(), e)))?;
+
+        let prompt = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&source).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&source).map_err(|e| e.to_string()),
+            _ => Err("expected a .toml, .yaml or .yml file".to_string()),
+        }
+        .map_err(|e| Error::PromptError(format!("{}: {}", path.display(), e)))?;
+
+        self.prompts.insert(name.to_string(), prompt);
+        Ok(())
+    }
+
+    /// Add or replace a prompt
+    pub fn insert(&mut self, name: impl Into<String>, prompt: Prompt) {
+        self.prompts.insert(name.into(), prompt);
+    }
+
+    /// Look up a prompt by name
+    pub fn get(&self, name: &str) -> Option<&Prompt> {
+        self.prompts.get(name)
+    }
+
+    /// The names of all prompts, in sorted order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prompts.keys().map(String::as_str)
+    }
+}
+
+impl Gemini {
+    /// Attach a prompt store for [`Gemini::run_prompt`]
+    pub fn with_prompt_store(mut self, store: PromptStore) -> Self {
+        self.prompts = Some(Arc::new(store));
+        self
+    }
+
+    /// Start a request for a named prompt, for adding messages or options
+    pub fn prompt<K, V>(
+        &self,
+        name: &str,
+        vars: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<ContentBuilder>
+    where
+        K: Into<String>,
+        V: ToString,
+    {
+        let prompt = self
+            .prompts
+            .as_ref()
+            .and_then(|store| store.get(name))
+            .ok_or_else(|| Error::PromptError(format!("Unknown prompt: {}", name)))?;
+        let vars = vars
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.to_string()))
+            .collect();
+        prompt.to_builder(self, &vars)
+    }
+
+    /// Render and execute a named prompt from the attached store
+    pub async fn run_prompt<K, V>(
+        &self,
+        name: &str,
+        vars: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<GenerationResponse>
+    where
+        K: Into<String>,
+        V: ToString,
+    {
+        self.prompt(name, vars)?.execute().await
+    }
+}
+
+/// Replace `{{name}}` placeholders, failing on variables that were not given
+fn render(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| Error::PromptError("Unclosed {{ in template".to_string()))?;
+        let name = after[..end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| Error::PromptError(format!("Missing prompt variable: {}", name)))?;
+        output.push_str(value);
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// How urgently a request should be sent when the client is busy
+///
+/// Only matters for clients built with `GeminiBuilder::with_max_in_flight`:
+/// when all slots are taken, waiting interactive requests get the next free
+/// slot before any background request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// A user is waiting for the answer, e.g. a chat UI (the default)
+    #[default]
+    Interactive,
+    /// Batch work that can wait, e.g. indexing or evaluations
+    Background,
+}
+
+/// Limits concurrent API calls, granting free slots by priority
+#[derive(Debug)]
+pub(crate) struct RequestQueue {
+    max_in_flight: usize,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    in_flight: usize,
+    /// Waiting requests, interactive first, each in arrival order
+    waiting: [VecDeque<oneshot::Sender<()>>; 2],
+}
+
+impl RequestQueue {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Wait for a free slot; the slot is released when the permit is dropped
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let receiver = {
+            let mut state = self.state();
+            if state.in_flight < self.max_in_flight {
+                state.in_flight += 1;
+                return Permit {
+                    queue: self.clone(),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiting[priority as usize].push_back(sender);
+            receiver
+        };
+
+        let mut waiting = Waiting {
+            queue: self.clone(),
+            receiver: Some(receiver),
+        };
+        if let Some(receiver) = waiting.receiver.as_mut() {
+            // The sender is only dropped after handing over a slot
+            let _ = receiver.await;
+        }
+        waiting.receiver = None;
+        Permit {
+            queue: self.clone(),
+        }
+    }
+
+    /// Hand a slot to the most urgent waiting request, or free it
+    fn release(&self) {
+        let mut state = self.state();
+        for queue in &mut state.waiting {
+            while let Some(sender) = queue.pop_front() {
+                // Fails if the waiting request was cancelled
+                if sender.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.in_flight -= 1;
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A slot in the request queue, released on drop
+#[derive(Debug)]
+pub(crate) struct Permit {
+    queue: Arc<RequestQueue>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+/// A request waiting for a slot
+///
+/// If the request is cancelled after a slot was handed to it but before it
+/// noticed, the slot is passed on instead of being lost.
+struct Waiting {
+    queue: Arc<RequestQueue>,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.queue.release();
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{
+    client::Gemini,
+    embeddings::TaskType,
+    models::{Candidate, GenerationResponse, Part},
+    Result,
+};
+
+/// A candidate with its rank information
+#[derive(Debug, Clone)]
+pub struct RankedCandidate {
+    /// The candidate itself
+    pub candidate: Candidate,
+    /// Position of the candidate in the response
+    pub index: usize,
+    /// Mean similarity to all other candidates, from 0 to 1
+    ///
+    /// Higher means more candidates agree with this one.
+    pub score: f64,
+    /// Positions of the candidates merged into this one as near-duplicates
+    pub duplicates: Vec<usize>,
+}
+
+impl RankedCandidate {
+    /// The text of the candidate, without thoughts
+    pub fn text(&self) -> String {
+        candidate_text(&self.candidate)
+    }
+}
+
+/// Distinct candidates of a response, best first
+///
+/// Candidates are ranked by agreement: the one most similar to all the
+/// others comes first, a simple form of self-consistency voting for
+/// n-best selection. Candidates at least as similar as the threshold to a
+/// better-ranked candidate are folded into it as duplicates.
+///
+/// Build one with [`GenerationResponse::ranked_candidates`] (text edit
+/// distance) or [`Gemini::rank_candidates_by_embedding`] (meaning).
+#[derive(Debug, Clone, Default)]
+pub struct RankedCandidates {
+    candidates: Vec<RankedCandidate>,
+}
+
+impl RankedCandidates {
+    /// Rank candidates given a symmetric similarity matrix
+    fn new(candidates: &[Candidate], similarity: &[Vec<f64>], threshold: f64) -> Self {
+        let n = candidates.len();
+        let scores: Vec<f64> = (0..n)
+            .map(|i| {
+                if n < 2 {
+                    return 1.0;
+                }
+                let total: f64 = (0..n).filter(|&j| j != i).map(|j| similarity[i][j]).sum();
+                total / (n - 1) as f64
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+
+        let mut kept: Vec<(usize, Vec<usize>)> = Vec::new();
+        for i in order {
+            match kept
+                .iter_mut()
+                .find(|(k, _)| similarity[*k][i] >= threshold)
+            {
+                Some((_, duplicates)) => duplicates.push(i),
+                None => kept.push((i, Vec::new())),
+            }
+        }
+
+        let candidates = kept
+            .into_iter()
+            .map(|(index, duplicates)| RankedCandidate {
+                candidate: candidates[index].clone(),
+                index,
+                score: scores[index],
+                duplicates,
+            })
+            .collect();
+        Self { candidates }
+    }
+
+    /// The best-ranked candidate
+    pub fn best(&self) -> Option<&RankedCandidate> {
+        self.candidates.first()
+    }
+
+    /// Iterate over the distinct candidates, best first
+    pub fn iter(&self) -> std::slice::Iter<'_, RankedCandidate> {
+        self.candidates.iter()
+    }
+
+    /// Number of distinct candidates
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Whether there are no candidates
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Texts of the distinct candidates, best first
+    pub fn texts(&self) -> Vec<String> {
+        self.candidates.iter().map(RankedCandidate::text).collect()
+    }
+
+    /// Consume the ranking, returning the distinct candidates best first
+    pub fn into_vec(self) -> Vec<RankedCandidate> {
+        self.candidates
+    }
+}
+
+impl IntoIterator for RankedCandidates {
+    type Item = RankedCandidate;
+    type IntoIter = std::vec::IntoIter<RankedCandidate>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.candidates.into_iter()
+    }
+}
+
+impl GenerationResponse {
+    /// Deduplicate and rank the candidates by text similarity
+    ///
+    /// Similarity is the normalized Levenshtein distance between the texts
+    /// after lowercasing and collapsing whitespace, from 0 (nothing in
+    /// common) to 1 (identical). Candidates with a similarity of at least
+    /// `threshold` to a better one are merged; 0.9 catches rewordings of
+    /// punctuation and small edits.
+    pub fn ranked_candidates(&self, threshold: f64) -> RankedCandidates {
+        let texts: Vec<Vec<char>> = self
+            .candidates
+            .iter()
+            .map(|c| normalize(&candidate_text(c)))
+            .collect();
+        let similarity = matrix(texts.len(), |i, j| text_similarity(&texts[i], &texts[j]));
+        RankedCandidates::new(&self.candidates, &similarity, threshold)
+    }
+}
+
+impl Gemini {
+    /// Deduplicate and rank the candidates of a response by meaning
+    ///
+    /// Each candidate text is embedded with the default embedding model and
+    /// compared by cosine similarity, which also catches paraphrases that
+    /// [`GenerationResponse::ranked_candidates`] would keep apart. This
+    /// makes one `embedContent` call per candidate. Thresholds around 0.95
+    /// merge only close paraphrases.
+    pub async fn rank_candidates_by_embedding(
+        &self,
+        response: &GenerationResponse,
+        threshold: f64,
+    ) -> Result<RankedCandidates> {
+        let mut embeddings = Vec::with_capacity(response.candidates.len());
+        for candidate in &response.candidates {
+            let embedding = self
+                .embed_content(candidate_text(candidate))
+                .with_task_type(TaskType::SemanticSimilarity)
+                .execute()
+                .await?;
+            embeddings.push(embedding.values);
+        }
+        let similarity = matrix(embeddings.len(), |i, j| {
+            cosine_similarity(&embeddings[i], &embeddings[j])
+        });
+        Ok(RankedCandidates::new(
+            &response.candidates,
+            &similarity,
+            threshold,
+        ))
+    }
+}
+
+/// The answer text of a candidate, joining all text parts
+fn candidate_text(candidate: &Candidate) -> String {
+    candidate
+        .content
+        .parts
+        .iter()
+        .filter_map(Part::text)
+        .collect()
+}
+
+/// Build a symmetric matrix with ones on the diagonal
+fn matrix(n: usize, similarity: impl Fn(usize, usize) -> f64) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| match i.cmp(&j) {
+                    std::cmp::Ordering::Equal => 1.0,
+                    std::cmp::Ordering::Less => similarity(i, j),
+                    std::cmp::Ordering::Greater => similarity(j, i),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Lowercase and collapse runs of whitespace
+fn normalize(text: &str) -> Vec<char> {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .collect()
+}
+
+/// One minus the Levenshtein distance divided by the longer length
+fn text_similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
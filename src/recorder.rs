@@ -0,0 +1,196 @@
+use crate::{models::GenerationResponse, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// Appends every streamed chunk to a JSON Lines transcript file
+///
+/// Attach one with `GeminiBuilder::with_stream_recorder` or
+/// `ContentBuilder::with_stream_recorder`. Each streamed request writes a
+/// `start` event when the response headers arrive, one `chunk` event per
+/// chunk with the raw JSON and its text, `error` events, and an `end` event
+/// when the stream is dropped, all tagged with the request's idempotency
+/// key as session id. Read a transcript back with [`StreamRecorder::load`]
+/// to replay sessions or analyze chunk latency.
+///
+/// Clones share the file. Failing writes are ignored so that recording
+/// never breaks a stream.
+#[derive(Clone)]
+pub struct StreamRecorder {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+/// One line of a stream transcript
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Identifies the streamed request the event belongs to
+    pub session: String,
+    /// `start`, `chunk`, `error` or `end`
+    pub event: String,
+    /// Model the request was sent to
+    pub model: String,
+    /// Wall-clock time of the event, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Time since the request was sent, in milliseconds
+    pub elapsed_ms: u64,
+    /// The chunk as received, for `chunk` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+    /// The answer text of the chunk, for `chunk` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// What went wrong, for `error` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Metadata set with `ContentBuilder::with_metadata`, for `start` events
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl RecordedEvent {
+    /// Parse the recorded chunk, for replaying a `chunk` event
+    pub fn response(&self) -> Option<Result<GenerationResponse>> {
+        let raw = self.raw.clone()?;
+        Some(serde_json::from_value(raw).map_err(Error::JsonError))
+    }
+}
+
+impl StreamRecorder {
+    /// Open a transcript file for appending, creating it if needed
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| {
+                Error::RequestError(format!("Cannot open transcript {}: {}", path.display(), e))
+            })?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Read all events of a transcript file, in the order they were written
+    ///
+    /// Lines that are not valid events, e.g. one cut short by a crash, are
+    /// skipped.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<RecordedEvent>> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            Error::RequestError(format!("Cannot read transcript {}: {}", path.display(), e))
+        })?;
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(std::io::Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+
+    /// Start recording one streamed request
+    pub(crate) fn session(
+        &self,
+        session: &str,
+        model: &str,
+        metadata: &BTreeMap<String, String>,
+        started: Instant,
+    ) -> Recording {
+        let recording = Recording {
+            recorder: self.clone(),
+            session: session.to_string(),
+            model: model.to_string(),
+            started,
+        };
+        recording.append("start", None, None, None, metadata.clone());
+        recording
+    }
+
+    fn append(&self, event: &RecordedEvent) {
+        let Ok(mut line) = serde_json::to_vec(event) else {
+            return;
+        };
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = file.write_all(&line);
+    }
+}
+
+impl std::fmt::Debug for StreamRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamRecorder")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// Records the events of one stream; writes the `end` event when dropped
+pub(crate) struct Recording {
+    recorder: StreamRecorder,
+    session: String,
+    model: String,
+    started: Instant,
+}
+
+impl Recording {
+    /// Record a data line of the stream and what it parsed to
+    pub(crate) fn chunk(&self, raw: &str, parsed: &Result<GenerationResponse>) {
+        match parsed {
+            Ok(response) => {
+                let raw = serde_json::from_str(raw).ok();
+                self.write("chunk", raw, Some(response.text().into_owned()), None);
+            }
+            Err(e) => self.error(e),
+        }
+    }
+
+    pub(crate) fn error(&self, error: &Error) {
+        self.write("error", None, None, Some(error.to_string()));
+    }
+
+    fn write(
+        &self,
+        event: &str,
+        raw: Option<serde_json::Value>,
+        text: Option<String>,
+        error: Option<String>,
+    ) {
+        self.append(event, raw, text, error, BTreeMap::new());
+    }
+
+    fn append(
+        &self,
+        event: &str,
+        raw: Option<serde_json::Value>,
+        text: Option<String>,
+        error: Option<String>,
+        metadata: BTreeMap<String, String>,
+    ) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.recorder.append(&RecordedEvent {
+            session: self.session.clone(),
+            event: event.to_string(),
+            model: self.model.clone(),
+            timestamp_ms,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            raw,
+            text,
+            error,
+            metadata,
+        });
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.write("end", None, None, None);
+    }
+}
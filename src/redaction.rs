@@ -0,0 +1,137 @@
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Placeholder written in place of redacted values
+const REDACTED: &str = "[REDACTED]";
+
+/// A single redaction rule
+#[derive(Clone)]
+enum Rule {
+    /// Replace the value at a dotted path; `*` matches any key or index
+    FieldPath(Vec<String>),
+    /// Replace matches inside every string value
+    #[cfg(feature = "regex")]
+    Pattern(regex::Regex),
+    /// Arbitrary rewrite of the whole document
+    Hook(Arc<dyn Fn(&mut Value) + Send + Sync>),
+}
+
+/// Strips sensitive data from request bodies before they are logged
+///
+/// Redaction only affects what the crate logs (the `gemini.request` trace
+/// event with the `tracing` feature); the request sent to the API is never
+/// modified. Attach one with `GeminiBuilder::with_redactor`, or call
+/// [`Redactor::redact`] from your own logging.
+///
+/// Field paths use the JSON names of the request, separated by dots, with
+/// `*` matching any object key or array element:
+/// `contents.*.parts.*.text` hides all message text,
+/// `systemInstruction` the whole system instruction.
+#[derive(Clone, Default)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+impl Redactor {
+    /// Create a redactor without rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the value at a field path with `[REDACTED]`
+    pub fn with_field_path(mut self, path: &str) -> Self {
+        self.rules.push(Rule::FieldPath(
+            path.split('.').map(str::to_string).collect(),
+        ));
+        self
+    }
+
+    /// Replace every match of a regular expression in string values
+    ///
+    /// Useful for PII with a recognizable shape, e.g. e-mail addresses or
+    /// card numbers, wherever it appears in the request.
+    #[cfg(feature = "regex")]
+    pub fn with_pattern(mut self, pattern: &str) -> crate::Result<Self> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| crate::Error::RequestError(format!("Invalid redaction pattern: {}", e)))?;
+        self.rules.push(Rule::Pattern(regex));
+        Ok(self)
+    }
+
+    /// Run a custom function over the serialized request
+    pub fn with_hook(mut self, hook: impl Fn(&mut Value) + Send + Sync + 'static) -> Self {
+        self.rules.push(Rule::Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Apply all rules, in the order they were added, to a JSON document
+    pub fn redact(&self, value: &mut Value) {
+        for rule in &self.rules {
+            match rule {
+                Rule::FieldPath(path) => redact_path(value, path),
+                #[cfg(feature = "regex")]
+                Rule::Pattern(regex) => redact_pattern(value, regex),
+                Rule::Hook(hook) => hook(value),
+            }
+        }
+    }
+
+    /// Serialize a value and return the redacted JSON
+    pub fn redact_json(&self, body: &impl serde::Serialize) -> String {
+        match serde_json::to_value(body) {
+            Ok(mut value) => {
+                self.redact(&mut value);
+                value.to_string()
+            }
+            Err(e) => format!("<unserializable: {}>", e),
+        }
+    }
+}
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Redactor")
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String]) {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match value {
+        Value::Object(map) if segment == "*" => {
+            map.values_mut().for_each(|v| redact_path(v, rest));
+        }
+        Value::Object(map) => {
+            if let Some(v) = map.get_mut(segment) {
+                redact_path(v, rest);
+            }
+        }
+        Value::Array(items) if segment == "*" => {
+            items.iter_mut().for_each(|v| redact_path(v, rest));
+        }
+        Value::Array(items) => {
+            if let Some(v) = segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                redact_path(v, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(feature = "regex")]
+fn redact_pattern(value: &mut Value, regex: &regex::Regex) {
+    match value {
+        Value::String(s) => {
+            if let std::borrow::Cow::Owned(replaced) = regex.replace_all(s, REDACTED) {
+                *s = replaced;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact_pattern(v, regex)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact_pattern(v, regex)),
+        _ => {}
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{Error, Result};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Header carrying the idempotency key of a `generateContent` call
+///
+/// Each logical request gets a fresh key that is sent again unchanged with
+/// every retry of it, so a gateway in front of the API can recognize a retry
+/// of a request whose response was delivered but lost with the connection,
+/// and avoid running (and billing) it twice. Follow-up turns of function
+/// calling and `with_auto_continue` are new requests with new keys.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Delay before the first retry, doubled for each further retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A limit on API calls and retry delays shared by one logical operation
+///
+/// Function calling loops, fallbacks and tool handlers that make requests
+/// of their own can multiply retries. Cloning a budget shares it, so
+/// attaching clones to every request made for a single user action caps
+/// the total number of API calls and the total time spent backing off.
+///
+/// Each API call takes one attempt from the budget. Transient failures
+/// (rate limits, server errors, timeouts) are retried with exponential
+/// backoff while attempts and delay remain; once the budget is spent the
+/// last error is returned, and requests that have not started fail with
+/// [`Error::RetryBudgetExhausted`].
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    state: Arc<Mutex<BudgetState>>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    max_attempts: u32,
+    max_delay: Duration,
+    attempts: u32,
+    delay: Duration,
+}
+
+impl RetryBudget {
+    /// Allow up to `max_attempts` API calls in total, with unlimited delay
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BudgetState {
+                max_attempts,
+                max_delay: Duration::MAX,
+                attempts: 0,
+                delay: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// Limit the total time spent waiting between retries
+    pub fn with_max_delay(self, max_delay: Duration) -> Self {
+        self.state().max_delay = max_delay;
+        self
+    }
+
+    /// Number of API calls made so far
+    pub fn attempts(&self) -> u32 {
+        self.state().attempts
+    }
+
+    /// Number of API calls still allowed
+    pub fn remaining_attempts(&self) -> u32 {
+        let state = self.state();
+        state.max_attempts.saturating_sub(state.attempts)
+    }
+
+    /// Total time spent waiting between retries so far
+    pub fn total_delay(&self) -> Duration {
+        self.state().delay
+    }
+
+    /// Take an attempt for a new API call
+    pub(crate) fn acquire(&self) -> Result<()> {
+        let mut state = self.state();
+        if state.attempts >= state.max_attempts {
+            return Err(Error::RetryBudgetExhausted {
+                attempts: state.attempts,
+                delay: state.delay,
+            });
+        }
+        state.attempts += 1;
+        Ok(())
+    }
+
+    /// Take an attempt and the backoff delay for retry number `retry`
+    ///
+    /// Returns the delay to wait, or `None` if the budget cannot cover it.
+    pub(crate) fn acquire_retry(&self, retry: u32) -> Option<Duration> {
+        let delay = backoff(retry);
+        let mut state = self.state();
+        let total = state.delay.saturating_add(delay);
+        if state.attempts >= state.max_attempts || total > state.max_delay {
+            return None;
+        }
+        state.attempts += 1;
+        state.delay = total;
+        Some(delay)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The delay before retry number `retry`, starting at 1
+pub(crate) fn backoff(retry: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// A random key for one logical request, formatted like a version 4 UUID
+pub(crate) fn idempotency_key() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    let bits = (u128::from(random(0)) << 64 | u128::from(random(1))) & !(0xf000 << 64 | 0xc << 60)
+        | 0x4000 << 64
+        | 0x8 << 60;
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
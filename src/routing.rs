@@ -0,0 +1,105 @@
+use crate::client::{model_path, Gemini};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Model used for calls with a tight latency budget
+pub(crate) const DEFAULT_FAST_MODEL: &str = "models/gemini-2.0-flash-lite";
+
+/// Latencies kept per model
+const WINDOW: usize = 50;
+
+/// Samples needed before a model's latency is trusted
+const MIN_SAMPLES: usize = 3;
+
+/// Age after which a sample no longer counts, so a model that was slow
+/// gets tried again
+const MAX_SAMPLE_AGE: Duration = Duration::from_secs(300);
+
+/// Recent latencies of a model's `generateContent` calls
+///
+/// Returned by [`Gemini::latency_stats`]. Covers the last 50 successful
+/// calls of the past five minutes, from sending the request to reading
+/// the response; time waiting for a queue slot is not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LatencyStats {
+    /// Number of calls the statistics are based on
+    pub samples: usize,
+    /// Average latency
+    pub mean: Duration,
+    /// Median latency
+    pub p50: Duration,
+    /// Latency 90% of the calls stayed below
+    pub p90: Duration,
+}
+
+/// Rolling latencies per model; shared by all clones of a client
+#[derive(Debug, Default)]
+pub(crate) struct LatencyTracker {
+    models: Mutex<HashMap<String, VecDeque<(Instant, Duration)>>>,
+}
+
+impl LatencyTracker {
+    /// Record the latency of a successful call
+    pub(crate) fn record(&self, model: &str, latency: Duration) {
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = models.entry(model.to_string()).or_default();
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), latency));
+    }
+
+    /// Statistics of the recent calls of a model, `None` without any
+    pub(crate) fn stats(&self, model: &str) -> Option<LatencyStats> {
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = models.get_mut(model)?;
+        samples.retain(|(at, _)| at.elapsed() < MAX_SAMPLE_AGE);
+        if samples.is_empty() {
+            return None;
+        }
+        let mut latencies: Vec<Duration> = samples.iter().map(|(_, latency)| *latency).collect();
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() * p / 100).min(latencies.len() - 1)];
+        Some(LatencyStats {
+            samples: latencies.len(),
+            mean: latencies.iter().sum::<Duration>() / latencies.len() as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+        })
+    }
+
+    /// The model to call within `budget`: `preferred` unless its recent p90
+    /// latency exceeds the budget and `fast` is not known to be slower
+    pub(crate) fn route<'a>(&self, preferred: &'a str, fast: &'a str, budget: Duration) -> &'a str {
+        let Some(expected) = self.expected(preferred) else {
+            return preferred;
+        };
+        if expected <= budget {
+            return preferred;
+        }
+        match self.expected(fast) {
+            Some(fast_expected) if fast_expected >= expected => preferred,
+            _ => fast,
+        }
+    }
+
+    /// The p90 latency of a model, once there are enough samples
+    fn expected(&self, model: &str) -> Option<Duration> {
+        self.stats(model)
+            .filter(|stats| stats.samples >= MIN_SAMPLES)
+            .map(|stats| stats.p90)
+    }
+}
+
+impl Gemini {
+    /// Recent latencies of a model's calls made through this client, e.g.
+    /// `gemini-2.0-flash`
+    ///
+    /// These decide where requests with `ContentBuilder::with_latency_budget`
+    /// are sent.
+    pub fn latency_stats(&self, model: &str) -> Option<LatencyStats> {
+        self.client.latency.stats(&model_path(model.to_string()))
+    }
+}
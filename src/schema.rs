@@ -0,0 +1,118 @@
+use serde_json::{json, Map, Value};
+
+/// Infer a structured output schema from an example response value
+///
+/// Objects become `OBJECT` schemas whose properties are all required,
+/// except those that are `null` in the example, which are `nullable`
+/// instead. The items of an array are merged into one schema: properties
+/// missing from some elements are optional, and integers mixed with
+/// fractions become `NUMBER`. Elements of conflicting types keep the type
+/// of the first one. An empty array gets `STRING` items.
+///
+/// Pass the result to `ContentBuilder::with_response_schema`, and review it
+/// before relying on it: descriptions, enums and formats can't be inferred.
+pub fn schema_from_example(example: &Value) -> Value {
+    let mut schema = infer(example);
+    fill_untyped(&mut schema);
+    schema
+}
+
+/// Infer a schema, leaving the type of `null` examples out
+fn infer(example: &Value) -> Value {
+    match example {
+        Value::Null => json!({ "nullable": true }),
+        Value::Bool(_) => json!({ "type": "BOOLEAN" }),
+        Value::Number(n) if n.is_f64() => json!({ "type": "NUMBER" }),
+        Value::Number(_) => json!({ "type": "INTEGER" }),
+        Value::String(_) => json!({ "type": "STRING" }),
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(infer)
+                .reduce(merge)
+                .unwrap_or_else(|| json!({ "type": "STRING" }));
+            json!({ "type": "ARRAY", "items": items })
+        }
+        Value::Object(fields) => {
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            for (name, value) in fields {
+                if !value.is_null() {
+                    required.push(Value::String(name.clone()));
+                }
+                properties.insert(name.clone(), infer(value));
+            }
+            json!({ "type": "OBJECT", "properties": properties, "required": required })
+        }
+    }
+}
+
+/// Merge the schemas of two values found in the same place
+fn merge(mut a: Value, b: Value) -> Value {
+    let nullable = is_nullable(&a) || is_nullable(&b);
+    // A null example says nothing about the type
+    if a.get("type").is_none() {
+        a = b;
+    } else {
+        match (a["type"].as_str(), b.get("type").and_then(Value::as_str)) {
+            (Some("INTEGER"), Some("NUMBER")) => a["type"] = b["type"].clone(),
+            (Some("ARRAY"), Some("ARRAY")) => {
+                a["items"] = merge(a["items"].take(), b["items"].clone());
+            }
+            (Some("OBJECT"), Some("OBJECT")) => merge_objects(&mut a, b),
+            _ => {}
+        }
+    }
+
+    if nullable {
+        a["nullable"] = Value::Bool(true);
+    }
+    a
+}
+
+/// Merge the properties of two object schemas; only properties required
+/// by both stay required
+fn merge_objects(a: &mut Value, mut b: Value) {
+    let required = |schema: &Value| -> Vec<Value> {
+        schema["required"].as_array().cloned().unwrap_or_default()
+    };
+    let b_required = required(&b);
+    let both: Vec<Value> = required(a)
+        .into_iter()
+        .filter(|name| b_required.contains(name))
+        .collect();
+
+    if let (Some(a_properties), Some(b_properties)) = (
+        a["properties"].as_object_mut(),
+        b["properties"].as_object_mut(),
+    ) {
+        for (name, schema) in std::mem::take(b_properties) {
+            let merged = match a_properties.remove(&name) {
+                Some(existing) => merge(existing, schema),
+                None => schema,
+            };
+            a_properties.insert(name, merged);
+        }
+    }
+    a["required"] = Value::Array(both);
+}
+
+fn is_nullable(schema: &Value) -> bool {
+    schema["nullable"] == Value::Bool(true)
+}
+
+/// Give schemas that only saw `null` the `STRING` type
+fn fill_untyped(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    if !object.contains_key("type") {
+        object.insert("type".to_string(), json!("STRING"));
+    }
+    if let Some(items) = object.get_mut("items") {
+        fill_untyped(items);
+    }
+    if let Some(properties) = object.get_mut("properties").and_then(Value::as_object_mut) {
+        properties.values_mut().for_each(fill_untyped);
+    }
+}
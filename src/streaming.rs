@@ -0,0 +1,670 @@
+use crate::{
+    models::{
+        Candidate, Content, GenerationResponse, HarmBlockThreshold, Part, Role, SafetyRating,
+        UsageMetadata,
+    },
+    tools::{FunctionCall, FunctionResponse},
+    Error, Result,
+};
+use futures::stream::{Stream, StreamExt};
+use futures::task::AtomicWaker;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Finish reason reported by `ContentBuilder::execute_stream_until` when
+/// the deadline passed before generation completed
+pub const FINISH_REASON_DEADLINE: &str = "DEADLINE";
+
+/// An incremental event from a streamed generation
+///
+/// Produced by `ContentBuilder::execute_stream_parts`, which flattens the
+/// parts of each streamed chunk so renderers can append deltas directly
+/// instead of diffing whole responses.
+#[derive(Debug, Clone)]
+pub enum PartDelta {
+    /// A piece of answer text
+    TextDelta(String),
+    /// A function call requested by the model
+    FunctionCallDelta(FunctionCall),
+    /// The result of a function call dispatched by a function registry
+    FunctionResult(FunctionResponse),
+    /// A piece of a thought summary from a thinking model
+    ThoughtDelta(String),
+    /// Safety ratings of the chunk, sent after its content
+    SafetyRatings(Vec<SafetyRating>),
+    /// Generation finished
+    Finish {
+        /// Why generation stopped, e.g. `STOP` or `MAX_TOKENS`
+        reason: Option<String>,
+        /// Token usage for the whole generation, if reported
+        usage: Option<UsageMetadata>,
+    },
+}
+
+impl PartDelta {
+    /// Split a streamed chunk into deltas, following the first candidate
+    pub(crate) fn from_response(response: GenerationResponse) -> Vec<PartDelta> {
+        let mut deltas = Vec::new();
+        let Some(candidate) = response.candidates.into_iter().next() else {
+            return deltas;
+        };
+
+        for part in candidate.content.parts {
+            match part {
+                Part::Text { text, .. } if !text.is_empty() => {
+                    deltas.push(PartDelta::TextDelta(text))
+                }
+                Part::Thought { text, .. } => deltas.push(PartDelta::ThoughtDelta(text)),
+                Part::FunctionCall { function_call, .. } => {
+                    deltas.push(PartDelta::FunctionCallDelta(function_call))
+                }
+                _ => {}
+            }
+        }
+        if let Some(ratings) = candidate.safety_ratings.filter(|r| !r.is_empty()) {
+            deltas.push(PartDelta::SafetyRatings(ratings));
+        }
+        if candidate.finish_reason.is_some() {
+            deltas.push(PartDelta::Finish {
+                reason: candidate.finish_reason,
+                usage: response.usage_metadata,
+            });
+        }
+        deltas
+    }
+}
+
+/// A streamed generation that can be cancelled
+///
+/// Returned by `ContentBuilder::execute_stream`. Calling
+/// [`GenerationStream::cancel`], cancelling a [`CancelHandle`] or dropping
+/// the stream closes the HTTP response at once instead of draining it: the
+/// connection is reset, which stops generation on the server along with
+/// the tokens it would bill. The stream then ends.
+pub struct GenerationStream {
+    inner: Option<Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>>,
+    cancel: CancelHandle,
+    /// When the request was sent
+    started: Instant,
+    first_token_latency: Option<Duration>,
+    on_first_token: Option<FirstTokenCallback>,
+}
+
+/// Called with the time to the first text of a stream
+pub(crate) type FirstTokenCallback = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Cancels a [`GenerationStream`] from elsewhere, e.g. a "stop" button
+///
+/// Cloning is cheap. Cancelling wakes the task polling the stream, so the
+/// connection is closed even while it waits for the next chunk.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    state: Arc<CancelState>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl GenerationStream {
+    pub(crate) fn new(
+        inner: Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>,
+        started: Instant,
+        on_first_token: Option<FirstTokenCallback>,
+    ) -> Self {
+        Self {
+            inner: Some(inner),
+            cancel: CancelHandle::default(),
+            started,
+            first_token_latency: None,
+            on_first_token,
+        }
+    }
+
+    /// Time from sending the request to the first answer text, once it
+    /// has arrived
+    ///
+    /// Thoughts and function calls don't count; a stream without answer
+    /// text never reports a latency.
+    pub fn first_token_latency(&self) -> Option<Duration> {
+        self.first_token_latency
+    }
+
+    /// Stop the generation and close the connection now
+    pub fn cancel(&mut self) {
+        self.cancel.cancel();
+        self.inner = None;
+    }
+
+    /// A handle that cancels this stream from another task
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Merge small text chunks into larger ones before passing them on
+    ///
+    /// Cancel handles taken earlier keep working.
+    pub fn coalesce_chunks(mut self, coalescing: ChunkCoalescing) -> Self {
+        self.inner = self.inner.take().map(|inner| coalesce(inner, coalescing));
+        self
+    }
+}
+
+impl Stream for GenerationStream {
+    type Item = Result<GenerationResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.cancel.state.waker.register(cx.waker());
+        if self.cancel.is_cancelled() {
+            self.inner = None;
+        }
+        let Some(inner) = self.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let item = futures::ready!(inner.as_mut().poll_next(cx));
+        match &item {
+            None => self.inner = None,
+            Some(Ok(chunk)) if self.first_token_latency.is_none() && has_text(chunk) => {
+                let latency = self.started.elapsed();
+                self.first_token_latency = Some(latency);
+                if let Some(callback) = &self.on_first_token {
+                    callback(latency);
+                }
+            }
+            Some(_) => {}
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl std::fmt::Debug for GenerationStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GenerationStream")
+            .field("open", &self.inner.is_some())
+            .field("cancelled", &self.cancel.is_cancelled())
+            .field("first_token_latency", &self.first_token_latency)
+            .finish()
+    }
+}
+
+/// Whether a chunk carries answer text
+fn has_text(chunk: &GenerationResponse) -> bool {
+    chunk.candidates.iter().any(|candidate| {
+        candidate
+            .content
+            .parts
+            .iter()
+            .any(|part| matches!(part, Part::Text { text, .. } if !text.is_empty()))
+    })
+}
+
+impl CancelHandle {
+    /// Stop the generation; the stream ends at its next poll
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+        self.state.waker.wake();
+    }
+
+    /// Whether the stream was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+}
+
+impl std::fmt::Debug for CancelHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// How a stream merges small text chunks before passing them on
+///
+/// Models often stream a few characters at a time, and re-rendering a UI
+/// for each of them is wasteful. Buffered text is passed on once it
+/// reaches `min_chars` characters or `min_interval` has passed since the
+/// last chunk was passed on, whichever comes first. The first text, function
+/// calls and the final chunk are passed on at once, so coalescing never
+/// delays the start or the end of an answer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChunkCoalescing {
+    min_chars: Option<usize>,
+    min_interval: Option<Duration>,
+}
+
+impl ChunkCoalescing {
+    /// Pass text on in pieces of at least `min_chars` characters
+    pub fn min_chars(min_chars: usize) -> Self {
+        Self::default().with_min_chars(min_chars)
+    }
+
+    /// Pass text on at most once per `min_interval`
+    pub fn min_interval(min_interval: Duration) -> Self {
+        Self::default().with_min_interval(min_interval)
+    }
+
+    /// Also pass text on once `min_chars` characters are buffered
+    pub fn with_min_chars(mut self, min_chars: usize) -> Self {
+        self.min_chars = Some(min_chars);
+        self
+    }
+
+    /// Also pass text on once `min_interval` has passed
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+}
+
+/// State of a coalescing stream
+struct Coalescer {
+    inner: Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>,
+    coalescing: ChunkCoalescing,
+    /// Chunks merged so far and not yet passed on
+    pending: Option<GenerationResponse>,
+    pending_chars: usize,
+    /// Merged chunks and errors ready to be passed on
+    ready: std::collections::VecDeque<Result<GenerationResponse>>,
+    last_flush: tokio::time::Instant,
+    sent_text: bool,
+    done: bool,
+}
+
+impl Coalescer {
+    fn flush(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.sent_text |= has_text(&pending);
+            self.ready.push_back(Ok(pending));
+        }
+        self.pending_chars = 0;
+        self.last_flush = tokio::time::Instant::now();
+    }
+
+    fn push(&mut self, chunk: GenerationResponse) {
+        // Anything but plain text is passed on right away
+        let urgent = (!self.sent_text && has_text(&chunk))
+            || chunk.prompt_feedback.is_some()
+            || chunk.candidates.iter().any(|candidate| {
+                candidate.finish_reason.is_some()
+                    || candidate
+                        .content
+                        .parts
+                        .iter()
+                        .any(|part| !matches!(part, Part::Text { .. } | Part::Thought { .. }))
+            });
+        self.pending_chars += chunk
+            .candidates
+            .iter()
+            .flat_map(|candidate| &candidate.content.parts)
+            .map(|part| match part {
+                Part::Text { text, .. } | Part::Thought { text, .. } => text.chars().count(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        match &mut self.pending {
+            Some(pending) => accumulate(pending, chunk),
+            None => self.pending = Some(chunk),
+        }
+        let enough_chars = self
+            .coalescing
+            .min_chars
+            .is_some_and(|min| self.pending_chars >= min);
+        let waited = self
+            .coalescing
+            .min_interval
+            .is_some_and(|min| self.last_flush.elapsed() >= min);
+        if urgent || enough_chars || waited {
+            self.flush();
+        }
+    }
+
+    async fn next(&mut self) -> Option<Result<GenerationResponse>> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+            let next = match self.coalescing.min_interval {
+                Some(interval) if self.pending.is_some() => {
+                    let deadline = self.last_flush + interval;
+                    match tokio::time::timeout_at(deadline, self.inner.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            self.flush();
+                            continue;
+                        }
+                    }
+                }
+                _ => self.inner.next().await,
+            };
+            match next {
+                Some(Ok(chunk)) => self.push(chunk),
+                Some(Err(e)) => {
+                    self.flush();
+                    self.ready.push_back(Err(e));
+                }
+                None => {
+                    self.flush();
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
+
+/// Merge the small text chunks of a stream, see [`ChunkCoalescing`]
+pub(crate) fn coalesce(
+    stream: Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>,
+    coalescing: ChunkCoalescing,
+) -> Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>> {
+    let state = Coalescer {
+        inner: stream,
+        coalescing,
+        pending: None,
+        pending_chars: 0,
+        ready: std::collections::VecDeque::new(),
+        last_flush: tokio::time::Instant::now(),
+        sent_text: false,
+        done: false,
+    };
+    Box::pin(futures::stream::unfold(state, |mut state| async move {
+        let item = state.next().await?;
+        Some((item, state))
+    }))
+}
+
+/// End a stream with [`Error::SafetyAbort`] at the first chunk with a
+/// rating that reaches the threshold
+///
+/// Prompt feedback ratings count too. The flagged chunk is not passed on.
+pub(crate) fn abort_on_safety(
+    stream: Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>>,
+    threshold: HarmBlockThreshold,
+) -> Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>> {
+    let checked = stream.scan(false, move |aborted, result| {
+        if *aborted {
+            return futures::future::ready(None);
+        }
+        let flagged = result.as_ref().ok().and_then(|response| {
+            let prompt = response
+                .prompt_feedback
+                .iter()
+                .flat_map(|f| &f.safety_ratings);
+            let candidates = response
+                .candidates
+                .iter()
+                .flat_map(|c| c.safety_ratings.iter().flatten());
+            prompt
+                .chain(candidates)
+                .find(|r| r.reaches(threshold))
+                .cloned()
+        });
+        let item = match flagged {
+            Some(rating) => {
+                *aborted = true;
+                Err(Error::SafetyAbort {
+                    category: rating.category,
+                    probability: rating.probability,
+                })
+            }
+            None => result,
+        };
+        futures::future::ready(Some(item))
+    });
+    Box::pin(checked)
+}
+
+/// Whether a failed `streamGenerateContent` call means streaming is not
+/// available, rather than that the request itself is bad
+///
+/// Proxies without streaming support typically answer 405 or 501. A 400
+/// or 404 only counts when it names the method, so that a misspelled model
+/// or a wrong base URL fails without a second call.
+pub(crate) fn is_unsupported(error: &Error) -> bool {
+    match error.root() {
+        Error::ApiError {
+            status_code: 405 | 501,
+            ..
+        } => true,
+        Error::ApiError {
+            status_code: 400 | 404,
+            message,
+            ..
+        } => message.contains("streamGenerateContent"),
+        _ => false,
+    }
+}
+
+/// A stream yielding a complete response as its only chunk
+pub(crate) fn once(
+    response: GenerationResponse,
+) -> Pin<Box<dyn Stream<Item = Result<GenerationResponse>> + Send>> {
+    Box::pin(futures::stream::once(futures::future::ready(Ok(response))))
+}
+
+/// Fold a streamed chunk into the response assembled so far
+///
+/// Consecutive text and thought parts are concatenated; metadata from later
+/// chunks replaces earlier values.
+pub(crate) fn accumulate(response: &mut GenerationResponse, chunk: GenerationResponse) {
+    if chunk.prompt_feedback.is_some() {
+        response.prompt_feedback = chunk.prompt_feedback;
+    }
+    if chunk.usage_metadata.is_some() {
+        response.usage_metadata = chunk.usage_metadata;
+    }
+    for (index, candidate) in chunk.candidates.into_iter().enumerate() {
+        let Some(existing) = response.candidates.get_mut(index) else {
+            response.candidates.push(candidate);
+            continue;
+        };
+        for part in candidate.content.parts {
+            append_part(&mut existing.content.parts, part);
+        }
+        if candidate.finish_reason.is_some() {
+            existing.finish_reason = candidate.finish_reason;
+        }
+        if candidate.finish_message.is_some() {
+            existing.finish_message = candidate.finish_message;
+        }
+        if candidate.safety_ratings.is_some() {
+            existing.safety_ratings = candidate.safety_ratings;
+        }
+        if candidate.citation_metadata.is_some() {
+            existing.citation_metadata = candidate.citation_metadata;
+        }
+        if candidate.grounding_metadata.is_some() {
+            existing.grounding_metadata = candidate.grounding_metadata;
+        }
+        if candidate.usage_metadata.is_some() {
+            existing.usage_metadata = candidate.usage_metadata;
+        }
+    }
+}
+
+/// Mark a partially streamed response as cut off by a deadline
+pub(crate) fn mark_deadline(response: &mut GenerationResponse) {
+    if response.candidates.is_empty() {
+        response.candidates.push(Candidate {
+            content: Content {
+                parts: Vec::new(),
+                role: Some(Role::Model),
+                ..Default::default()
+            },
+            safety_ratings: None,
+            citation_metadata: None,
+            grounding_metadata: None,
+            finish_reason: None,
+            finish_message: None,
+            usage_metadata: None,
+        });
+    }
+    for candidate in &mut response.candidates {
+        candidate.finish_reason = Some(FINISH_REASON_DEADLINE.to_string());
+    }
+}
+
+fn append_part(parts: &mut Vec<Part>, part: Part) {
+    match (parts.last_mut(), part) {
+        (
+            Some(Part::Text {
+                text,
+                thought_signature,
+            }),
+            Part::Text {
+                text: more,
+                thought_signature: signature,
+            },
+        ) => {
+            text.push_str(&more);
+            if signature.is_some() {
+                *thought_signature = signature;
+            }
+        }
+        (
+            Some(Part::Thought {
+                text,
+                thought_signature,
+                ..
+            }),
+            Part::Thought {
+                text: more,
+                thought_signature: signature,
+                ..
+            },
+        ) => {
+            text.push_str(&more);
+            // Keep the signature whichever chunk of the thought carried it
+            if signature.is_some() {
+                *thought_signature = signature;
+            }
+        }
+        (_, part) => parts.push(part),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockGemini, MockReply};
+
+    #[tokio::test]
+    async fn fallback_only_when_streaming_is_unsupported() {
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Error {
+            status: 404,
+            message: "Method streamGenerateContent not found".to_string(),
+        })
+        .push_text("Hi");
+        let mut stream = mock
+            .client()
+            .generate_content()
+            .with_user_message("Hello")
+            .execute_stream_or_fallback()
+            .await
+            .unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().text(), "Hi");
+        assert_eq!(mock.requests().len(), 2);
+
+        // A model that doesn't exist is not retried without streaming
+        let mock = MockGemini::start().await.unwrap();
+        mock.push(MockReply::Error {
+            status: 404,
+            message: "models/gemini-typo is not found".to_string(),
+        });
+        let result = mock
+            .client()
+            .generate_content()
+            .with_user_message("Hello")
+            .execute_stream_or_fallback()
+            .await;
+        let Err(error) = result else {
+            panic!("expected an error");
+        };
+        assert!(matches!(
+            error.root(),
+            Error::ApiError {
+                status_code: 404,
+                ..
+            }
+        ));
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    /// A mock streaming its answer in 40 chunks, one every 25 ms
+    async fn slow_stream() -> (MockGemini, GenerationStream) {
+        let mock = MockGemini::start()
+            .await
+            .unwrap()
+            .with_stream_chunks(40)
+            .with_chunk_delay(Duration::from_millis(25));
+        mock.push_text("word ".repeat(200));
+        let mut stream = mock
+            .client()
+            .generate_content()
+            .with_user_message("Go on")
+            .execute_stream()
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        (mock, stream)
+    }
+
+    /// Whether the mock saw the connection close before the last chunk
+    async fn closed_early(mock: &MockGemini) -> bool {
+        for _ in 0..40 {
+            if mock.streams_closed_early() > 0 {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn dropping_the_stream_aborts_the_request() {
+        let (mock, stream) = slow_stream().await;
+        drop(stream);
+        assert!(closed_early(&mock).await);
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_stream_aborts_the_request() {
+        let (mock, mut stream) = slow_stream().await;
+        stream.cancel();
+        assert!(stream.next().await.is_none());
+        assert!(closed_early(&mock).await);
+    }
+
+    #[tokio::test]
+    async fn cancel_handle_aborts_the_request_while_waiting() {
+        let (mock, mut stream) = slow_stream().await;
+        let handle = stream.cancel_handle();
+        let consumer = tokio::spawn(async move {
+            let mut received = 0;
+            while stream.next().await.is_some() {
+                received += 1;
+            }
+            received
+        });
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        handle.cancel();
+
+        // The consumer stops at once instead of reading the remaining chunks
+        let received = tokio::time::timeout(Duration::from_millis(200), consumer)
+            .await
+            .expect("the stream did not end when cancelled")
+            .unwrap();
+        assert!(received < 10, "{received} chunks after cancelling");
+        assert!(handle.is_cancelled());
+        assert!(closed_early(&mock).await);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    client::GeminiClient,
+    models::{Content, GenerateContentRequest, Part, Role},
+    Result,
+};
+use serde::Deserialize;
+
+/// Tokens counted for each inline image or document page
+///
+/// The API bills images up to 384px, and each tile of larger images, at 258
+/// tokens; without decoding the blob that is the best available guess.
+const INLINE_DATA_TOKENS: usize = 258;
+
+/// Roughly estimate how many tokens a text uses, without calling the API
+///
+/// The heuristic is tuned for Gemini's tokenizer: about four characters
+/// per token for words in Latin scripts, one token per CJK character and
+/// one per punctuation mark. Expect estimates within 10-20% of
+/// `countTokens` for typical prose; use the API when an exact count
+/// matters, e.g. close to a model's context limit.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut word_len: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() && !is_cjk(c) {
+            word_len += 1;
+            continue;
+        }
+        tokens += word_len.div_ceil(4);
+        word_len = 0;
+        if is_cjk(c) || (!c.is_whitespace() && !c.is_alphanumeric()) {
+            tokens += 1;
+        }
+    }
+    tokens + word_len.div_ceil(4)
+}
+
+/// Characters that the tokenizer typically encodes one per token
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'   // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul syllables
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+    )
+}
+
+/// Estimate the tokens of a content's parts
+pub(crate) fn content_tokens(content: &Content) -> usize {
+    content
+        .parts
+        .iter()
+        .map(|part| match part {
+            Part::Text { text, .. } | Part::Thought { text, .. } => estimate_tokens(text),
+            Part::InlineData { .. } | Part::FileData { .. } => INLINE_DATA_TOKENS,
+            Part::FunctionCall { function_call, .. } => {
+                estimate_tokens(&function_call.name)
+                    + estimate_tokens(&function_call.args.to_string())
+            }
+            Part::FunctionResponse { function_response } => {
+                estimate_tokens(&serde_json::to_string(function_response).unwrap_or_default())
+            }
+            Part::Other(fields) => {
+                estimate_tokens(&serde_json::to_string(fields).unwrap_or_default())
+            }
+        })
+        .sum()
+}
+
+impl GenerateContentRequest {
+    /// Estimate the prompt tokens of the request, without calling the API
+    ///
+    /// Counts the contents, the system instruction and the tool
+    /// declarations using [`estimate_tokens`]. Each inline blob counts as
+    /// one image; audio and video are billed by duration and will be
+    /// underestimated.
+    pub fn estimated_prompt_tokens(&self) -> usize {
+        let contents: usize = self.contents.iter().map(content_tokens).sum();
+        let system = self
+            .system_instruction
+            .as_ref()
+            .map(content_tokens)
+            .unwrap_or(0);
+        let tools = self
+            .tools
+            .as_ref()
+            .map(|tools| estimate_tokens(&serde_json::to_string(tools).unwrap_or_default()))
+            .unwrap_or(0);
+        contents + system + tools
+    }
+}
+
+/// Where the prompt tokens of a request go, counted by the API
+///
+/// Returned by `ContentBuilder::count_tokens_breakdown`. Each part is
+/// counted on its own, so the parts add up to slightly less than
+/// [`total`](Self::total); the difference, in [`other`](Self::other), is
+/// spent on tool declarations and message framing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBreakdown {
+    /// Prompt tokens of the whole request
+    pub total: u32,
+    /// Tokens of the system instruction
+    pub system_instruction: u32,
+    /// Tokens of each message, in conversation order
+    pub messages: Vec<MessageTokens>,
+}
+
+/// Tokens of one message of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTokens {
+    /// Position of the message in the conversation
+    pub index: usize,
+    /// Who sent the message
+    pub role: Option<Role>,
+    /// Tokens of all parts of the message
+    pub tokens: u32,
+    /// Tokens of each part
+    pub parts: Vec<PartTokens>,
+}
+
+/// Tokens of one part of a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartTokens {
+    /// Position of the part in its message
+    pub index: usize,
+    /// What the part is, e.g. `text`, `image/png` or `functionCall get_weather`
+    pub label: String,
+    /// Tokens of the part
+    pub tokens: u32,
+}
+
+impl TokenBreakdown {
+    /// Tokens not attributed to the system instruction or any part
+    pub fn other(&self) -> u32 {
+        let counted: u32 = self.messages.iter().map(|m| m.tokens).sum();
+        self.total
+            .saturating_sub(self.system_instruction)
+            .saturating_sub(counted)
+    }
+
+    /// The `n` most expensive parts, as message index and part, largest first
+    pub fn largest_parts(&self, n: usize) -> Vec<(usize, &PartTokens)> {
+        let mut parts: Vec<_> = self
+            .messages
+            .iter()
+            .flat_map(|m| m.parts.iter().map(move |p| (m.index, p)))
+            .collect();
+        parts.sort_by_key(|(_, part)| std::cmp::Reverse(part.tokens));
+        parts.truncate(n);
+        parts
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountTokensResponse {
+    #[serde(default)]
+    total_tokens: u32,
+}
+
+/// Count the tokens of a request, its system instruction and each part
+///
+/// Makes one `countTokens` call for the request, one for the system
+/// instruction and one per part.
+pub(crate) async fn count_breakdown(
+    client: &GeminiClient,
+    request: &GenerateContentRequest,
+) -> Result<TokenBreakdown> {
+    let endpoint = "countTokens";
+    let result: Result<TokenBreakdown> = async {
+        let mut whole = serde_json::to_value(request)?;
+        whole["model"] = serde_json::Value::String(client.model.clone());
+        let total = count(
+            client,
+            serde_json::json!({ "generateContentRequest": whole }),
+        )
+        .await?;
+
+        let system_instruction = match &request.system_instruction {
+            Some(system) if !system.parts.is_empty() => {
+                count_contents(client, system.parts.clone(), Some(Role::User)).await?
+            }
+            _ => 0,
+        };
+
+        let mut messages = Vec::with_capacity(request.contents.len());
+        for (index, content) in request.contents.iter().enumerate() {
+            let mut parts = Vec::with_capacity(content.parts.len());
+            for (part_index, part) in content.parts.iter().enumerate() {
+                parts.push(PartTokens {
+                    index: part_index,
+                    label: part_label(part),
+                    tokens: count_contents(client, vec![part.clone()], content.role.clone())
+                        .await?,
+                });
+            }
+            messages.push(MessageTokens {
+                index,
+                role: content.role.clone(),
+                tokens: parts.iter().map(|p| p.tokens).sum(),
+                parts,
+            });
+        }
+
+        Ok(TokenBreakdown {
+            total,
+            system_instruction,
+            messages,
+        })
+    }
+    .await;
+    result.map_err(|e| client.context(e, endpoint))
+}
+
+async fn count_contents(
+    client: &GeminiClient,
+    parts: Vec<Part>,
+    role: Option<Role>,
+) -> Result<u32> {
+    let contents = [Content {
+        parts,
+        role,
+        ..Default::default()
+    }];
+    count(client, serde_json::json!({ "contents": contents })).await
+}
+
+async fn count(client: &GeminiClient, body: serde_json::Value) -> Result<u32> {
+    let response = client.post("countTokens", &body).await?;
+    let response: CountTokensResponse = response.json().await?;
+    Ok(response.total_tokens)
+}
+
+fn part_label(part: &Part) -> String {
+    match part {
+        Part::Text { .. } => "text".to_string(),
+        Part::Thought { .. } => "thought".to_string(),
+        Part::InlineData { inline_data, .. } => inline_data.mime_type.clone(),
+        Part::FileData { file_data, .. } if file_data.mime_type.is_empty() => {
+            "fileData".to_string()
+        }
+        Part::FileData { file_data, .. } => file_data.mime_type.clone(),
+        Part::FunctionCall { function_call, .. } => {
+            format!("functionCall {}", function_call.name)
+        }
+        Part::FunctionResponse { function_response } => {
+            format!("functionResponse {}", function_response.name)
+        }
+        Part::Other(fields) => fields.keys().next().cloned().unwrap_or_default(),
+    }
+}
@@ -8,11 +8,13 @@ pub enum Tool {
     /// Function-based tool
     Function {
         /// The function declaration for the tool
+        #[serde(rename = "functionDeclarations", alias = "function_declarations")]
         function_declarations: Vec<FunctionDeclaration>,
     },
     /// Google Search tool
     GoogleSearch {
         /// The Google Search configuration
+        #[serde(rename = "googleSearch", alias = "google_search")]
         google_search: GoogleSearchConfig,
     },
 }
@@ -44,6 +46,122 @@ impl Tool {
     }
 }
 
+/// A set of tools, merged into the shape the API expects
+///
+/// All function declarations are merged into a single `Tool::Function`
+/// entry. A function declared twice keeps its last declaration; the
+/// replaced names are available from [`Tools::duplicate_names`] (and
+/// logged as warnings with the `tracing` feature).
+#[derive(Debug, Clone, Default)]
+pub struct Tools {
+    functions: Vec<FunctionDeclaration>,
+    google_search: bool,
+    duplicates: Vec<String>,
+}
+
+impl Tools {
+    /// Create an empty set of tools
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tool, merging function declarations with existing ones
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.add(tool);
+        self
+    }
+
+    /// Add a function declaration
+    pub fn with_function(mut self, function: FunctionDeclaration) -> Self {
+        self.add_function(function);
+        self
+    }
+
+    /// Add the Google Search tool
+    pub fn with_google_search(mut self) -> Self {
+        self.google_search = true;
+        self
+    }
+
+    /// Add a tool, merging function declarations with existing ones
+    pub fn add(&mut self, tool: Tool) {
+        match tool {
+            Tool::Function {
+                function_declarations,
+            } => function_declarations
+                .into_iter()
+                .for_each(|f| self.add_function(f)),
+            Tool::GoogleSearch { .. } => self.google_search = true,
+        }
+    }
+
+    fn add_function(&mut self, function: FunctionDeclaration) {
+        if let Some(existing) = self.functions.iter_mut().find(|f| f.name == function.name) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(name = %function.name, "function declared twice, keeping the last declaration");
+            self.duplicates.push(function.name.clone());
+            *existing = function;
+        } else {
+            self.functions.push(function);
+        }
+    }
+
+    /// Names of functions that were declared more than once
+    pub fn duplicate_names(&self) -> &[String] {
+        &self.duplicates
+    }
+
+    /// Whether no tools were added
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && !self.google_search
+    }
+
+    /// Check that the model supports this combination of tools
+    ///
+    /// Gemini 1.x models do not support the `googleSearch` tool, and models
+    /// before Gemini 2.5 reject requests that combine it with function
+    /// declarations. Unknown models are assumed to support everything.
+    pub fn validate_for_model(&self, model: &str) -> crate::Result<()> {
+        if !self.google_search {
+            return Ok(());
+        }
+        let name = model.rsplit('/').next().unwrap_or(model);
+        if name.starts_with("gemini-1.") {
+            return Err(crate::Error::IncompatibleOptions(format!(
+                "{} does not support the google_search tool",
+                name
+            )));
+        }
+        if !self.functions.is_empty() && name.starts_with("gemini-2.0") {
+            return Err(crate::Error::IncompatibleOptions(format!(
+                "{} cannot combine the google_search tool with function declarations",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// The tools in the form sent to the API
+    pub fn into_vec(self) -> Vec<Tool> {
+        let mut tools = Vec::new();
+        if !self.functions.is_empty() {
+            tools.push(Tool::with_functions(self.functions));
+        }
+        if self.google_search {
+            tools.push(Tool::google_search());
+        }
+        tools
+    }
+}
+
+impl FromIterator<Tool> for Tools {
+    fn from_iter<I: IntoIterator<Item = Tool>>(iter: I) -> Self {
+        let mut tools = Tools::new();
+        iter.into_iter().for_each(|tool| tools.add(tool));
+        tools
+    }
+}
+
 /// Declaration of a function that can be called by the model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDeclaration {
@@ -68,6 +186,444 @@ impl FunctionDeclaration {
             parameters,
         }
     }
+
+    /// Convert an OpenAI tool definition
+    ///
+    /// Accepts a chat-completions tool (`{"type": "function", "function":
+    /// {...}}`) or the bare function object with `name`, `description` and
+    /// a JSON schema in `parameters`. Types are upper-cased, and nullable
+    /// unions such as `["string", "null"]` or an `anyOf` with a `null`
+    /// variant become `nullable` properties. `format`, `minItems`,
+    /// `maxItems`, `anyOf` and nested object properties are kept.
+    /// Validation-only keywords Gemini has no field for (`minimum`,
+    /// `additionalProperties`, `default`, ...) are dropped; keywords that
+    /// change the shape of the arguments (`$ref`, `oneOf`, non-string
+    /// enums, ...) are reported as
+    /// [`Error::InvalidToolDeclaration`](crate::Error::InvalidToolDeclaration).
+    pub fn from_openai_schema(value: serde_json::Value) -> crate::Result<Self> {
+        let function = match value.get("function") {
+            Some(function) if value.get("type").and_then(|t| t.as_str()) == Some("function") => {
+                function
+            }
+            _ => &value,
+        };
+        let name = function
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let description = function
+            .get("description")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let mut issues = Vec::new();
+        let mut issue = |path: &str, message: String| {
+            issues.push(Issue {
+                function: name.clone(),
+                path: path.to_string(),
+                message,
+            })
+        };
+        if name.is_empty() {
+            issue("name", "is missing".to_string());
+        }
+
+        let mut parameters = FunctionParameters::object();
+        match function.get("parameters").and_then(|p| p.as_object()) {
+            None => {}
+            Some(schema) => {
+                let param_type = openai_type(schema.get("type"));
+                if param_type.as_deref().is_some_and(|t| t != "OBJECT") {
+                    issue(
+                        "parameters.type",
+                        "must be object for function parameters".to_string(),
+                    );
+                }
+                for (keyword, _) in schema {
+                    let known = matches!(keyword.as_str(), "type" | "properties" | "required");
+                    if !known && !IGNORED_SCHEMA_KEYWORDS.contains(&keyword.as_str()) {
+                        issue(
+                            &format!("parameters.{}", keyword),
+                            "is not supported".to_string(),
+                        );
+                    }
+                }
+                let required: Vec<String> = schema
+                    .get("required")
+                    .and_then(|r| r.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|r| r.as_str().map(String::from))
+                    .collect();
+                let properties = schema.get("properties").and_then(|p| p.as_object());
+                for (key, value) in properties.into_iter().flatten() {
+                    let path = format!("parameters.properties.{}", key);
+                    let details = openai_property(value, &path, &mut issue);
+                    parameters = parameters.with_property(key, details, required.contains(key));
+                }
+                // Keep required names that have no property, for `issues` to report
+                for key in required {
+                    if !properties.is_some_and(|p| p.contains_key(&key)) {
+                        parameters.required.get_or_insert_with(Vec::new).push(key);
+                    }
+                }
+            }
+        }
+
+        if !issues.is_empty() {
+            return Err(crate::Error::InvalidToolDeclaration(issues));
+        }
+        Ok(Self::new(name, description, parameters))
+    }
+
+    /// The declaration as an OpenAI chat-completions tool
+    ///
+    /// Types are lower-cased to JSON schema spelling and empty property
+    /// descriptions are left out.
+    pub fn to_openai_schema(&self) -> serde_json::Value {
+        let mut parameters = serde_json::json!({
+            "type": self.parameters.param_type.to_lowercase(),
+            "properties": self
+                .parameters
+                .properties
+                .iter()
+                .flatten()
+                .map(|(name, details)| (name.clone(), details.to_openai_schema()))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        if let Some(required) = self.parameters.required.as_ref().filter(|r| !r.is_empty()) {
+            parameters["required"] = serde_json::json!(required);
+        }
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": parameters,
+            },
+        })
+    }
+
+    /// Problems with the declaration that the API would reject or that
+    /// keep the model from calling the function reliably
+    ///
+    /// Checks the name against the API's pattern (a letter or underscore,
+    /// then up to 63 letters, digits, `_`, `.` or `-`), requires a
+    /// description and checks the parameter schema for unsupported types,
+    /// arrays without `items`, keywords on types they don't apply to and
+    /// required or ordered properties that are not defined.
+    pub fn issues(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut issue = |path: &str, message: String| {
+            issues.push(Issue {
+                function: self.name.clone(),
+                path: path.to_string(),
+                message,
+            })
+        };
+
+        let mut chars = self.name.chars();
+        let valid_name = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+            && self.name.len() <= 64;
+        if !valid_name {
+            issue(
+                "name",
+                "must start with a letter or underscore and contain at most 64 letters, \
+                 digits, underscores, dots or dashes"
+                    .to_string(),
+            );
+        }
+        if self.description.trim().is_empty() {
+            issue("description", "must not be empty".to_string());
+        }
+
+        let parameters = &self.parameters;
+        if !parameters.param_type.eq_ignore_ascii_case("object") {
+            issue(
+                "parameters.type",
+                format!("must be OBJECT, not {}", parameters.param_type),
+            );
+        }
+        check_object(
+            "parameters",
+            parameters.properties.as_ref(),
+            parameters.required.as_deref(),
+            parameters.property_ordering.as_deref(),
+            &mut issue,
+        );
+        issues
+    }
+}
+
+/// JSON schema keywords that only constrain values and are dropped when
+/// converting OpenAI tool definitions
+const IGNORED_SCHEMA_KEYWORDS: &[&str] = &[
+    "$schema",
+    "additionalProperties",
+    "default",
+    "examples",
+    "exclusiveMaximum",
+    "exclusiveMinimum",
+    "maxLength",
+    "maximum",
+    "minLength",
+    "minimum",
+    "multipleOf",
+    "pattern",
+    "strict",
+    "title",
+    "uniqueItems",
+];
+
+/// The Gemini spelling of a JSON schema `type`, skipping `null` in unions
+fn openai_type(value: Option<&serde_json::Value>) -> Option<String> {
+    match value? {
+        serde_json::Value::String(t) => Some(t.to_uppercase()),
+        serde_json::Value::Array(types) => types
+            .iter()
+            .filter_map(|t| t.as_str())
+            .find(|t| *t != "null")
+            .map(str::to_uppercase),
+        _ => None,
+    }
+}
+
+/// Deepest chain of `$ref`s followed when inlining them
+const MAX_REF_DEPTH: usize = 16;
+
+/// Replace `$ref`s to `#/$defs/...` or `#/definitions/...` with the schema
+/// they point to, dropping the definitions
+///
+/// Keywords next to a `$ref`, such as a description, are kept.
+fn inline_refs(
+    value: &serde_json::Value,
+    root: &serde_json::Value,
+    depth: usize,
+    path: &str,
+    issue: &mut impl FnMut(&str, String),
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(schema) => {
+            let mut inlined = serde_json::Map::new();
+            if let Some(reference) = schema.get("$ref") {
+                let target = reference
+                    .as_str()
+                    .and_then(|r| r.strip_prefix('#'))
+                    .filter(|r| r.starts_with("/$defs/") || r.starts_with("/definitions/"))
+                    .and_then(|pointer| root.pointer(pointer));
+                match target {
+                    Some(_) if depth >= MAX_REF_DEPTH => issue(
+                        &format!("{}.$ref", path),
+                        "is recursive, which is not supported".to_string(),
+                    ),
+                    Some(target) => {
+                        if let serde_json::Value::Object(target) =
+                            inline_refs(target, root, depth + 1, path, issue)
+                        {
+                            inlined = target;
+                        }
+                    }
+                    None => issue(
+                        &format!("{}.$ref", path),
+                        format!("{} cannot be resolved", reference),
+                    ),
+                }
+            }
+            for (keyword, child) in schema {
+                if matches!(keyword.as_str(), "$ref" | "$defs" | "definitions") {
+                    continue;
+                }
+                let child_path = format!("{}.{}", path, keyword);
+                inlined.insert(
+                    keyword.clone(),
+                    inline_refs(child, root, depth, &child_path, issue),
+                );
+            }
+            serde_json::Value::Object(inlined)
+        }
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    inline_refs(item, root, depth, &format!("{}.{}", path, index), issue)
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Convert a property schema of an OpenAI tool definition
+fn openai_property(
+    value: &serde_json::Value,
+    path: &str,
+    issue: &mut impl FnMut(&str, String),
+) -> PropertyDetails {
+    let empty = serde_json::Map::new();
+    let schema = value.as_object().unwrap_or(&empty);
+    for (keyword, _) in schema {
+        let known = matches!(
+            keyword.as_str(),
+            "type"
+                | "description"
+                | "enum"
+                | "items"
+                | "format"
+                | "minItems"
+                | "maxItems"
+                | "nullable"
+                | "properties"
+                | "required"
+                | "propertyOrdering"
+                | "anyOf"
+                | "const"
+        );
+        if !known && !IGNORED_SCHEMA_KEYWORDS.contains(&keyword.as_str()) {
+            issue(
+                &format!("{}.{}", path, keyword),
+                "is not supported".to_string(),
+            );
+        }
+    }
+
+    let enum_values = schema.get("enum").and_then(|e| e.as_array()).map(|values| {
+        if values.iter().any(|v| !v.is_string()) {
+            issue(
+                &format!("{}.enum", path),
+                "must only contain strings".to_string(),
+            );
+        }
+        values
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect()
+    });
+    // A `const` is an enum with one value
+    let enum_values = match schema.get("const") {
+        Some(serde_json::Value::String(value)) if enum_values.is_none() => {
+            Some(vec![value.clone()])
+        }
+        Some(_) => {
+            issue(
+                &format!("{}.const", path),
+                "must be a string and not combined with enum".to_string(),
+            );
+            enum_values
+        }
+        None => enum_values,
+    };
+    // `{"type": "null"}` variants make the property nullable instead
+    let any_of: Option<Vec<&serde_json::Value>> = schema
+        .get("anyOf")
+        .and_then(|a| a.as_array())
+        .map(|variants| variants.iter().filter(|v| !is_null_type(v)).collect());
+    let nullable = is_nullable_union(schema.get("type"))
+        || schema.get("nullable").and_then(|n| n.as_bool()) == Some(true)
+        || schema
+            .get("anyOf")
+            .and_then(|a| a.as_array())
+            .is_some_and(|variants| variants.iter().any(is_null_type));
+    // An enum without a type lists strings
+    let property_type = match openai_type(schema.get("type")) {
+        Some(property_type) => property_type,
+        None if enum_values.is_some() => "STRING".to_string(),
+        None if any_of.is_some() => String::new(),
+        None => {
+            issue(path, "has no type".to_string());
+            "STRING".to_string()
+        }
+    };
+    let items = schema
+        .get("items")
+        .map(|items| Box::new(openai_property(items, &format!("{}.items", path), issue)));
+    let properties = schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, value)| {
+                    let path = format!("{}.properties.{}", path, name);
+                    (name.clone(), openai_property(value, &path, issue))
+                })
+                .collect()
+        });
+    let any_of = any_of.map(|variants| {
+        variants
+            .into_iter()
+            .enumerate()
+            .map(|(index, variant)| {
+                openai_property(variant, &format!("{}.anyOf.{}", path, index), issue)
+            })
+            .collect()
+    });
+    let count = |keyword: &str| schema.get(keyword).and_then(|n| n.as_u64());
+
+    PropertyDetails {
+        property_type,
+        description: schema
+            .get("description")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        nullable: nullable.then_some(true),
+        format: schema
+            .get("format")
+            .and_then(|f| f.as_str())
+            .map(String::from),
+        enum_values,
+        items,
+        min_items: count("minItems"),
+        max_items: count("maxItems"),
+        properties,
+        required: string_list(schema.get("required")),
+        property_ordering: string_list(schema.get("propertyOrdering")),
+        any_of,
+    }
+}
+
+/// Whether a JSON schema `type` is a union including `null`
+fn is_nullable_union(value: Option<&serde_json::Value>) -> bool {
+    value
+        .and_then(|t| t.as_array())
+        .is_some_and(|types| types.iter().any(|t| t == "null"))
+}
+
+/// Whether a schema only allows `null`
+fn is_null_type(schema: &serde_json::Value) -> bool {
+    schema.get("type").and_then(|t| t.as_str()) == Some("null")
+}
+
+fn string_list(value: Option<&serde_json::Value>) -> Option<Vec<String>> {
+    value.and_then(|v| v.as_array()).map(|values| {
+        values
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect()
+    })
+}
+
+/// A problem found in a function declaration before sending it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The declared function's name
+    pub function: String,
+    /// Where in the declaration the problem is, e.g. `parameters.properties.city`
+    pub path: String,
+    /// What is wrong
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} {}", self.function, self.path, self.message)
+    }
 }
 
 pub fn value_to_function_parameters(value: serde_json::Value) -> FunctionParameters {
@@ -106,6 +662,7 @@ pub fn value_to_function_parameters(value: serde_json::Value) -> FunctionParamet
             param_type,
             properties: Some(properties),
             required: Some(required),
+            property_ordering: string_list(obj.get("propertyOrdering")),
         }
     } else {
         // Default to object type if not an object
@@ -113,6 +670,7 @@ pub fn value_to_function_parameters(value: serde_json::Value) -> FunctionParamet
             param_type: "OBJECT".to_string(),
             properties: Some(properties),
             required: Some(required),
+            property_ordering: None,
         }
     }
 }
@@ -120,10 +678,16 @@ pub fn value_to_function_parameters(value: serde_json::Value) -> FunctionParamet
 fn extract_property_details(value: &serde_json::Value) -> Option<PropertyDetails> {
     let obj = value.as_object()?;
 
+    // `anyOf` properties have no type of their own
+    let default_type = if obj.contains_key("anyOf") {
+        ""
+    } else {
+        "string"
+    };
     let property_type = obj
         .get("type")
         .and_then(|t| t.as_str())
-        .unwrap_or("string")
+        .unwrap_or(default_type)
         .to_uppercase();
 
     let description = obj
@@ -140,14 +704,39 @@ fn extract_property_details(value: &serde_json::Value) -> Option<PropertyDetails
 
     let items = obj
         .get("items")
-        .and_then(|i| extract_property_details(i))
+        .and_then(extract_property_details)
         .map(Box::new);
 
+    let properties = obj
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .map(|props| {
+            props
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), extract_property_details(value)?)))
+                .collect()
+        });
+    let any_of = obj.get("anyOf").and_then(|a| a.as_array()).map(|variants| {
+        variants
+            .iter()
+            .filter_map(extract_property_details)
+            .collect()
+    });
+    let count = |keyword: &str| obj.get(keyword).and_then(|n| n.as_u64());
+
     Some(PropertyDetails {
         property_type,
         description,
+        nullable: obj.get("nullable").and_then(|n| n.as_bool()),
+        format: obj.get("format").and_then(|f| f.as_str()).map(String::from),
         enum_values,
         items,
+        min_items: count("minItems"),
+        max_items: count("maxItems"),
+        properties,
+        required: string_list(obj.get("required")),
+        property_ordering: string_list(obj.get("propertyOrdering")),
+        any_of,
     })
 }
 
@@ -163,6 +752,13 @@ pub struct FunctionParameters {
     /// The required properties
     #[serde(skip_serializing_if = "Option::is_none")]
     pub required: Option<Vec<String>>,
+    /// The order the properties should be generated in
+    #[serde(
+        rename = "propertyOrdering",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub property_ordering: Option<Vec<String>>,
 }
 
 impl FunctionParameters {
@@ -172,6 +768,7 @@ impl FunctionParameters {
             param_type: "OBJECT".to_string(),
             properties: Some(HashMap::new()),
             required: Some(Vec::new()),
+            property_ordering: None,
         }
     }
 
@@ -193,72 +790,112 @@ impl FunctionParameters {
         }
         self
     }
+
+    /// Set the order the properties should be generated in
+    ///
+    /// Properties are otherwise generated in alphabetical order. Every name
+    /// must be a defined property.
+    pub fn with_property_ordering(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.property_ordering = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
 }
 
 /// Details about a property
+///
+/// Also usable as a response schema: serialize it with `serde_json::to_value`
+/// and pass it to `ContentBuilder::with_response_schema`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PropertyDetails {
-    /// The type of the property
-    #[serde(rename = "type")]
+    /// The type of the property, empty for `any_of` properties
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
     pub property_type: String,
     /// The description of the property
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
+    /// Whether the value may be `null`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nullable: Option<bool>,
+    /// The format of the value, e.g. `date-time` for a STRING or `int64`
+    /// for an INTEGER
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
     /// The enum values if the property is an enum
-    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
     pub enum_values: Option<Vec<String>>,
     /// The items if the property is an array
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub items: Option<Box<PropertyDetails>>,
+    /// The least number of items of an array
+    #[serde(rename = "minItems", default, skip_serializing_if = "Option::is_none")]
+    pub min_items: Option<u64>,
+    /// The greatest number of items of an array
+    #[serde(rename = "maxItems", default, skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<u64>,
+    /// The properties if the property is an object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<HashMap<String, PropertyDetails>>,
+    /// The required properties of an object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    /// The order the properties of an object should be generated in
+    #[serde(
+        rename = "propertyOrdering",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub property_ordering: Option<Vec<String>>,
+    /// Schemas of which the value must match at least one
+    #[serde(rename = "anyOf", default, skip_serializing_if = "Option::is_none")]
+    pub any_of: Option<Vec<PropertyDetails>>,
 }
 
 impl PropertyDetails {
-    /// Create a new string property
-    pub fn string(description: impl Into<String>) -> Self {
+    fn of_type(property_type: &str, description: impl Into<String>) -> Self {
         Self {
-            property_type: "STRING".to_string(),
+            property_type: property_type.to_string(),
             description: description.into(),
+            nullable: None,
+            format: None,
             enum_values: None,
             items: None,
+            min_items: None,
+            max_items: None,
+            properties: None,
+            required: None,
+            property_ordering: None,
+            any_of: None,
         }
     }
 
+    /// Create a new string property
+    pub fn string(description: impl Into<String>) -> Self {
+        Self::of_type("STRING", description)
+    }
+
     /// Create a new number property
     pub fn number(description: impl Into<String>) -> Self {
-        Self {
-            property_type: "NUMBER".to_string(),
-            description: description.into(),
-            enum_values: None,
-            items: None,
-        }
+        Self::of_type("NUMBER", description)
     }
 
     /// Create a new integer property
     pub fn integer(description: impl Into<String>) -> Self {
-        Self {
-            property_type: "INTEGER".to_string(),
-            description: description.into(),
-            enum_values: None,
-            items: None,
-        }
+        Self::of_type("INTEGER", description)
     }
 
     /// Create a new boolean property
     pub fn boolean(description: impl Into<String>) -> Self {
-        Self {
-            property_type: "BOOLEAN".to_string(),
-            description: description.into(),
-            enum_values: None,
-            items: None,
-        }
+        Self::of_type("BOOLEAN", description)
     }
 
     /// Create a new array property
     pub fn array(description: impl Into<String>, items: PropertyDetails) -> Self {
         Self {
-            property_type: "ARRAY".to_string(),
-            description: description.into(),
-            enum_values: None,
             items: Some(Box::new(items)),
+            ..Self::of_type("ARRAY", description)
         }
     }
 
@@ -268,11 +905,261 @@ impl PropertyDetails {
         enum_values: impl IntoIterator<Item = impl Into<String>>,
     ) -> Self {
         Self {
-            property_type: "STRING".to_string(),
-            description: description.into(),
             enum_values: Some(enum_values.into_iter().map(|s| s.into()).collect()),
-            items: None,
+            ..Self::of_type("STRING", description)
+        }
+    }
+
+    /// Create a new object property without properties
+    pub fn object(description: impl Into<String>) -> Self {
+        Self {
+            properties: Some(HashMap::new()),
+            required: Some(Vec::new()),
+            ..Self::of_type("OBJECT", description)
+        }
+    }
+
+    /// Create a property whose value matches at least one of `variants`
+    pub fn any_of(
+        description: impl Into<String>,
+        variants: impl IntoIterator<Item = PropertyDetails>,
+    ) -> Self {
+        Self {
+            any_of: Some(variants.into_iter().collect()),
+            ..Self::of_type("", description)
+        }
+    }
+
+    /// Add a property to an object property
+    pub fn with_property(
+        mut self,
+        name: impl Into<String>,
+        details: PropertyDetails,
+        required: bool,
+    ) -> Self {
+        let name = name.into();
+        self.properties
+            .get_or_insert_with(HashMap::new)
+            .insert(name.clone(), details);
+        if required {
+            self.required.get_or_insert_with(Vec::new).push(name);
+        }
+        self
+    }
+
+    /// Set the order the properties of an object should be generated in
+    pub fn with_property_ordering(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.property_ordering = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Allow the value to be `null`
+    pub fn with_nullable(mut self, nullable: bool) -> Self {
+        self.nullable = Some(nullable);
+        self
+    }
+
+    /// Set the format of the value, e.g. `date-time`, `enum` or `int32`
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    /// Require an array to have at least `min` items
+    pub fn with_min_items(mut self, min: u64) -> Self {
+        self.min_items = Some(min);
+        self
+    }
+
+    /// Allow an array at most `max` items
+    pub fn with_max_items(mut self, max: u64) -> Self {
+        self.max_items = Some(max);
+        self
+    }
+}
+
+impl PropertyDetails {
+    /// Convert a JSON schema, e.g. of an OpenAI `response_format`, to the
+    /// subset Gemini accepts
+    ///
+    /// `$ref`s to `$defs` or `definitions` are inlined and a string `const`
+    /// becomes a one-value enum; other keywords are handled as by
+    /// [`FunctionDeclaration::from_openai_schema`].
+    pub fn from_openai_schema(schema: &serde_json::Value) -> crate::Result<Self> {
+        let mut issues = Vec::new();
+        let mut issue = |path: &str, message: String| issues.push(format!("{} {}", path, message));
+        let inlined = inline_refs(schema, schema, 0, "schema", &mut issue);
+        let details = openai_property(&inlined, "schema", &mut issue);
+        if !issues.is_empty() {
+            return Err(crate::Error::RequestError(format!(
+                "Unsupported JSON schema: {}",
+                issues.join("; ")
+            )));
+        }
+        Ok(details)
+    }
+
+    /// Types the API accepts in function parameter schemas
+    const TYPES: [&'static str; 6] = ["STRING", "NUMBER", "INTEGER", "BOOLEAN", "ARRAY", "OBJECT"];
+
+    /// The property as a JSON schema with OpenAI's lower-case types
+    ///
+    /// Nullable properties get a `[type, "null"]` union; the property
+    /// ordering has no JSON schema equivalent and is left out.
+    fn to_openai_schema(&self) -> serde_json::Value {
+        let mut schema = serde_json::json!({});
+        if !self.property_type.is_empty() {
+            let property_type = self.property_type.to_lowercase();
+            schema["type"] = match self.nullable {
+                Some(true) => serde_json::json!([property_type, "null"]),
+                _ => serde_json::json!(property_type),
+            };
+        }
+        if !self.description.is_empty() {
+            schema["description"] = serde_json::json!(self.description);
+        }
+        if let Some(format) = &self.format {
+            schema["format"] = serde_json::json!(format);
+        }
+        if let Some(values) = &self.enum_values {
+            schema["enum"] = serde_json::json!(values);
+        }
+        if let Some(items) = &self.items {
+            schema["items"] = items.to_openai_schema();
+        }
+        if let Some(min) = self.min_items {
+            schema["minItems"] = serde_json::json!(min);
+        }
+        if let Some(max) = self.max_items {
+            schema["maxItems"] = serde_json::json!(max);
+        }
+        if let Some(properties) = &self.properties {
+            schema["properties"] = properties
+                .iter()
+                .map(|(name, details)| (name.clone(), details.to_openai_schema()))
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        if let Some(required) = self.required.as_ref().filter(|r| !r.is_empty()) {
+            schema["required"] = serde_json::json!(required);
+        }
+        if let Some(variants) = &self.any_of {
+            let variants: Vec<_> = variants.iter().map(Self::to_openai_schema).collect();
+            schema["anyOf"] = serde_json::json!(variants);
         }
+        schema
+    }
+
+    fn check(&self, path: &str, issue: &mut impl FnMut(&str, String)) {
+        let property_type = self.property_type.to_ascii_uppercase();
+        match &self.any_of {
+            Some(variants) => {
+                if variants.is_empty() {
+                    issue(
+                        &format!("{}.anyOf", path),
+                        "must list at least one schema".to_string(),
+                    );
+                }
+                for (index, variant) in variants.iter().enumerate() {
+                    variant.check(&format!("{}.anyOf.{}", path, index), issue);
+                }
+            }
+            None if !Self::TYPES.contains(&property_type.as_str()) => issue(
+                &format!("{}.type", path),
+                format!("{} is not a supported type", self.property_type),
+            ),
+            None => {}
+        }
+        if self.enum_values.is_some() && property_type != "STRING" {
+            issue(
+                &format!("{}.enum", path),
+                "is only supported on STRING properties".to_string(),
+            );
+        }
+        if self.format.is_some()
+            && !matches!(property_type.as_str(), "STRING" | "NUMBER" | "INTEGER")
+        {
+            issue(
+                &format!("{}.format", path),
+                "is only supported on STRING, NUMBER and INTEGER properties".to_string(),
+            );
+        }
+        match (&self.items, property_type.as_str()) {
+            (Some(items), "ARRAY") => items.check(&format!("{}.items", path), issue),
+            (None, "ARRAY") => issue(path, "is an ARRAY without items".to_string()),
+            (Some(_), _) => issue(
+                &format!("{}.items", path),
+                "is only supported on ARRAY properties".to_string(),
+            ),
+            (None, _) => {}
+        }
+        if (self.min_items.is_some() || self.max_items.is_some()) && property_type != "ARRAY" {
+            issue(
+                &format!("{}.minItems", path),
+                "is only supported on ARRAY properties".to_string(),
+            );
+        }
+        if let (Some(min), Some(max)) = (self.min_items, self.max_items) {
+            if min > max {
+                issue(
+                    &format!("{}.minItems", path),
+                    format!("is {} but maxItems is {}", min, max),
+                );
+            }
+        }
+
+        let has_object_keywords = self.properties.is_some()
+            || self.required.as_ref().is_some_and(|r| !r.is_empty())
+            || self.property_ordering.is_some();
+        if has_object_keywords && property_type != "OBJECT" {
+            issue(
+                &format!("{}.properties", path),
+                "is only supported on OBJECT properties".to_string(),
+            );
+        }
+        check_object(
+            path,
+            self.properties.as_ref(),
+            self.required.as_deref(),
+            self.property_ordering.as_deref(),
+            issue,
+        );
+    }
+}
+
+/// Check that the required and ordered names of an object schema are
+/// defined, then check each property
+fn check_object(
+    path: &str,
+    properties: Option<&HashMap<String, PropertyDetails>>,
+    required: Option<&[String]>,
+    ordering: Option<&[String]>,
+    issue: &mut impl FnMut(&str, String),
+) {
+    let defined = |name: &String| properties.is_some_and(|p| p.contains_key(name));
+    for name in required.into_iter().flatten() {
+        if !defined(name) {
+            issue(
+                &format!("{}.required", path),
+                format!("{} is required but not defined", name),
+            );
+        }
+    }
+    for name in ordering.into_iter().flatten() {
+        if !defined(name) {
+            issue(
+                &format!("{}.propertyOrdering", path),
+                format!("{} is ordered but not defined", name),
+            );
+        }
+    }
+    let mut names: Vec<_> = properties.into_iter().flatten().collect();
+    names.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, details) in names {
+        details.check(&format!("{}.properties.{}", path, name), issue);
     }
 }
 
@@ -317,6 +1204,250 @@ impl FunctionCall {
             )),
         }
     }
+
+    /// Get a numeric parameter as an `i64` without going through `f64`
+    pub fn get_i64(&self, key: &str) -> crate::Result<i64> {
+        self.number(key)?.as_i64().ok_or_else(|| {
+            crate::Error::FunctionCallError(format!("Parameter {} is not an i64", key))
+        })
+    }
+
+    /// Get a numeric parameter as a `u64` without going through `f64`
+    pub fn get_u64(&self, key: &str) -> crate::Result<u64> {
+        self.number(key)?.as_u64().ok_or_else(|| {
+            crate::Error::FunctionCallError(format!("Parameter {} is not a u64", key))
+        })
+    }
+
+    /// Get a numeric parameter as its decimal string representation
+    ///
+    /// With the `arbitrary-precision` feature the digits are exactly those
+    /// sent by the API, so values beyond `u64`/`f64` range or precision can
+    /// be handed to a decimal or bignum type. Numbers sent as strings are
+    /// returned as-is.
+    pub fn get_decimal(&self, key: &str) -> crate::Result<String> {
+        match self.param(key)? {
+            serde_json::Value::String(s) if is_decimal(s) => Ok(s.clone()),
+            _ => Ok(self.number(key)?.to_string()),
+        }
+    }
+
+    /// Look up a raw parameter value
+    fn param(&self, key: &str) -> crate::Result<&serde_json::Value> {
+        match &self.args {
+            serde_json::Value::Object(obj) => obj.get(key).ok_or_else(|| {
+                crate::Error::FunctionCallError(format!("Missing parameter: {}", key))
+            }),
+            _ => Err(crate::Error::FunctionCallError(
+                "Arguments are not an object".to_string(),
+            )),
+        }
+    }
+
+    /// Look up a numeric parameter
+    fn number(&self, key: &str) -> crate::Result<&serde_json::Number> {
+        match self.param(key)? {
+            serde_json::Value::Number(n) => Ok(n),
+            _ => Err(crate::Error::FunctionCallError(format!(
+                "Parameter {} is not a number",
+                key
+            ))),
+        }
+    }
+
+    /// Deserialize all arguments into a typed value
+    ///
+    /// Arguments that arrive as a JSON-encoded string (as produced by some
+    /// gateways) are parsed strictly first.
+    pub fn args_as<T: serde::de::DeserializeOwned>(&self) -> crate::Result<T> {
+        let result = match &self.args {
+            serde_json::Value::String(raw) => serde_json::from_str(raw),
+            args => serde_json::from_value(args.clone()),
+        };
+        result.map_err(|e| {
+            crate::Error::FunctionCallError(format!(
+                "Error deserializing arguments of {}: {}",
+                self.name, e
+            ))
+        })
+    }
+
+    /// Deserialize all arguments, repairing slightly malformed JSON if needed
+    ///
+    /// Opt-in alternative to [`FunctionCall::args_as`] for models that emit
+    /// trailing commas, single-quoted strings and similar near-JSON. The
+    /// repairs that were applied are returned alongside the value; an empty
+    /// list means the arguments were valid as-is.
+    pub fn args_as_lenient<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> crate::Result<(T, Vec<JsonRepair>)> {
+        if let Ok(value) = self.args_as() {
+            return Ok((value, Vec::new()));
+        }
+        let raw = match &self.args {
+            serde_json::Value::String(raw) => raw.clone(),
+            args => args.to_string(),
+        };
+        let (repaired, repairs) = repair_json(&raw);
+        serde_json::from_str(&repaired)
+            .map(|value| (value, repairs.clone()))
+            .map_err(|e| {
+                let applied = if repairs.is_empty() {
+                    "no repairs applied".to_string()
+                } else {
+                    let names: Vec<_> = repairs.iter().map(|r| r.to_string()).collect();
+                    format!("after repairs: {}", names.join(", "))
+                };
+                crate::Error::FunctionCallError(format!(
+                    "Error deserializing arguments of {} ({}): {}",
+                    self.name, applied, e
+                ))
+            })
+    }
+}
+
+/// Whether a string is a plain decimal number such as `-12.50`
+fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let mut parts = digits.splitn(2, '.');
+    let int = parts.next().unwrap_or_default();
+    let frac = parts.next();
+    !int.is_empty()
+        && int.bytes().all(|b| b.is_ascii_digit())
+        && frac.is_none_or(|f| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// A fix applied to malformed JSON by the lenient argument parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonRepair {
+    /// Removed a surrounding markdown code fence
+    StrippedCodeFence,
+    /// Removed a comma before a closing bracket or brace
+    TrailingComma,
+    /// Converted single-quoted strings to double-quoted ones
+    SingleQuotes,
+    /// Quoted bare object keys
+    UnquotedKeys,
+    /// Replaced Python `True`/`False`/`None` with JSON literals
+    PythonLiterals,
+}
+
+impl std::fmt::Display for JsonRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::StrippedCodeFence => "stripped code fence",
+            Self::TrailingComma => "removed trailing comma",
+            Self::SingleQuotes => "converted single quotes",
+            Self::UnquotedKeys => "quoted bare keys",
+            Self::PythonLiterals => "replaced Python literals",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Rewrite near-JSON into JSON, reporting which repairs were needed
+fn repair_json(input: &str) -> (String, Vec<JsonRepair>) {
+    let mut repairs = Vec::new();
+    fn note(repair: JsonRepair, repairs: &mut Vec<JsonRepair>) {
+        if !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    }
+
+    let mut text = input.trim();
+    if let Some(fenced) = text.strip_prefix("```") {
+        let body = fenced.split_once('\n').map_or("", |(_, body)| body);
+        text = body.trim_end().strip_suffix("```").unwrap_or(body).trim();
+        note(JsonRepair::StrippedCodeFence, &mut repairs);
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' => {
+                out.push('"');
+                i += 1;
+                while i < chars.len() {
+                    out.push(chars[i]);
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        out.push(chars[i + 1]);
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    if chars[i - 1] == '"' {
+                        break;
+                    }
+                }
+            }
+            '\'' => {
+                note(JsonRepair::SingleQuotes, &mut repairs);
+                out.push('"');
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    match chars[i] {
+                        '\\' if chars.get(i + 1) == Some(&'\'') => {
+                            out.push('\'');
+                            i += 1;
+                        }
+                        '\\' if i + 1 < chars.len() => {
+                            out.push('\\');
+                            out.push(chars[i + 1]);
+                            i += 1;
+                        }
+                        '"' => out.push_str("\\\""),
+                        other => out.push(other),
+                    }
+                    i += 1;
+                }
+                out.push('"');
+                i += 1;
+            }
+            ',' => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if matches!(next, Some('}') | Some(']')) {
+                    note(JsonRepair::TrailingComma, &mut repairs);
+                } else {
+                    out.push(',');
+                }
+                i += 1;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let next = chars[i..].iter().find(|c| !c.is_whitespace());
+                match word.as_str() {
+                    "True" | "False" | "None" if next != Some(&':') => {
+                        note(JsonRepair::PythonLiterals, &mut repairs);
+                        out.push_str(match word.as_str() {
+                            "True" => "true",
+                            "False" => "false",
+                            _ => "null",
+                        });
+                    }
+                    _ if next == Some(&':') => {
+                        note(JsonRepair::UnquotedKeys, &mut repairs);
+                        out.push('"');
+                        out.push_str(&word);
+                        out.push('"');
+                    }
+                    _ => out.push_str(&word),
+                }
+            }
+            other => {
+                out.push(other);
+                i += 1;
+            }
+        }
+    }
+
+    (out, repairs)
 }
 
 /// A response from a function
@@ -351,3 +1482,337 @@ impl FunctionResponse {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Deserialize a documented example and check it serializes back unchanged
+    fn round_trip(example: serde_json::Value) -> PropertyDetails {
+        let details: PropertyDetails = serde_json::from_value(example.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&details).unwrap(), example);
+        details
+    }
+
+    #[test]
+    fn property_ordering_example() {
+        // Structured output guide, "Property ordering"
+        let example = json!({
+            "type": "ARRAY",
+            "items": {
+                "type": "OBJECT",
+                "properties": {
+                    "recipeName": { "type": "STRING" },
+                    "ingredients": { "type": "ARRAY", "items": { "type": "STRING" } }
+                },
+                "propertyOrdering": ["recipeName", "ingredients"]
+            }
+        });
+        let details = round_trip(example.clone());
+        let items = details.items.as_deref().unwrap();
+        assert_eq!(
+            items.property_ordering.as_deref(),
+            Some(&["recipeName".to_string(), "ingredients".to_string()][..])
+        );
+
+        let built = PropertyDetails::array(
+            "",
+            PropertyDetails::object("")
+                .with_property("recipeName", PropertyDetails::string(""), false)
+                .with_property(
+                    "ingredients",
+                    PropertyDetails::array("", PropertyDetails::string("")),
+                    false,
+                )
+                .with_property_ordering(["recipeName", "ingredients"]),
+        );
+        let mut built = serde_json::to_value(built).unwrap();
+        // The builder always sends the list of required properties
+        assert_eq!(built["items"]["required"].take(), json!([]));
+        built["items"].as_object_mut().unwrap().remove("required");
+        assert_eq!(built, example);
+    }
+
+    #[test]
+    fn nullable_format_and_item_count_example() {
+        // Schema reference: `nullable`, `format`, `minItems` and `maxItems`
+        let example = json!({
+            "type": "OBJECT",
+            "properties": {
+                "name": { "type": "STRING", "description": "Full name" },
+                "birthday": { "type": "STRING", "format": "date-time", "nullable": true },
+                "count": { "type": "INTEGER", "format": "int64" },
+                "tags": {
+                    "type": "ARRAY",
+                    "items": { "type": "STRING", "format": "enum", "enum": ["new", "used"] },
+                    "minItems": 1,
+                    "maxItems": 3
+                }
+            },
+            "required": ["name"],
+            "propertyOrdering": ["name", "birthday", "count", "tags"]
+        });
+        let details = round_trip(example.clone());
+        let properties = details.properties.as_ref().unwrap();
+        assert_eq!(properties["birthday"].nullable, Some(true));
+        assert_eq!(properties["count"].format.as_deref(), Some("int64"));
+        assert_eq!(properties["tags"].min_items, Some(1));
+        assert_eq!(properties["tags"].max_items, Some(3));
+
+        let built = PropertyDetails::object("")
+            .with_property("name", PropertyDetails::string("Full name"), true)
+            .with_property(
+                "birthday",
+                PropertyDetails::string("")
+                    .with_format("date-time")
+                    .with_nullable(true),
+                false,
+            )
+            .with_property(
+                "count",
+                PropertyDetails::integer("").with_format("int64"),
+                false,
+            )
+            .with_property(
+                "tags",
+                PropertyDetails::array(
+                    "",
+                    PropertyDetails::enum_type("", ["new", "used"]).with_format("enum"),
+                )
+                .with_min_items(1)
+                .with_max_items(3),
+                false,
+            )
+            .with_property_ordering(["name", "birthday", "count", "tags"]);
+        assert_eq!(serde_json::to_value(built).unwrap(), example);
+    }
+
+    #[test]
+    fn any_of_example() {
+        // Schema reference: `anyOf`
+        let example = json!({
+            "type": "OBJECT",
+            "properties": {
+                "id": {
+                    "anyOf": [
+                        { "type": "INTEGER", "format": "int64" },
+                        { "type": "STRING" }
+                    ],
+                    "description": "Numeric or string identifier"
+                }
+            },
+            "required": ["id"]
+        });
+        let details = round_trip(example.clone());
+        let id = &details.properties.as_ref().unwrap()["id"];
+        assert!(id.property_type.is_empty());
+        assert_eq!(id.any_of.as_ref().map(Vec::len), Some(2));
+
+        let built = PropertyDetails::object("").with_property(
+            "id",
+            PropertyDetails::any_of(
+                "Numeric or string identifier",
+                [
+                    PropertyDetails::integer("").with_format("int64"),
+                    PropertyDetails::string(""),
+                ],
+            ),
+            true,
+        );
+        assert_eq!(serde_json::to_value(built).unwrap(), example);
+    }
+
+    #[test]
+    fn function_declaration_example() {
+        // Function calling guide, `schedule_meeting`
+        let example = json!({
+            "name": "schedule_meeting",
+            "description": "Schedules a meeting with specified attendees at a given time and date.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "attendees": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "List of people attending the meeting."
+                    },
+                    "date": {
+                        "type": "string",
+                        "description": "Date of the meeting (e.g., '2024-07-29')"
+                    },
+                    "time": {
+                        "type": "string",
+                        "description": "Time of the meeting (e.g., '15:00')"
+                    },
+                    "topic": {
+                        "type": "string",
+                        "description": "The subject or topic of the meeting."
+                    }
+                },
+                "required": ["attendees", "date", "time", "topic"]
+            }
+        });
+        let declaration: FunctionDeclaration = serde_json::from_value(example.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&declaration).unwrap(), example);
+        let properties = declaration.parameters.properties.as_ref().unwrap();
+        assert_eq!(
+            properties["attendees"]
+                .items
+                .as_ref()
+                .unwrap()
+                .property_type,
+            "string"
+        );
+    }
+
+    #[test]
+    fn response_schema_inlines_refs_and_const() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "step": { "$ref": "#/$defs/step", "description": "First step" },
+                "kind": { "const": "plan" }
+            },
+            "required": ["step", "kind"],
+            "$defs": {
+                "step": {
+                    "type": "object",
+                    "properties": { "text": { "type": "string" } }
+                }
+            }
+        });
+        let details = PropertyDetails::from_openai_schema(&schema).unwrap();
+        let properties = details.properties.as_ref().unwrap();
+        let step = &properties["step"];
+        assert_eq!(step.property_type, "OBJECT");
+        assert_eq!(step.description, "First step");
+        assert!(step.properties.as_ref().unwrap().contains_key("text"));
+        assert_eq!(
+            properties["kind"].enum_values,
+            Some(vec!["plan".to_string()])
+        );
+
+        let recursive = json!({
+            "$ref": "#/$defs/node",
+            "$defs": { "node": { "type": "object", "properties": { "next": { "$ref": "#/$defs/node" } } } }
+        });
+        assert!(PropertyDetails::from_openai_schema(&recursive).is_err());
+    }
+
+    /// Repair `input` and check the result and the repairs reported
+    fn repaired(input: &str, expected: serde_json::Value, repairs: &[JsonRepair]) {
+        let (output, applied) = repair_json(input);
+        let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(value, expected, "repairing {input:?}");
+        assert_eq!(applied, repairs, "repairing {input:?}");
+    }
+
+    #[test]
+    fn repair_code_fence() {
+        repaired(
+            "```json\n{\"city\": \"Paris\"}\n```",
+            json!({"city": "Paris"}),
+            &[JsonRepair::StrippedCodeFence],
+        );
+        repaired(
+            "```\n[1, 2]\n```",
+            json!([1, 2]),
+            &[JsonRepair::StrippedCodeFence],
+        );
+    }
+
+    #[test]
+    fn repair_trailing_comma() {
+        repaired(
+            "{\"tags\": [1, 2,\n   ],\n}",
+            json!({"tags": [1, 2]}),
+            &[JsonRepair::TrailingComma],
+        );
+        // Commas inside strings are left alone
+        repaired("{\"a\": \"x,]\"}", json!({"a": "x,]"}), &[]);
+    }
+
+    #[test]
+    fn repair_single_quotes() {
+        repaired(
+            r#"{'name': 'it\'s', 'quote': 'say "hi"'}"#,
+            json!({"name": "it's", "quote": "say \"hi\""}),
+            &[JsonRepair::SingleQuotes],
+        );
+        // Apostrophes inside double-quoted strings stay as they are
+        repaired(r#"{"name": "it's"}"#, json!({"name": "it's"}), &[]);
+    }
+
+    #[test]
+    fn repair_unquoted_keys() {
+        repaired(
+            "{city: \"Paris\", max_results: 3}",
+            json!({"city": "Paris", "max_results": 3}),
+            &[JsonRepair::UnquotedKeys],
+        );
+        // Python literal spellings used as keys are keys, not literals
+        repaired(
+            "{True: 1, None : 2}",
+            json!({"True": 1, "None": 2}),
+            &[JsonRepair::UnquotedKeys],
+        );
+    }
+
+    #[test]
+    fn repair_python_literals() {
+        repaired(
+            "{\"open\": True, \"closed\": False, \"note\": None}",
+            json!({"open": true, "closed": false, "note": null}),
+            &[JsonRepair::PythonLiterals],
+        );
+        repaired("[\"True\"]", json!(["True"]), &[]);
+    }
+
+    #[test]
+    fn lenient_args_report_repairs() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Args {
+            city: String,
+            open: bool,
+        }
+
+        let call = FunctionCall::new("find", json!({"city": "Paris", "open": true}));
+        let (args, repairs) = call.args_as_lenient::<Args>().unwrap();
+        assert_eq!(args.city, "Paris");
+        assert!(repairs.is_empty());
+
+        let call = FunctionCall::new(
+            "find",
+            json!("```json\n{'city': 'Paris', open: True,}\n```"),
+        );
+        let (args, repairs) = call.args_as_lenient::<Args>().unwrap();
+        assert_eq!(
+            args,
+            Args {
+                city: "Paris".to_string(),
+                open: true
+            }
+        );
+        assert_eq!(
+            repairs,
+            [
+                JsonRepair::StrippedCodeFence,
+                JsonRepair::SingleQuotes,
+                JsonRepair::UnquotedKeys,
+                JsonRepair::PythonLiterals,
+                JsonRepair::TrailingComma,
+            ]
+        );
+
+        let call = FunctionCall::new("find", json!("{'city': }"));
+        let error = call.args_as_lenient::<Args>().unwrap_err().to_string();
+        assert!(
+            error.contains("after repairs: converted single quotes"),
+            "{error}"
+        );
+        let call = FunctionCall::new("find", json!("{\"city\": }"));
+        let error = call.args_as_lenient::<Args>().unwrap_err().to_string();
+        assert!(error.contains("no repairs applied"), "{error}");
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{
+    client::{ContentBuilder, Gemini},
+    mime::{mime_type_from_extension, sniff_mime_type},
+    models::{Blob, Content, FileData, Part, Role},
+    Error, Result,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::Path;
+use std::time::Duration;
+
+const TIMESTAMP_INSTRUCTION: &str = "When you refer to a moment in the video, cite it with a \
+     MM:SS timestamp, or MM:SS-MM:SS for a span.";
+
+/// Which part of a video the model should look at, and how closely
+///
+/// Sent as the `videoMetadata` of a video part.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+    /// Start of the clip to analyze
+    #[serde(default, with = "offset", skip_serializing_if = "Option::is_none")]
+    pub start_offset: Option<Duration>,
+    /// End of the clip to analyze
+    #[serde(default, with = "offset", skip_serializing_if = "Option::is_none")]
+    pub end_offset: Option<Duration>,
+    /// Frames sampled per second (the API default is 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<f64>,
+}
+
+impl VideoMetadata {
+    /// Create metadata that analyzes the whole video at the default rate
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only analyze the video between `start` and `end`
+    pub fn with_clip(mut self, start: Duration, end: Duration) -> Self {
+        self.start_offset = Some(start);
+        self.end_offset = Some(end);
+        self
+    }
+
+    /// Sample a different number of frames per second
+    ///
+    /// Higher rates help with fast motion, lower ones save tokens on long,
+    /// mostly static videos.
+    pub fn with_fps(mut self, fps: f64) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+}
+
+/// Offsets travel as protobuf durations, e.g. `"12.5s"`
+mod offset {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match value {
+            Some(duration) => serializer.serialize_str(&format!("{}s", duration.as_secs_f64())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Option<Duration>, D::Error> {
+        let Some(text) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        text.trim_end_matches('s')
+            .parse::<f64>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid duration {:?}", text)))
+    }
+}
+
+/// A video to send to the model
+///
+/// Videos read from files or bytes are sent inline, which suits short
+/// clips; the whole request must stay under [`Blob::MAX_INLINE_SIZE`].
+/// Longer videos can be uploaded with the Files API and referenced with
+/// `Video::from_file` (`files` feature), or linked on YouTube with
+/// [`Video::from_uri`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Video {
+    source: VideoSource,
+    metadata: Option<VideoMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum VideoSource {
+    Inline(Blob),
+    File(FileData),
+}
+
+impl Video {
+    /// Read a video file
+    ///
+    /// The type is taken from the extension, or from the file's contents
+    /// when the extension is missing or unknown. To force a type, read the
+    /// file with [`Blob::from_path`] and convert it.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            Error::RequestError(format!("Cannot read video {}: {}", path.display(), e))
+        })?;
+        let mime_type = mime_type_from_extension(path)
+            .filter(|mime_type| mime_type.starts_with("video/"))
+            .or_else(|| sniff_mime_type(&bytes).filter(|mime_type| mime_type.starts_with("video/")))
+            .ok_or_else(|| {
+                Error::RequestError(format!("Unsupported video type: {}", path.display()))
+            })?;
+        Ok(Blob::from_vec(mime_type, bytes).into())
+    }
+
+    /// Use video bytes of a known type, e.g. `video/mp4`
+    pub fn from_bytes(mime_type: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        Blob::from_bytes(mime_type, bytes).into()
+    }
+
+    /// Reference a video uploaded with the Files API
+    ///
+    /// The file must be `ACTIVE`, see [`FileInfo::state`](crate::FileInfo::state).
+    #[cfg(feature = "files")]
+    pub fn from_file(file: &crate::FileInfo) -> Self {
+        Self {
+            source: VideoSource::File(FileData::new(file.mime_type.clone(), file.uri.clone())),
+            metadata: None,
+        }
+    }
+
+    /// Reference a video by URI, e.g. a public YouTube video
+    pub fn from_uri(uri: impl Into<String>) -> Self {
+        Self {
+            source: VideoSource::File(FileData::from_uri(uri)),
+            metadata: None,
+        }
+    }
+
+    /// Set the clip and frame rate to analyze
+    pub fn with_metadata(mut self, metadata: VideoMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// The video as an inline data or file data part
+    pub fn to_part(&self) -> Part {
+        match &self.source {
+            VideoSource::Inline(blob) => Part::InlineData {
+                inline_data: blob.clone(),
+                video_metadata: self.metadata,
+            },
+            VideoSource::File(file_data) => Part::FileData {
+                file_data: file_data.clone(),
+                video_metadata: self.metadata,
+            },
+        }
+    }
+}
+
+impl From<Blob> for Video {
+    fn from(blob: Blob) -> Self {
+        Self {
+            source: VideoSource::Inline(blob),
+            metadata: None,
+        }
+    }
+}
+
+/// A reply split at the timestamps it cites
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimestampedAnswer {
+    /// The full reply text
+    pub text: String,
+    /// The cited moments, in the order they appear in the text
+    pub segments: Vec<TimestampedSegment>,
+}
+
+/// Text that follows a timestamp citation, up to the next one
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimestampedSegment {
+    /// The cited moment, or the start of a cited span
+    pub start: Duration,
+    /// The end of a cited span, e.g. `01:20` in `00:45-01:20`
+    pub end: Option<Duration>,
+    /// What the reply says about that moment
+    pub text: String,
+}
+
+impl TimestampedAnswer {
+    /// Find `MM:SS`, `H:MM:SS` and `MM:SS-MM:SS` citations in a text
+    pub fn parse(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut found: Vec<(usize, usize, Duration, Option<Duration>)> = Vec::new();
+        let mut at = 0;
+        while at < text.len() {
+            let Some((start, end)) = timestamp_at(&text, at) else {
+                at += text[at..].chars().next().map_or(1, char::len_utf8);
+                continue;
+            };
+            let (span_end, last) = range_end(&text, end).unwrap_or((end, None));
+            found.push((at, span_end, start, last));
+            at = span_end;
+        }
+
+        let segments = found
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, end, start, last))| {
+                let next = found.get(i + 1).map_or(text.len(), |f| f.0);
+                TimestampedSegment {
+                    start,
+                    end: last,
+                    text: clean_segment(&text[end..next]),
+                }
+            })
+            .collect();
+        Self { text, segments }
+    }
+}
+
+impl ContentBuilder {
+    /// Add a video, with its clip and frame rate settings, as a user message
+    pub fn with_video(mut self, video: &Video) -> Self {
+        self.contents.push(Content {
+            parts: vec![video.to_part()],
+            role: Some(Role::User),
+            ..Default::default()
+        });
+        self
+    }
+}
+
+impl Gemini {
+    /// Ask a question about a video, parsing the timestamps the answer cites
+    pub async fn ask_about_video(
+        &self,
+        video: &Video,
+        question: &str,
+    ) -> Result<TimestampedAnswer> {
+        let response = self
+            .generate_content()
+            .with_system_instruction(TIMESTAMP_INSTRUCTION)
+            .with_message(Content {
+                parts: vec![
+                    video.to_part(),
+                    Part::Text {
+                        text: question.to_string(),
+                        thought_signature: None,
+                    },
+                ],
+                role: Some(Role::User),
+                ..Default::default()
+            })
+            .execute()
+            .await?;
+        Ok(TimestampedAnswer::parse(response.text()))
+    }
+}
+
+/// Parse a timestamp starting exactly at `at`, returning it and its end
+fn timestamp_at(text: &str, at: usize) -> Option<(Duration, usize)> {
+    let bytes = text.as_bytes();
+    if at > 0 && (bytes[at - 1].is_ascii_digit() || bytes[at - 1] == b':') {
+        return None;
+    }
+    let mut fields = Vec::new();
+    let mut end = at;
+    loop {
+        let digits = bytes[end..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        // The first field may have one or two digits, later ones exactly two
+        if digits == 0 || digits > 2 || (!fields.is_empty() && digits != 2) {
+            return None;
+        }
+        fields.push(text[end..end + digits].parse::<u64>().ok()?);
+        end += digits;
+        let more = bytes.get(end) == Some(&b':')
+            && bytes.get(end + 1).is_some_and(u8::is_ascii_digit)
+            && fields.len() < 3;
+        if !more {
+            break;
+        }
+        end += 1;
+    }
+    // A trailing colon is punctuation, `1:02:03:04` is not a timestamp
+    let continues = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+    if fields.len() < 2 || continues(end) || (bytes.get(end) == Some(&b':') && continues(end + 1)) {
+        return None;
+    }
+    if fields[1..].iter().any(|field| *field >= 60) {
+        return None;
+    }
+    let seconds = fields.iter().fold(0, |total, field| total * 60 + field);
+    Some((Duration::from_secs(seconds), end))
+}
+
+/// Parse the second half of a `start-end` span following a timestamp
+fn range_end(text: &str, at: usize) -> Option<(usize, Option<Duration>)> {
+    let rest = &text[at..];
+    let trimmed = rest.trim_start();
+    let separator = ["-", "–", "—", "to "]
+        .iter()
+        .find(|s| trimmed.starts_with(**s))?;
+    let after = &trimmed[separator.len()..];
+    let start = at + rest.len() - after.trim_start().len();
+    let (end_time, end) = timestamp_at(text, start)?;
+    Some((end, Some(end_time)))
+}
+
+/// Strip list markers and brackets left around a citation
+fn clean_segment(text: &str) -> String {
+    let strip: &[char] = &[
+        ' ', '\t', '\n', '\r', ':', '-', '–', '—', ')', ']', '*', ',',
+    ];
+    let text = text.trim_start_matches(strip);
+    let text = text.trim_end_matches([' ', '\t', '\n', '\r', '(', '[', '*', '-']);
+    text.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    /// The start, end and text of each segment found in `text`
+    fn segments(text: &str) -> Vec<(Duration, Option<Duration>, String)> {
+        TimestampedAnswer::parse(text)
+            .segments
+            .into_iter()
+            .map(|segment| (segment.start, segment.end, segment.text))
+            .collect()
+    }
+
+    #[test]
+    fn timestamp_formats() {
+        assert_eq!(
+            segments("- 00:45 A dog runs in\n- 1:02:03 It leaves"),
+            [
+                (secs(45), None, "A dog runs in".to_string()),
+                (secs(3723), None, "It leaves".to_string()),
+            ]
+        );
+        assert_eq!(
+            segments("00:45-01:20 The chase. (02:10 to 02:30) The end."),
+            [
+                (secs(45), Some(secs(80)), "The chase.".to_string()),
+                (secs(130), Some(secs(150)), "The end.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_timestamps() {
+        for text in ["build 1:02:03:04 failed", "ratio 3:1", "at 12:75"] {
+            assert_eq!(segments(text), [], "{text:?}");
+        }
+        assert_eq!(timestamp_at("12:75", 0), None);
+        assert_eq!(timestamp_at("3:1", 0), None);
+        assert_eq!(timestamp_at("1:02:03:04", 0), None);
+        assert_eq!(timestamp_at("1:02:03", 0), Some((secs(3723), 7)));
+    }
+
+    #[test]
+    fn trailing_colon_is_punctuation() {
+        assert_eq!(
+            segments("At 00:45: the dog barks."),
+            [(secs(45), None, "the dog barks.".to_string())]
+        );
+    }
+
+    #[test]
+    fn non_ascii_text_between_citations() {
+        assert_eq!(
+            segments("**00:10** – Café öffnet 🎬\n**01:05** — Schluss"),
+            [
+                (secs(10), None, "Café öffnet 🎬".to_string()),
+                (secs(65), None, "Schluss".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use crate::{
+    client::Gemini,
+    models::{Blob, Content, GenerationResponse, ImageMediaType, Part, Role},
+    postprocess::{PostProcessor, StripCodeFences},
+    Error, Result,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+
+const DESCRIBE_PROMPT: &str = "Describe this image in detail: the main subject, the setting, \
+     notable objects, any visible text, and the overall mood.";
+
+const DETECT_PROMPT: &str = "Detect the prominent objects in the image. For each object give a \
+     short label and its bounding box as box_2d: [ymin, xmin, ymax, xmax], normalized to 0-1000.";
+
+/// Scale of the normalized coordinates the model returns
+const BOX_SCALE: f64 = 1000.0;
+
+const COMPARE_PROMPT: &str = "Compare the two images. List what they have in common and how \
+     they differ, then summarize the comparison in one or two sentences.";
+
+/// An image to send to the model
+///
+/// Built from a file or from bytes already in memory; the data is sent
+/// inline with the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    blob: Blob,
+    /// Width and height in pixels, if the header could be read
+    size: Option<(u32, u32)>,
+}
+
+impl Image {
+    /// Read an image file
+    ///
+    /// The type is taken from the file contents, falling back to the
+    /// extension for formats that are not recognized.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            Error::RequestError(format!("Cannot read image {}: {}", path.display(), e))
+        })?;
+        let media_type = sniff_media_type(&bytes)
+            .or_else(|| media_type_from_extension(path))
+            .ok_or_else(|| {
+                Error::RequestError(format!("Unsupported image type: {}", path.display()))
+            })?;
+        let size = image_size(&bytes);
+        Ok(Self {
+            blob: Blob::from_vec(media_type, bytes),
+            size,
+        })
+    }
+
+    /// Use image bytes of a known type
+    pub fn from_bytes(media_type: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        let bytes = bytes.as_ref();
+        Self {
+            blob: Blob::from_bytes(media_type, bytes),
+            size: image_size(bytes),
+        }
+    }
+
+    /// Use image bytes, detecting JPEG, PNG, GIF and WebP from their contents
+    pub fn detect(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        let bytes = bytes.as_ref();
+        let media_type = sniff_media_type(bytes)
+            .ok_or_else(|| Error::RequestError("Unrecognized image format".to_string()))?;
+        Ok(Self::from_bytes(media_type, bytes))
+    }
+
+    /// Width and height in pixels, read from the JPEG, PNG, GIF or WebP header
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    /// The image as an inline data part
+    pub fn to_part(&self) -> Part {
+        Part::InlineData {
+            inline_data: self.blob.clone(),
+            video_metadata: None,
+        }
+    }
+}
+
+impl From<Blob> for Image {
+    fn from(blob: Blob) -> Self {
+        let size = blob.decode().ok().and_then(|bytes| image_size(&bytes));
+        Self { blob, size }
+    }
+}
+
+/// A rectangle in pixel coordinates, origin at the top left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    /// Left edge
+    pub x_min: u32,
+    /// Top edge
+    pub y_min: u32,
+    /// Right edge
+    pub x_max: u32,
+    /// Bottom edge
+    pub y_max: u32,
+}
+
+impl BoundingBox {
+    /// Scale a `[ymin, xmin, ymax, xmax]` box normalized to 0-1000 to pixels
+    fn from_normalized(normalized: [f64; 4], (width, height): (u32, u32)) -> Self {
+        let scale = |value: f64, size: u32| {
+            (value.clamp(0.0, BOX_SCALE) / BOX_SCALE * size as f64).round() as u32
+        };
+        let [y_min, x_min, y_max, x_max] = normalized;
+        Self {
+            x_min: scale(x_min, width),
+            y_min: scale(y_min, height),
+            x_max: scale(x_max, width),
+            y_max: scale(y_max, height),
+        }
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> u32 {
+        self.x_max.saturating_sub(self.x_min)
+    }
+
+    /// Height in pixels
+    pub fn height(&self) -> u32 {
+        self.y_max.saturating_sub(self.y_min)
+    }
+}
+
+/// An object found by [`Gemini::detect_objects`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedObject {
+    /// What the object is, e.g. `dog`
+    pub label: String,
+    /// Where the object is, scaled to the image's pixel size
+    pub box_2d: BoundingBox,
+}
+
+/// A segmented object, as returned by [`Gemini::segment_objects`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentationMask {
+    /// What the object is
+    pub label: String,
+    /// Where the object is, scaled to the image's pixel size
+    pub box_2d: BoundingBox,
+    /// The mask as a PNG image
+    ///
+    /// The model sizes masks freely; they cover the bounding box and have
+    /// to be stretched to it. Each pixel is the probability (0-255) that it
+    /// belongs to the object.
+    pub mask_png: Vec<u8>,
+}
+
+impl SegmentationMask {
+    /// Parse the segmentation masks in a response to your own request
+    ///
+    /// The text must be a JSON list of entries with `box_2d`, `mask` and
+    /// `label`, optionally in a Markdown code block. `image` is the image
+    /// the masks refer to, used to scale the boxes to pixels.
+    pub fn parse_response(response: &GenerationResponse, image: &Image) -> Result<Vec<Self>> {
+        let size = image.dimensions().ok_or_else(|| {
+            Error::RequestError("Cannot read the pixel size of the image".to_string())
+        })?;
+        let text = StripCodeFences.process(response.text().into_owned())?;
+        let raw: Vec<RawMask> = serde_json::from_str(&text)?;
+        raw.into_iter()
+            .map(|raw| {
+                let data = raw
+                    .mask
+                    .split_once(',')
+                    .map_or(raw.mask.as_str(), |(_, d)| d);
+                let mask_png = BASE64.decode(data.trim()).map_err(|e| {
+                    Error::RequestError(format!("Invalid mask for {:?}: {}", raw.label, e))
+                })?;
+                Ok(Self {
+                    label: raw.label,
+                    box_2d: BoundingBox::from_normalized(raw.box_2d, size),
+                    mask_png,
+                })
+            })
+            .collect()
+    }
+
+    /// Decode the mask, stretched to the size of the bounding box
+    #[cfg(feature = "image")]
+    pub fn decode(&self) -> Result<image::GrayImage> {
+        let mask = image::load_from_memory_with_format(&self.mask_png, image::ImageFormat::Png)
+            .map_err(|e| Error::RequestError(format!("Invalid mask for {:?}: {}", self.label, e)))?
+            .into_luma8();
+        Ok(image::imageops::resize(
+            &mask,
+            self.box_2d.width().max(1),
+            self.box_2d.height().max(1),
+            image::imageops::FilterType::Triangle,
+        ))
+    }
+
+    /// An alpha mask the size of the whole image
+    ///
+    /// Pixels of the object with a probability of at least `threshold`
+    /// are 255, everything else is 0.
+    #[cfg(feature = "image")]
+    pub fn to_alpha_mask(
+        &self,
+        width: u32,
+        height: u32,
+        threshold: u8,
+    ) -> Result<image::GrayImage> {
+        let mask = self.decode()?;
+        let mut alpha = image::GrayImage::new(width, height);
+        for (x, y, pixel) in mask.enumerate_pixels() {
+            let (x, y) = (self.box_2d.x_min + x, self.box_2d.y_min + y);
+            if x < width && y < height && pixel.0[0] >= threshold {
+                alpha.put_pixel(x, y, image::Luma([255]));
+            }
+        }
+        Ok(alpha)
+    }
+}
+
+/// A mask as returned by the model
+#[derive(Debug, Deserialize)]
+struct RawMask {
+    label: String,
+    /// `[ymin, xmin, ymax, xmax]`, normalized to 0-1000
+    box_2d: [f64; 4],
+    /// Base64 PNG, usually as a `data:image/png;base64,` URL
+    mask: String,
+}
+
+/// An object as returned by the model, with coordinates normalized to 0-1000
+#[derive(Debug, Deserialize)]
+struct RawDetection {
+    label: String,
+    /// `[ymin, xmin, ymax, xmax]`
+    box_2d: [f64; 4],
+}
+
+/// What two images have in common and how they differ
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageComparison {
+    /// Things both images share
+    #[serde(default)]
+    pub similarities: Vec<String>,
+    /// Ways the images differ
+    #[serde(default)]
+    pub differences: Vec<String>,
+    /// A short overall comparison
+    #[serde(default)]
+    pub summary: String,
+}
+
+impl Gemini {
+    /// Describe an image in a paragraph or two
+    pub async fn describe_image(&self, image: &Image) -> Result<String> {
+        self.ask_about_images(&[image], DESCRIBE_PROMPT).await
+    }
+
+    /// Ask a question about an image and return the text of the answer
+    pub async fn ask_about_image(&self, image: &Image, question: &str) -> Result<String> {
+        self.ask_about_images(&[image], question).await
+    }
+
+    /// Compare two images, returning their similarities and differences
+    pub async fn compare_images(&self, first: &Image, second: &Image) -> Result<ImageComparison> {
+        let response = self
+            .generate_content()
+            .with_message(image_message(&[first, second], COMPARE_PROMPT))
+            .with_response_mime_type("application/json")
+            .with_response_schema(json!({
+                "type": "object",
+                "properties": {
+                    "similarities": { "type": "array", "items": { "type": "string" } },
+                    "differences": { "type": "array", "items": { "type": "string" } },
+                    "summary": { "type": "string" }
+                },
+                "required": ["similarities", "differences", "summary"]
+            }))
+            .execute()
+            .await?;
+        response.parse_json()
+    }
+
+    /// Find the prominent objects in an image
+    ///
+    /// The model returns boxes normalized to 0-1000, which are scaled to
+    /// the pixel size read from the image header. Fails if the size cannot
+    /// be read.
+    pub async fn detect_objects(&self, image: &Image) -> Result<Vec<DetectedObject>> {
+        self.detect(image, DETECT_PROMPT).await
+    }
+
+    /// Find the objects in an image that match a description, e.g. `"red cars"`
+    pub async fn detect_objects_matching(
+        &self,
+        image: &Image,
+        description: &str,
+    ) -> Result<Vec<DetectedObject>> {
+        let prompt = format!(
+            "Detect every {} in the image. For each one give a short label and its \
+             bounding box as box_2d: [ymin, xmin, ymax, xmax], normalized to 0-1000.",
+            description
+        );
+        self.detect(image, &prompt).await
+    }
+
+    async fn detect(&self, image: &Image, prompt: &str) -> Result<Vec<DetectedObject>> {
+        let (width, height) = image.dimensions().ok_or_else(|| {
+            Error::RequestError("Cannot read the pixel size of the image".to_string())
+        })?;
+        let response = self
+            .generate_content()
+            .with_message(image_message(&[image], prompt))
+            .with_response_mime_type("application/json")
+            .with_response_schema(json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "label": { "type": "string" },
+                        "box_2d": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["label", "box_2d"]
+                }
+            }))
+            .execute()
+            .await?;
+        let detections: Vec<RawDetection> = response.parse_json()?;
+
+        Ok(detections
+            .into_iter()
+            .map(|raw| DetectedObject {
+                label: raw.label,
+                box_2d: BoundingBox::from_normalized(raw.box_2d, (width, height)),
+            })
+            .collect())
+    }
+
+    /// Segment the prominent objects in an image
+    ///
+    /// Needs a model with segmentation support, e.g. `gemini-2.5-flash`.
+    /// Fails if the pixel size of the image cannot be read.
+    pub async fn segment_objects(&self, image: &Image) -> Result<Vec<SegmentationMask>> {
+        self.segment(image, "the prominent objects").await
+    }
+
+    /// Segment the objects in an image that match a description
+    pub async fn segment_objects_matching(
+        &self,
+        image: &Image,
+        description: &str,
+    ) -> Result<Vec<SegmentationMask>> {
+        self.segment(image, description).await
+    }
+
+    async fn segment(&self, image: &Image, description: &str) -> Result<Vec<SegmentationMask>> {
+        let prompt = format!(
+            "Give the segmentation masks for {}. Output a JSON list where each entry has \
+             the 2D bounding box in \"box_2d\" as [ymin, xmin, ymax, xmax] normalized to \
+             0-1000, the segmentation mask as a base64 PNG in \"mask\", and a short text \
+             label in \"label\".",
+            description
+        );
+        let response = self
+            .generate_content()
+            .with_message(image_message(&[image], &prompt))
+            .with_response_mime_type("application/json")
+            .execute()
+            .await?;
+        SegmentationMask::parse_response(&response, image)
+    }
+
+    async fn ask_about_images(&self, images: &[&Image], prompt: &str) -> Result<String> {
+        let response = self
+            .generate_content()
+            .with_message(image_message(images, prompt))
+            .execute()
+            .await?;
+        Ok(response.text().into_owned())
+    }
+}
+
+/// A user message with the images followed by the prompt
+fn image_message(images: &[&Image], prompt: &str) -> Content {
+    let mut parts: Vec<Part> = images.iter().map(|image| image.to_part()).collect();
+    parts.push(Part::Text {
+        text: prompt.to_string(),
+        thought_signature: None,
+    });
+    Content {
+        parts,
+        role: Some(Role::User),
+        ..Default::default()
+    }
+}
+
+/// Detect the image type from its leading bytes
+fn sniff_media_type(bytes: &[u8]) -> Option<ImageMediaType> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some(ImageMediaType::Jpeg),
+        [0x89, b'P', b'N', b'G', ..] => Some(ImageMediaType::Png),
+        [b'G', b'I', b'F', b'8', ..] => Some(ImageMediaType::Gif),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+            Some(ImageMediaType::WebP)
+        }
+        _ => None,
+    }
+}
+
+fn media_type_from_extension(path: &Path) -> Option<ImageMediaType> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some(ImageMediaType::Jpeg),
+        "png" => Some(ImageMediaType::Png),
+        "gif" => Some(ImageMediaType::Gif),
+        "webp" => Some(ImageMediaType::WebP),
+        _ => None,
+    }
+}
+
+/// Read the pixel size from a JPEG, PNG, GIF or WebP header
+fn image_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le24 = |at: usize| {
+        let b = bytes.get(at..at + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    match sniff_media_type(bytes)? {
+        ImageMediaType::Png => Some((be32(16)?, be32(20)?)),
+        ImageMediaType::Gif => Some((le16(6)?, le16(8)?)),
+        ImageMediaType::WebP => match bytes.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        ImageMediaType::Jpeg => {
+            // Walk the marker segments up to the start-of-frame header
+            let mut at = 2;
+            loop {
+                if *bytes.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *bytes.get(at + 1)?;
+                let is_frame =
+                    matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+                if is_frame {
+                    return Some((be16(at + 7)?, be16(at + 5)?));
+                }
+                at += 2 + be16(at + 2)? as usize;
+            }
+        }
+    }
+}